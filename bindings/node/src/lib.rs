@@ -0,0 +1,237 @@
+//! Node.js bindings of Fingerprunk.
+//!
+//! [`start_search`] runs a search in a background thread and calls back into JavaScript for every
+//! found key and every second with the progress. The returned [`Search`] can be polled, stopped
+//! and awaited.
+
+use std::{
+    num::NonZeroU64,
+    ops::ControlFlow,
+    sync::Mutex,
+    thread::{self, JoinHandle},
+    time::Duration,
+};
+
+use fingerprunk::{
+    Config, Fingerprunk, RunSummary, StatsHandle, StopHandle,
+    observer::{Event, Observer},
+    sink::{self, KeySink, MatchMeta},
+};
+use napi::{
+    Env, Error, Result, Status, Task,
+    bindgen_prelude::{AsyncTask, Unknown},
+    threadsafe_function::{ThreadsafeFunction, ThreadsafeFunctionCallMode},
+};
+use napi_derive::napi;
+use sequoia_openpgp::Cert;
+
+/// What the search thread returns.
+type SearchResult = std::result::Result<RunSummary, fingerprunk::Error>;
+
+/// A JavaScript callback which may be called from the search thread.
+type Callback<T> = ThreadsafeFunction<T, Unknown<'static>, T, Status, false>;
+
+/// Configuration of [`start_search`].
+#[napi(object)]
+pub struct SearchConfig {
+    /// Regexes to match the fingerprints against, in the same syntax as on the command line.
+    pub regexes: Vec<String>,
+    /// Stop after this many keys have been found.
+    pub stop_after: Option<u32>,
+    /// Password to encrypt the found keys with.
+    pub password: Option<String>,
+}
+
+/// A key passed to the `onFound` callback.
+#[napi(object)]
+pub struct FoundKey {
+    /// Fingerprint of the key in uppercase hex.
+    pub fingerprint: String,
+    /// The regex the key matched.
+    pub pattern: String,
+    /// The secret key, armored and encrypted with the password if one was given.
+    pub armored: String,
+    /// Number of keys tried until this one was found.
+    pub tried: f64,
+}
+
+/// Progress of a search, passed to the `onProgress` callback and returned by [`Search::stats`].
+#[napi(object)]
+pub struct Progress {
+    pub tried: f64,
+    pub found: f64,
+    pub elapsed_secs: f64,
+    /// Keys tried per second, smoothed over the last few seconds.
+    pub rate: f64,
+}
+
+/// Result of a finished search, resolved by [`Search::wait`].
+#[napi(object)]
+pub struct Summary {
+    pub tried: f64,
+    pub found: f64,
+    pub elapsed_secs: f64,
+    pub fingerprints: Vec<String>,
+}
+
+/// Starts searching in the background, calling `onFound` for every found key and `onProgress`
+/// every second.
+#[napi]
+pub fn start_search(
+    config: SearchConfig,
+    on_found: Callback<FoundKey>,
+    on_progress: Option<Callback<Progress>>,
+) -> Result<Search> {
+    let mut builder = Config::builder()
+        .stop_after(config.stop_after.and_then(|n| NonZeroU64::new(n.into())))
+        .password(config.password.map(Into::into));
+    for regex in config.regexes {
+        builder = builder.regex(regex);
+    }
+    let config = builder.build().map_err(to_error)?;
+
+    let mut fingerprunk = Fingerprunk::new_from_config(config);
+    fingerprunk.set_sink(CallbackSink(on_found));
+    if let Some(on_progress) = on_progress {
+        fingerprunk.add_observer(ProgressObserver(on_progress));
+    }
+    let stop_handle = fingerprunk.stop_handle();
+    let stats_handle = fingerprunk.stats_handle();
+    let search = thread::Builder::new()
+        .name("search".to_string())
+        .spawn(move || fingerprunk.run())
+        .map_err(to_error)?;
+
+    Ok(Search {
+        stop_handle,
+        stats_handle,
+        search: Mutex::new(Some(search)),
+    })
+}
+
+/// A search running in the background.
+#[napi]
+pub struct Search {
+    stop_handle: StopHandle,
+    stats_handle: StatsHandle,
+    search: Mutex<Option<JoinHandle<SearchResult>>>,
+}
+
+#[napi]
+impl Search {
+    /// Asks the search to stop. It may still find a few keys before it has stopped.
+    #[napi]
+    pub fn stop(&self) {
+        self.stop_handle.stop();
+    }
+
+    /// The current progress of the search.
+    #[napi]
+    pub fn stats(&self) -> Progress {
+        let stats = self.stats_handle.stats();
+        progress(stats.tried, stats.found, stats.elapsed, stats.rate)
+    }
+
+    /// Whether the search is still running.
+    #[napi(getter)]
+    pub fn running(&self) -> bool {
+        self.search
+            .lock()
+            .unwrap()
+            .as_ref()
+            .is_some_and(|search| !search.is_finished())
+    }
+
+    /// Resolves once the search has stopped, by itself or after `stop()`. Can only be called once.
+    #[napi(ts_return_type = "Promise<Summary>")]
+    pub fn wait(&self) -> AsyncTask<Wait> {
+        AsyncTask::new(Wait(self.search.lock().unwrap().take()))
+    }
+}
+
+/// Joins the search thread off the JavaScript thread.
+pub struct Wait(Option<JoinHandle<SearchResult>>);
+
+impl Task for Wait {
+    type Output = RunSummary;
+    type JsValue = Summary;
+
+    fn compute(&mut self) -> Result<Self::Output> {
+        let search = self
+            .0
+            .take()
+            .ok_or_else(|| Error::from_reason("The search has already been waited for"))?;
+        match search.join() {
+            Ok(result) => result.map_err(to_error),
+            Err(_) => Err(Error::from_reason("The search panicked")),
+        }
+    }
+
+    fn resolve(&mut self, _env: Env, summary: Self::Output) -> Result<Self::JsValue> {
+        Ok(Summary {
+            tried: summary.tried as f64,
+            found: summary.found as f64,
+            elapsed_secs: summary.elapsed.as_secs_f64(),
+            fingerprints: summary.fingerprints,
+        })
+    }
+}
+
+/// Hands the found keys to the `onFound` callback.
+struct CallbackSink(Callback<FoundKey>);
+
+impl KeySink for CallbackSink {
+    fn found(&mut self, cert: Cert, meta: MatchMeta) -> anyhow::Result<ControlFlow<()>> {
+        let mut armored = Vec::new();
+        sink::write_armored(&cert, &meta, &mut armored)?;
+        let status = self.0.call(
+            FoundKey {
+                fingerprint: meta.fingerprint,
+                pattern: meta.pattern,
+                armored: String::from_utf8(armored)?,
+                tried: meta.tried as f64,
+            },
+            ThreadsafeFunctionCallMode::Blocking,
+        );
+        // The callback is gone once the environment shuts down
+        Ok(if status == Status::Ok {
+            ControlFlow::Continue(())
+        } else {
+            ControlFlow::Break(())
+        })
+    }
+}
+
+/// Hands the progress events to the `onProgress` callback.
+struct ProgressObserver(Callback<Progress>);
+
+impl Observer for ProgressObserver {
+    fn on_event(&self, event: &Event) {
+        if let Event::Progress {
+            tried,
+            rate,
+            found,
+            elapsed,
+        } = *event
+        {
+            // Queued without blocking the thread emitting the events
+            self.0.call(
+                progress(tried, found, elapsed, rate),
+                ThreadsafeFunctionCallMode::NonBlocking,
+            );
+        }
+    }
+}
+
+fn progress(tried: u64, found: u64, elapsed: Duration, rate: f64) -> Progress {
+    Progress {
+        tried: tried as f64,
+        found: found as f64,
+        elapsed_secs: elapsed.as_secs_f64(),
+        rate,
+    }
+}
+
+fn to_error(err: impl Into<anyhow::Error>) -> Error {
+    Error::from_reason(format!("{:#}", err.into()))
+}
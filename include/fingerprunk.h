@@ -0,0 +1,45 @@
+/*
+ * C API of Fingerprunk, available when building the library with the ffi feature:
+ *
+ *     cargo rustc --lib --release --no-default-features --features ffi --crate-type cdylib
+ *
+ * See src/ffi.rs for the documentation of the functions.
+ */
+
+#ifndef FINGERPRUNK_H
+#define FINGERPRUNK_H
+
+#include <stdbool.h>
+#include <stddef.h>
+#include <stdint.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+typedef struct FingerprunkSearch FingerprunkSearch;
+
+typedef struct FingerprunkStats {
+    uint64_t tried;
+    uint64_t found;
+    double elapsed_secs;
+    double rate;
+} FingerprunkStats;
+
+/* Called from the search thread for every found key; return non-zero to stop the search. */
+typedef int (*FingerprunkKeyCallback)(void *user_data, const char *fingerprint,
+                                      const char *armored_key);
+
+FingerprunkSearch *fingerprunk_start(const char *const *regexes, size_t regex_count,
+                                     uint64_t stop_after, FingerprunkKeyCallback on_key,
+                                     void *user_data, char **error);
+void fingerprunk_stop(const FingerprunkSearch *search);
+bool fingerprunk_poll_stats(const FingerprunkSearch *search, FingerprunkStats *stats);
+int fingerprunk_wait(FingerprunkSearch *search, char **error);
+void fingerprunk_string_free(char *string);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif
@@ -0,0 +1,214 @@
+//! Searching for age identities, see [`KeyFormat::Age`](crate::format::KeyFormat::Age).
+//!
+//! The workers generate X25519 keys and match the patterns against their age recipients, the
+//! Bech32 encoding of the public key after `age1`, which is lowercase and has 62 characters, the
+//! last 6 of them a checksum. Found keys are written by [`AgeSink`] as identity files like those
+//! of `age-keygen`.
+
+use std::{
+    fmt,
+    io::{self, Write},
+    ops::ControlFlow,
+    time::SystemTime,
+};
+
+use anyhow::{Context, bail};
+use sequoia_openpgp::{
+    Cert,
+    crypto::{self, mpi},
+    packet::{
+        Key,
+        key::{KeyParts, KeyRole, SecretKeyMaterial},
+    },
+};
+
+use crate::{
+    keygen::HexBuffer,
+    sink::{KeySink, MatchMeta, write_file},
+    status::format_timestamp,
+};
+
+/// Length of an age recipient, including the `age1` prefix.
+pub const RECIPIENT_LEN: usize = 62;
+
+/// The Bech32 digits.
+pub(crate) const CHARSET: &[u8; 32] = b"qpzry9x8gf2tvdw0s3jn54khce6mua7l";
+
+const RECIPIENT_HRP: &str = "age";
+const IDENTITY_HRP: &str = "age-secret-key-";
+/// Number of 5-bit groups of a 32-byte key.
+const KEY_GROUPS: usize = 52;
+const CHECKSUM_LEN: usize = 6;
+
+/// Writes the age recipient of the X25519 public key `public` into `buf` and returns it, as the
+/// workers do for every key.
+pub fn encode_recipient<'a>(public: &[u8; 32], buf: &'a mut HexBuffer) -> &'a str {
+    let len = bech32(RECIPIENT_HRP, public, buf);
+    debug_assert_eq!(len, RECIPIENT_LEN);
+    std::str::from_utf8(&buf[..len]).expect("Bech32 should be valid UTF-8")
+}
+
+/// The age recipient of an X25519 key, which is what patterns are matched against, or `None` for
+/// keys of other algorithms.
+#[must_use]
+pub fn recipient<P: KeyParts, R: KeyRole>(key: &Key<P, R>) -> Option<String> {
+    let mut buf: HexBuffer = [0; 64];
+    Some(encode_recipient(&x25519_public(key)?, &mut buf).to_string())
+}
+
+/// An X25519 key as an age identity.
+pub struct AgeIdentity {
+    public: [u8; 32],
+    secret: crypto::mem::Protected,
+}
+
+impl AgeIdentity {
+    /// Takes the key of a certificate built by Fingerprunk. Fails if the key isn't an
+    /// unencrypted X25519 key.
+    pub fn from_cert(cert: &Cert) -> anyhow::Result<Self> {
+        let key = cert.primary_key().key();
+        let Some(public) = x25519_public(key) else {
+            bail!("age identities need X25519 keys, not {}", key.pk_algo());
+        };
+        let secret = match key.optional_secret() {
+            Some(SecretKeyMaterial::Unencrypted(secret)) => secret.map(|secret| match secret {
+                mpi::SecretKeyMaterial::X25519 { x } => Some(x.clone()),
+                _ => None,
+            }),
+            Some(SecretKeyMaterial::Encrypted(_)) => bail!("The secret key is encrypted"),
+            None => None,
+        }
+        .context("The certificate has no secret key")?;
+        Ok(Self { public, secret })
+    }
+
+    /// The recipient to encrypt to, e.g. `age1...`.
+    #[must_use]
+    pub fn recipient(&self) -> String {
+        let mut buf: HexBuffer = [0; 64];
+        encode_recipient(&self.public, &mut buf).to_string()
+    }
+
+    /// The identity file of `age-keygen`, with the time of creation and the recipient as
+    /// comments.
+    #[must_use]
+    pub fn identity_file(&self) -> String {
+        let mut secret = [0u8; IDENTITY_HRP.len() + 1 + KEY_GROUPS + CHECKSUM_LEN];
+        let secret_bytes: &[u8; 32] = self.secret[..]
+            .try_into()
+            .expect("X25519 secret keys should have 32 bytes");
+        let len = bech32(IDENTITY_HRP, secret_bytes, &mut secret);
+        secret[..len].make_ascii_uppercase();
+        format!(
+            "# created: {}\n# public key: {}\n{}\n",
+            format_timestamp(SystemTime::now()),
+            self.recipient(),
+            std::str::from_utf8(&secret[..len]).expect("Bech32 should be valid UTF-8"),
+        )
+    }
+}
+
+impl fmt::Debug for AgeIdentity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("AgeIdentity")
+            .field("recipient", &self.recipient())
+            .finish_non_exhaustive()
+    }
+}
+
+/// Writes the found keys as age identity files, the default sink for
+/// [`KeyFormat::Age`](crate::format::KeyFormat::Age).
+///
+/// Like [`OutputSink`](crate::sink::OutputSink), every key goes into the directory the
+/// configuration routes it to, here as a file named after its recipient with `.txt` appended, or
+/// to stdout if there is none.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct AgeSink;
+
+impl KeySink for AgeSink {
+    fn found(&mut self, cert: Cert, meta: MatchMeta) -> anyhow::Result<ControlFlow<()>> {
+        let identity = AgeIdentity::from_cert(&cert)?.identity_file();
+        match &meta.dir {
+            Some(dir) => {
+                let name = format!("{}.txt", meta.fingerprint);
+                write_file(dir, &name, identity.as_bytes(), true)?;
+            }
+            None => {
+                let mut stdout = io::stdout().lock();
+                stdout.write_all(identity.as_bytes())?;
+                stdout.flush()?;
+            }
+        }
+        Ok(ControlFlow::Continue(()))
+    }
+}
+
+/// The public key of an X25519 key, or `None` for keys of other algorithms.
+pub(crate) fn x25519_public<P: KeyParts, R: KeyRole>(key: &Key<P, R>) -> Option<[u8; 32]> {
+    match key.mpis() {
+        mpi::PublicKey::X25519 { u } => Some(*u),
+        _ => None,
+    }
+}
+
+/// Writes the Bech32 encoding (BIP 173) of `key` with the human-readable part `hrp` into `out`
+/// and returns its length.
+fn bech32(hrp: &str, key: &[u8; 32], out: &mut [u8]) -> usize {
+    // Regrouped into 5 bits, the last group padded with zeros
+    let mut groups = [0u8; KEY_GROUPS];
+    let (mut acc, mut bits, mut n) = (0u32, 0, 0);
+    for &byte in key {
+        acc = (acc << 8 | u32::from(byte)) & 0xFFF;
+        bits += 8;
+        while bits >= 5 {
+            bits -= 5;
+            groups[n] = (acc >> bits) as u8 & 0x1F;
+            n += 1;
+        }
+    }
+    if bits > 0 {
+        groups[n] = (acc << (5 - bits)) as u8 & 0x1F;
+    }
+
+    let hrp_high = hrp.bytes().map(|b| b >> 5);
+    let hrp_low = hrp.bytes().map(|b| b & 0x1F);
+    let checksum = polymod(
+        hrp_high
+            .chain([0])
+            .chain(hrp_low)
+            .chain(groups)
+            .chain([0; CHECKSUM_LEN]),
+    ) ^ 1;
+
+    out[..hrp.len()].copy_from_slice(hrp.as_bytes());
+    out[hrp.len()] = b'1';
+    let data = &mut out[hrp.len() + 1..hrp.len() + 1 + KEY_GROUPS + CHECKSUM_LEN];
+    for (digit, group) in data.iter_mut().zip(groups) {
+        *digit = CHARSET[usize::from(group)];
+    }
+    for (i, digit) in data[KEY_GROUPS..].iter_mut().enumerate() {
+        *digit = CHARSET[(checksum >> (5 * (CHECKSUM_LEN - 1 - i)) & 0x1F) as usize];
+    }
+    hrp.len() + 1 + KEY_GROUPS + CHECKSUM_LEN
+}
+
+/// The BCH checksum of Bech32 over 5-bit values.
+fn polymod(values: impl Iterator<Item = u8>) -> u32 {
+    const GENERATORS: [u32; 5] = [
+        0x3b6a_57b2,
+        0x2650_8e6d,
+        0x1ea1_19fa,
+        0x3d42_33dd,
+        0x2a14_62b3,
+    ];
+
+    values.fold(1, |chk, value| {
+        let top = chk >> 25;
+        let chk = (chk & 0x1FF_FFFF) << 5 ^ u32::from(value);
+        GENERATORS
+            .iter()
+            .enumerate()
+            .filter(|(i, _)| top >> i & 1 == 1)
+            .fold(chk, |chk, (_, generator)| chk ^ generator)
+    })
+}
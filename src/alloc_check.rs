@@ -0,0 +1,146 @@
+//! Counting the heap allocations of the workers, to check that trying a key allocates nothing
+//! besides what the key generator does.
+//!
+//! This installs a global allocator counting the allocations of every thread, so the
+//! `alloc-check` feature is meant for checking builds rather than for embedding. Debug builds
+//! assert that matching a fingerprint against plain regexes doesn't allocate once the regexes'
+//! scratch space has been set up, and the allocations per key are logged with `-v`.
+
+#![allow(unsafe_code)]
+
+use std::{
+    alloc::{GlobalAlloc, Layout, System},
+    cell::Cell,
+    sync::atomic::{AtomicU64, Ordering},
+};
+
+use crate::matcher::PatternSet;
+
+#[global_allocator]
+static ALLOCATOR: CountingAllocator = CountingAllocator;
+
+thread_local! {
+    /// Never allocates itself, as it has a constant initializer and no destructor.
+    static ALLOCATIONS: Cell<u64> = const { Cell::new(0) };
+}
+
+struct CountingAllocator;
+
+impl CountingAllocator {
+    fn count() {
+        // Fails while the thread is being torn down, which doesn't concern the workers
+        let _ = ALLOCATIONS.try_with(|count| count.set(count.get() + 1));
+    }
+}
+
+// SAFETY: Everything is delegated to the system allocator, only the count is added.
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        Self::count();
+        // SAFETY: Called with the caller's layout, which meets the same requirements.
+        unsafe { System.alloc(layout) }
+    }
+
+    unsafe fn alloc_zeroed(&self, layout: Layout) -> *mut u8 {
+        Self::count();
+        // SAFETY: See `alloc`.
+        unsafe { System.alloc_zeroed(layout) }
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        Self::count();
+        // SAFETY: `ptr` was allocated by `System` with `layout`, see `alloc`.
+        unsafe { System.realloc(ptr, layout, new_size) }
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        // SAFETY: `ptr` was allocated by `System` with `layout`, see `alloc`.
+        unsafe { System.dealloc(ptr, layout) }
+    }
+}
+
+/// The number of allocations of the current thread so far.
+pub(crate) fn allocations() -> u64 {
+    ALLOCATIONS.with(Cell::get)
+}
+
+/// The allocations of all workers, split up into generating and matching the keys.
+#[derive(Debug, Default)]
+pub(crate) struct WorkerAllocations {
+    generating: AtomicU64,
+    matching: AtomicU64,
+}
+
+impl WorkerAllocations {
+    /// Adds the allocations of a batch of keys.
+    pub(crate) fn add(&self, generating: u64, matching: u64) {
+        self.generating.fetch_add(generating, Ordering::Relaxed);
+        self.matching.fetch_add(matching, Ordering::Relaxed);
+    }
+
+    /// The allocations per key for generating and for matching it, given the number of tried
+    /// keys.
+    pub(crate) fn per_key(&self, tried: u64) -> (f64, f64) {
+        let tried = tried.max(1) as f64;
+        (
+            self.generating.load(Ordering::Relaxed) as f64 / tried,
+            self.matching.load(Ordering::Relaxed) as f64 / tried,
+        )
+    }
+}
+
+/// Counts the allocations of a worker while it tries the keys of a batch.
+#[derive(Debug)]
+pub(crate) struct Meter {
+    /// Whether matching is asserted not to allocate, see [`PatternSet::allocation_free`].
+    check: bool,
+    /// Whether a key has been matched already, so that the scratch space has been set up.
+    warmed_up: bool,
+    mark: u64,
+    generating: u64,
+    matching: u64,
+}
+
+impl Meter {
+    /// Starts counting for a worker using `matcher`.
+    pub(crate) fn new(matcher: &PatternSet) -> Self {
+        Self {
+            check: matcher.allocation_free(),
+            warmed_up: false,
+            mark: allocations(),
+            generating: 0,
+            matching: 0,
+        }
+    }
+
+    /// Counts the allocations since the last key as generating the next one.
+    pub(crate) fn generated(&mut self) {
+        let now = allocations();
+        self.generating += now - self.mark;
+        self.mark = now;
+    }
+
+    /// Counts the allocations since the key was generated as matching it.
+    ///
+    /// Keys that matched may allocate, e.g. to find out which regex it was.
+    pub(crate) fn matched(&mut self, fingerprint_hex: &str, found: bool) {
+        let now = allocations();
+        debug_assert!(
+            !self.check || !self.warmed_up || found || now == self.mark,
+            "Matching {fingerprint_hex} allocated {} times",
+            now - self.mark
+        );
+        self.matching += now - self.mark;
+        self.mark = now;
+        self.warmed_up = true;
+    }
+
+    /// Adds the allocations counted so far to `total`.
+    pub(crate) fn flush(&mut self, total: &WorkerAllocations) {
+        total.add(self.generating, self.matching);
+        self.generating = 0;
+        self.matching = 0;
+        // Updating the counters between the batches is none of the keys' business
+        self.mark = allocations();
+    }
+}
@@ -0,0 +1,305 @@
+//! An append-only record of every emitted secret key, so that it can be proven later which keys
+//! a machine produced.
+//!
+//! Every line of the audit log is a JSON object with a sequence number, the time, the key's
+//! fingerprint, the pattern it matched, where it went, a hash of the search's configuration and
+//! the hash of the previous record. Its own `hash` is the SHA-256 of these fields, so that
+//! removing, reordering or changing a record breaks the chain from there on. [`verify`] checks
+//! the chain, and so does opening a log to append to it.
+
+use std::{
+    fs::{self, File, OpenOptions},
+    io::Write,
+    path::{Path, PathBuf},
+    time::SystemTime,
+};
+
+use anyhow::{Context, bail};
+use sequoia_openpgp::types::HashAlgorithm;
+use serde_json::{Value, json};
+
+use crate::status::format_timestamp;
+
+/// The previous hash of the first record.
+const GENESIS: &str = "0000000000000000000000000000000000000000000000000000000000000000";
+
+/// What an audit log contains, as found by [`verify`].
+#[derive(Clone, Debug)]
+#[non_exhaustive]
+pub struct AuditSummary {
+    pub records: u64,
+    /// The hash of the last record, which commits to all others.
+    pub last_hash: String,
+}
+
+/// An audit log opened for appending.
+#[derive(Debug)]
+pub(crate) struct AuditLog {
+    path: PathBuf,
+    file: File,
+    next_seq: u64,
+    last_hash: String,
+}
+
+impl AuditLog {
+    /// Opens the audit log at `path`, creating it if needed, after checking its chain.
+    pub(crate) fn open(path: &Path) -> anyhow::Result<Self> {
+        let summary = if path.exists() {
+            verify(path)?
+        } else {
+            AuditSummary {
+                records: 0,
+                last_hash: GENESIS.to_string(),
+            }
+        };
+
+        let mut options = OpenOptions::new();
+        options.append(true).create(true);
+        #[cfg(unix)]
+        std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+        let file = options
+            .open(path)
+            .with_context(|| format!("Failed to open audit log {}", path.display()))?;
+        Ok(Self {
+            path: path.to_path_buf(),
+            file,
+            next_seq: summary.records,
+            last_hash: summary.last_hash,
+        })
+    }
+
+    /// Appends the record of an emitted key, written to `destination`, and syncs it to disk.
+    pub(crate) fn record(
+        &mut self,
+        fingerprint: &str,
+        pattern: &str,
+        destination: &str,
+        config_hash: u64,
+    ) -> anyhow::Result<()> {
+        let fields = Fields {
+            seq: self.next_seq,
+            time: format_timestamp(SystemTime::now()),
+            fingerprint: fingerprint.to_string(),
+            pattern: pattern.to_string(),
+            destination: destination.to_string(),
+            config_hash: format!("{config_hash:016x}"),
+            prev: self.last_hash.clone(),
+        };
+        let hash = fields.hash()?;
+        let mut line = fields.to_json(&hash).to_string();
+        line.push('\n');
+
+        self.file
+            .write_all(line.as_bytes())
+            .and_then(|()| self.file.sync_data())
+            .with_context(|| format!("Failed to write to audit log {}", self.path.display()))?;
+        self.next_seq += 1;
+        self.last_hash = hash;
+        Ok(())
+    }
+}
+
+/// Checks the chain of the audit log at `path`, failing at the first record that doesn't belong
+/// to it.
+pub fn verify(path: &Path) -> anyhow::Result<AuditSummary> {
+    let content = fs::read_to_string(path)
+        .with_context(|| format!("Failed to read audit log {}", path.display()))?;
+
+    let mut summary = AuditSummary {
+        records: 0,
+        last_hash: GENESIS.to_string(),
+    };
+    for (num, line) in content.lines().enumerate() {
+        let context = || format!("Audit log {} is broken at line {}", path.display(), num + 1);
+        let record: Value = serde_json::from_str(line).with_context(context)?;
+        let fields = Fields::from_json(&record).with_context(context)?;
+        if fields.seq != summary.records {
+            return Err(anyhow::anyhow!(
+                "Expected record {}, found {}",
+                summary.records,
+                fields.seq
+            ))
+            .with_context(context);
+        }
+        if fields.prev != summary.last_hash {
+            return Err(anyhow::anyhow!("The previous hash doesn't match")).with_context(context);
+        }
+        let hash = fields.hash()?;
+        if record["hash"].as_str() != Some(hash.as_str()) {
+            return Err(anyhow::anyhow!("The hash doesn't match the record")).with_context(context);
+        }
+        summary.records += 1;
+        summary.last_hash = hash;
+    }
+    Ok(summary)
+}
+
+/// The hashed fields of a record.
+struct Fields {
+    seq: u64,
+    time: String,
+    fingerprint: String,
+    pattern: String,
+    destination: String,
+    config_hash: String,
+    prev: String,
+}
+
+impl Fields {
+    fn from_json(record: &Value) -> anyhow::Result<Self> {
+        let string = |name: &str| match record[name].as_str() {
+            Some(value) => Ok(value.to_string()),
+            None => bail!("Missing {name}"),
+        };
+        Ok(Self {
+            seq: record["seq"].as_u64().context("Missing sequence number")?,
+            time: string("time")?,
+            fingerprint: string("fingerprint")?,
+            pattern: string("pattern")?,
+            destination: string("destination")?,
+            config_hash: string("config_hash")?,
+            prev: string("prev")?,
+        })
+    }
+
+    fn to_json(&self, hash: &str) -> Value {
+        json!({
+            "seq": self.seq,
+            "time": self.time,
+            "fingerprint": self.fingerprint,
+            "pattern": self.pattern,
+            "destination": self.destination,
+            "config_hash": self.config_hash,
+            "prev": self.prev,
+            "hash": hash,
+        })
+    }
+
+    /// The SHA-256 in hex of the fields as a JSON array, whose serialization, unlike that of an
+    /// object, doesn't depend on the order of the keys.
+    fn hash(&self) -> anyhow::Result<String> {
+        let canonical = json!([
+            self.seq,
+            self.time,
+            self.fingerprint,
+            self.pattern,
+            self.destination,
+            self.config_hash,
+            self.prev,
+        ])
+        .to_string();
+        let mut context = HashAlgorithm::SHA256.context()?.for_digest();
+        context.update(canonical.as_bytes());
+        let digest = context.into_digest()?;
+        Ok(digest.iter().map(|byte| format!("{byte:02x}")).collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// An audit log with three records in the temporary directory, removed when dropped.
+    struct TestLog(PathBuf);
+
+    impl TestLog {
+        fn new(name: &str) -> Self {
+            let path = std::env::temp_dir().join(format!(
+                "fingerprunk-audit-{name}-{}.jsonl",
+                std::process::id()
+            ));
+            let _ = fs::remove_file(&path);
+            let mut log = AuditLog::open(&path).unwrap();
+            for (i, fingerprint) in ["CAFE01", "CAFE02", "CAFE03"].iter().enumerate() {
+                let file = format!("{fingerprint}.asc");
+                log.record(fingerprint, "^CAFE", &file, i as u64).unwrap();
+            }
+            Self(path)
+        }
+
+        fn lines(&self) -> Vec<String> {
+            let content = fs::read_to_string(&self.0).unwrap();
+            content.lines().map(ToString::to_string).collect()
+        }
+
+        fn write_lines(&self, lines: &[String]) {
+            fs::write(
+                &self.0,
+                lines
+                    .iter()
+                    .map(|line| format!("{line}\n"))
+                    .collect::<String>(),
+            )
+            .unwrap();
+        }
+    }
+
+    impl Drop for TestLog {
+        fn drop(&mut self) {
+            let _ = fs::remove_file(&self.0);
+        }
+    }
+
+    #[test]
+    fn verify_accepts_intact_log() {
+        let log = TestLog::new("intact");
+        let summary = verify(&log.0).unwrap();
+        assert_eq!(summary.records, 3);
+
+        // Appending continues the chain
+        AuditLog::open(&log.0)
+            .unwrap()
+            .record("CAFE04", "^CAFE", "stdout", 3)
+            .unwrap();
+        assert_eq!(verify(&log.0).unwrap().records, 4);
+    }
+
+    #[test]
+    fn verify_rejects_tampered_record() {
+        let log = TestLog::new("tampered");
+        let mut lines = log.lines();
+        lines[1] = lines[1].replace("CAFE02", "BEEF02");
+        log.write_lines(&lines);
+        let err = verify(&log.0).unwrap_err();
+        assert!(format!("{err:#}").contains("line 2"), "{err:#}");
+        assert!(AuditLog::open(&log.0).is_err());
+    }
+
+    #[test]
+    fn verify_rejects_reordered_records() {
+        let log = TestLog::new("reordered");
+        let mut lines = log.lines();
+        lines.swap(1, 2);
+        log.write_lines(&lines);
+        let err = verify(&log.0).unwrap_err();
+        assert!(format!("{err:#}").contains("line 2"), "{err:#}");
+    }
+
+    #[test]
+    fn verify_rejects_removed_first_record() {
+        let log = TestLog::new("head");
+        let lines = log.lines();
+        log.write_lines(&lines[1..]);
+        let err = verify(&log.0).unwrap_err();
+        assert!(format!("{err:#}").contains("line 1"), "{err:#}");
+    }
+
+    #[test]
+    fn verify_reveals_truncation_by_last_hash() {
+        let log = TestLog::new("truncated");
+        let full = verify(&log.0).unwrap();
+
+        let mut lines = log.lines();
+        // A record cut off while being written
+        let half = lines[2].len() / 2;
+        lines[2].truncate(half);
+        log.write_lines(&lines);
+        assert!(verify(&log.0).is_err());
+
+        // Dropping whole records at the end keeps the chain intact, but changes the last hash
+        log.write_lines(&lines[..2]);
+        let truncated = verify(&log.0).unwrap();
+        assert_eq!(truncated.records, 2);
+        assert_ne!(truncated.last_hash, full.last_hash);
+    }
+}
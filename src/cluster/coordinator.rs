@@ -0,0 +1,546 @@
+//! The coordinator, which hands out patterns to remote workers and collects their results.
+
+use std::{
+    collections::BTreeMap,
+    io::{self, ErrorKind, Write},
+    net::{SocketAddr, TcpListener, TcpStream, ToSocketAddrs},
+    num::NonZeroU64,
+    ops::Range,
+    path::PathBuf,
+    sync::{
+        Arc, Mutex,
+        atomic::{AtomicBool, AtomicU64, Ordering},
+    },
+    thread,
+    time::{Duration, Instant, SystemTime},
+};
+
+use anyhow::{Context, bail};
+use fancy_regex::Regex;
+use rustls::{ServerConfig, ServerConnection, StreamOwned};
+
+use super::{
+    dashboard,
+    partition::{RangeAllocator, Seed},
+    protocol::{Connection, Stream, ToCoordinator, ToWorker, VERSION},
+    tls, tokens_match,
+};
+use crate::{
+    matcher,
+    status::{DurationDhms, RateMeter, format_timestamp, humanize},
+};
+
+/// How often the coordinator checks whether it should stop.
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+/// Interval of the status lines.
+const STATUS_INTERVAL: Duration = Duration::from_secs(10);
+/// How long a worker may stay silent before its connection is considered dead. Workers send
+/// their statistics every few seconds.
+const IDLE_TIMEOUT: Duration = Duration::from_secs(60);
+/// How long to wait for keys that workers found before they received `STOP`.
+const STOP_GRACE_PERIOD: Duration = Duration::from_secs(5);
+
+#[derive(Clone, Debug)]
+pub struct CoordinatorConfig {
+    /// Address to accept worker connections on, e.g. `0.0.0.0:7878`. Addresses other than
+    /// loopback require a [`token`](Self::token).
+    pub listen: String,
+    pub regexes: Vec<Regex>,
+    pub patterns_file: Option<PathBuf>,
+    pub stop_after: Option<NonZeroU64>,
+    pub humanize_numbers: bool,
+    /// Shared secret that workers have to present.
+    pub token: Option<String>,
+    /// PEM files with the certificate chain and private key for accepting TLS connections.
+    pub tls: Option<(PathBuf, PathBuf)>,
+    /// Address to serve the web dashboard on, e.g. `127.0.0.1:8080`.
+    pub dashboard: Option<String>,
+    /// Refuse keys that workers didn't encrypt, see [`WorkerConfig::encrypt_to`].
+    ///
+    /// [`WorkerConfig::encrypt_to`]: super::WorkerConfig::encrypt_to
+    pub require_encryption: bool,
+    /// Have the workers vary the creation time of keys derived from a common seed instead of
+    /// generating fresh keys, each in its own ranges of candidates, see
+    /// [`partition`](super::partition). Found keys are reported with the range they were found
+    /// in. Requires OpenPGP v4 keys and keeps the workers from auto-tuning.
+    pub grind_timestamps: bool,
+}
+
+/// What the coordinator knows about a remote worker.
+#[derive(Clone, Debug)]
+pub(super) struct RemoteWorker {
+    pub(super) address: SocketAddr,
+    pub(super) tried: u64,
+    pub(super) found: u64,
+    pub(super) connected: bool,
+    pub(super) last_seen: Instant,
+    pub(super) rate_meter: RateMeter,
+}
+
+/// A key found by a worker, as shown on the dashboard.
+#[derive(Clone, Debug)]
+pub(super) struct CollectedKey {
+    pub(super) fingerprint: String,
+    pub(super) worker: String,
+    pub(super) time: SystemTime,
+    /// Index of the first regex that the fingerprint matches.
+    pub(super) pattern: Option<usize>,
+    /// The range of candidates the key was found in, when grinding timestamps.
+    pub(super) range: Option<Range<u64>>,
+}
+
+/// Distributes the pattern set to remote workers, aggregates their statistics and writes all
+/// found keys to stdout.
+#[derive(Debug)]
+pub struct Coordinator {
+    pub(super) config: CoordinatorConfig,
+    /// The regexes from the configuration and the patterns file.
+    pub(super) regexes: Vec<Regex>,
+    pub(super) started_instant: Instant,
+    pub(super) stop: Arc<AtomicBool>,
+    pub(super) counter_found: AtomicU64,
+    /// All workers that ever connected, by name, so that reconnecting workers keep their
+    /// statistics.
+    pub(super) workers: Mutex<BTreeMap<String, RemoteWorker>>,
+    pub(super) collected_keys: Mutex<Vec<CollectedKey>>,
+    /// The seed of the candidates when grinding timestamps.
+    seed: Option<Seed>,
+    pub(super) ranges: Mutex<RangeAllocator>,
+}
+
+impl Coordinator {
+    #[must_use]
+    pub fn new(config: CoordinatorConfig) -> Self {
+        Self {
+            config,
+            regexes: Vec::new(),
+            started_instant: Instant::now(),
+            stop: Arc::new(AtomicBool::new(false)),
+            counter_found: AtomicU64::new(0),
+            workers: Mutex::new(BTreeMap::new()),
+            collected_keys: Mutex::new(Vec::new()),
+            seed: None,
+            ranges: Mutex::new(RangeAllocator::default()),
+        }
+    }
+
+    pub fn run(mut self) -> anyhow::Result<()> {
+        self.regexes = self.config.regexes.clone();
+        if let Some(path) = &self.config.patterns_file {
+            self.regexes
+                .extend(matcher::read_patterns_file(path, None)?);
+        }
+        if self.regexes.is_empty() {
+            bail!("No patterns given");
+        }
+        if self.config.grind_timestamps {
+            self.seed = Some(Seed::generate()?);
+        }
+
+        let tls_config = match &self.config.tls {
+            Some((cert, key)) => Some(tls::server_config(cert, key)?),
+            None => None,
+        };
+
+        // Anyone reaching the port gets the patterns and can hand in keys
+        if self.config.token.is_none() {
+            let addresses = self
+                .config
+                .listen
+                .to_socket_addrs()
+                .with_context(|| format!("Invalid listen address {}", self.config.listen))?;
+            if addresses
+                .into_iter()
+                .any(|address| !address.ip().is_loopback())
+            {
+                bail!(
+                    "Refusing to listen on {} without a token, as any peer could join the search",
+                    self.config.listen
+                );
+            }
+        }
+        let listener = TcpListener::bind(&self.config.listen)
+            .with_context(|| format!("Failed to listen on {}", self.config.listen))?;
+        listener.set_nonblocking(true)?;
+        eprintln!(
+            "Coordinating search for {} on {}{}",
+            self.patterns().join(" | "),
+            listener.local_addr()?,
+            if tls_config.is_some() { " (TLS)" } else { "" }
+        );
+        let dashboard = match &self.config.dashboard {
+            Some(address) => {
+                let server = dashboard::listen(address)?;
+                eprintln!("Serving the dashboard on http://{}", server.server_addr());
+                Some(server)
+            }
+            None => None,
+        };
+
+        #[cfg(feature = "cli")]
+        {
+            let stop = Arc::clone(&self.stop);
+            ctrlc::set_handler(move || stop.store(true, Ordering::Relaxed))?;
+        }
+        self.started_instant = Instant::now();
+
+        thread::scope(|scope| {
+            let ref_self = &self;
+
+            thread::Builder::new()
+                .name("status".to_string())
+                .spawn_scoped(scope, move || ref_self.status_thread())?;
+
+            if let Some(dashboard) = &dashboard {
+                thread::Builder::new()
+                    .name("dashboard".to_string())
+                    .spawn_scoped(scope, move || ref_self.dashboard_thread(dashboard))?;
+            }
+
+            let mut next_id = 0;
+            while !self.stop.load(Ordering::Relaxed) {
+                let (stream, address) = match listener.accept() {
+                    Ok(connection) => connection,
+                    Err(err) if err.kind() == ErrorKind::WouldBlock => {
+                        thread::sleep(POLL_INTERVAL);
+                        continue;
+                    }
+                    Err(err) => {
+                        eprintln!("Failed to accept connection: {err}");
+                        thread::sleep(POLL_INTERVAL);
+                        continue;
+                    }
+                };
+
+                let tls_config = tls_config.clone();
+                thread::Builder::new()
+                    .name(format!("connection-{next_id}"))
+                    .spawn_scoped(scope, move || {
+                        if let Err(err) = ref_self.handle_connection(stream, address, tls_config) {
+                            eprintln!("Connection from {address} failed: {err:#}");
+                        }
+                    })?;
+                next_id += 1;
+            }
+
+            anyhow::Ok(())
+        })?;
+
+        self.print_status(None);
+        Ok(())
+    }
+
+    fn handle_connection(
+        &self,
+        stream: TcpStream,
+        address: SocketAddr,
+        tls_config: Option<Arc<ServerConfig>>,
+    ) -> anyhow::Result<()> {
+        stream.set_nonblocking(false)?;
+        stream.set_read_timeout(Some(POLL_INTERVAL))?;
+        let stream: Box<dyn Stream> = match tls_config {
+            Some(tls_config) => {
+                Box::new(StreamOwned::new(ServerConnection::new(tls_config)?, stream))
+            }
+            None => Box::new(stream),
+        };
+        let mut connection = Connection::new(stream);
+
+        let stop = || self.stop.load(Ordering::Relaxed);
+        let name = match connection.receive_from_worker(stop)? {
+            Some(ToCoordinator::Hello { version, name }) if version == VERSION => name,
+            Some(ToCoordinator::Hello { version, .. }) => {
+                connection.send_to_worker(&ToWorker::Error(format!(
+                    "unsupported protocol {version}, expected {VERSION}"
+                )))?;
+                bail!("Unsupported protocol {version}");
+            }
+            Some(_) => bail!("Expected HELLO"),
+            None => return Ok(()),
+        };
+
+        if let Some(token) = &self.config.token {
+            match connection.receive_from_worker(stop)? {
+                Some(ToCoordinator::Auth { token: given }) if tokens_match(&given, token) => {}
+                Some(_) => {
+                    connection
+                        .send_to_worker(&ToWorker::Error("authentication failed".to_string()))?;
+                    bail!("Worker {name} failed to authenticate");
+                }
+                None => return Ok(()),
+            }
+        }
+
+        if !self.connect(&name, address) {
+            connection.send_to_worker(&ToWorker::Error(format!(
+                "a worker named {name} is already connected"
+            )))?;
+            bail!("A worker named {name} is already connected");
+        }
+        eprintln!("Worker {name} connected from {address}");
+
+        let result = self.serve_worker(&name, &mut connection);
+        self.disconnect(&name);
+        result
+    }
+
+    /// Hands out the patterns and processes the worker's messages until either side stops.
+    fn serve_worker(
+        &self,
+        name: &str,
+        connection: &mut Connection<Box<dyn Stream>>,
+    ) -> anyhow::Result<()> {
+        for pattern in self.patterns() {
+            connection.send_to_worker(&ToWorker::Pattern(pattern))?;
+        }
+        if let Some(seed) = self.seed {
+            connection.send_to_worker(&ToWorker::Seed(seed))?;
+        }
+        connection.send_to_worker(&ToWorker::Ready)?;
+
+        let mut last_message = Instant::now();
+        loop {
+            // The dashboard shows when the worker was last heard of
+            if let Some(worker) = self
+                .workers
+                .lock()
+                .expect("lock should not be poisoned")
+                .get_mut(name)
+            {
+                worker.last_seen = last_message;
+            }
+
+            let give_up =
+                || self.stop.load(Ordering::Relaxed) || last_message.elapsed() > IDLE_TIMEOUT;
+            let Some(message) = connection.receive_from_worker(give_up)? else {
+                break;
+            };
+            last_message = Instant::now();
+            self.process_message(name, message, connection)?;
+        }
+        if !self.stop.load(Ordering::Relaxed) {
+            if last_message.elapsed() > IDLE_TIMEOUT {
+                eprintln!("Worker {name} timed out");
+            }
+            return Ok(());
+        }
+
+        // Tell the worker to stop and collect the keys it found in the meantime
+        connection.send_to_worker(&ToWorker::Stop)?;
+        let deadline = Instant::now() + STOP_GRACE_PERIOD;
+        while let Some(message) = connection.receive_from_worker(|| Instant::now() >= deadline)? {
+            self.process_message(name, message, connection)?;
+        }
+        Ok(())
+    }
+
+    fn process_message(
+        &self,
+        name: &str,
+        message: ToCoordinator,
+        connection: &mut Connection<Box<dyn Stream>>,
+    ) -> anyhow::Result<()> {
+        match message {
+            ToCoordinator::Stats { tried, .. } => {
+                let mut workers = self.workers.lock().expect("lock should not be poisoned");
+                if let Some(worker) = workers.get_mut(name) {
+                    worker.tried = tried;
+                    worker.rate_meter.update(Instant::now(), tried);
+                }
+            }
+            ToCoordinator::Key {
+                fingerprint,
+                armored,
+                range,
+            } => self.collect_key(name, &fingerprint, &armored, range)?,
+            ToCoordinator::Next if self.seed.is_some() => {
+                // Once stopping, the worker gets STOP instead
+                if !self.stop.load(Ordering::Relaxed) {
+                    let range = self
+                        .ranges
+                        .lock()
+                        .expect("lock should not be poisoned")
+                        .assign(name);
+                    connection.send_to_worker(&ToWorker::Range(range))?;
+                }
+            }
+            ToCoordinator::Done(range) if self.seed.is_some() => {
+                let completed = self
+                    .ranges
+                    .lock()
+                    .expect("lock should not be poisoned")
+                    .complete(name, &range);
+                if !completed {
+                    eprintln!(
+                        "Worker {name} finished {}..{}, which isn't assigned to it",
+                        range.start, range.end
+                    );
+                }
+            }
+            ToCoordinator::Next | ToCoordinator::Done(_) => {
+                bail!("Unexpected range message, as timestamps aren't ground")
+            }
+            // Sent by workers with a token even if the coordinator doesn't require one
+            ToCoordinator::Auth { .. } => {}
+            ToCoordinator::Hello { .. } => bail!("Unexpected HELLO"),
+        }
+        Ok(())
+    }
+
+    /// Writes a key found by a worker to stdout and stops if enough keys have been found.
+    fn collect_key(
+        &self,
+        worker: &str,
+        fingerprint: &str,
+        armored: &str,
+        range: Option<Range<u64>>,
+    ) -> io::Result<()> {
+        if self.config.require_encryption && !armored.starts_with("-----BEGIN PGP MESSAGE-----") {
+            eprintln!("Refusing unencrypted key {fingerprint} from worker {worker}");
+            return Ok(());
+        }
+
+        {
+            let mut stdout = io::stdout().lock();
+            stdout.write_all(armored.as_bytes())?;
+            stdout.flush()?;
+        }
+        match &range {
+            Some(range) => eprintln!(
+                "Worker {worker} found {fingerprint} in range {}..{}",
+                range.start, range.end
+            ),
+            None => eprintln!("Worker {worker} found {fingerprint}"),
+        }
+        if let Some(remote_worker) = self
+            .workers
+            .lock()
+            .expect("lock should not be poisoned")
+            .get_mut(worker)
+        {
+            remote_worker.found += 1;
+        }
+        self.collected_keys
+            .lock()
+            .expect("lock should not be poisoned")
+            .push(CollectedKey {
+                fingerprint: fingerprint.to_string(),
+                worker: worker.to_string(),
+                time: SystemTime::now(),
+                pattern: self
+                    .regexes
+                    .iter()
+                    .position(|regex| regex.is_match(fingerprint).unwrap_or(false)),
+                range,
+            });
+
+        let prev = self.counter_found.fetch_add(1, Ordering::Relaxed);
+        if self.config.stop_after.is_some_and(|s| prev + 1 >= s.get()) {
+            self.stop.store(true, Ordering::Relaxed);
+        }
+        Ok(())
+    }
+
+    /// Marks a worker as connected, returning `false` if one with the same name already is.
+    fn connect(&self, name: &str, address: SocketAddr) -> bool {
+        let mut workers = self.workers.lock().expect("lock should not be poisoned");
+        let now = Instant::now();
+        let worker = workers.entry(name.to_string()).or_insert(RemoteWorker {
+            address,
+            tried: 0,
+            found: 0,
+            connected: false,
+            last_seen: now,
+            rate_meter: RateMeter::new(now),
+        });
+        if worker.connected {
+            return false;
+        }
+        worker.connected = true;
+        worker.address = address;
+        worker.last_seen = now;
+        // Reconnecting workers report their cumulative count, so the rate continues from there
+        worker.rate_meter = RateMeter::new(now);
+        worker.rate_meter.update(now, worker.tried);
+        true
+    }
+
+    fn patterns(&self) -> Vec<String> {
+        self.regexes.iter().map(ToString::to_string).collect()
+    }
+
+    fn disconnect(&self, name: &str) {
+        let mut workers = self.workers.lock().expect("lock should not be poisoned");
+        if let Some(worker) = workers.get_mut(name) {
+            worker.connected = false;
+        }
+        // Ranges the worker didn't finish are handed out to the next worker asking
+        self.ranges
+            .lock()
+            .expect("lock should not be poisoned")
+            .release(name);
+        if !self.stop.load(Ordering::Relaxed) {
+            eprintln!("Worker {name} disconnected");
+        }
+    }
+
+    fn status_thread(&self) {
+        let mut rate_meter = RateMeter::new(self.started_instant);
+        let mut next_status = Instant::now() + STATUS_INTERVAL;
+
+        while !self.stop.load(Ordering::Relaxed) {
+            thread::sleep(POLL_INTERVAL);
+            if Instant::now() < next_status {
+                continue;
+            }
+            next_status += STATUS_INTERVAL;
+
+            rate_meter.update(Instant::now(), self.tried());
+            self.print_status(rate_meter.smoothed());
+        }
+    }
+
+    /// Total number of keys tried by all workers, including disconnected ones.
+    pub(super) fn tried(&self) -> u64 {
+        let workers = self.workers.lock().expect("lock should not be poisoned");
+        workers.values().map(|worker| worker.tried).sum()
+    }
+
+    fn print_status(&self, rate: Option<f64>) {
+        let elapsed = self.started_instant.elapsed();
+        let tried = self.tried();
+        let rate = rate.unwrap_or(tried as f64 / elapsed.as_secs_f64());
+        let connected = {
+            let workers = self.workers.lock().expect("lock should not be poisoned");
+            workers.values().filter(|worker| worker.connected).count()
+        };
+
+        let covered = if self.seed.is_some() {
+            let covered = self
+                .ranges
+                .lock()
+                .expect("lock should not be poisoned")
+                .covered();
+            format!(", covered {}", self.format_number(covered as f64))
+        } else {
+            String::new()
+        };
+
+        eprintln!(
+            "[{}] elapsed {}, {connected} workers, tried {} keys, {} keys/s, found {} keys{covered}",
+            format_timestamp(SystemTime::now()),
+            DurationDhms(elapsed),
+            self.format_number(tried as f64),
+            self.format_number(rate),
+            self.counter_found.load(Ordering::Relaxed),
+        );
+    }
+
+    pub(super) fn format_number(&self, value: f64) -> String {
+        if self.config.humanize_numbers {
+            humanize(value).trim_end().to_string()
+        } else {
+            format!("{value:.0}")
+        }
+    }
+}
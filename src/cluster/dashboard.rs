@@ -0,0 +1,168 @@
+//! Web dashboard of the coordinator, showing the progress of the search across all workers.
+//!
+//! Endpoints:
+//!
+//! - `GET /`: the dashboard page, which polls `/status`
+//! - `GET /status`: aggregate and per-worker statistics, per-pattern progress and the
+//!   fingerprints of the collected keys (never any key material), with the ranges they were
+//!   found in when grinding timestamps
+//!
+//! The dashboard is read-only and has no authentication, so it should only listen on trusted
+//! interfaces.
+
+use std::{
+    sync::atomic::Ordering,
+    time::{Duration, Instant, UNIX_EPOCH},
+};
+
+use anyhow::anyhow;
+use serde_json::{Value, json};
+use tiny_http::{Header, Method, Request, Response, Server};
+
+use super::Coordinator;
+use crate::estimate::Estimate;
+
+/// How often the dashboard thread checks whether the coordinator has stopped.
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+const PAGE: &str = include_str!("dashboard.html");
+
+/// Starts listening on `address`, e.g. `127.0.0.1:8080`.
+pub(super) fn listen(address: &str) -> anyhow::Result<Server> {
+    Server::http(address).map_err(|err| anyhow!("Failed to listen on {address}: {err}"))
+}
+
+impl Coordinator {
+    /// Serves the dashboard until the coordinator stops.
+    pub(super) fn dashboard_thread(&self, server: &Server) {
+        // Estimating may sample random fingerprints, so only do it once
+        let estimates: Vec<_> = self.regexes.iter().map(Estimate::for_regex).collect();
+        let estimate = Estimate::for_regexes(&self.regexes);
+
+        while !self.stop.load(Ordering::Relaxed) {
+            match server.recv_timeout(POLL_INTERVAL) {
+                Ok(Some(request)) => self.handle_dashboard_request(request, &estimates, estimate),
+                Ok(None) => {}
+                Err(err) => eprintln!("Failed to receive dashboard request: {err}"),
+            }
+        }
+    }
+
+    fn handle_dashboard_request(
+        &self,
+        request: Request,
+        estimates: &[Option<Estimate>],
+        estimate: Option<Estimate>,
+    ) {
+        let (status, content_type, body) = match (request.method(), request.url()) {
+            (Method::Get, "/") => (200, "text/html; charset=utf-8", PAGE.to_string()),
+            (Method::Get, "/status") => (
+                200,
+                "application/json",
+                self.dashboard_status(estimates, estimate).to_string(),
+            ),
+            (_, "/" | "/status") => (
+                405,
+                "application/json",
+                json!({ "error": "method not allowed" }).to_string(),
+            ),
+            _ => (
+                404,
+                "application/json",
+                json!({ "error": "not found" }).to_string(),
+            ),
+        };
+
+        let content_type =
+            Header::from_bytes("Content-Type", content_type).expect("header should be valid");
+        let response = Response::from_string(body)
+            .with_status_code(status)
+            .with_header(content_type);
+        // The browser will just poll again
+        let _ = request.respond(response);
+    }
+
+    fn dashboard_status(
+        &self,
+        estimates: &[Option<Estimate>],
+        estimate: Option<Estimate>,
+    ) -> Value {
+        let now = Instant::now();
+        let elapsed = now.duration_since(self.started_instant).as_secs_f64();
+        let tried = self.tried();
+        let found = self.counter_found.load(Ordering::Relaxed);
+
+        let mut rate = 0.0;
+        let workers: Vec<_> = self
+            .workers
+            .lock()
+            .expect("lock should not be poisoned")
+            .iter()
+            .map(|(name, worker)| {
+                let worker_rate = worker.rate_meter.smoothed().filter(|_| worker.connected);
+                rate += worker_rate.unwrap_or(0.0);
+                json!({
+                    "name": name,
+                    "address": worker.address.to_string(),
+                    "connected": worker.connected,
+                    "tried": worker.tried,
+                    "found": worker.found,
+                    "rate": worker_rate,
+                    "last_seen_secs": now.duration_since(worker.last_seen).as_secs_f64(),
+                })
+            })
+            .collect();
+
+        let collected_keys = self
+            .collected_keys
+            .lock()
+            .expect("lock should not be poisoned");
+        let patterns: Vec<_> = self
+            .regexes
+            .iter()
+            .zip(estimates)
+            .enumerate()
+            .map(|(index, (regex, estimate))| {
+                json!({
+                    "pattern": regex.to_string(),
+                    "found": collected_keys.iter().filter(|key| key.pattern == Some(index)).count(),
+                    "expected_tries": estimate.map(|estimate| estimate.expected_tries()),
+                })
+            })
+            .collect();
+        let keys: Vec<_> = collected_keys
+            .iter()
+            .map(|key| {
+                json!({
+                    "fingerprint": key.fingerprint,
+                    "worker": key.worker,
+                    "range": key.range.as_ref().map(|range| [range.start, range.end]),
+                    "time": key
+                        .time
+                        .duration_since(UNIX_EPOCH)
+                        .map_or(0, |time| time.as_secs()),
+                })
+            })
+            .collect();
+
+        json!({
+            "elapsed_secs": elapsed,
+            "tried": tried,
+            "found": found,
+            "stop_after": self.config.stop_after,
+            "rate": rate,
+            "avg_rate": tried as f64 / elapsed,
+            "expected_tries": estimate.map(|estimate| estimate.expected_tries()),
+            "stopping": self.stop.load(Ordering::Relaxed),
+            "workers": workers,
+            "patterns": patterns,
+            "keys": keys,
+            "covered": self.config.grind_timestamps.then(|| {
+                self.ranges
+                    .lock()
+                    .expect("lock should not be poisoned")
+                    .covered()
+            }),
+        })
+    }
+}
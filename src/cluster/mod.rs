@@ -0,0 +1,28 @@
+//! Distributing a search over several machines, enabled with the `cluster` feature.
+//!
+//! A coordinator started with `fingerprunk serve` hands out the patterns to remote workers
+//! started with `fingerprunk worker`, aggregates their statistics and collects the keys they
+//! find.
+
+mod coordinator;
+mod dashboard;
+mod partition;
+mod protocol;
+mod tls;
+mod worker;
+
+pub use coordinator::{Coordinator, CoordinatorConfig};
+pub(crate) use partition::TimestampGenerator;
+pub use worker::WorkerConfig;
+pub(crate) use worker::{CoordinatorLink, CoordinatorSink};
+
+/// Compares two tokens in constant time, so that response times don't reveal how much of a
+/// guessed token is correct.
+fn tokens_match(given: &str, expected: &str) -> bool {
+    given.len() == expected.len()
+        && given
+            .bytes()
+            .zip(expected.bytes())
+            .fold(0, |diff, (a, b)| diff | (a ^ b))
+            == 0
+}
@@ -0,0 +1,511 @@
+//! Partitioning a timestamp-grinding search into disjoint ranges of candidates, see
+//! [`CoordinatorConfig::grind_timestamps`](super::CoordinatorConfig::grind_timestamps).
+//!
+//! Instead of generating a fresh key for every candidate, the workers derive keys from a seed
+//! sent by the coordinator and vary their creation time, which is part of the fingerprint. The
+//! candidates are numbered: candidate `n` is key number `n / WINDOW` derived from the seed,
+//! created `n % WINDOW` seconds before the base time of the seed. The coordinator hands out the
+//! numbers in ranges of [`RANGE_LEN`], so no two workers try the same candidate, the ranges of
+//! disconnected workers are handed out again, and every found key can be traced back to the
+//! range it was found in.
+
+use std::{
+    collections::{BTreeMap, VecDeque},
+    fmt,
+    ops::Range,
+    sync::{Arc, Condvar, Mutex, MutexGuard},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use anyhow::{Context, bail};
+use sequoia_openpgp::{
+    crypto,
+    packet::{
+        Key,
+        key::{Key4, KeyParts, PrimaryRole, SecretParts},
+    },
+    types::HashAlgorithm,
+};
+
+use crate::keygen::{Candidate, KeyGenerator};
+
+/// Number of creation times tried per derived key, about half a year in seconds.
+pub(super) const WINDOW: u64 = 1 << 24;
+
+/// Number of candidates in a range handed out to a worker. Divides [`WINDOW`], so that every range
+/// belongs to a single derived key.
+pub(super) const RANGE_LEN: u64 = 1 << 20;
+
+/// Number of candidates a worker thread takes from a range at once.
+const CHUNK_LEN: u64 = 1 << 12;
+
+/// Number of ranges a worker keeps to trace found keys back to, see [`RangeQueue::locate`].
+const HISTORY_LEN: usize = 1024;
+
+/// The secret that the keys of a timestamp-grinding search are derived from, along with the
+/// latest creation time of the candidates.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub(crate) struct Seed {
+    bytes: [u8; 32],
+    /// Seconds since the Unix epoch.
+    base: u64,
+}
+
+impl Seed {
+    /// A random seed whose candidates are created up to now.
+    pub(super) fn generate() -> anyhow::Result<Self> {
+        let mut bytes = [0; 32];
+        crypto::random(&mut bytes)?;
+        let base = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs();
+        Ok(Self { bytes, base })
+    }
+
+    /// Parses the arguments of a `SEED` message, see [`Self::to_message_args`].
+    pub(super) fn from_message_args(hex: &str, base: &str) -> anyhow::Result<Self> {
+        let mut bytes = [0; 32];
+        if hex.len() != 2 * bytes.len() {
+            bail!("Invalid seed length");
+        }
+        for (byte, pair) in bytes.iter_mut().zip(hex.as_bytes().chunks_exact(2)) {
+            *byte = std::str::from_utf8(pair)
+                .ok()
+                .and_then(|pair| u8::from_str_radix(pair, 16).ok())
+                .context("Invalid seed")?;
+        }
+        let base = base.parse().context("Invalid base time")?;
+        if base < WINDOW {
+            bail!("Base time {base} is too early");
+        }
+        Ok(Self { bytes, base })
+    }
+
+    /// The seed in hex and the base time, as sent in `SEED` messages.
+    pub(super) fn to_message_args(self) -> String {
+        let hex: String = self
+            .bytes
+            .iter()
+            .map(|byte| format!("{byte:02x}"))
+            .collect();
+        format!("{hex} {}", self.base)
+    }
+
+    /// Derives key number `index`, whose secret is the SHA-256 of the seed and the index.
+    fn key(&self, index: u64) -> anyhow::Result<Key4<SecretParts, PrimaryRole>> {
+        let mut context = HashAlgorithm::SHA256.context()?.for_digest();
+        context.update(&self.bytes);
+        context.update(&index.to_be_bytes());
+        let secret = context.into_digest()?;
+        Key4::import_secret_ed25519(&secret, self.creation_time(index * WINDOW))
+    }
+
+    /// The creation time of candidate `n`.
+    fn creation_time(&self, n: u64) -> SystemTime {
+        UNIX_EPOCH + Duration::from_secs(self.base - n % WINDOW)
+    }
+
+    /// The number of the candidate with the key of `key`, if it is in `range`.
+    fn locate<P: KeyParts>(&self, key: &Key<P, PrimaryRole>, range: &Range<u64>) -> Option<u64> {
+        let created = key.creation_time().duration_since(UNIX_EPOCH).ok()?;
+        let offset = self.base.checked_sub(created.as_secs())?;
+        if offset >= WINDOW {
+            return None;
+        }
+        let index = range.start / WINDOW;
+        let n = index * WINDOW + offset;
+        let derived = self.key(index).ok()?;
+        (range.contains(&n) && derived.mpis() == key.mpis()).then_some(n)
+    }
+}
+
+impl fmt::Debug for Seed {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Seed")
+            .field("base", &self.base)
+            .finish_non_exhaustive()
+    }
+}
+
+/// Hands out the candidates of a timestamp-grinding search in disjoint ranges, on the
+/// coordinator.
+///
+/// Ranges are assigned to a worker until it reports them done. The ranges of workers that
+/// disconnect are handed out again, before any new ones.
+#[derive(Debug, Default)]
+pub(super) struct RangeAllocator {
+    /// Start of the ranges that haven't been handed out yet.
+    next: u64,
+    /// Ranges given up by workers.
+    returned: Vec<Range<u64>>,
+    /// Ranges handed out and not done yet, by worker.
+    assigned: BTreeMap<String, Vec<Range<u64>>>,
+}
+
+impl RangeAllocator {
+    /// Assigns the next range to `worker`.
+    pub(super) fn assign(&mut self, worker: &str) -> Range<u64> {
+        let range = self.returned.pop().unwrap_or_else(|| {
+            let start = self.next;
+            self.next += RANGE_LEN;
+            start..self.next
+        });
+        self.assigned
+            .entry(worker.to_string())
+            .or_default()
+            .push(range.clone());
+        range
+    }
+
+    /// Marks a range as done, returning `false` if it wasn't assigned to `worker`.
+    pub(super) fn complete(&mut self, worker: &str, range: &Range<u64>) -> bool {
+        let Some(ranges) = self.assigned.get_mut(worker) else {
+            return false;
+        };
+        let Some(position) = ranges.iter().position(|assigned| assigned == range) else {
+            return false;
+        };
+        ranges.swap_remove(position);
+        true
+    }
+
+    /// Takes back the ranges of `worker` that aren't done, e.g. because it disconnected.
+    pub(super) fn release(&mut self, worker: &str) {
+        if let Some(ranges) = self.assigned.remove(worker) {
+            self.returned.extend(ranges);
+        }
+    }
+
+    /// The number below which all candidates have been tried.
+    pub(super) fn covered(&self) -> u64 {
+        self.assigned
+            .values()
+            .flatten()
+            .chain(&self.returned)
+            .map(|range| range.start)
+            .min()
+            .unwrap_or(self.next)
+    }
+}
+
+/// The ranges a worker received from the coordinator, which its threads take their candidates
+/// from with a [`TimestampGenerator`] each.
+#[derive(Debug)]
+pub(crate) struct RangeQueue {
+    state: Mutex<QueueState>,
+    available: Condvar,
+}
+
+#[derive(Debug)]
+struct QueueState {
+    seed: Seed,
+    /// Ranges that no thread has started on.
+    queued: VecDeque<Range<u64>>,
+    /// Ranges that threads take candidates from or still try candidates of.
+    started: Vec<StartedRange>,
+    /// Ranges all candidates of which were tried, to be reported to the coordinator.
+    done: Vec<Range<u64>>,
+    /// The latest ranges with their seeds, to trace found keys back to.
+    history: VecDeque<(Seed, Range<u64>)>,
+    /// Set once the search stops, which ends the generators.
+    closed: bool,
+}
+
+#[derive(Debug)]
+struct StartedRange {
+    range: Range<u64>,
+    /// The next candidate to hand out.
+    next: u64,
+    /// Number of candidates tried so far.
+    tried: u64,
+}
+
+/// Candidates taken from a range by a [`TimestampGenerator`].
+#[derive(Debug)]
+struct Chunk {
+    seed: Seed,
+    range: Range<u64>,
+    /// The candidates not generated yet.
+    numbers: Range<u64>,
+    len: u64,
+}
+
+impl RangeQueue {
+    pub(super) fn new(seed: Seed) -> Self {
+        Self {
+            state: Mutex::new(QueueState {
+                seed,
+                queued: VecDeque::new(),
+                started: Vec::new(),
+                done: Vec::new(),
+                history: VecDeque::new(),
+                closed: false,
+            }),
+            available: Condvar::new(),
+        }
+    }
+
+    fn state(&self) -> MutexGuard<'_, QueueState> {
+        self.state.lock().expect("lock should not be poisoned")
+    }
+
+    /// Forgets the ranges of a previous connection, which the coordinator takes back, and
+    /// continues with `seed`. Chunks that threads are working on are still tried.
+    pub(super) fn reset(&self, seed: Seed) {
+        let mut state = self.state();
+        state.seed = seed;
+        state.queued.clear();
+        state.started.clear();
+        state.done.clear();
+    }
+
+    /// Adds a range assigned by the coordinator.
+    pub(super) fn push(&self, range: Range<u64>) {
+        let mut state = self.state();
+        let seed = state.seed;
+        if state.history.len() == HISTORY_LEN {
+            state.history.pop_front();
+        }
+        state.history.push_back((seed, range.clone()));
+        state.queued.push_back(range);
+        self.available.notify_all();
+    }
+
+    /// Number of ranges that no thread has started on yet.
+    pub(super) fn queued(&self) -> usize {
+        self.state().queued.len()
+    }
+
+    /// Takes the ranges that are done since the last call.
+    pub(super) fn take_done(&self) -> Vec<Range<u64>> {
+        std::mem::take(&mut self.state().done)
+    }
+
+    /// Makes the generators fail instead of waiting for more ranges.
+    pub(super) fn close(&self) {
+        self.state().closed = true;
+        self.available.notify_all();
+    }
+
+    /// The range that `key` was found in, if it is a candidate of a recent range.
+    pub(super) fn locate<P: KeyParts>(&self, key: &Key<P, PrimaryRole>) -> Option<Range<u64>> {
+        let history = self.state().history.clone();
+        history
+            .into_iter()
+            .rev()
+            .find(|(seed, range)| seed.locate(key, range).is_some())
+            .map(|(_, range)| range)
+    }
+
+    /// Takes the next candidates to try, waiting for the coordinator if there are none.
+    fn take(&self) -> anyhow::Result<Chunk> {
+        let mut state = self.state();
+        loop {
+            if state.closed {
+                bail!("The search stopped while waiting for candidates from the coordinator");
+            }
+            let seed = state.seed;
+            if let Some(started) = state
+                .started
+                .iter_mut()
+                .find(|started| started.next < started.range.end)
+            {
+                let start = started.next;
+                started.next = (start + CHUNK_LEN).min(started.range.end);
+                return Ok(Chunk {
+                    seed,
+                    range: started.range.clone(),
+                    numbers: start..started.next,
+                    len: started.next - start,
+                });
+            }
+            if let Some(range) = state.queued.pop_front() {
+                state.started.push(StartedRange {
+                    next: range.start,
+                    range,
+                    tried: 0,
+                });
+                continue;
+            }
+            state = self
+                .available
+                .wait(state)
+                .expect("lock should not be poisoned");
+        }
+    }
+
+    /// Counts the candidates of `chunk` as tried.
+    fn finish(&self, chunk: &Chunk) {
+        let mut state = self.state();
+        // Chunks of a previous connection belong to ranges that were handed out again
+        if chunk.seed != state.seed {
+            return;
+        }
+        let Some(position) = state
+            .started
+            .iter()
+            .position(|started| started.range == chunk.range)
+        else {
+            return;
+        };
+        let started = &mut state.started[position];
+        started.tried += chunk.len;
+        if started.tried == started.range.end - started.range.start {
+            let started = state.started.swap_remove(position);
+            state.done.push(started.range);
+        }
+    }
+}
+
+/// Generates the candidates of the ranges in a [`RangeQueue`], see [`crate::cluster::partition`].
+#[derive(Debug)]
+pub(crate) struct TimestampGenerator {
+    queue: Arc<RangeQueue>,
+    chunk: Option<Chunk>,
+    /// The derived key of the current chunk, by seed and index.
+    key: Option<(Seed, u64, Key4<SecretParts, PrimaryRole>)>,
+}
+
+impl TimestampGenerator {
+    pub(crate) fn new(queue: Arc<RangeQueue>) -> Self {
+        Self {
+            queue,
+            chunk: None,
+            key: None,
+        }
+    }
+}
+
+impl KeyGenerator for TimestampGenerator {
+    fn generate(&mut self) -> anyhow::Result<Candidate> {
+        loop {
+            if let Some(chunk) = &mut self.chunk
+                && let Some(n) = chunk.numbers.next()
+            {
+                let seed = chunk.seed;
+                let index = n / WINDOW;
+                let key = match &self.key {
+                    Some((key_seed, key_index, key))
+                        if *key_seed == seed && *key_index == index =>
+                    {
+                        key
+                    }
+                    _ => &self.key.insert((seed, index, seed.key(index)?)).2,
+                };
+                let mut key = key.clone();
+                key.set_creation_time(seed.creation_time(n))?;
+                return Ok(Candidate::new(Key::V4(key)));
+            }
+            // The last candidate of the chunk was checked before asking for the next one
+            if let Some(chunk) = self.chunk.take() {
+                self.queue.finish(&chunk);
+            }
+            self.chunk = Some(self.queue.take()?);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Checks that `ranges` don't overlap and together are exactly `0..end`.
+    fn assert_partition(mut ranges: Vec<Range<u64>>, end: u64) {
+        ranges.sort_by_key(|range| range.start);
+        let mut covered = 0;
+        for range in ranges {
+            assert_eq!(range.start, covered, "gap or overlap at {range:?}");
+            assert_eq!(range.end - range.start, RANGE_LEN);
+            covered = range.end;
+        }
+        assert_eq!(covered, end);
+    }
+
+    #[test]
+    fn assigned_ranges_partition_the_candidates() {
+        let mut allocator = RangeAllocator::default();
+        let mut done = Vec::new();
+        for i in 0..10 {
+            for worker in ["a", "b", "c"] {
+                let range = allocator.assign(worker);
+                // Every other range is done, the rest stays assigned
+                if i % 2 == 0 {
+                    assert!(allocator.complete(worker, &range));
+                    done.push(range);
+                }
+            }
+        }
+        assert!(!allocator.complete("a", &done[0]));
+        assert!(!allocator.complete("d", &(0..RANGE_LEN)));
+
+        // The ranges of a disconnected worker are handed out again instead of new ones
+        allocator.release("b");
+        let end = allocator.next;
+        for _ in 0..5 {
+            let range = allocator.assign("d");
+            assert!(range.end <= end);
+            assert!(allocator.complete("d", &range));
+            done.push(range);
+        }
+        assert_eq!(allocator.next, end);
+        assert_eq!(allocator.assign("d"), end..end + RANGE_LEN);
+
+        let outstanding = allocator.assigned.values().flatten().cloned();
+        assert_partition(
+            done.into_iter().chain(outstanding).collect(),
+            end + RANGE_LEN,
+        );
+    }
+
+    #[test]
+    fn covered_stops_at_the_first_open_range() {
+        let mut allocator = RangeAllocator::default();
+        let first = allocator.assign("a");
+        let second = allocator.assign("b");
+        assert_eq!(allocator.covered(), 0);
+        allocator.complete("a", &first);
+        assert_eq!(allocator.covered(), second.start);
+        allocator.release("b");
+        assert_eq!(allocator.covered(), second.start);
+        let again = allocator.assign("a");
+        allocator.complete("a", &again);
+        assert_eq!(allocator.covered(), second.end);
+    }
+
+    #[test]
+    fn generator_tries_every_candidate_of_its_ranges_once() {
+        let seed = Seed::generate().unwrap();
+        let queue = Arc::new(RangeQueue::new(seed));
+        let range = WINDOW..WINDOW + 2 * CHUNK_LEN;
+        queue.push(range.clone());
+        let mut generator = TimestampGenerator::new(Arc::clone(&queue));
+
+        let mut fingerprints = Vec::new();
+        for n in range.clone() {
+            let candidate = generator.generate().unwrap();
+            assert_eq!(candidate.key.creation_time(), seed.creation_time(n));
+            if n % 1000 == 0 {
+                assert_eq!(seed.locate(&candidate.key, &range), Some(n));
+                assert_eq!(queue.locate(&candidate.key), Some(range.clone()));
+            }
+            fingerprints.push(candidate.fingerprint);
+        }
+        fingerprints.sort();
+        fingerprints.dedup();
+        assert_eq!(fingerprints.len(), range.clone().count());
+
+        // The range is done once the generator asks for more
+        assert!(queue.take_done().is_empty());
+        queue.close();
+        assert!(generator.generate().is_err());
+        assert_eq!(queue.take_done(), [range]);
+    }
+
+    #[test]
+    fn seed_survives_the_message() {
+        let seed = Seed::generate().unwrap();
+        let args = seed.to_message_args();
+        let (hex, base) = args.split_once(' ').unwrap();
+        assert_eq!(Seed::from_message_args(hex, base).unwrap(), seed);
+        assert!(Seed::from_message_args(&hex[2..], base).is_err());
+        assert!(Seed::from_message_args(hex, "1").is_err());
+    }
+}
@@ -0,0 +1,291 @@
+//! Line-based protocol spoken between the coordinator and remote workers.
+//!
+//! After connecting, a worker introduces itself with `HELLO <version> <name>`, followed by
+//! `AUTH <token>` if it has a token. The coordinator answers with one `PATTERN <regex>` line per
+//! pattern, followed by `READY`. From then on, the
+//! worker sends `STATS <tried> <found>` with its cumulative counters and `KEY <fingerprint> <n>`
+//! followed by the `n` lines of an armored key for every match, until the coordinator sends
+//! `STOP`. Errors are reported with `ERROR <message>` before closing the connection.
+//!
+//! When grinding timestamps, the coordinator sends `SEED <hex> <base>` before `READY`, see
+//! [`super::partition`]. Workers then ask for ranges of candidates with `NEXT`, which the
+//! coordinator answers with `RANGE <start> <end>`, report finished ranges with
+//! `DONE <start> <end>` and append the range a key was found in to its message, as in
+//! `KEY <fingerprint> <n> <start> <end>`.
+
+use std::{
+    io::{self, BufRead, BufReader, ErrorKind, Read, Write},
+    ops::Range,
+};
+
+use anyhow::{Context, anyhow, bail};
+
+use super::partition::Seed;
+
+/// Protocol version sent in `HELLO`.
+pub(crate) const VERSION: &str = "fingerprunk-cluster/1";
+
+/// Upper bound for the number of lines of a single armored key, so that a misbehaving peer
+/// can't make us buffer arbitrary amounts of data.
+const MAX_KEY_LINES: usize = 1000;
+
+/// Upper bound for the length of a line, for the same reason, which leaves room for long
+/// patterns.
+const MAX_LINE_LEN: usize = 64 * 1024;
+
+/// A byte stream to a peer, i.e. a plain TCP stream or a TLS session on top of one.
+pub(crate) trait Stream: Read + Write + Send {}
+
+impl<S: Read + Write + Send> Stream for S {}
+
+/// Messages sent from a worker to the coordinator.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub(crate) enum ToCoordinator {
+    Hello {
+        version: String,
+        name: String,
+    },
+    Auth {
+        token: String,
+    },
+    Stats {
+        tried: u64,
+        found: u64,
+    },
+    Key {
+        fingerprint: String,
+        armored: String,
+        /// The range of candidates the key was found in, when grinding timestamps.
+        range: Option<Range<u64>>,
+    },
+    Next,
+    Done(Range<u64>),
+}
+
+/// Messages sent from the coordinator to a worker.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub(crate) enum ToWorker {
+    Pattern(String),
+    Seed(Seed),
+    Ready,
+    Range(Range<u64>),
+    Stop,
+    Error(String),
+}
+
+/// A line-oriented connection whose reads can be given up on, e.g. for stopping.
+///
+/// The underlying stream is expected to have a read timeout, so that reads return regularly to
+/// check whether to give up.
+pub(crate) struct Connection<S: Read + Write> {
+    reader: BufReader<S>,
+    line: Vec<u8>,
+}
+
+impl<S: Read + Write> Connection<S> {
+    pub(crate) fn new(stream: S) -> Self {
+        Self {
+            reader: BufReader::new(stream),
+            line: Vec::new(),
+        }
+    }
+
+    /// Reads the next line without its line break.
+    ///
+    /// Returns `None` once `give_up` returns `true`, fails with [`ErrorKind::UnexpectedEof`] at
+    /// the end of the stream and with [`ErrorKind::InvalidData`] for lines longer than
+    /// [`MAX_LINE_LEN`]. A partially received line is kept for the next call.
+    fn read_line(&mut self, give_up: impl Fn() -> bool) -> io::Result<Option<String>> {
+        loop {
+            match read_line_capped(&mut self.reader, &mut self.line) {
+                // A line without line break is complete at the end of the stream
+                Ok(_) if self.line.is_empty() => return Err(ErrorKind::UnexpectedEof.into()),
+                Ok(_) => break,
+                Err(err) if matches!(err.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut) => {
+                    if give_up() {
+                        return Ok(None);
+                    }
+                }
+                Err(err) => return Err(err),
+            }
+        }
+
+        let line = String::from_utf8_lossy(&self.line)
+            .trim_end_matches(['\r', '\n'])
+            .to_string();
+        self.line.clear();
+        Ok(Some(line))
+    }
+
+    /// Receives the next message from a worker.
+    ///
+    /// Returns `None` at the end of the stream or once `give_up` returns `true`.
+    pub(crate) fn receive_from_worker(
+        &mut self,
+        give_up: impl Fn() -> bool,
+    ) -> anyhow::Result<Option<ToCoordinator>> {
+        let line = match self.read_line(&give_up) {
+            Ok(Some(line)) => line,
+            Ok(None) => return Ok(None),
+            Err(err) if err.kind() == ErrorKind::UnexpectedEof => return Ok(None),
+            Err(err) => return Err(err.into()),
+        };
+        let (command, args) = split_message(&line);
+        let mut args = args.split(' ');
+        let mut arg = |name: &str| {
+            args.next()
+                .filter(|arg| !arg.is_empty())
+                .ok_or_else(|| anyhow!("Missing {name} in {command} message"))
+        };
+
+        let message = match command {
+            "HELLO" => ToCoordinator::Hello {
+                version: arg("version")?.to_string(),
+                name: arg("name")?.to_string(),
+            },
+            "AUTH" => ToCoordinator::Auth {
+                token: arg("token")?.to_string(),
+            },
+            "STATS" => ToCoordinator::Stats {
+                tried: arg("tried")?.parse().context("Invalid tried count")?,
+                found: arg("found")?.parse().context("Invalid found count")?,
+            },
+            "KEY" => {
+                let fingerprint = arg("fingerprint")?.to_string();
+                let lines: usize = arg("line count")?.parse().context("Invalid line count")?;
+                let range = match args.next() {
+                    Some(start) => Some(parse_range(start, args.next())?),
+                    None => None,
+                };
+                if lines > MAX_KEY_LINES {
+                    bail!("Key {fingerprint} is too large");
+                }
+                let mut armored = String::new();
+                for _ in 0..lines {
+                    let line = self
+                        .read_line(&give_up)?
+                        .ok_or_else(|| anyhow!("Incomplete key {fingerprint}"))?;
+                    armored.push_str(&line);
+                    armored.push('\n');
+                }
+                ToCoordinator::Key {
+                    fingerprint,
+                    armored,
+                    range,
+                }
+            }
+            "NEXT" => ToCoordinator::Next,
+            "DONE" => {
+                let start = arg("start")?;
+                ToCoordinator::Done(parse_range(start, args.next())?)
+            }
+            _ => bail!("Unknown message {command:?}"),
+        };
+        Ok(Some(message))
+    }
+
+    /// Receives the next message from the coordinator.
+    ///
+    /// Returns `None` if nothing arrives within the read timeout of the stream. The end of the
+    /// stream is an error, as the coordinator ends connections with `STOP` or `ERROR`.
+    pub(crate) fn receive_from_coordinator(&mut self) -> anyhow::Result<Option<ToWorker>> {
+        let line = match self.read_line(|| true) {
+            Ok(Some(line)) => line,
+            Ok(None) => return Ok(None),
+            Err(err) if err.kind() == ErrorKind::UnexpectedEof => {
+                bail!("Connection closed by the coordinator")
+            }
+            Err(err) => return Err(err.into()),
+        };
+        let (command, args) = split_message(&line);
+
+        let message = match command {
+            "PATTERN" => ToWorker::Pattern(args.to_string()),
+            "SEED" => {
+                let (hex, base) = split_message(args);
+                ToWorker::Seed(Seed::from_message_args(hex, base)?)
+            }
+            "READY" => ToWorker::Ready,
+            "RANGE" => {
+                let (start, end) = split_message(args);
+                ToWorker::Range(parse_range(start, Some(end))?)
+            }
+            "STOP" => ToWorker::Stop,
+            "ERROR" => ToWorker::Error(args.to_string()),
+            _ => bail!("Unknown message {command:?}"),
+        };
+        Ok(Some(message))
+    }
+
+    pub(crate) fn send_to_coordinator(&mut self, message: &ToCoordinator) -> io::Result<()> {
+        let stream = self.reader.get_mut();
+        match message {
+            ToCoordinator::Hello { version, name } => writeln!(stream, "HELLO {version} {name}")?,
+            ToCoordinator::Auth { token } => writeln!(stream, "AUTH {token}")?,
+            ToCoordinator::Stats { tried, found } => writeln!(stream, "STATS {tried} {found}")?,
+            ToCoordinator::Key {
+                fingerprint,
+                armored,
+                range,
+            } => {
+                write!(stream, "KEY {fingerprint} {}", armored.lines().count())?;
+                if let Some(range) = range {
+                    write!(stream, " {} {}", range.start, range.end)?;
+                }
+                writeln!(stream)?;
+                for line in armored.lines() {
+                    writeln!(stream, "{line}")?;
+                }
+            }
+            ToCoordinator::Next => writeln!(stream, "NEXT")?,
+            ToCoordinator::Done(range) => writeln!(stream, "DONE {} {}", range.start, range.end)?,
+        }
+        stream.flush()
+    }
+
+    pub(crate) fn send_to_worker(&mut self, message: &ToWorker) -> io::Result<()> {
+        let stream = self.reader.get_mut();
+        match message {
+            ToWorker::Pattern(pattern) => writeln!(stream, "PATTERN {pattern}")?,
+            ToWorker::Seed(seed) => writeln!(stream, "SEED {}", seed.to_message_args())?,
+            ToWorker::Ready => writeln!(stream, "READY")?,
+            ToWorker::Range(range) => writeln!(stream, "RANGE {} {}", range.start, range.end)?,
+            ToWorker::Stop => writeln!(stream, "STOP")?,
+            ToWorker::Error(message) => writeln!(stream, "ERROR {message}")?,
+        }
+        stream.flush()
+    }
+}
+
+/// Splits a message line into the command and its arguments.
+fn split_message(line: &str) -> (&str, &str) {
+    line.split_once(' ').unwrap_or((line, ""))
+}
+
+/// Parses the start and end of a range of candidates.
+fn parse_range(start: &str, end: Option<&str>) -> anyhow::Result<Range<u64>> {
+    let start = start.parse().context("Invalid range start")?;
+    let end = end
+        .context("Missing range end")?
+        .parse()
+        .context("Invalid range end")?;
+    if start >= end {
+        bail!("Empty range {start}..{end}");
+    }
+    Ok(start..end)
+}
+
+/// Like [`BufRead::read_until`] with a line break, but fails with [`ErrorKind::InvalidData`]
+/// once `line` would grow beyond [`MAX_LINE_LEN`] without one.
+fn read_line_capped(reader: &mut impl BufRead, line: &mut Vec<u8>) -> io::Result<usize> {
+    let limit = MAX_LINE_LEN.saturating_sub(line.len()) as u64;
+    let read = reader.take(limit).read_until(b'\n', line)?;
+    if line.len() >= MAX_LINE_LEN && !line.ends_with(b"\n") {
+        line.clear();
+        return Err(io::Error::new(
+            ErrorKind::InvalidData,
+            format!("Line longer than {MAX_LINE_LEN} bytes"),
+        ));
+    }
+    Ok(read)
+}
@@ -0,0 +1,44 @@
+//! TLS for connections between the coordinator and workers.
+
+use std::{path::Path, sync::Arc};
+
+use anyhow::Context;
+use rustls::{
+    ClientConfig, RootCertStore, ServerConfig,
+    pki_types::{CertificateDer, PrivateKeyDer, pem::PemObject},
+};
+
+/// Builds the coordinator's TLS configuration from PEM files with its certificate chain and
+/// private key.
+pub(crate) fn server_config(cert: &Path, key: &Path) -> anyhow::Result<Arc<ServerConfig>> {
+    let chain = read_certs(cert)?;
+    let key = PrivateKeyDer::from_pem_file(key)
+        .with_context(|| format!("Failed to read TLS key {}", key.display()))?;
+
+    let config = ServerConfig::builder()
+        .with_no_client_auth()
+        .with_single_cert(chain, key)
+        .context("Invalid TLS certificate or key")?;
+    Ok(Arc::new(config))
+}
+
+/// Builds a worker's TLS configuration, trusting the certificates in the given PEM file.
+pub(crate) fn client_config(ca: &Path) -> anyhow::Result<Arc<ClientConfig>> {
+    let mut roots = RootCertStore::empty();
+    for cert in read_certs(ca)? {
+        roots
+            .add(cert)
+            .with_context(|| format!("Invalid certificate in {}", ca.display()))?;
+    }
+
+    let config = ClientConfig::builder()
+        .with_root_certificates(roots)
+        .with_no_client_auth();
+    Ok(Arc::new(config))
+}
+
+fn read_certs(path: &Path) -> anyhow::Result<Vec<CertificateDer<'static>>> {
+    CertificateDer::pem_file_iter(path)
+        .and_then(Iterator::collect)
+        .with_context(|| format!("Failed to read TLS certificates from {}", path.display()))
+}
@@ -0,0 +1,437 @@
+//! Remote workers, which receive their patterns from a coordinator and report back to it.
+
+use std::{
+    collections::VecDeque,
+    io::{self, Write},
+    net::{TcpStream, ToSocketAddrs},
+    ops::{ControlFlow, Range},
+    path::PathBuf,
+    sync::{Arc, atomic::Ordering, mpsc},
+    thread,
+    time::{Duration, Instant},
+};
+
+use anyhow::{Context, anyhow, bail};
+use crossbeam_channel::Sender;
+use fancy_regex::Regex;
+use rustls::{ClientConfig, ClientConnection, StreamOwned, pki_types::ServerName};
+use sequoia_openpgp::{
+    Cert,
+    parse::Parse,
+    policy::StandardPolicy,
+    serialize::stream::{Armorer, Encryptor, LiteralWriter, Message as PgpMessage},
+};
+
+use super::{
+    partition::{RangeQueue, Seed},
+    protocol::{Connection, Stream, ToCoordinator, ToWorker, VERSION},
+    tls,
+};
+use crate::{
+    Fingerprunk, Message, Verbosity,
+    matcher::PatternSet,
+    sink::{self, KeySink, MatchMeta},
+};
+
+/// How long reads from the coordinator block, i.e. how often the worker checks for found keys
+/// and whether it should stop.
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+/// How long connecting and receiving the patterns may take.
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(30);
+/// Interval of the statistics sent to the coordinator.
+const STATS_INTERVAL: Duration = Duration::from_secs(5);
+/// Delay before the first attempt to reconnect, which doubles up to [`MAX_RECONNECT_DELAY`].
+const MIN_RECONNECT_DELAY: Duration = Duration::from_secs(1);
+const MAX_RECONNECT_DELAY: Duration = Duration::from_secs(60);
+
+#[derive(Clone, Debug)]
+pub struct WorkerConfig {
+    /// Address of the coordinator, e.g. `coordinator.example:7878`.
+    pub connect: String,
+    /// Name identifying this worker to the coordinator.
+    pub name: String,
+    /// Shared secret to present to the coordinator.
+    pub token: Option<String>,
+    /// PEM file with the certificates to trust; enables TLS.
+    pub tls_ca: Option<PathBuf>,
+    /// OpenPGP certificate to encrypt found keys to before sending them to the coordinator.
+    pub encrypt_to: Option<PathBuf>,
+}
+
+/// Number of ranges of candidates a worker asks for ahead of time when grinding timestamps, so
+/// that its threads don't wait for the coordinator.
+const PREFETCH_RANGES: usize = 2;
+
+/// A found key to be sent to the coordinator, as fingerprint, armored key and the range of
+/// candidates it was found in.
+pub(crate) type FoundKey = (String, String, Option<Range<u64>>);
+
+/// Sink handing the found keys to the cluster thread, which sends them to the coordinator.
+pub(crate) struct CoordinatorSink {
+    keys: mpsc::Sender<FoundKey>,
+    ranges: Option<Arc<RangeQueue>>,
+}
+
+impl CoordinatorSink {
+    pub(crate) fn new(keys: mpsc::Sender<FoundKey>, link: &CoordinatorLink) -> Self {
+        Self {
+            keys,
+            ranges: link.ranges.clone(),
+        }
+    }
+}
+
+impl KeySink for CoordinatorSink {
+    fn found(&mut self, cert: Cert, meta: MatchMeta) -> anyhow::Result<ControlFlow<()>> {
+        let mut armored = Vec::new();
+        sink::write_armored(&cert, &meta, &mut armored)?;
+        let armored = String::from_utf8(armored).expect("armored key should be valid UTF-8");
+        let range = self
+            .ranges
+            .as_ref()
+            .and_then(|ranges| ranges.locate(cert.primary_key().key()));
+        let _ = self.keys.send((meta.fingerprint, armored, range));
+        Ok(ControlFlow::Continue(()))
+    }
+}
+
+/// The connection of a worker to its coordinator, which is re-established when lost.
+pub(crate) struct CoordinatorLink {
+    config: WorkerConfig,
+    tls_config: Option<Arc<ClientConfig>>,
+    recipient: Option<Cert>,
+    connection: Option<Connection<Box<dyn Stream>>>,
+    /// The ranges of candidates assigned by a coordinator that grinds timestamps.
+    ranges: Option<Arc<RangeQueue>>,
+    /// Number of ranges asked for that haven't arrived yet.
+    requested_ranges: usize,
+}
+
+impl CoordinatorLink {
+    /// Connects to the coordinator and returns the link along with the patterns to search for.
+    pub(crate) fn connect(config: &WorkerConfig) -> anyhow::Result<(Self, Vec<Regex>)> {
+        let tls_config = match &config.tls_ca {
+            Some(ca) => Some(tls::client_config(ca)?),
+            None => None,
+        };
+        let recipient = match &config.encrypt_to {
+            Some(path) => {
+                let cert = Cert::from_file(path)
+                    .with_context(|| format!("Failed to read certificate {}", path.display()))?;
+                // Fail now rather than when the first key is found
+                encrypt_key("", &cert)
+                    .with_context(|| format!("Can't encrypt to certificate {}", path.display()))?;
+                Some(cert)
+            }
+            None => None,
+        };
+        let mut link = Self {
+            config: config.clone(),
+            tls_config,
+            recipient,
+            connection: None,
+            ranges: None,
+            requested_ranges: 0,
+        };
+        let Handshake {
+            connection,
+            regexes,
+            seed,
+        } = link.handshake()?;
+        link.ranges = seed.map(|seed| Arc::new(RangeQueue::new(seed)));
+        link.connection = Some(connection);
+        Ok((link, regexes))
+    }
+
+    /// The ranges of candidates to generate, if the coordinator grinds timestamps.
+    pub(crate) fn ranges(&self) -> Option<&Arc<RangeQueue>> {
+        self.ranges.as_ref()
+    }
+
+    /// Re-establishes the connection, returning the patterns received from the coordinator.
+    fn reconnect(&mut self) -> anyhow::Result<Vec<Regex>> {
+        self.connection = None;
+        let Handshake {
+            connection,
+            regexes,
+            seed,
+        } = self.handshake()?;
+        // The generators were chosen when connecting for the first time
+        match (&self.ranges, seed) {
+            (Some(ranges), Some(seed)) => ranges.reset(seed),
+            (None, None) => {}
+            _ => bail!("The coordinator switched grinding timestamps on or off"),
+        }
+        self.requested_ranges = 0;
+        self.connection = Some(connection);
+        Ok(regexes)
+    }
+
+    /// Connects to the coordinator and receives the patterns and the seed.
+    fn handshake(&self) -> anyhow::Result<Handshake> {
+        let stream = connect_tcp(&self.config.connect)?;
+        stream.set_read_timeout(Some(POLL_INTERVAL))?;
+        let stream: Box<dyn Stream> = match &self.tls_config {
+            Some(tls_config) => {
+                let server_name = server_name(&self.config.connect)?;
+                let tls = ClientConnection::new(Arc::clone(tls_config), server_name)?;
+                Box::new(StreamOwned::new(tls, stream))
+            }
+            None => Box::new(stream),
+        };
+        let mut connection = Connection::new(stream);
+
+        connection.send_to_coordinator(&ToCoordinator::Hello {
+            version: VERSION.to_string(),
+            name: self.config.name.clone(),
+        })?;
+        if let Some(token) = &self.config.token {
+            connection.send_to_coordinator(&ToCoordinator::Auth {
+                token: token.clone(),
+            })?;
+        }
+
+        let deadline = Instant::now() + CONNECT_TIMEOUT;
+        let mut regexes = Vec::new();
+        let mut seed = None;
+        loop {
+            if Instant::now() >= deadline {
+                bail!("Timed out waiting for the patterns");
+            }
+            match connection.receive_from_coordinator()? {
+                Some(ToWorker::Pattern(pattern)) => regexes.push(
+                    Regex::new(&pattern)
+                        .with_context(|| format!("Invalid regex from coordinator: {pattern}"))?,
+                ),
+                Some(ToWorker::Seed(received)) => seed = Some(received),
+                Some(ToWorker::Ready) => break,
+                Some(ToWorker::Stop) => bail!("The coordinator is stopping"),
+                Some(ToWorker::Error(message)) => bail!("The coordinator refused: {message}"),
+                Some(ToWorker::Range(_)) => bail!("Unexpected RANGE before READY"),
+                None => {}
+            }
+        }
+
+        Ok(Handshake {
+            connection,
+            regexes,
+            seed,
+        })
+    }
+}
+
+/// A fresh connection to the coordinator with what it sent before `READY`.
+struct Handshake {
+    connection: Connection<Box<dyn Stream>>,
+    regexes: Vec<Regex>,
+    seed: Option<Seed>,
+}
+
+/// Encrypts an armored key to the given certificate, returning an armored OpenPGP message.
+fn encrypt_key(armored: &str, recipient: &Cert) -> anyhow::Result<String> {
+    let policy = StandardPolicy::new();
+    let recipients = recipient
+        .keys()
+        .with_policy(&policy, None)
+        .supported()
+        .alive()
+        .revoked(false)
+        .for_transport_encryption();
+
+    let mut encrypted = Vec::new();
+    let message = Armorer::new(PgpMessage::new(&mut encrypted)).build()?;
+    let message = Encryptor::for_recipients(message, recipients).build()?;
+    let mut message = LiteralWriter::new(message).build()?;
+    message.write_all(armored.as_bytes())?;
+    message.finalize()?;
+
+    Ok(String::from_utf8(encrypted).expect("armored message should be valid UTF-8"))
+}
+
+/// Connects to the first reachable address that `address` resolves to.
+fn connect_tcp(address: &str) -> anyhow::Result<TcpStream> {
+    let mut last_err = None;
+    for socket_address in address
+        .to_socket_addrs()
+        .with_context(|| format!("Failed to resolve {address}"))?
+    {
+        match TcpStream::connect_timeout(&socket_address, CONNECT_TIMEOUT) {
+            Ok(stream) => return Ok(stream),
+            Err(err) => last_err = Some(err),
+        }
+    }
+    Err(last_err.map_or_else(
+        || anyhow!("{address} did not resolve to any address"),
+        |err| anyhow::Error::new(err).context(format!("Failed to connect to {address}")),
+    ))
+}
+
+/// The name to verify the coordinator's certificate against, i.e. the host part of `address`.
+fn server_name(address: &str) -> anyhow::Result<ServerName<'static>> {
+    let host = address.rsplit_once(':').map_or(address, |(host, _)| host);
+    let host = host.trim_start_matches('[').trim_end_matches(']');
+    ServerName::try_from(host.to_string()).with_context(|| format!("Invalid host name {host}"))
+}
+
+impl Fingerprunk {
+    /// Reports statistics and found keys to the coordinator until the search stops,
+    /// reconnecting whenever the connection is lost.
+    ///
+    /// Keys are encrypted before being queued if configured. Keys that can't be delivered before
+    /// stopping are written to stdout instead, so that they aren't lost.
+    pub(crate) fn cluster_thread(
+        &self,
+        mut link: CoordinatorLink,
+        keys: mpsc::Receiver<FoundKey>,
+        sender: Sender<Message>,
+    ) {
+        let mut pending = VecDeque::new();
+        let mut next_stats = Instant::now();
+        let mut reconnect_delay = MIN_RECONNECT_DELAY;
+        let mut next_reconnect = Instant::now();
+
+        loop {
+            let stopping = self.stop.load(Ordering::Relaxed);
+            // Workers waiting for candidates would otherwise never see the search stop
+            if stopping && let Some(ranges) = &link.ranges {
+                ranges.close();
+            }
+            // The finalizer drops its sender once it has processed all keys
+            let received: Vec<_> = if stopping {
+                keys.iter().collect()
+            } else {
+                keys.try_iter().collect()
+            };
+            for (fingerprint, armored, range) in received {
+                let armored = match &link.recipient {
+                    Some(recipient) => match encrypt_key(&armored, recipient) {
+                        Ok(encrypted) => encrypted,
+                        Err(err) => {
+                            // Sending the key unencrypted is not an option, so keep it locally
+                            self.log(
+                                Verbosity::Quiet,
+                                format_args!("Failed to encrypt {fingerprint}: {err:#}"),
+                            );
+                            let mut stdout = io::stdout().lock();
+                            let _ = stdout.write_all(armored.as_bytes());
+                            let _ = stdout.flush();
+                            continue;
+                        }
+                    },
+                    None => armored,
+                };
+                pending.push_back((fingerprint, armored, range));
+            }
+
+            let Some(connection) = &mut link.connection else {
+                if stopping {
+                    break;
+                }
+                if Instant::now() < next_reconnect {
+                    thread::sleep(POLL_INTERVAL);
+                    continue;
+                }
+                match link.reconnect() {
+                    Ok(regexes) => {
+                        self.log(Verbosity::Normal, "Reconnected to the coordinator");
+                        reconnect_delay = MIN_RECONNECT_DELAY;
+                        self.update_patterns(regexes);
+                    }
+                    Err(err) => {
+                        self.log(
+                            Verbosity::Normal,
+                            format_args!("Failed to reconnect to the coordinator: {err:#}"),
+                        );
+                        next_reconnect = Instant::now() + reconnect_delay;
+                        reconnect_delay = (reconnect_delay * 2).min(MAX_RECONNECT_DELAY);
+                    }
+                }
+                continue;
+            };
+
+            let result = (|| {
+                while let Some((fingerprint, armored, range)) = pending.front() {
+                    connection.send_to_coordinator(&ToCoordinator::Key {
+                        fingerprint: fingerprint.clone(),
+                        armored: armored.clone(),
+                        range: range.clone(),
+                    })?;
+                    pending.pop_front();
+                }
+                if let Some(ranges) = &link.ranges {
+                    for range in ranges.take_done() {
+                        connection.send_to_coordinator(&ToCoordinator::Done(range))?;
+                    }
+                    while !stopping && ranges.queued() + link.requested_ranges < PREFETCH_RANGES {
+                        connection.send_to_coordinator(&ToCoordinator::Next)?;
+                        link.requested_ranges += 1;
+                    }
+                }
+                if stopping || Instant::now() >= next_stats {
+                    connection.send_to_coordinator(&ToCoordinator::Stats {
+                        tried: self.counter_tried.load(Ordering::Relaxed),
+                        found: self.counter_found.load(Ordering::Relaxed),
+                    })?;
+                    next_stats = Instant::now() + STATS_INTERVAL;
+                }
+                if stopping {
+                    return anyhow::Ok(true);
+                }
+
+                match connection.receive_from_coordinator()? {
+                    Some(ToWorker::Stop) => {
+                        self.log(Verbosity::Normal, "Stopped by the coordinator");
+                        let _ = sender.send(Message::Stop);
+                    }
+                    Some(ToWorker::Error(message)) => bail!("The coordinator failed: {message}"),
+                    Some(ToWorker::Range(range)) => {
+                        let Some(ranges) = &link.ranges else {
+                            bail!("Unexpected RANGE, as timestamps aren't ground");
+                        };
+                        link.requested_ranges = link.requested_ranges.saturating_sub(1);
+                        ranges.push(range);
+                    }
+                    Some(ToWorker::Pattern(_) | ToWorker::Seed(_) | ToWorker::Ready) | None => {}
+                }
+                anyhow::Ok(false)
+            })();
+
+            match result {
+                Ok(true) => break,
+                Ok(false) => {}
+                Err(err) => {
+                    self.log(
+                        Verbosity::Normal,
+                        format_args!("Lost connection to the coordinator: {err:#}"),
+                    );
+                    link.connection = None;
+                    next_reconnect = Instant::now();
+                }
+            }
+        }
+
+        if !pending.is_empty() {
+            let mut stdout = io::stdout().lock();
+            for (fingerprint, armored, _) in pending {
+                self.log(
+                    Verbosity::Quiet,
+                    format_args!("Failed to send {fingerprint} to the coordinator"),
+                );
+                let _ = stdout.write_all(armored.as_bytes());
+            }
+            let _ = stdout.flush();
+        }
+    }
+
+    /// Switches to the patterns received when reconnecting, which may have changed meanwhile.
+    fn update_patterns(&self, regexes: Vec<Regex>) {
+        let matcher = PatternSet::from_regexes(regexes);
+        if matcher.to_string() != self.matcher().to_string() {
+            self.log(
+                Verbosity::Normal,
+                format_args!("Patterns changed to {matcher}"),
+            );
+            self.set_matcher(matcher);
+        }
+    }
+}
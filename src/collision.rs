@@ -0,0 +1,169 @@
+//! Screening found keys for key ID collisions with existing keys, so that a vanity key doesn't
+//! shadow someone else's key in tools that still look keys up by their 64-bit ID.
+//!
+//! Found keys are screened against a given list of key IDs and, with the `keyserver` feature,
+//! against the keys published on a keyserver.
+
+use std::{collections::HashSet, fmt, fs, path::Path, sync::Arc};
+
+use anyhow::{Context, bail};
+use sequoia_openpgp::{KeyID, cert::CertParser, parse::Parse};
+
+#[cfg(feature = "keyserver")]
+use crate::{Fingerprunk, Verbosity};
+
+/// The 64-bit key IDs of keys that found keys must not collide with.
+#[derive(Clone, Default)]
+pub struct KeyIds(Arc<HashSet<u64>>);
+
+impl KeyIds {
+    /// Reads the key IDs from an OpenPGP keyring, armored or binary, or from a text file with a
+    /// fingerprint or 64-bit key ID in hex on every line. Empty lines and lines starting with `#`
+    /// are skipped.
+    ///
+    /// The key IDs of all keys of a keyring count, including the subkeys.
+    pub fn read(path: &Path) -> anyhow::Result<Self> {
+        let content =
+            fs::read(path).with_context(|| format!("Failed to read {}", path.display()))?;
+        let ids = match String::from_utf8(content) {
+            Ok(text) if !text.trim_start().starts_with("-----BEGIN PGP") => parse_list(&text),
+            Ok(text) => read_keyring(text.as_bytes()),
+            Err(err) => read_keyring(err.as_bytes()),
+        }
+        .with_context(|| format!("Failed to read key IDs from {}", path.display()))?;
+        Ok(Self(Arc::new(ids)))
+    }
+
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// Whether `key_id` is one of the key IDs.
+    pub fn contains(&self, key_id: &KeyID) -> bool {
+        key_id_number(key_id).is_some_and(|id| self.0.contains(&id))
+    }
+}
+
+impl fmt::Debug for KeyIds {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "KeyIds({})", self.0.len())
+    }
+}
+
+impl FromIterator<KeyID> for KeyIds {
+    fn from_iter<I: IntoIterator<Item = KeyID>>(iter: I) -> Self {
+        Self(Arc::new(
+            iter.into_iter()
+                .filter_map(|id| key_id_number(&id))
+                .collect(),
+        ))
+    }
+}
+
+fn parse_list(text: &str) -> anyhow::Result<HashSet<u64>> {
+    let mut ids = HashSet::new();
+    for (num, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let hex: String = line
+            .trim_start_matches("0x")
+            .chars()
+            .filter(|c| !c.is_whitespace())
+            .collect();
+        // Key IDs are the last 64 bits of v4 fingerprints and the first 64 bits of v6 ones
+        let id = match hex.len() {
+            16 => &hex[..],
+            40 => &hex[24..],
+            64 => &hex[..16],
+            _ => bail!(
+                "Line {}: expected a fingerprint or a 64-bit key ID, got {line:?}",
+                num + 1
+            ),
+        };
+        let id = u64::from_str_radix(id, 16)
+            .with_context(|| format!("Line {}: {line:?} is not hex", num + 1))?;
+        ids.insert(id);
+    }
+    Ok(ids)
+}
+
+fn read_keyring(data: &[u8]) -> anyhow::Result<HashSet<u64>> {
+    let mut ids = HashSet::new();
+    for cert in CertParser::from_bytes(data)? {
+        ids.extend(
+            cert?
+                .keys()
+                .filter_map(|key| key_id_number(&key.key().keyid())),
+        );
+    }
+    Ok(ids)
+}
+
+fn key_id_number(key_id: &KeyID) -> Option<u64> {
+    Some(u64::from_be_bytes(key_id.as_bytes().try_into().ok()?))
+}
+
+#[cfg(feature = "keyserver")]
+impl Fingerprunk {
+    /// Warns if a key with `key_id` is published on the keyserver at `url`, or if it can't be
+    /// looked up. The found key is kept either way.
+    pub(crate) fn check_published(&self, url: &str, fingerprint: &str, key_id: &KeyID) {
+        match lookup(url, key_id) {
+            Ok(published) if published.is_empty() => self.log(
+                Verbosity::Verbose,
+                format_args!("No key with the key ID {key_id:X} of {fingerprint} is published"),
+            ),
+            Ok(published) => self.log(
+                Verbosity::Normal,
+                format_args!(
+                    "Warning: the key ID {key_id:X} of {fingerprint} collides with {} published \
+                     on {url}",
+                    published.join(", ")
+                ),
+            ),
+            Err(err) => self.log(
+                Verbosity::Normal,
+                format_args!("Warning: failed to look up {fingerprint} on {url}: {err:#}"),
+            ),
+        }
+    }
+}
+
+/// Looks up `key_id` on the VKS keyserver at `url`, returning the fingerprints of the published
+/// certificates having a key with it.
+#[cfg(feature = "keyserver")]
+fn lookup(url: &str, key_id: &KeyID) -> anyhow::Result<Vec<String>> {
+    use std::time::Duration;
+
+    const TIMEOUT: Duration = Duration::from_secs(10);
+
+    let agent: ureq::Agent = ureq::Agent::config_builder()
+        .timeout_global(Some(TIMEOUT))
+        .http_status_as_error(false)
+        .build()
+        .into();
+    let mut response = agent
+        .get(format!(
+            "{}/vks/v1/by-keyid/{key_id:X}",
+            url.trim_end_matches('/')
+        ))
+        .call()?;
+    match response.status().as_u16() {
+        200 => {}
+        404 => return Ok(Vec::new()),
+        status => bail!("The keyserver answered with status {status}"),
+    }
+
+    let body = response.body_mut().read_to_vec()?;
+    let mut fingerprints = Vec::new();
+    for cert in CertParser::from_bytes(&body)? {
+        fingerprints.push(format!("{:X}", cert?.fingerprint()));
+    }
+    Ok(fingerprints)
+}
@@ -0,0 +1,666 @@
+//! Building a [`Config`] with validation.
+
+use std::{
+    collections::BTreeMap,
+    num::{NonZeroU64, NonZeroUsize},
+    path::PathBuf,
+    sync::Arc,
+    time::Duration,
+};
+
+use sequoia_openpgp::crypto::Password;
+
+#[cfg(feature = "hybrid")]
+use crate::CorePolicy;
+use crate::{
+    Config, OutputOrder, Policy, Verbosity,
+    collision::KeyIds,
+    error::ConfigError,
+    format::{FormatPattern, KeyFormat},
+    jobs::Job,
+    keygen::{Certifier, KeyVersion},
+    matcher::Matcher,
+    notify::EmailConfig,
+    pattern::{check_pattern_length, parse_pattern_for},
+    status::{StatusMode, StatusTemplate},
+};
+
+impl Config {
+    /// Starts building a configuration, with everything but the patterns disabled.
+    pub fn builder() -> ConfigBuilder {
+        ConfigBuilder::default()
+    }
+}
+
+/// Builder of a [`Config`], whose [`build`](Self::build) checks the regexes with
+/// [`parse_pattern`] and rejects inconsistent settings.
+///
+/// Settings not given keep the defaults of a library: no status display, output to stdout and no
+/// notifications.
+#[derive(Debug)]
+#[must_use]
+pub struct ConfigBuilder {
+    patterns: Vec<String>,
+    labeled_patterns: Vec<(String, String)>,
+    subkey_pattern: Option<String>,
+    farm: Option<NonZeroU64>,
+    config: Config,
+}
+
+impl Default for ConfigBuilder {
+    fn default() -> Self {
+        Self {
+            patterns: Vec::new(),
+            labeled_patterns: Vec::new(),
+            subkey_pattern: None,
+            farm: None,
+            config: Config {
+                regexes: Vec::new(),
+                labeled_regexes: Vec::new(),
+                label_outputs: BTreeMap::new(),
+                matchers: Vec::new(),
+                patterns_file: None,
+                #[cfg(feature = "watch")]
+                watch_patterns: false,
+                status_mode: StatusMode::Disabled,
+                stop_after: None,
+                farm: false,
+                threads: None,
+                batch_size: None,
+                auto_tune: false,
+                finalizers: None,
+                output_order: OutputOrder::Found,
+                #[cfg(feature = "numa")]
+                numa: false,
+                #[cfg(feature = "hybrid")]
+                cores: CorePolicy::All,
+                password: None,
+                key_version: KeyVersion::V4,
+                user_id: None,
+                subkey_regex: None,
+                certify_with: None,
+                sign_proof: None,
+                proofs: Vec::new(),
+                humanize_numbers: true,
+                status_template: StatusTemplate::default(),
+                #[cfg(feature = "tui")]
+                tui: false,
+                verbosity: Verbosity::Normal,
+                bell: false,
+                bell_repeat: None,
+                #[cfg(feature = "webhook")]
+                webhook: None,
+                #[cfg(feature = "otel")]
+                otel: false,
+                notify_email: None,
+                #[cfg(unix)]
+                log_syslog: false,
+                #[cfg(unix)]
+                control_socket: None,
+                stdin_control: false,
+                state_file: None,
+                resume: false,
+                allow_config_change: false,
+                output_dir: None,
+                jobs: Vec::new(),
+                avoid_keyids: None,
+                #[cfg(feature = "keyserver")]
+                keyserver: None,
+                stop_with_priority: None,
+                json_logs: false,
+                #[cfg(feature = "http-api")]
+                listen: None,
+                #[cfg(feature = "cluster")]
+                coordinator: None,
+                #[cfg(feature = "sandbox")]
+                sandbox: false,
+                offline: false,
+                policy: None,
+                audit_log: None,
+                key_format: KeyFormat::OpenPgp,
+            },
+        }
+    }
+}
+
+impl ConfigBuilder {
+    /// Adds a regex to search for.
+    pub fn regex(mut self, pattern: impl Into<String>) -> Self {
+        self.patterns.push(pattern.into());
+        self
+    }
+
+    /// Adds a regex whose matches can be routed with [`Self::label_output`].
+    pub fn labeled_regex(mut self, label: impl Into<String>, pattern: impl Into<String>) -> Self {
+        self.labeled_patterns.push((label.into(), pattern.into()));
+        self
+    }
+
+    /// Writes the keys matching the regexes labeled `label` to `dir`.
+    pub fn label_output(mut self, label: impl Into<String>, dir: impl Into<PathBuf>) -> Self {
+        self.config.label_outputs.insert(label.into(), dir.into());
+        self
+    }
+
+    /// Adds a matcher other than a regex, see [`crate::matcher`].
+    pub fn matcher(mut self, matcher: impl Matcher + 'static) -> Self {
+        self.config.matchers.push(Arc::new(matcher));
+        self
+    }
+
+    pub fn patterns_file(mut self, path: Option<PathBuf>) -> Self {
+        self.config.patterns_file = path;
+        self
+    }
+
+    #[cfg(feature = "watch")]
+    pub fn watch_patterns(mut self, watch: bool) -> Self {
+        self.config.watch_patterns = watch;
+        self
+    }
+
+    pub fn status_mode(mut self, mode: StatusMode) -> Self {
+        self.config.status_mode = mode;
+        self
+    }
+
+    pub fn stop_after(mut self, stop_after: Option<NonZeroU64>) -> Self {
+        self.config.stop_after = stop_after;
+        self
+    }
+
+    /// Generates `count` keys without matching them against anything, e.g. to provision keys for
+    /// a classroom, still with the user ID, subkey and password configured. Can't be combined
+    /// with patterns or [`Self::stop_after`].
+    pub fn farm(mut self, count: Option<NonZeroU64>) -> Self {
+        self.farm = count;
+        self
+    }
+
+    /// Runs this many worker threads instead of one per CPU, e.g. to leave some for other work.
+    pub fn threads(mut self, threads: Option<NonZeroUsize>) -> Self {
+        self.config.threads = threads;
+        self
+    }
+
+    /// Lets every worker try this many keys at a time before it updates the counters and checks
+    /// whether to stop, which saves a bit of synchronization.
+    pub fn batch_size(mut self, batch_size: Option<NonZeroUsize>) -> Self {
+        self.config.batch_size = batch_size;
+        self
+    }
+
+    /// Measures which number of threads and batch size searches fastest before searching, see
+    /// [`Fingerprunk::auto_tune`](crate::Fingerprunk::auto_tune).
+    pub fn auto_tune(mut self, auto_tune: bool) -> Self {
+        self.config.auto_tune = auto_tune;
+        self
+    }
+
+    /// Builds the certificates of found keys on this many threads, in `order`, which helps if
+    /// signing or encrypting them is slower than finding them.
+    pub fn finalizers(mut self, finalizers: Option<NonZeroUsize>, order: OutputOrder) -> Self {
+        self.config.finalizers = finalizers;
+        self.config.output_order = order;
+        self
+    }
+
+    /// Spreads the workers over the nodes of a multi-socket machine and pins every worker to a
+    /// CPU, so that its memory stays on its node.
+    #[cfg(feature = "numa")]
+    pub fn numa(mut self, numa: bool) -> Self {
+        self.config.numa = numa;
+        self
+    }
+
+    /// Runs the workers on the cores allowed by `policy` on CPUs with performance and efficiency
+    /// cores, see [`CorePolicy`].
+    #[cfg(feature = "hybrid")]
+    pub fn cores(mut self, policy: CorePolicy) -> Self {
+        self.config.cores = policy;
+        self
+    }
+
+    pub fn password(mut self, password: Option<Password>) -> Self {
+        self.config.password = password;
+        self
+    }
+
+    /// Generates keys of `version`, checking the lengths of the regexes against its fingerprints.
+    pub fn key_version(mut self, version: KeyVersion) -> Self {
+        self.config.key_version = version;
+        self
+    }
+
+    pub fn user_id(mut self, user_id: Option<String>) -> Self {
+        self.config.user_id = user_id;
+        self
+    }
+
+    /// Certifies the user IDs of the found keys with another key, see [`Config::certify_with`].
+    pub fn certify_with(mut self, certifier: Option<Certifier>) -> Self {
+        self.config.certify_with = certifier;
+        self
+    }
+
+    /// Signs this message with every found key, see [`Config::sign_proof`].
+    pub fn sign_proof(mut self, message: Option<Vec<u8>>) -> Self {
+        self.config.sign_proof = message;
+        self
+    }
+
+    /// Adds identity proofs to the found keys, see [`Config::proofs`].
+    pub fn proofs(mut self, proofs: Vec<String>) -> Self {
+        self.config.proofs = proofs;
+        self
+    }
+
+    /// Grinds an encryption subkey matching this regex for every found key, see
+    /// [`Config::subkey_regex`].
+    pub fn subkey_regex(mut self, pattern: Option<String>) -> Self {
+        self.subkey_pattern = pattern;
+        self
+    }
+
+    pub fn humanize_numbers(mut self, humanize: bool) -> Self {
+        self.config.humanize_numbers = humanize;
+        self
+    }
+
+    pub fn status_template(mut self, template: StatusTemplate) -> Self {
+        self.config.status_template = template;
+        self
+    }
+
+    #[cfg(feature = "tui")]
+    pub fn tui(mut self, tui: bool) -> Self {
+        self.config.tui = tui;
+        self
+    }
+
+    pub fn verbosity(mut self, verbosity: Verbosity) -> Self {
+        self.config.verbosity = verbosity;
+        self
+    }
+
+    pub fn bell(mut self, bell: bool, repeat: Option<Duration>) -> Self {
+        self.config.bell = bell;
+        self.config.bell_repeat = repeat;
+        self
+    }
+
+    #[cfg(feature = "webhook")]
+    pub fn webhook(mut self, url: Option<String>) -> Self {
+        self.config.webhook = url;
+        self
+    }
+
+    /// Exports metrics and spans of the search via OTLP, see [`Config::otel`].
+    #[cfg(feature = "otel")]
+    pub fn otel(mut self, enabled: bool) -> Self {
+        self.config.otel = enabled;
+        self
+    }
+
+    pub fn notify_email(mut self, email: Option<EmailConfig>) -> Self {
+        self.config.notify_email = email;
+        self
+    }
+
+    #[cfg(unix)]
+    pub fn log_syslog(mut self, log_syslog: bool) -> Self {
+        self.config.log_syslog = log_syslog;
+        self
+    }
+
+    #[cfg(unix)]
+    pub fn control_socket(mut self, path: Option<PathBuf>) -> Self {
+        self.config.control_socket = path;
+        self
+    }
+
+    /// Reads control commands from stdin, see [`Config::stdin_control`].
+    pub fn stdin_control(mut self, enabled: bool) -> Self {
+        self.config.stdin_control = enabled;
+        self
+    }
+
+    /// Persists checkpoints in `path`, continuing from an existing one unless the patterns or
+    /// settings have changed and `allow_config_change` isn't set. With `resume`, a checkpoint is
+    /// required.
+    pub fn state_file(
+        mut self,
+        path: Option<PathBuf>,
+        resume: bool,
+        allow_config_change: bool,
+    ) -> Self {
+        self.config.state_file = path;
+        self.config.resume = resume;
+        self.config.allow_config_change = allow_config_change;
+        self
+    }
+
+    pub fn output_dir(mut self, dir: Option<PathBuf>) -> Self {
+        self.config.output_dir = dir;
+        self
+    }
+
+    pub fn jobs(mut self, jobs: Vec<Job>) -> Self {
+        self.config.jobs = jobs;
+        self
+    }
+
+    /// Discards found keys whose key ID collides with one of `key_ids`.
+    pub fn avoid_keyids(mut self, key_ids: Option<KeyIds>) -> Self {
+        self.config.avoid_keyids = key_ids;
+        self
+    }
+
+    /// Looks up found keys' key IDs on the keyserver at `url`, warning about collisions.
+    #[cfg(feature = "keyserver")]
+    pub fn keyserver(mut self, url: Option<String>) -> Self {
+        self.config.keyserver = url;
+        self
+    }
+
+    pub fn stop_with_priority(mut self, priority: Option<i64>) -> Self {
+        self.config.stop_with_priority = priority;
+        self
+    }
+
+    pub fn json_logs(mut self, json_logs: bool) -> Self {
+        self.config.json_logs = json_logs;
+        self
+    }
+
+    #[cfg(feature = "http-api")]
+    pub fn listen(mut self, address: Option<String>) -> Self {
+        self.config.listen = address;
+        self
+    }
+
+    #[cfg(feature = "cluster")]
+    pub fn coordinator(mut self, coordinator: Option<crate::cluster::WorkerConfig>) -> Self {
+        self.config.coordinator = coordinator;
+        self
+    }
+
+    /// Confines the process to what the search needs once it is set up, see
+    /// [`Config::sandbox`].
+    #[cfg(feature = "sandbox")]
+    pub fn sandbox(mut self, sandbox: bool) -> Self {
+        self.config.sandbox = sandbox;
+        self
+    }
+
+    /// Refuses options using the network and blocks it where possible, see
+    /// [`Config::offline`].
+    pub fn offline(mut self, offline: bool) -> Self {
+        self.config.offline = offline;
+        self
+    }
+
+    /// Checks the issued keys against key-issuance rules, see [`Config::policy`].
+    pub fn policy(mut self, policy: Option<Policy>) -> Self {
+        self.config.policy = policy;
+        self
+    }
+
+    /// Appends a hash-chained record of every emitted key to a file, see [`Config::audit_log`].
+    pub fn audit_log(mut self, path: Option<PathBuf>) -> Self {
+        self.config.audit_log = path;
+        self
+    }
+
+    /// Searches for keys of another format than OpenPGP, see [`Config::key_format`].
+    pub fn key_format(mut self, format: KeyFormat) -> Self {
+        self.config.key_format = format;
+        self
+    }
+
+    /// Checks the regexes and settings and builds the configuration.
+    pub fn build(self) -> Result<Config, ConfigError> {
+        let mut config = self.config;
+        let mut patterns = self.patterns;
+        if let Some(count) = self.farm {
+            if !patterns.is_empty()
+                || !self.labeled_patterns.is_empty()
+                || config.patterns_file.is_some()
+                || !config.matchers.is_empty()
+                || !config.jobs.is_empty()
+                || config.stop_after.is_some()
+            {
+                return Err(ConfigError::FarmWithPatterns);
+            }
+            // Matches every key
+            patterns.push(String::new());
+            config.stop_after = Some(count);
+            config.farm = true;
+        }
+        config.subkey_regex = self
+            .subkey_pattern
+            .as_deref()
+            .map(|pattern| parse_pattern_for(pattern, config.key_version))
+            .transpose()?;
+        if config.key_format != KeyFormat::OpenPgp {
+            if let Some(option) = openpgp_only_option(&config, &self.labeled_patterns) {
+                return Err(ConfigError::NotForFormat(option, config.key_format));
+            }
+            for pattern in &patterns {
+                config
+                    .matchers
+                    .push(Arc::new(FormatPattern::new(pattern, config.key_format)?));
+            }
+        } else {
+            config.regexes = patterns
+                .iter()
+                .map(|pattern| parse_pattern_for(pattern, config.key_version))
+                .collect::<Result<_, _>>()?;
+        }
+        config.labeled_regexes = self
+            .labeled_patterns
+            .iter()
+            .map(|(label, pattern)| {
+                Ok((
+                    label.clone(),
+                    parse_pattern_for(pattern, config.key_version)?,
+                ))
+            })
+            .collect::<Result<_, _>>()?;
+        for job in &config.jobs {
+            check_pattern_length(&job.regex, config.key_version)?;
+        }
+
+        #[cfg(feature = "cluster")]
+        let remote = config.coordinator.is_some();
+        #[cfg(not(feature = "cluster"))]
+        let remote = false;
+        if config.regexes.is_empty()
+            && config.labeled_regexes.is_empty()
+            && config.matchers.is_empty()
+            && config.patterns_file.is_none()
+            && config.jobs.is_empty()
+            && !remote
+        {
+            return Err(ConfigError::NoPatterns);
+        }
+
+        if !config.jobs.is_empty() && config.stop_after.is_some() {
+            return Err(ConfigError::StopAfterWithJobs);
+        }
+        if !config.jobs.is_empty() && config.user_id.is_some() {
+            return Err(ConfigError::UserIdWithJobs);
+        }
+        if config.certify_with.is_some() && config.user_id.is_none() && config.jobs.is_empty() {
+            return Err(ConfigError::CertifyWithoutUserId);
+        }
+        if let Some(proof) = config.proofs.iter().find(|proof| !is_uri(proof)) {
+            return Err(ConfigError::InvalidProof(proof.clone()));
+        }
+        // A line break in the address would end the To header of the email
+        if let Some(email) = &config.notify_email
+            && email.to.contains(char::is_control)
+        {
+            return Err(ConfigError::InvalidEmail(email.to.clone()));
+        }
+        if config.jobs.is_empty() && config.stop_with_priority.is_some() {
+            return Err(ConfigError::StopWithPriorityWithoutJobs);
+        }
+        if let Some(label) = config
+            .label_outputs
+            .keys()
+            .find(|label| !config.labeled_regexes.iter().any(|(l, _)| l == *label))
+        {
+            return Err(ConfigError::UnknownLabel(label.clone()));
+        }
+        #[cfg(all(feature = "numa", feature = "hybrid"))]
+        if config.numa && config.cores != CorePolicy::All {
+            return Err(ConfigError::NumaWithCores);
+        }
+        if config.offline
+            && let Some(option) = network_option(&config)
+        {
+            return Err(ConfigError::NetworkWhileOffline(option));
+        }
+        #[cfg(feature = "sandbox")]
+        if config.sandbox && config.notify_email.is_some() {
+            return Err(ConfigError::SandboxWithEmail);
+        }
+        if config.policy == Some(Policy::Strict)
+            && let Some(warning) = crate::policy::evaluate(&config)
+                .into_iter()
+                .find(|warning| warning.denied_by_strict())
+        {
+            return Err(ConfigError::PolicyViolation(warning));
+        }
+
+        Ok(config)
+    }
+}
+
+/// The first option of `config` that only applies to OpenPGP keys, if any.
+fn openpgp_only_option(
+    config: &Config,
+    labeled_patterns: &[(String, String)],
+) -> Option<&'static str> {
+    #[cfg(feature = "keyserver")]
+    if config.keyserver.is_some() {
+        return Some("The keyserver lookup");
+    }
+    #[cfg(feature = "cluster")]
+    if config.coordinator.is_some() {
+        return Some("A coordinator");
+    }
+    [
+        (!labeled_patterns.is_empty(), "Labeled regexes"),
+        (config.patterns_file.is_some(), "A patterns file"),
+        (!config.jobs.is_empty(), "Jobs"),
+        (config.password.is_some(), "A password"),
+        (config.key_version != KeyVersion::V4, "The key version"),
+        (config.avoid_keyids.is_some(), "Avoiding key IDs"),
+        (config.subkey_regex.is_some(), "A subkey regex"),
+        (config.certify_with.is_some(), "Certifying"),
+        (config.sign_proof.is_some(), "A proof message"),
+        (!config.proofs.is_empty(), "Identity proofs"),
+        (config.policy.is_some(), "A policy"),
+        // Only OpenSSH keys have a place for it, as comment
+        (
+            config.key_format != KeyFormat::Ssh && config.user_id.is_some(),
+            "A user ID",
+        ),
+    ]
+    .into_iter()
+    .find_map(|(used, option)| used.then_some(option))
+}
+
+/// The first option of `config` using the network, if any.
+fn network_option(config: &Config) -> Option<&'static str> {
+    #[cfg(feature = "webhook")]
+    if config.webhook.is_some() {
+        return Some("The webhook");
+    }
+    #[cfg(feature = "keyserver")]
+    if config.keyserver.is_some() {
+        return Some("The keyserver lookup");
+    }
+    #[cfg(feature = "http-api")]
+    if config.listen.is_some() {
+        return Some("The HTTP API");
+    }
+    #[cfg(feature = "cluster")]
+    if config.coordinator.is_some() {
+        return Some("Searching for a coordinator");
+    }
+    #[cfg(feature = "otel")]
+    if config.otel {
+        return Some("The OpenTelemetry export");
+    }
+    // Sendmail usually relays the email to a mail server
+    config
+        .notify_email
+        .is_some()
+        .then_some("Email notification")
+}
+
+/// Whether `proof` starts with a URI scheme followed by something, which is all Keyoxide needs
+/// to tell the service apart.
+fn is_uri(proof: &str) -> bool {
+    proof.split_once(':').is_some_and(|(scheme, rest)| {
+        scheme.starts_with(|c: char| c.is_ascii_alphabetic())
+            && scheme
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || matches!(c, '+' | '-' | '.'))
+            && !rest.is_empty()
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn email() -> Option<EmailConfig> {
+        Some(EmailConfig {
+            to: "security@example.org".to_string(),
+            sendmail: PathBuf::from("/usr/sbin/sendmail"),
+        })
+    }
+
+    #[test]
+    fn network_option_finds_network_options() {
+        let config = Config::builder().regex("^AB").build().unwrap();
+        assert_eq!(network_option(&config), None);
+
+        let config = Config::builder()
+            .regex("^AB")
+            .notify_email(email())
+            .build()
+            .unwrap();
+        assert_eq!(network_option(&config), Some("Email notification"));
+    }
+
+    #[cfg(feature = "webhook")]
+    #[test]
+    fn network_option_names_the_first_option() {
+        let config = Config::builder()
+            .regex("^AB")
+            .webhook(Some("https://example.org/hook".to_string()))
+            .notify_email(email())
+            .build()
+            .unwrap();
+        assert_eq!(network_option(&config), Some("The webhook"));
+    }
+
+    #[test]
+    fn offline_rejects_network_options() {
+        let result = Config::builder()
+            .regex("^AB")
+            .offline(true)
+            .notify_email(email())
+            .build();
+        assert!(matches!(
+            result,
+            Err(ConfigError::NetworkWhileOffline("Email notification"))
+        ));
+        assert!(Config::builder().regex("^AB").offline(true).build().is_ok());
+    }
+}
@@ -0,0 +1,223 @@
+//! Control interfaces on a Unix domain socket and on stdin.
+//!
+//! Both accept newline-delimited commands and respond with one line for each:
+//!
+//! - `status`: `tried=<n> found=<n> rate=<keys/s> elapsed=<secs> paused=<bool>`
+//! - `pause`, `resume`, `stop`: `ok`
+//! - `add-pattern <regex>`: `ok`, after which keys matching the regex are found, too
+//!
+//! Failed commands are answered with `error: <description>`.
+
+#[cfg(unix)]
+use std::{
+    fs,
+    io::{BufReader, ErrorKind, Read, Write},
+    os::unix::net::{UnixListener, UnixStream},
+    path::{Path, PathBuf},
+};
+use std::{
+    io::{self, BufRead},
+    sync::{atomic::Ordering, mpsc},
+    thread,
+    time::Duration,
+};
+
+use anyhow::{Context, bail};
+use crossbeam_channel::Sender;
+
+use crate::{Fingerprunk, Message, Verbosity, format::KeyFormat, pattern::parse_pattern_for};
+
+/// How often the control thread checks whether the search has stopped.
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Upper bound for the length of a command, so that a client can't make us buffer without limit.
+#[cfg(unix)]
+const MAX_LINE_LEN: usize = 64 * 1024;
+
+/// A bound control socket, which is removed when dropped.
+#[cfg(unix)]
+pub(crate) struct ControlSocket {
+    listener: UnixListener,
+    path: PathBuf,
+}
+
+#[cfg(unix)]
+impl ControlSocket {
+    /// Binds the socket, replacing a stale socket file left behind by a previous run.
+    pub(crate) fn bind(path: &Path) -> anyhow::Result<Self> {
+        if path.exists() {
+            if UnixStream::connect(path).is_ok() {
+                bail!("Control socket {} is already in use", path.display());
+            }
+            fs::remove_file(path)
+                .with_context(|| format!("Failed to remove stale socket {}", path.display()))?;
+        }
+
+        let listener = UnixListener::bind(path)
+            .with_context(|| format!("Failed to bind control socket {}", path.display()))?;
+        listener.set_nonblocking(true)?;
+
+        Ok(Self {
+            listener,
+            path: path.to_path_buf(),
+        })
+    }
+}
+
+#[cfg(unix)]
+impl Drop for ControlSocket {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.path);
+    }
+}
+
+/// Spawns a thread that forwards the lines read from stdin.
+///
+/// The thread is detached because it blocks on reading stdin, so it outlives
+/// [`Fingerprunk::run`]. The channel is closed when stdin is.
+pub(crate) fn spawn_stdin_reader() -> io::Result<mpsc::Receiver<String>> {
+    let (line_sender, line_receiver) = mpsc::channel();
+    thread::Builder::new()
+        .name("stdin_reader".to_string())
+        .spawn(move || {
+            for line in io::stdin().lines() {
+                let Ok(line) = line else { break };
+                if line_sender.send(line).is_err() {
+                    break;
+                }
+            }
+        })?;
+    Ok(line_receiver)
+}
+
+impl Fingerprunk {
+    /// Executes commands read from stdin until it is closed or the search stops.
+    ///
+    /// Responses are printed to stderr because stdout is reserved for found keys.
+    pub(crate) fn stdin_command_thread(
+        &self,
+        lines: mpsc::Receiver<String>,
+        sender: Sender<Message>,
+    ) {
+        while !self.stop.load(Ordering::Relaxed) {
+            let line = match lines.recv_timeout(POLL_INTERVAL) {
+                Ok(line) => line,
+                Err(mpsc::RecvTimeoutError::Timeout) => continue,
+                Err(mpsc::RecvTimeoutError::Disconnected) => break,
+            };
+            let response = match self.control_command(&line, &sender) {
+                Ok(response) => response,
+                Err(err) => format!("error: {err:#}"),
+            };
+            self.log(Verbosity::Quiet, response);
+        }
+    }
+
+    /// Serves control connections one after another until the search stops.
+    #[cfg(unix)]
+    pub(crate) fn control_thread(&self, socket: &ControlSocket, sender: Sender<Message>) {
+        while !self.stop.load(Ordering::Relaxed) {
+            match socket.listener.accept() {
+                Ok((stream, _)) => {
+                    if let Err(err) = self.serve_control_connection(stream, &sender) {
+                        self.log(
+                            Verbosity::Verbose,
+                            format_args!("Control connection failed: {err}"),
+                        );
+                    }
+                }
+                Err(err) if err.kind() == ErrorKind::WouldBlock => thread::sleep(POLL_INTERVAL),
+                Err(err) => {
+                    self.log(
+                        Verbosity::Normal,
+                        format_args!("Failed to accept control connection: {err}"),
+                    );
+                    thread::sleep(POLL_INTERVAL);
+                }
+            }
+        }
+    }
+
+    #[cfg(unix)]
+    fn serve_control_connection(
+        &self,
+        stream: UnixStream,
+        sender: &Sender<Message>,
+    ) -> io::Result<()> {
+        // Time out reads so that an idle client can't keep us from stopping
+        stream.set_nonblocking(false)?;
+        stream.set_read_timeout(Some(POLL_INTERVAL))?;
+        let mut writer = stream.try_clone()?;
+        let mut reader = BufReader::new(stream);
+        let mut line = Vec::new();
+
+        while !self.stop.load(Ordering::Relaxed) {
+            let limit = MAX_LINE_LEN.saturating_sub(line.len()) as u64;
+            match (&mut reader).take(limit).read_until(b'\n', &mut line) {
+                Ok(_) if line.len() >= MAX_LINE_LEN && !line.ends_with(b"\n") => {
+                    writeln!(writer, "error: Command longer than {MAX_LINE_LEN} bytes")?;
+                    return Err(io::Error::new(
+                        ErrorKind::InvalidData,
+                        "Control command too long",
+                    ));
+                }
+                Ok(0) => break,
+                Ok(_) => {}
+                Err(err) if matches!(err.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut) => {
+                    continue;
+                }
+                Err(err) => return Err(err),
+            }
+
+            let response = match self.control_command(&String::from_utf8_lossy(&line), sender) {
+                Ok(response) => response,
+                Err(err) => format!("error: {err:#}"),
+            };
+            writeln!(writer, "{response}")?;
+            line.clear();
+        }
+
+        Ok(())
+    }
+
+    /// Executes a single command and returns the response line.
+    fn control_command(&self, line: &str, sender: &Sender<Message>) -> anyhow::Result<String> {
+        let line = line.trim();
+        let (command, argument) = line.split_once(' ').unwrap_or((line, ""));
+
+        match command {
+            "status" => {
+                let elapsed = self.started_instant.elapsed().as_secs_f64();
+                let tried = self.counter_tried.load(Ordering::Relaxed);
+                Ok(format!(
+                    "tried={tried} found={} rate={:.0} elapsed={elapsed:.0} paused={}",
+                    self.counter_found.load(Ordering::Relaxed),
+                    tried as f64 / elapsed,
+                    self.paused.load(Ordering::Relaxed),
+                ))
+            }
+            "pause" | "resume" => {
+                self.paused.store(command == "pause", Ordering::Relaxed);
+                Ok("ok".to_string())
+            }
+            "stop" => {
+                let _ = sender.send(Message::Stop);
+                Ok("ok".to_string())
+            }
+            "add-pattern" => {
+                if self.config.key_format != KeyFormat::OpenPgp {
+                    bail!(
+                        "Patterns can't be added to a search for {} keys",
+                        self.config.key_format.name()
+                    );
+                }
+                let regex = parse_pattern_for(argument.trim(), self.config.key_version)?;
+                self.log(Verbosity::Normal, format_args!("Added pattern {regex}"));
+                self.add_regex(regex);
+                Ok("ok".to_string())
+            }
+            "" => bail!("Empty command"),
+            _ => bail!("Unknown command {command:?}"),
+        }
+    }
+}
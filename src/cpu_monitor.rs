@@ -0,0 +1,243 @@
+//! Watching the utilization and clock of the CPUs on Linux, to tell when thermal or power limits
+//! throttle the search, which otherwise just looks like the rate dropping for no reason.
+
+use std::{fmt, fs, path::Path};
+
+use crate::{Fingerprunk, Verbosity};
+
+const PROC_STAT: &str = "/proc/stat";
+const CPUS_DIR: &str = "/sys/devices/system/cpu";
+
+/// Utilization from which the CPUs count as busy with the search, so that their clock should be
+/// as high as it gets.
+const BUSY: f64 = 0.9;
+
+/// Fraction of the highest clock seen while busy below which the CPUs count as throttled.
+const THROTTLED_CLOCK: f64 = 0.85;
+
+/// Samples in a row needed for throttling to count as started or stopped, so that the log isn't
+/// flooded by a CPU hovering around its limits.
+const SETTLE_SAMPLES: u32 = 5;
+
+/// Why the CPUs are slower than they were.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) enum Throttling {
+    /// The throttle counters of the cores or packages have gone up since the last sample.
+    Thermal,
+    /// The clock is at this fraction of the highest seen while busy, e.g. because of a power
+    /// limit.
+    Clock(f64),
+}
+
+impl fmt::Display for Throttling {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Thermal => f.write_str("thermal throttling"),
+            Self::Clock(fraction) => write!(f, "clock throttled to {:.0} %", fraction * 100.0),
+        }
+    }
+}
+
+/// Samples the CPUs and keeps the results of the last sample.
+#[derive(Debug)]
+pub(crate) struct CpuMonitor {
+    /// Number, busy and total time of every CPU at the last sample.
+    times: Vec<(usize, u64, u64)>,
+    throttle_count: u64,
+    /// Utilization of every CPU between the last two samples, between 0 and 1.
+    utilization: Vec<f64>,
+    /// Mean clock of the CPUs in kHz, if known.
+    clock: Option<u64>,
+    /// Highest mean clock seen while the CPUs were busy.
+    peak_clock: u64,
+    throttling: Option<Throttling>,
+    /// Samples in a row that disagree with `throttling` about whether the CPUs are throttled.
+    disagreeing: u32,
+}
+
+impl CpuMonitor {
+    /// Takes the first sample. Returns `None` if the CPU times are unknown, e.g. on other
+    /// systems than Linux.
+    pub(crate) fn start() -> Option<Self> {
+        Some(Self {
+            times: read_cpu_times()?,
+            throttle_count: read_throttle_count(),
+            utilization: Vec::new(),
+            clock: None,
+            peak_clock: 0,
+            throttling: None,
+            disagreeing: 0,
+        })
+    }
+
+    /// Takes another sample and returns whether the CPUs have started or stopped being
+    /// throttled.
+    pub(crate) fn update(&mut self) -> bool {
+        let Some(times) = read_cpu_times() else {
+            return false;
+        };
+        self.utilization = times
+            .iter()
+            .zip(&self.times)
+            .map(|(&(_, busy, total), &(_, last_busy, last_total))| {
+                let total = total.saturating_sub(last_total);
+                if total == 0 {
+                    0.0
+                } else {
+                    busy.saturating_sub(last_busy) as f64 / total as f64
+                }
+            })
+            .collect();
+        self.clock = read_mean_clock(times.iter().map(|&(cpu, _, _)| cpu));
+        self.times = times;
+
+        let throttle_count = read_throttle_count();
+        let throttling = if throttle_count > self.throttle_count {
+            Some(Throttling::Thermal)
+        } else {
+            match self.clock {
+                Some(clock) if self.mean_utilization() >= BUSY => {
+                    self.peak_clock = self.peak_clock.max(clock);
+                    let fraction = clock as f64 / self.peak_clock as f64;
+                    (fraction < THROTTLED_CLOCK).then_some(Throttling::Clock(fraction))
+                }
+                // Idle CPUs clock down anyway
+                _ => None,
+            }
+        };
+        self.throttle_count = throttle_count;
+
+        if throttling.is_some() == self.throttling.is_some() {
+            self.throttling = throttling;
+            self.disagreeing = 0;
+            return false;
+        }
+        self.disagreeing += 1;
+        if self.disagreeing < SETTLE_SAMPLES {
+            return false;
+        }
+        self.throttling = throttling;
+        self.disagreeing = 0;
+        true
+    }
+
+    fn mean_utilization(&self) -> f64 {
+        self.utilization.iter().sum::<f64>() / self.utilization.len().max(1) as f64
+    }
+}
+
+/// E.g. `97 % busy (min 91 %) at 3.12 GHz, thermal throttling`.
+impl fmt::Display for CpuMonitor {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.utilization.is_empty() {
+            return f.write_str("-");
+        }
+
+        let min = self
+            .utilization
+            .iter()
+            .copied()
+            .fold(f64::INFINITY, f64::min);
+        write!(
+            f,
+            "{:.0} % busy (min {:.0} %)",
+            self.mean_utilization() * 100.0,
+            min * 100.0
+        )?;
+        if let Some(clock) = self.clock {
+            write!(f, " at {:.2} GHz", clock as f64 / 1e6)?;
+        }
+        if let Some(throttling) = self.throttling {
+            write!(f, ", {throttling}")?;
+        }
+        Ok(())
+    }
+}
+
+impl Fingerprunk {
+    /// Samples the CPUs, logging when throttling starts or stops.
+    pub(crate) fn update_cpu_monitor(&self) {
+        let mut monitor = self
+            .cpu_monitor
+            .lock()
+            .expect("lock should not be poisoned");
+        let Some(monitor) = monitor.as_mut() else {
+            return;
+        };
+        if !monitor.update() {
+            return;
+        }
+
+        match monitor.throttling {
+            Some(throttling) => self.log(
+                Verbosity::Normal,
+                format_args!("The CPUs are slowed down by {throttling}"),
+            ),
+            None => self.log(Verbosity::Normal, "The CPUs are no longer throttled"),
+        }
+    }
+
+    /// Utilization, clock and throttling of the CPUs as of the last sample, or `-` if unknown.
+    pub(crate) fn cpu_status(&self) -> String {
+        match &*self
+            .cpu_monitor
+            .lock()
+            .expect("lock should not be poisoned")
+        {
+            Some(monitor) => monitor.to_string(),
+            None => "-".to_string(),
+        }
+    }
+}
+
+/// Reads the number, busy and total time of every online CPU.
+fn read_cpu_times() -> Option<Vec<(usize, u64, u64)>> {
+    let stat = fs::read_to_string(PROC_STAT).ok()?;
+    let times: Vec<_> = stat
+        .lines()
+        .filter_map(|line| {
+            let mut fields = line.split_whitespace();
+            // The line of all CPUs together has no number
+            let cpu = fields.next()?.strip_prefix("cpu")?.parse().ok()?;
+            // User, nice, system, idle, iowait, irq, softirq and steal; guest time is part of user
+            let times: Vec<u64> = fields
+                .take(8)
+                .filter_map(|field| field.parse().ok())
+                .collect();
+            let total = times.iter().sum::<u64>();
+            let idle = times.iter().skip(3).take(2).sum::<u64>();
+            Some((cpu, total - idle, total))
+        })
+        .collect();
+    (!times.is_empty()).then_some(times)
+}
+
+/// Reads the mean current clock of `cpus` in kHz.
+fn read_mean_clock(cpus: impl Iterator<Item = usize>) -> Option<u64> {
+    let clocks = cpus
+        .map(|cpu| {
+            read_number(&Path::new(CPUS_DIR).join(format!("cpu{cpu}/cpufreq/scaling_cur_freq")))
+        })
+        .collect::<Option<Vec<_>>>()?;
+    Some(clocks.iter().sum::<u64>() / clocks.len().max(1) as u64)
+}
+
+/// Reads the sum of the thermal throttle counters of all cores and packages, which only Intel
+/// CPUs have.
+fn read_throttle_count() -> u64 {
+    let Ok(entries) = fs::read_dir(CPUS_DIR) else {
+        return 0;
+    };
+    entries
+        .flatten()
+        .flat_map(|entry| {
+            let dir = entry.path().join("thermal_throttle");
+            ["core_throttle_count", "package_throttle_count"]
+                .map(|name| read_number(&dir.join(name)).unwrap_or(0))
+        })
+        .sum()
+}
+
+fn read_number(path: &Path) -> Option<u64> {
+    fs::read_to_string(path).ok()?.trim().parse().ok()
+}
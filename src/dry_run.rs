@@ -0,0 +1,152 @@
+//! Checking a configuration before searching, so that a typo in an output path or an unreachable
+//! webhook shows up right away instead of when the first key is found hours later.
+
+use std::{
+    fs::{self, File},
+    path::Path,
+    time::Instant,
+};
+
+use anyhow::{Context, bail};
+
+use crate::{ConfigError, Error, Fingerprunk};
+
+impl Fingerprunk {
+    /// Resolves the configuration like [`Self::run`] does and checks everything the search would
+    /// need, without generating any keys.
+    ///
+    /// The patterns must be able to match, the output directories and the state file must be
+    /// writable and the configured network endpoints reachable. Returns the effective
+    /// configuration as name/value pairs, as logged at startup of a verbose search.
+    pub fn dry_run(mut self) -> Result<Vec<(&'static str, String)>, Error> {
+        self.check_config().map_err(Error::from_anyhow)?;
+        Ok(self.config_lines())
+    }
+
+    fn check_config(&mut self) -> anyhow::Result<()> {
+        self.started_instant = Instant::now();
+
+        if self.config.patterns_file.is_some() {
+            self.reload_patterns()?;
+        }
+        // The coordinator hands out the patterns, so connecting to it is part of resolving them
+        #[cfg(feature = "cluster")]
+        if let Some(worker_config) = &self.config.coordinator {
+            let (_link, regexes) = crate::cluster::CoordinatorLink::connect(worker_config)?;
+            self.set_matcher(crate::matcher::PatternSet::from_regexes(regexes));
+        }
+        if self.matcher().is_empty() {
+            return Err(ConfigError::NoPatterns.into());
+        }
+        self.load_state()?;
+        if !self.config.jobs.is_empty() {
+            self.update_job_matcher();
+        }
+
+        match &self.config.output_dir {
+            Some(dir) => check_writable(dir)?,
+            None if self.config.json_logs => {
+                bail!("JSON logs are written to stdout, so found keys need an output directory")
+            }
+            None => {}
+        }
+        for job in &self.config.jobs {
+            let Some(dir) = self.job_dir(job) else {
+                bail!("Job {} needs an output directory", job.name);
+            };
+            check_writable(&dir)?;
+        }
+        for (label, dir) in &self.config.label_outputs {
+            if !self.config.labeled_regexes.iter().any(|(l, _)| l == label) {
+                return Err(ConfigError::UnknownLabel(label.clone()).into());
+            }
+            check_writable(dir)?;
+        }
+        if let Some(path) = &self.config.state_file {
+            check_writable(path.parent().unwrap_or(Path::new("")))?;
+        }
+        if let Some(path) = &self.config.audit_log {
+            if path.exists() {
+                crate::audit::verify(path)?;
+            }
+            check_writable(path.parent().unwrap_or(Path::new("")))?;
+        }
+
+        // Bound and released again right away
+        #[cfg(unix)]
+        if let Some(path) = &self.config.control_socket {
+            crate::control::ControlSocket::bind(path)?;
+        }
+        #[cfg(feature = "http-api")]
+        if let Some(address) = &self.config.listen {
+            crate::http::listen(address)?;
+        }
+        #[cfg(feature = "webhook")]
+        if let Some(url) = &self.config.webhook {
+            check_webhook(url)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Checks that files can be created in `dir` or, if it doesn't exist yet, in the closest ancestor
+/// it would be created in.
+fn check_writable(dir: &Path) -> anyhow::Result<()> {
+    let existing = dir
+        .ancestors()
+        .map(|path| {
+            if path.as_os_str().is_empty() {
+                Path::new(".")
+            } else {
+                path
+            }
+        })
+        .find(|path| path.exists())
+        .unwrap_or(Path::new("."));
+    if !existing.is_dir() {
+        bail!("{} is not a directory", existing.display());
+    }
+
+    let probe = existing.join(format!(".fingerprunk-dry-run-{}", std::process::id()));
+    File::create_new(&probe)
+        .and_then(|_| fs::remove_file(&probe))
+        .with_context(|| format!("Can't create files in {}", dir.display()))
+}
+
+/// Checks that the host of the webhook accepts connections, without posting anything to it.
+#[cfg(feature = "webhook")]
+fn check_webhook(url: &str) -> anyhow::Result<()> {
+    use std::{net::TcpStream, net::ToSocketAddrs, time::Duration};
+
+    const TIMEOUT: Duration = Duration::from_secs(10);
+
+    let uri: ureq::http::Uri = url
+        .parse()
+        .with_context(|| format!("Invalid webhook URL {url}"))?;
+    let Some(host) = uri.host() else {
+        bail!("Webhook URL {url} has no host");
+    };
+    let port = uri
+        .port_u16()
+        .unwrap_or(if uri.scheme_str() == Some("http") {
+            80
+        } else {
+            443
+        });
+
+    let addresses = (host, port)
+        .to_socket_addrs()
+        .with_context(|| format!("Failed to resolve webhook host {host}"))?;
+    let mut last_err = None;
+    for address in addresses {
+        match TcpStream::connect_timeout(&address, TIMEOUT) {
+            Ok(_) => return Ok(()),
+            Err(err) => last_err = Some(err),
+        }
+    }
+    match last_err {
+        Some(err) => Err(err).with_context(|| format!("Failed to connect to webhook host {host}")),
+        None => bail!("Webhook host {host} did not resolve to any address"),
+    }
+}
@@ -0,0 +1,128 @@
+//! Estimating the energy used by the search from the RAPL counters of the CPU packages, which
+//! Linux exposes for Intel and AMD CPUs under `/sys/class/powercap`.
+//!
+//! The counters measure whole packages, so whatever else runs on the CPUs is counted, too. Most
+//! distributions only let root read them, as they leak hints about cryptographic operations;
+//! without access, no energy is reported.
+
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use crate::{Fingerprunk, status::humanize};
+
+const POWERCAP_DIR: &str = "/sys/class/powercap";
+
+/// The energy counter of a CPU package.
+#[derive(Debug)]
+struct Package {
+    counter: PathBuf,
+    /// Value in microjoules at which the counter wraps around to 0.
+    range: u64,
+    last: u64,
+    used: u64,
+}
+
+/// Sums up the energy used by all CPU packages since it was started.
+#[derive(Debug)]
+pub(crate) struct EnergyMeter {
+    packages: Vec<Package>,
+}
+
+impl EnergyMeter {
+    /// Starts metering at the current counter values. Returns `None` if no package counter is
+    /// readable.
+    pub(crate) fn start() -> Option<Self> {
+        let mut packages = Vec::new();
+        for entry in fs::read_dir(POWERCAP_DIR).ok()?.flatten() {
+            let dir = entry.path();
+            // Subdomains like `intel-rapl:0:1` are part of their package, and other top-level
+            // domains like `psys` contain the packages
+            let is_domain = entry
+                .file_name()
+                .to_str()
+                .and_then(|name| name.strip_prefix("intel-rapl:"))
+                .is_some_and(|index| index.parse::<usize>().is_ok());
+            if !is_domain
+                || !fs::read_to_string(dir.join("name"))
+                    .is_ok_and(|name| name.starts_with("package"))
+            {
+                continue;
+            }
+
+            let counter = dir.join("energy_uj");
+            let (Some(last), Some(range)) = (
+                read_number(&counter),
+                read_number(&dir.join("max_energy_range_uj")),
+            ) else {
+                continue;
+            };
+            packages.push(Package {
+                counter,
+                range,
+                last,
+                used: 0,
+            });
+        }
+        (!packages.is_empty()).then_some(Self { packages })
+    }
+
+    /// Reads the counters. This needs to happen more often than they wrap around, which takes
+    /// about an hour under full load.
+    pub(crate) fn update(&mut self) {
+        for package in &mut self.packages {
+            let Some(now) = read_number(&package.counter) else {
+                continue;
+            };
+            package.used += if now >= package.last {
+                now - package.last
+            } else {
+                package.range - package.last + now
+            };
+            package.last = now;
+        }
+    }
+
+    /// The joules used since starting, as of the last update.
+    pub(crate) fn joules(&self) -> f64 {
+        self.packages
+            .iter()
+            .map(|package| package.used as f64 / 1e6)
+            .sum()
+    }
+}
+
+impl Fingerprunk {
+    /// Updates the energy meter and returns the joules used by the search, if the RAPL counters
+    /// are readable.
+    pub(crate) fn energy_used(&self) -> Option<f64> {
+        let mut meter = self.energy.lock().expect("lock should not be poisoned");
+        let meter = meter.as_mut()?;
+        meter.update();
+        Some(meter.joules())
+    }
+
+    /// The energy used so far and per found key, e.g. `1.25 MJ, 418 kJ per key`, or `-` if
+    /// unknown.
+    pub(crate) fn energy_summary(&self, found: u64) -> String {
+        match self.energy_used() {
+            Some(joules) if found > 0 => format!(
+                "{}, {} per key",
+                format_joules(joules),
+                format_joules(joules / found as f64)
+            ),
+            Some(joules) => format_joules(joules),
+            None => "-".to_string(),
+        }
+    }
+}
+
+/// Formats an energy with an SI prefix, e.g. `1.25 MJ`.
+fn format_joules(joules: f64) -> String {
+    format!("{}J", humanize(joules))
+}
+
+fn read_number(path: &Path) -> Option<u64> {
+    fs::read_to_string(path).ok()?.trim().parse().ok()
+}
@@ -0,0 +1,155 @@
+//! Errors in the configuration and errors that end a search.
+
+use std::any::Any;
+
+use crate::{
+    format::KeyFormat,
+    keygen::KeyVersion,
+    matcher::{RANDOMART_HEIGHT, RANDOMART_WIDTH},
+    policy::PolicyWarning,
+};
+
+/// Why [`Fingerprunk::run`](crate::Fingerprunk::run) failed.
+#[derive(Debug, thiserror::Error)]
+#[non_exhaustive]
+pub enum Error {
+    /// Generating a key failed in one of the worker threads.
+    #[error("Failed to generate a key")]
+    KeyGeneration(#[source] anyhow::Error),
+    /// A regex couldn't be evaluated, e.g. because it backtracks too much.
+    #[error("Failed to match regex {regex}")]
+    Regex {
+        regex: String,
+        #[source]
+        source: Box<fancy_regex::Error>,
+    },
+    /// A found key couldn't be turned into a certificate or written out.
+    #[error("Failed to output key {fingerprint}")]
+    Output {
+        fingerprint: String,
+        #[source]
+        source: anyhow::Error,
+    },
+    /// A worker or finalizer thread panicked, so the search was stopped.
+    #[error("Thread {thread} panicked: {message}")]
+    Panicked { thread: String, message: String },
+    /// The configuration turned out to be invalid when the search was set up, e.g. because the
+    /// patterns file is empty.
+    #[error(transparent)]
+    Config(#[from] ConfigError),
+    /// Anything else, e.g. a failure to set up the search.
+    #[error(transparent)]
+    Other(#[from] anyhow::Error),
+}
+
+/// Why a [`ConfigBuilder`](crate::ConfigBuilder) or
+/// [`parse_pattern`](crate::pattern::parse_pattern) rejected the configuration.
+#[derive(Debug, thiserror::Error)]
+#[non_exhaustive]
+pub enum ConfigError {
+    #[error("Invalid regex {pattern}")]
+    Regex {
+        pattern: String,
+        #[source]
+        source: Box<fancy_regex::Error>,
+    },
+    /// The regex contains a literal character that fingerprints don't.
+    #[error("Regex {pattern} can never match: {character:?} is not a hex digit")]
+    NotHex { pattern: String, character: char },
+    /// The regex matches too many or, if anchored at both ends, too few characters for the
+    /// fingerprints of `version`, or of any version if `None`.
+    #[error("Regex {pattern} can never match: {}", length_reason(*.min, *.max, *.version))]
+    Length {
+        pattern: String,
+        min: usize,
+        max: Option<usize>,
+        anchored: bool,
+        version: Option<KeyVersion>,
+    },
+    #[error("No patterns given")]
+    NoPatterns,
+    #[error("Jobs have their own quotas, so stop_after can't be used with them")]
+    StopAfterWithJobs,
+    #[error("A key farm keeps every key up to its count, so it takes no patterns or stop_after")]
+    FarmWithPatterns,
+    #[error("Only user IDs can be certified, so certify_with needs a user ID or jobs")]
+    CertifyWithoutUserId,
+    #[error("Identity proof {0:?} is not a URI")]
+    InvalidProof(String),
+    #[error("Jobs have their own user IDs, so user_id can't be used with them")]
+    UserIdWithJobs,
+    #[error("stop_with_priority requires jobs")]
+    StopWithPriorityWithoutJobs,
+    #[error("There is no regex labeled {0}")]
+    UnknownLabel(String),
+    #[error("numa and cores both pin the workers, so only one of them can be used")]
+    NumaWithCores,
+    #[error("{0} needs the network, which offline forbids")]
+    NetworkWhileOffline(&'static str),
+    #[error("Email notifications run sendmail, which the sandbox forbids")]
+    SandboxWithEmail,
+    #[error("Email address {0:?} contains control characters")]
+    InvalidEmail(String),
+    #[error("{option} can't be used for {format} keys", option = .0, format = .1.name())]
+    NotForFormat(&'static str, KeyFormat),
+    #[error("The strict policy forbids this: {0}")]
+    PolicyViolation(PolicyWarning),
+    #[error("Randomart templates need {RANDOMART_HEIGHT} lines of {RANDOMART_WIDTH} characters")]
+    RandomartTemplate,
+    #[error(
+        "The coordinator grinds the timestamps of OpenPGP v4 keys, so workers can't use other \
+         keys, their own key generator or auto_tune"
+    )]
+    GrindTimestamps,
+}
+
+fn length_reason(min: usize, max: Option<usize>, version: Option<KeyVersion>) -> String {
+    let fingerprints = match version {
+        Some(version) => format!(
+            "{version} fingerprints have {}",
+            version.fingerprint_hex_len()
+        ),
+        None => {
+            let lens: Vec<_> = KeyVersion::ALL
+                .iter()
+                .map(|version| version.fingerprint_hex_len().to_string())
+                .collect();
+            format!("fingerprints have {}", lens.join(" or "))
+        }
+    };
+    let longest = version
+        .map_or(KeyVersion::V6, |version| version)
+        .fingerprint_hex_len();
+    match max.unwrap_or(min) {
+        _ if min > longest => format!("it matches at least {min} characters, but {fingerprints}"),
+        max if max == min => format!(
+            "it is anchored at both ends and matches exactly {max} characters, but {fingerprints}"
+        ),
+        max => format!(
+            "it is anchored at both ends and matches {min} to {max} characters, but \
+             {fingerprints}"
+        ),
+    }
+}
+
+impl Error {
+    /// The error for a thread that panicked with `payload`.
+    pub(crate) fn panicked(thread: impl Into<String>, payload: &(dyn Any + Send)) -> Self {
+        let message = payload
+            .downcast_ref::<&str>()
+            .map(|message| (*message).to_string())
+            .or_else(|| payload.downcast_ref::<String>().cloned())
+            .unwrap_or_else(|| "unknown cause".to_string());
+        Self::Panicked {
+            thread: thread.into(),
+            message,
+        }
+    }
+
+    /// Recovers an error that was passed through [`anyhow`] internally.
+    pub(crate) fn from_anyhow(err: anyhow::Error) -> Self {
+        err.downcast()
+            .or_else(|err| err.downcast().map(Self::Config))
+            .unwrap_or_else(Self::Other)
+    }
+}
@@ -0,0 +1,213 @@
+//! Estimation of how likely a random fingerprint is to match a regex.
+
+use std::fmt::{self, Write};
+
+use fancy_regex::Regex;
+use sequoia_openpgp::crypto;
+
+/// Length of the hexadecimal representation of a v4 fingerprint.
+pub const FINGERPRINT_HEX_LEN: usize = 40;
+
+/// Number of random fingerprints checked when the probability can't be derived from the pattern.
+const SAMPLES: usize = 100_000;
+
+/// How an [`Estimate`] was obtained, from most to least precise.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Method {
+    /// Derived from an anchored literal pattern.
+    Exact,
+    /// Derived from an unanchored literal pattern, neglecting overlapping occurrences.
+    Approximate,
+    /// Measured by matching the pattern against random fingerprints.
+    Sampled,
+}
+
+impl fmt::Display for Method {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Exact => "exact",
+            Self::Approximate => "approximate",
+            Self::Sampled => "sampled",
+        })
+    }
+}
+
+/// The estimated probability of a single random key matching a pattern.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Estimate {
+    pub probability: f64,
+    pub method: Method,
+}
+
+impl Estimate {
+    /// Estimates the match probability of `regex`.
+    ///
+    /// Returns `None` if the pattern is too complex for an analytical estimate and too unlikely
+    /// to match for sampling to produce any hits.
+    pub fn for_regex(regex: &Regex) -> Option<Self> {
+        Self::analytical(regex.as_str()).or_else(|| Self::sampled(|hex| regex.is_match(hex).ok()))
+    }
+
+    /// Estimates the probability of at least one of `regexes` matching.
+    ///
+    /// The patterns are assumed to match independently of each other, so the result is
+    /// approximate if there is more than one.
+    pub fn for_regexes(regexes: &[Regex]) -> Option<Self> {
+        Self::combine(regexes.iter().map(Self::for_regex))
+    }
+
+    /// Estimates the probability of at least one of the patterns with the given estimates
+    /// matching, like [`Self::for_regexes`].
+    pub(crate) fn combine(estimates: impl ExactSizeIterator<Item = Option<Self>>) -> Option<Self> {
+        // Work with logarithms so that tiny probabilities don't vanish in 1.0 - p
+        let mut ln_miss_probability = 0.0;
+        let count = estimates.len();
+        let mut method = if count > 1 {
+            Method::Approximate
+        } else {
+            Method::Exact
+        };
+        for estimate in estimates {
+            let estimate = estimate?;
+            ln_miss_probability += (-estimate.probability).ln_1p();
+            method = method.max(estimate.method);
+        }
+
+        (count > 0).then(|| Self {
+            probability: -f64::exp_m1(ln_miss_probability),
+            method,
+        })
+    }
+
+    /// The expected number of keys to try per match.
+    #[must_use]
+    pub fn expected_tries(&self) -> f64 {
+        1.0 / self.probability
+    }
+
+    /// The median number of keys to try until the next match.
+    #[must_use]
+    pub fn median_tries(&self) -> f64 {
+        // Matches are geometrically distributed; ln_1p keeps this precise for tiny probabilities.
+        -std::f64::consts::LN_2 / (-self.probability).ln_1p()
+    }
+
+    /// The number of keys to try for finding a match with the given chance, e.g. 0.9 for 90%.
+    #[must_use]
+    pub fn tries_for_chance(&self, chance: f64) -> f64 {
+        (-chance).ln_1p() / (-self.probability).ln_1p()
+    }
+
+    fn analytical(pattern: &str) -> Option<Self> {
+        let (rest, at_start) = match pattern.strip_prefix('^') {
+            Some(rest) => (rest, true),
+            None => (pattern, false),
+        };
+        let (literal, at_end) = match rest.strip_suffix('$') {
+            Some(literal) => (literal, true),
+            None => (rest, false),
+        };
+        let anchored = at_start || at_end;
+
+        if literal.is_empty() || !literal.bytes().all(|b| b.is_ascii_alphanumeric()) {
+            return None;
+        }
+
+        let n = literal.len();
+        // Anchored at both ends, the literal has to be the whole fingerprint
+        let fits = if at_start && at_end {
+            n == FINGERPRINT_HEX_LEN
+        } else {
+            n <= FINGERPRINT_HEX_LEN
+        };
+        let possible = fits
+            && literal
+                .bytes()
+                .all(|b| b.is_ascii_digit() || (b'A'..=b'F').contains(&b));
+        if !possible {
+            return Some(Self {
+                probability: 0.0,
+                method: Method::Exact,
+            });
+        }
+
+        let single = 16f64.powi(-(n as i32));
+        Some(if anchored {
+            Self {
+                probability: single,
+                method: Method::Exact,
+            }
+        } else {
+            Self {
+                probability: ((FINGERPRINT_HEX_LEN - n + 1) as f64 * single).min(1.0),
+                method: Method::Approximate,
+            }
+        })
+    }
+
+    /// Measures how many random fingerprints `is_match` accepts, which returns `None` if a
+    /// fingerprint couldn't be checked.
+    pub(crate) fn sampled(is_match: impl Fn(&str) -> Option<bool>) -> Option<Self> {
+        let mut bytes = [0u8; FINGERPRINT_HEX_LEN / 2];
+        let mut hex = String::with_capacity(FINGERPRINT_HEX_LEN);
+        let mut hits = 0usize;
+
+        for _ in 0..SAMPLES {
+            crypto::random(&mut bytes).ok()?;
+            hex.clear();
+            for byte in bytes {
+                write!(hex, "{byte:02X}").expect("should write into string without error");
+            }
+            if is_match(&hex)? {
+                hits += 1;
+            }
+        }
+
+        (hits > 0).then(|| Self {
+            probability: hits as f64 / SAMPLES as f64,
+            method: Method::Sampled,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn analytical(pattern: &str) -> (f64, Method) {
+        let estimate = Estimate::analytical(pattern).unwrap();
+        (estimate.probability, estimate.method)
+    }
+
+    #[test]
+    fn anchored_at_start() {
+        assert_eq!(analytical("^CAFE"), (16f64.powi(-4), Method::Exact));
+    }
+
+    #[test]
+    fn anchored_at_end() {
+        assert_eq!(analytical("CAFE$"), (16f64.powi(-4), Method::Exact));
+    }
+
+    #[test]
+    fn anchored_at_both_ends() {
+        assert_eq!(analytical("^CAFE$"), (0.0, Method::Exact));
+        let fingerprint = "^0123456789ABCDEF0123456789ABCDEF01234567$";
+        assert_eq!(analytical(fingerprint), (16f64.powi(-40), Method::Exact));
+    }
+
+    #[test]
+    fn unanchored() {
+        assert_eq!(
+            analytical("CAFE"),
+            (37.0 * 16f64.powi(-4), Method::Approximate)
+        );
+    }
+
+    #[test]
+    fn impossible_literals() {
+        assert_eq!(analytical("^CAFX"), (0.0, Method::Exact));
+        assert_eq!(analytical(&"A".repeat(41)), (0.0, Method::Exact));
+        assert!(Estimate::analytical("^CA.E").is_none());
+    }
+}
@@ -0,0 +1,270 @@
+//! C API for applications not written in Rust, see `include/fingerprunk.h`.
+//!
+//! A search is started with [`fingerprunk_start`], which calls back for every found key from a
+//! background thread. It can be polled with [`fingerprunk_poll_stats`], stopped with
+//! [`fingerprunk_stop`] and must be ended with [`fingerprunk_wait`], which frees it.
+
+#![allow(unsafe_code)]
+
+use std::{
+    ffi::{CStr, CString, c_char, c_int, c_void},
+    num::NonZeroU64,
+    ops::ControlFlow,
+    ptr,
+    thread::{self, JoinHandle},
+};
+
+use anyhow::Context;
+use sequoia_openpgp::Cert;
+
+use crate::{
+    Config, Error, Fingerprunk, RunSummary, StatsHandle, StopHandle,
+    sink::{self, KeySink, MatchMeta},
+};
+
+/// Called with the fingerprint and the armored secret key of every found key. Both strings are
+/// only valid during the call. Returning non-zero stops the search.
+pub type FingerprunkKeyCallback = Option<
+    unsafe extern "C" fn(
+        user_data: *mut c_void,
+        fingerprint: *const c_char,
+        armored_key: *const c_char,
+    ) -> c_int,
+>;
+
+/// A search running in the background.
+pub struct FingerprunkSearch {
+    stop_handle: StopHandle,
+    stats_handle: StatsHandle,
+    search: JoinHandle<Result<RunSummary, Error>>,
+}
+
+/// Progress of a search, see [`crate::Stats`].
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default)]
+pub struct FingerprunkStats {
+    pub tried: u64,
+    pub found: u64,
+    pub elapsed_secs: f64,
+    pub rate: f64,
+}
+
+/// Hands the found keys to the C callback.
+struct CallbackSink {
+    callback: unsafe extern "C" fn(*mut c_void, *const c_char, *const c_char) -> c_int,
+    user_data: *mut c_void,
+}
+
+// SAFETY: The caller of `fingerprunk_start` guarantees that the callback and its user data may be
+// used from the search thread.
+unsafe impl Send for CallbackSink {}
+
+impl KeySink for CallbackSink {
+    fn found(&mut self, cert: Cert, meta: MatchMeta) -> anyhow::Result<ControlFlow<()>> {
+        let mut armored = Vec::new();
+        sink::write_armored(&cert, &meta, &mut armored)?;
+        let armored = CString::new(armored).context("Armored key contains a NUL byte")?;
+        let fingerprint =
+            CString::new(meta.fingerprint).context("Fingerprint contains a NUL byte")?;
+
+        // SAFETY: Guaranteed by the caller of `fingerprunk_start`.
+        let result =
+            unsafe { (self.callback)(self.user_data, fingerprint.as_ptr(), armored.as_ptr()) };
+        Ok(if result == 0 {
+            ControlFlow::Continue(())
+        } else {
+            ControlFlow::Break(())
+        })
+    }
+}
+
+/// Starts searching for keys matching any of the `regex_count` regexes in `regexes` until
+/// `stop_after` keys have been found (0 for no limit) or the search is stopped.
+///
+/// Returns `NULL` if the regexes are invalid, setting `*error` (if `error` isn't `NULL`) to a
+/// message to be freed with [`fingerprunk_string_free`].
+///
+/// # Safety
+///
+/// `regexes` must point to `regex_count` NUL-terminated strings. `on_key` must be safe to call
+/// with `user_data` from another thread until [`fingerprunk_wait`] returns. `error` must be
+/// `NULL` or valid for writes.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn fingerprunk_start(
+    regexes: *const *const c_char,
+    regex_count: usize,
+    stop_after: u64,
+    on_key: FingerprunkKeyCallback,
+    user_data: *mut c_void,
+    error: *mut *mut c_char,
+) -> *mut FingerprunkSearch {
+    // SAFETY: Guaranteed by the caller.
+    let config = unsafe { config(regexes, regex_count, stop_after) };
+    let (config, callback) = match (config, on_key) {
+        (Ok(config), Some(callback)) => (config, callback),
+        (Err(err), _) => {
+            // SAFETY: Guaranteed by the caller.
+            unsafe { set_error(error, &err) };
+            return ptr::null_mut();
+        }
+        (_, None) => {
+            // SAFETY: Guaranteed by the caller.
+            unsafe { set_error(error, &anyhow::anyhow!("No key callback given")) };
+            return ptr::null_mut();
+        }
+    };
+
+    let mut fingerprunk = Fingerprunk::new_from_config(config);
+    fingerprunk.set_sink(CallbackSink {
+        callback,
+        user_data,
+    });
+    let stop_handle = fingerprunk.stop_handle();
+    let stats_handle = fingerprunk.stats_handle();
+    let search = match thread::Builder::new()
+        .name("search".to_string())
+        .spawn(move || fingerprunk.run())
+    {
+        Ok(search) => search,
+        Err(err) => {
+            // SAFETY: Guaranteed by the caller.
+            unsafe { set_error(error, &anyhow::Error::from(err)) };
+            return ptr::null_mut();
+        }
+    };
+
+    Box::into_raw(Box::new(FingerprunkSearch {
+        stop_handle,
+        stats_handle,
+        search,
+    }))
+}
+
+/// Asks the search to stop. It may still find a few keys before it has stopped.
+///
+/// # Safety
+///
+/// `search` must have been returned by [`fingerprunk_start`] and not been passed to
+/// [`fingerprunk_wait`] yet.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn fingerprunk_stop(search: *const FingerprunkSearch) {
+    // SAFETY: Guaranteed by the caller.
+    if let Some(search) = unsafe { search.as_ref() } {
+        search.stop_handle.stop();
+    }
+}
+
+/// Writes the current progress of the search to `*stats`. Returns whether the search is still
+/// running.
+///
+/// # Safety
+///
+/// `search` must be as for [`fingerprunk_stop`], `stats` must be valid for writes.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn fingerprunk_poll_stats(
+    search: *const FingerprunkSearch,
+    stats: *mut FingerprunkStats,
+) -> bool {
+    // SAFETY: Guaranteed by the caller.
+    let Some(search) = (unsafe { search.as_ref() }) else {
+        return false;
+    };
+    let current = search.stats_handle.stats();
+    if !stats.is_null() {
+        // SAFETY: Guaranteed by the caller.
+        unsafe {
+            stats.write(FingerprunkStats {
+                tried: current.tried,
+                found: current.found,
+                elapsed_secs: current.elapsed.as_secs_f64(),
+                rate: current.rate,
+            });
+        }
+    }
+    !search.search.is_finished()
+}
+
+/// Waits until the search has stopped, by itself or after [`fingerprunk_stop`], and frees it.
+///
+/// Returns 0 if the search succeeded. Otherwise, returns -1 and sets `*error` (if `error` isn't
+/// `NULL`) to a message to be freed with [`fingerprunk_string_free`].
+///
+/// # Safety
+///
+/// `search` must have been returned by [`fingerprunk_start`] and is invalid afterwards. `error`
+/// must be `NULL` or valid for writes.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn fingerprunk_wait(
+    search: *mut FingerprunkSearch,
+    error: *mut *mut c_char,
+) -> c_int {
+    if search.is_null() {
+        return -1;
+    }
+    // SAFETY: Guaranteed by the caller.
+    let search = unsafe { Box::from_raw(search) };
+    let result = match search.search.join() {
+        Ok(result) => result.map_err(anyhow::Error::from),
+        Err(_) => Err(anyhow::anyhow!("The search panicked")),
+    };
+    match result {
+        Ok(_) => 0,
+        Err(err) => {
+            // SAFETY: Guaranteed by the caller.
+            unsafe { set_error(error, &err) };
+            -1
+        }
+    }
+}
+
+/// Frees a string returned by this API.
+///
+/// # Safety
+///
+/// `string` must be `NULL` or have been returned by this API and not been freed before.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn fingerprunk_string_free(string: *mut c_char) {
+    if !string.is_null() {
+        // SAFETY: Guaranteed by the caller.
+        drop(unsafe { CString::from_raw(string) });
+    }
+}
+
+/// Builds the configuration from the arguments of [`fingerprunk_start`].
+///
+/// # Safety
+///
+/// As for [`fingerprunk_start`].
+unsafe fn config(
+    regexes: *const *const c_char,
+    regex_count: usize,
+    stop_after: u64,
+) -> anyhow::Result<Config> {
+    let mut builder = Config::builder().stop_after(NonZeroU64::new(stop_after));
+    if regex_count > 0 {
+        // SAFETY: Guaranteed by the caller.
+        for &regex in unsafe { std::slice::from_raw_parts(regexes, regex_count) } {
+            // SAFETY: Guaranteed by the caller.
+            let regex = unsafe { CStr::from_ptr(regex) }
+                .to_str()
+                .context("Regex is not valid UTF-8")?;
+            builder = builder.regex(regex);
+        }
+    }
+    Ok(builder.build()?)
+}
+
+/// Sets `*error` to the message of `err`, including its causes.
+///
+/// # Safety
+///
+/// `error` must be `NULL` or valid for writes.
+unsafe fn set_error(error: *mut *mut c_char, err: &anyhow::Error) {
+    if error.is_null() {
+        return;
+    }
+    let message = format!("{err:#}").replace('\0', "");
+    let message = CString::new(message).expect("message should not contain NUL bytes");
+    // SAFETY: Guaranteed by the caller.
+    unsafe { error.write(message.into_raw()) };
+}
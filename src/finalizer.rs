@@ -0,0 +1,339 @@
+//! Handling the keys found by the workers: building their certificates, which may be spread over
+//! several threads, and handing them to the key sink one after another.
+
+use std::{
+    collections::BTreeMap,
+    ops::ControlFlow,
+    path::Path,
+    sync::{atomic::Ordering, mpsc},
+};
+
+use anyhow::Context;
+use crossbeam_channel::{Receiver, Sender};
+use fancy_regex::Regex;
+use sequoia_openpgp::Cert;
+use serde_json::json;
+
+use crate::{
+    Error, Fingerprunk, Message, Verbosity,
+    keygen::{self, HexBuffer, SecretKey, SecretSubkey},
+    matcher::Matcher,
+    notify::FoundEvent,
+    observer::Event,
+    sink::{KeySink, MatchMeta},
+};
+
+/// In which order the keys are output if their certificates are built by several threads, see
+/// [`Config::finalizers`](crate::Config::finalizers).
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum OutputOrder {
+    /// In the order the keys were found, holding back keys built early.
+    #[default]
+    Found,
+    /// As soon as their certificates are built.
+    Built,
+}
+
+/// How many subkeys are generated between checks whether the search has stopped.
+const SUBKEY_STOP_INTERVAL: u64 = 256;
+
+/// The search stopped while a subkey was being ground for a found key, which is discarded then.
+#[derive(Debug, thiserror::Error)]
+#[error("The search stopped before a matching subkey was found")]
+struct SubkeyInterrupted;
+
+/// A found key waiting for its certificate.
+#[derive(Debug)]
+pub(crate) struct Found {
+    key: SecretKey,
+    /// Index into [`Config::jobs`](crate::Config::jobs) of the job the key was found for.
+    job: Option<usize>,
+    meta: MatchMeta,
+}
+
+/// Holds back certificates built out of order until those of the keys found before them are
+/// built, too.
+#[derive(Debug, Default)]
+pub(crate) struct Reorder {
+    next: u64,
+    built: BTreeMap<u64, (Box<Found>, anyhow::Result<Cert>)>,
+}
+
+impl Reorder {
+    /// Adds the certificate of the key numbered `seq` and returns the keys to output now.
+    pub(crate) fn push(
+        &mut self,
+        order: OutputOrder,
+        seq: u64,
+        found: Box<Found>,
+        cert: anyhow::Result<Cert>,
+    ) -> Vec<(Box<Found>, anyhow::Result<Cert>)> {
+        if order == OutputOrder::Built {
+            return vec![(found, cert)];
+        }
+
+        self.built.insert(seq, (found, cert));
+        let mut ready = Vec::new();
+        while let Some(next) = self.built.remove(&self.next) {
+            ready.push(next);
+            self.next += 1;
+        }
+        ready
+    }
+}
+
+impl Fingerprunk {
+    /// Finds out what a key matched and where it goes. Returns `None` for keys colliding with a
+    /// key ID to avoid and keys of jobs whose quota has been met in the meantime.
+    pub(crate) fn prepare_found(&self, key: SecretKey) -> Result<Option<Found>, Error> {
+        let fingerprint_hex = self.config.key_format.text(&key);
+        if let Some(avoid_keyids) = &self.config.avoid_keyids
+            && avoid_keyids.contains(&key.keyid())
+        {
+            self.log(
+                Verbosity::Normal,
+                format_args!(
+                    "Discarded {fingerprint_hex}, whose key ID {:X} collides with a key to avoid",
+                    key.keyid()
+                ),
+            );
+            return Ok(None);
+        }
+        #[cfg(feature = "keyserver")]
+        if let Some(url) = &self.config.keyserver {
+            self.check_published(url, &fingerprint_hex, &key.keyid());
+        }
+        let matcher = self.matcher();
+        let pattern = matcher.matching(&fingerprint_hex)?.map(ToString::to_string);
+        let job = pattern.as_ref().and_then(|pattern| {
+            self.config
+                .jobs
+                .iter()
+                .position(|job| job.regex.as_str() == pattern)
+        });
+        let label = pattern.as_ref().and_then(|pattern| {
+            self.config
+                .labeled_regexes
+                .iter()
+                .find(|(_, labeled)| labeled.as_str() == pattern)
+                .map(|(label, _)| label.clone())
+        });
+        let pattern = pattern.unwrap_or_else(|| matcher.to_string());
+        if !self.config.jobs.is_empty() && job.is_none() {
+            // Found by a worker before the job's quota was met
+            return Ok(None);
+        }
+
+        let job_ref = job.map(|job| &self.config.jobs[job]);
+        let meta = MatchMeta {
+            fingerprint: fingerprint_hex,
+            pattern,
+            dir: self.key_dir(job_ref, label.as_deref()),
+            label,
+            job: job_ref.map(|job| job.name.clone()),
+            tried: self.counter_tried.load(Ordering::Relaxed),
+            elapsed: self.started_instant.elapsed(),
+            proof: None,
+        };
+        Ok(Some(Found { key, job, meta }))
+    }
+
+    /// Builds the certificate of a found key, binding the user ID of its job, if any, and a
+    /// subkey matching the subkey regex, if any, and signs the proof message, if any.
+    pub(crate) fn build_found(&self, found: &mut Found) -> anyhow::Result<Cert> {
+        if let Some(message) = &self.config.sign_proof {
+            // Before the key gets encrypted with the password
+            found.meta.proof = Some(keygen::sign_proof(&found.key, message)?);
+        }
+        let user_id = match found.job {
+            Some(job) => Some(self.config.jobs[job].user_id()),
+            None => self.config.user_id.clone(),
+        };
+        let subkey = match &self.config.subkey_regex {
+            Some(regex) => Some(self.grind_subkey(regex, &found.meta.fingerprint)?),
+            None => None,
+        };
+        self.key_to_cert(&found.key, user_id, subkey)
+    }
+
+    /// Generates encryption subkeys for the key with the given fingerprint until the fingerprint
+    /// of one matches `regex`. Fails with [`SubkeyInterrupted`] if the search stops before.
+    fn grind_subkey(&self, regex: &Regex, fingerprint_hex: &str) -> anyhow::Result<SecretSubkey> {
+        let mut hex_buffer: HexBuffer = [0; 64];
+        let mut tried = 0u64;
+        loop {
+            tried += 1;
+            if tried.is_multiple_of(SUBKEY_STOP_INTERVAL) && self.stop.load(Ordering::Relaxed) {
+                return Err(SubkeyInterrupted.into());
+            }
+            let subkey = keygen::generate_subkey(self.config.key_version)?;
+            let subkey_hex = keygen::encode_fingerprint_hex(&subkey.fingerprint(), &mut hex_buffer)
+                .context("The subkey's fingerprint is too long")?;
+            if Matcher::is_match(regex, subkey_hex)? {
+                self.log(
+                    Verbosity::Verbose,
+                    format_args!(
+                        "Found subkey {subkey_hex} for {fingerprint_hex} after {tried} tries"
+                    ),
+                );
+                return Ok(subkey);
+            }
+        }
+    }
+
+    /// Where a found key routed to `dir` goes: its file, `stdout` without an output directory or
+    /// e.g. `coordinator` for keys that Fingerprunk doesn't write out itself.
+    pub(crate) fn key_destination(&self, dir: Option<&Path>, fingerprint_hex: &str) -> String {
+        match (self.routed_to, dir) {
+            (Some(routed_to), _) => routed_to.to_string(),
+            (None, Some(dir)) => dir
+                .join(self.config.key_format.file_name(fingerprint_hex))
+                .display()
+                .to_string(),
+            (None, None) => "stdout".to_string(),
+        }
+    }
+
+    /// Hands a found key to `sink` and counts it. Returns [`ControlFlow::Break`] if the search is
+    /// done then.
+    pub(crate) fn deliver_found(
+        &self,
+        found: Box<Found>,
+        cert: anyhow::Result<Cert>,
+        sink: &mut dyn KeySink,
+        notify_sender: Option<&mpsc::Sender<FoundEvent>>,
+    ) -> Result<ControlFlow<()>, Error> {
+        let Found { job, meta, .. } = *found;
+        let job = job.map(|job| &self.config.jobs[job]);
+        // Keys built in parallel may arrive after their job's quota has been met
+        if job.is_some_and(|job| self.job_found(job) >= job.quota.get()) {
+            return Ok(ControlFlow::Continue(()));
+        }
+        if let Err(err) = &cert
+            && err.is::<SubkeyInterrupted>()
+        {
+            self.log(
+                Verbosity::Normal,
+                format_args!(
+                    "Discarded {}, as the search stopped before its subkey was found",
+                    meta.fingerprint
+                ),
+            );
+            return Ok(ControlFlow::Continue(()));
+        }
+
+        let fingerprint_hex = meta.fingerprint.clone();
+        let pattern = meta.pattern.clone();
+        let label = meta.label.clone();
+        let destination = self.key_destination(meta.dir.as_deref(), &fingerprint_hex);
+        let observed_meta = self.observed().then(|| meta.clone());
+        let flow = cert
+            .and_then(|cert| sink.found(cert, meta))
+            .map_err(|source| Error::Output {
+                fingerprint: fingerprint_hex.clone(),
+                source,
+            })?;
+        // Only keys that were actually emitted are recorded
+        if let Some(audit_log) = self
+            .audit_log
+            .lock()
+            .expect("lock should not be poisoned")
+            .as_mut()
+        {
+            audit_log.record(&fingerprint_hex, &pattern, &destination, self.config_hash())?;
+        }
+
+        #[cfg(feature = "tracing")]
+        tracing::info!(
+            fingerprint = %fingerprint_hex,
+            tried = self.counter_tried.load(Ordering::Relaxed),
+            "key found"
+        );
+        self.log_match(&fingerprint_hex);
+        if self.config.json_logs {
+            self.print_json(
+                "found",
+                json!({
+                    "fingerprint": fingerprint_hex,
+                    "pattern": pattern,
+                    "label": label,
+                    "job": job.map(|job| &job.name),
+                    "tried": self.counter_tried.load(Ordering::Relaxed),
+                }),
+            );
+        }
+        *self
+            .found_per_pattern
+            .lock()
+            .expect("lock should not be poisoned")
+            .entry(pattern.clone())
+            .or_default() += 1;
+        self.found_fingerprints
+            .lock()
+            .expect("lock should not be poisoned")
+            .push(fingerprint_hex.clone());
+        if self.config.bell {
+            self.bell_pending.store(true, Ordering::Relaxed);
+        }
+        if let Some(notify_sender) = notify_sender {
+            let _ = notify_sender.send(FoundEvent {
+                fingerprint: fingerprint_hex,
+                pattern,
+                label,
+                destination,
+                elapsed: self.started_instant.elapsed(),
+                tried: self.counter_tried.load(Ordering::Relaxed),
+            });
+        }
+
+        if let Some(meta) = observed_meta {
+            self.emit(Event::Found(meta));
+        }
+
+        // Increase "found" counter and stop if enough matches have been found
+        let prev = self.counter_found.fetch_add(1, Ordering::Relaxed);
+        if self.config.stop_after.is_some_and(|s| prev + 1 >= s.get()) {
+            return Ok(ControlFlow::Break(()));
+        }
+        if flow.is_break() {
+            self.log(Verbosity::Verbose, "Stopped by the key sink");
+            self.interrupted.store(true, Ordering::Relaxed);
+            return Ok(ControlFlow::Break(()));
+        }
+        if let Some(job) = job {
+            if self.job_found(job) == job.quota.get() {
+                self.log(
+                    Verbosity::Normal,
+                    format_args!("Found all keys for {}", job.name),
+                );
+            }
+            if !self.update_job_matcher() {
+                self.log(Verbosity::Normal, self.jobs_done_message());
+                return Ok(ControlFlow::Break(()));
+            }
+        }
+        Ok(ControlFlow::Continue(()))
+    }
+
+    /// The number of threads building certificates, see
+    /// [`Config::finalizers`](crate::Config::finalizers).
+    pub(crate) fn finalizers(&self) -> usize {
+        self.config
+            .finalizers
+            .map_or(1, |finalizers| finalizers.get())
+    }
+
+    /// Builds the certificates of the keys received from the finalizer and sends them back to it,
+    /// until the finalizer is done.
+    pub(crate) fn finalizer_thread(&self, keys: Receiver<(u64, Found)>, sender: Sender<Message>) {
+        for (seq, mut found) in keys {
+            let cert = self.build_found(&mut found);
+            if sender
+                .send(Message::Built(seq, Box::new(found), cert))
+                .is_err()
+            {
+                break;
+            }
+        }
+    }
+}
@@ -0,0 +1,318 @@
+//! The formats of the keys searched for, see [`Config::key_format`](crate::Config::key_format).
+//!
+//! Whatever the format, the workers generate OpenPGP keys, but for other formats than OpenPGP
+//! they match the patterns against a text derived from the public key instead of the hex of its
+//! fingerprint, like the OpenSSH fingerprint for [`ssh`](crate::ssh), the recipient for
+//! [`age`](crate::age) or the address for [`onion`](crate::onion), and the found keys are written
+//! in the files of that format.
+
+use std::{fmt, ops::Range, str::FromStr, sync::Arc};
+
+use anyhow::bail;
+use fancy_regex::Regex;
+use sequoia_openpgp::{
+    crypto,
+    packet::{
+        Key,
+        key::{KeyParts, KeyRole},
+    },
+};
+
+use crate::{
+    ConfigError, Error, age,
+    estimate::{Estimate, Method},
+    keygen::{self, HexBuffer},
+    matcher::Matcher,
+    onion,
+    sink::{KeySink, OutputSink},
+    ssh,
+};
+
+/// Number of random texts checked when the probability can't be derived from the pattern.
+const SAMPLES: usize = 100_000;
+
+/// The format of the keys searched for, which decides what the patterns are matched against and
+/// how found keys are written.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum KeyFormat {
+    /// OpenPGP certificates, matched by the hex of their fingerprint.
+    #[default]
+    OpenPgp,
+    /// OpenSSH Ed25519 keys, matched by their SHA-256 fingerprint in base64.
+    Ssh,
+    /// age X25519 identities, matched by their Bech32 recipient `age1...`.
+    Age,
+    /// Tor onion service Ed25519 keys, matched by their v3 address in base32 without `.onion`.
+    Onion,
+}
+
+impl KeyFormat {
+    pub const ALL: [Self; 4] = [Self::OpenPgp, Self::Ssh, Self::Age, Self::Onion];
+
+    /// The name of the format in messages, e.g. "SSH".
+    #[must_use]
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::OpenPgp => "OpenPGP",
+            Self::Ssh => "SSH",
+            Self::Age => "age",
+            Self::Onion => "onion",
+        }
+    }
+
+    /// The algorithm of the keys of this format, or `None` if any will do.
+    #[must_use]
+    pub fn algorithm(self) -> Option<&'static str> {
+        match self {
+            Self::OpenPgp => None,
+            Self::Ssh | Self::Onion => Some("Ed25519"),
+            Self::Age => Some("X25519"),
+        }
+    }
+
+    /// The number of characters of the texts the patterns are matched against, for OpenPGP those
+    /// of v4 keys.
+    #[must_use]
+    pub fn text_len(self) -> usize {
+        match self {
+            Self::OpenPgp => 40,
+            Self::Ssh => ssh::FINGERPRINT_LEN,
+            Self::Age => age::RECIPIENT_LEN,
+            Self::Onion => onion::ADDRESS_LEN,
+        }
+    }
+
+    /// The characters the texts consist of, after [`Self::prefix`].
+    fn alphabet(self) -> &'static [u8] {
+        match self {
+            Self::OpenPgp => b"0123456789ABCDEF",
+            Self::Ssh => ssh::BASE64_DIGITS,
+            Self::Age => age::CHARSET,
+            Self::Onion => onion::BASE32_DIGITS,
+        }
+    }
+
+    /// What all texts start with.
+    fn prefix(self) -> &'static str {
+        match self {
+            Self::Age => "age1",
+            Self::OpenPgp | Self::Ssh | Self::Onion => "",
+        }
+    }
+
+    /// Writes the text the patterns are matched against into `buf` and returns it, as the
+    /// workers do for every key. For OpenPGP keys, this is the hex of the fingerprint.
+    ///
+    /// Returns `None` for keys of another algorithm than the format's and fingerprints of
+    /// unknown versions.
+    pub fn encode_text<'a, P: KeyParts, R: KeyRole>(
+        self,
+        key: &Key<P, R>,
+        buf: &'a mut HexBuffer,
+    ) -> Option<&'a str> {
+        match self {
+            Self::OpenPgp => keygen::encode_fingerprint_hex(&key.fingerprint(), buf),
+            Self::Ssh => ssh::encode_fingerprint(key, buf),
+            Self::Age => Some(age::encode_recipient(&age::x25519_public(key)?, buf)),
+            Self::Onion => Some(onion::encode_address(&ssh::ed25519_public(key)?, buf)),
+        }
+    }
+
+    /// The text the patterns are matched against, see [`Self::encode_text`], falling back to the
+    /// hex of the fingerprint.
+    #[must_use]
+    pub fn text<P: KeyParts, R: KeyRole>(self, key: &Key<P, R>) -> String {
+        let mut buf: HexBuffer = [0; 64];
+        match self.encode_text(key, &mut buf) {
+            Some(text) => text.to_string(),
+            None => keygen::fingerprint_hex(key),
+        }
+    }
+
+    /// The name of the file holding the secret key whose text is `text`, or of the directory for
+    /// onion services.
+    #[must_use]
+    pub fn file_name(self, text: &str) -> String {
+        match self {
+            Self::OpenPgp => format!("{text}.asc"),
+            Self::Ssh => ssh::file_name(text),
+            Self::Age => format!("{text}.txt"),
+            Self::Onion => format!("{text}.onion"),
+        }
+    }
+
+    /// The sink writing the found keys if no other is given.
+    pub(crate) fn default_sink(self) -> Box<dyn KeySink> {
+        match self {
+            Self::OpenPgp => Box::new(OutputSink),
+            Self::Ssh => Box::new(ssh::SshSink),
+            Self::Age => Box::new(age::AgeSink),
+            Self::Onion => Box::new(onion::OnionSink),
+        }
+    }
+
+    /// The text of a key whose public key is `public`, for estimates from random texts. For
+    /// OpenPGP, this is the hex of the first 20 bytes, like a v4 fingerprint.
+    fn encode_public<'a>(self, public: &[u8; 32], buf: &'a mut HexBuffer) -> Option<&'a str> {
+        match self {
+            Self::OpenPgp => {
+                const DIGITS: &[u8; 16] = b"0123456789ABCDEF";
+                let hex = &mut buf[..40];
+                for (pair, byte) in hex.chunks_exact_mut(2).zip(public) {
+                    pair[0] = DIGITS[usize::from(byte >> 4)];
+                    pair[1] = DIGITS[usize::from(byte & 0xF)];
+                }
+                std::str::from_utf8(hex).ok()
+            }
+            Self::Ssh => Some(ssh::encode_public(public, buf)),
+            Self::Age => Some(age::encode_recipient(public, buf)),
+            Self::Onion => Some(onion::encode_address(public, buf)),
+        }
+    }
+}
+
+impl fmt::Display for KeyFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::OpenPgp => "openpgp",
+            Self::Ssh => "ssh",
+            Self::Age => "age",
+            Self::Onion => "onion",
+        })
+    }
+}
+
+impl FromStr for KeyFormat {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> anyhow::Result<Self> {
+        match s.to_lowercase().as_str() {
+            "openpgp" | "pgp" => Ok(Self::OpenPgp),
+            "ssh" | "openssh" => Ok(Self::Ssh),
+            "age" => Ok(Self::Age),
+            "onion" | "tor" => Ok(Self::Onion),
+            _ => bail!("Unknown key format {s:?}, expected openpgp, ssh, age or onion"),
+        }
+    }
+}
+
+/// Compiles a regex to match the texts of other formats than OpenPGP with, which, unlike
+/// [`parse_pattern`](crate::pattern::parse_pattern), is case-sensitive and isn't restricted to
+/// hex digits.
+pub fn parse_pattern(pattern: &str) -> Result<Regex, ConfigError> {
+    Regex::new(pattern).map_err(|source| ConfigError::Regex {
+        pattern: pattern.to_string(),
+        source: Box::new(source),
+    })
+}
+
+/// Matches the texts of a format other than OpenPGP against a regex, see [`parse_pattern`].
+///
+/// Unlike a plain regex, it estimates the match probability for the alphabet and length of the
+/// format's texts rather than for hex fingerprints.
+#[derive(Clone, Debug)]
+pub struct FormatPattern {
+    regex: Regex,
+    format: KeyFormat,
+}
+
+impl FormatPattern {
+    pub fn new(pattern: &str, format: KeyFormat) -> Result<Self, ConfigError> {
+        Ok(Self {
+            regex: parse_pattern(pattern)?,
+            format,
+        })
+    }
+
+    /// The probability of the pattern matching if it is an alphanumeric literal, anchored or
+    /// not.
+    fn literal_estimate(&self) -> Option<Estimate> {
+        let pattern = self.regex.as_str();
+        let starts = pattern.starts_with('^');
+        let ends = pattern.ends_with('$') && !pattern.ends_with("\\$");
+        let mut literal = &pattern[usize::from(starts)..pattern.len() - usize::from(ends)];
+        if literal.is_empty() || !literal.bytes().all(|b| b.is_ascii_alphanumeric()) {
+            return None;
+        }
+        if starts {
+            // Anything else but the whole prefix is left to sampling
+            literal = literal.strip_prefix(self.format.prefix())?;
+        }
+        if !literal.bytes().all(|b| self.format.alphabet().contains(&b)) {
+            return Some(Estimate {
+                probability: 0.0,
+                method: Method::Exact,
+            });
+        }
+
+        let n = literal.len();
+        let len = self.format.text_len() - self.format.prefix().len();
+        // The last character may carry fewer bits, which is neglected for patterns ending there
+        let single = (self.format.alphabet().len() as f64).powi(-(n as i32));
+        Some(match (starts, ends) {
+            _ if n > len => Estimate {
+                probability: 0.0,
+                method: Method::Exact,
+            },
+            (true, true) => Estimate {
+                probability: if n == len { single } else { 0.0 },
+                method: Method::Approximate,
+            },
+            (true, false) => Estimate {
+                probability: single,
+                method: Method::Exact,
+            },
+            (false, true) => Estimate {
+                probability: single,
+                method: Method::Approximate,
+            },
+            (false, false) => Estimate {
+                probability: ((len - n + 1) as f64 * single).min(1.0),
+                method: Method::Approximate,
+            },
+        })
+    }
+
+    /// Measures how many texts of random public keys match.
+    fn sampled_estimate(&self) -> Option<Estimate> {
+        let mut public = [0u8; 32];
+        let mut buf: HexBuffer = [0; 64];
+        let mut hits = 0usize;
+        for _ in 0..SAMPLES {
+            crypto::random(&mut public).ok()?;
+            let text = self.format.encode_public(&public, &mut buf)?;
+            if self.regex.is_match(text).ok()? {
+                hits += 1;
+            }
+        }
+
+        (hits > 0).then(|| Estimate {
+            probability: hits as f64 / SAMPLES as f64,
+            method: Method::Sampled,
+        })
+    }
+}
+
+impl Matcher for FormatPattern {
+    fn is_match(&self, text: &str) -> Result<bool, Error> {
+        Matcher::is_match(&self.regex, text)
+    }
+
+    fn find(&self, text: &str) -> Option<Range<usize>> {
+        Matcher::find(&self.regex, text)
+    }
+
+    fn estimate(&self) -> Option<Estimate> {
+        self.literal_estimate().or_else(|| self.sampled_estimate())
+    }
+
+    fn clone_for_worker(&self) -> Option<Arc<dyn Matcher>> {
+        Some(Arc::new(self.clone()))
+    }
+}
+
+impl fmt::Display for FormatPattern {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Display::fmt(&self.regex, f)
+    }
+}
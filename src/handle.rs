@@ -0,0 +1,144 @@
+//! Controlling a search running in the background, e.g. from a GUI.
+
+use std::{
+    sync::{
+        Arc, Mutex, MutexGuard,
+        atomic::{AtomicBool, Ordering},
+        mpsc,
+    },
+    thread::{self, JoinHandle},
+    time::Duration,
+};
+
+use crate::{
+    Error, Fingerprunk, RunSummary, Stats, StatsHandle, StopHandle,
+    sink::{ChannelSink, FoundKey},
+};
+
+/// Handle to a search running in a background thread, see [`Fingerprunk::spawn`].
+///
+/// The handle can be shared between threads, e.g. by a UI thread pausing the search and showing
+/// its stats and another one receiving the found keys. Dropping the handle stops the search
+/// without waiting for it.
+#[derive(Debug)]
+pub struct SearchHandle {
+    keys: Mutex<mpsc::Receiver<FoundKey>>,
+    stop_handle: StopHandle,
+    stats_handle: StatsHandle,
+    paused: Arc<AtomicBool>,
+    search: Mutex<Option<JoinHandle<Result<RunSummary, Error>>>>,
+}
+
+impl SearchHandle {
+    pub(crate) fn spawn(mut fingerprunk: Fingerprunk) -> Self {
+        let (sender, keys) = mpsc::channel();
+        fingerprunk.set_sink(ChannelSink::new(sender));
+        let stop_handle = fingerprunk.stop_handle();
+        let stats_handle = fingerprunk.stats_handle();
+        let paused = Arc::clone(&fingerprunk.paused);
+        let search = thread::Builder::new()
+            .name("search".to_string())
+            .spawn(move || fingerprunk.run())
+            .expect("should spawn the search thread");
+        Self {
+            keys: Mutex::new(keys),
+            stop_handle,
+            stats_handle,
+            paused,
+            search: Mutex::new(Some(search)),
+        }
+    }
+
+    /// Pauses the workers until [`Self::resume`] is called. Keys already found are still handed
+    /// out.
+    pub fn pause(&self) {
+        self.paused.store(true, Ordering::Relaxed);
+    }
+
+    pub fn resume(&self) {
+        self.paused.store(false, Ordering::Relaxed);
+    }
+
+    #[must_use]
+    pub fn is_paused(&self) -> bool {
+        self.paused.load(Ordering::Relaxed)
+    }
+
+    /// Asks the search to stop. It may still find a few keys before it has stopped.
+    pub fn stop(&self) {
+        self.stop_handle.stop();
+    }
+
+    /// Returns a handle to stop the search, e.g. from a signal handler.
+    #[must_use]
+    pub fn stop_handle(&self) -> StopHandle {
+        self.stop_handle.clone()
+    }
+
+    /// The current [`Stats`] of the search.
+    #[must_use]
+    pub fn stats(&self) -> Stats {
+        self.stats_handle.stats()
+    }
+
+    /// Whether the search has stopped, by itself or after [`Self::stop`].
+    #[must_use]
+    pub fn is_finished(&self) -> bool {
+        self.lock_search()
+            .as_ref()
+            .is_none_or(JoinHandle::is_finished)
+    }
+
+    /// Returns the next found key without waiting, e.g. once per frame of a GUI.
+    #[must_use]
+    pub fn try_recv(&self) -> Option<FoundKey> {
+        self.lock_keys().try_recv().ok()
+    }
+
+    /// Waits for the next found key. Returns `None` once the search has stopped and all its keys
+    /// have been received.
+    ///
+    /// Keys are received by one thread at a time, so other threads calling [`Self::try_recv`]
+    /// wait meanwhile.
+    #[must_use]
+    pub fn recv(&self) -> Option<FoundKey> {
+        self.lock_keys().recv().ok()
+    }
+
+    /// Like [`Self::recv`], but gives up after `timeout`.
+    #[must_use]
+    pub fn recv_timeout(&self, timeout: Duration) -> Option<FoundKey> {
+        self.lock_keys().recv_timeout(timeout).ok()
+    }
+
+    /// Waits until the search has stopped and returns its result. Keys not received yet can still
+    /// be received afterwards.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the search panicked or has already been joined.
+    pub fn join(&self) -> Result<RunSummary, Error> {
+        // Taken out of the lock first, so that the other methods don't wait for the search
+        let search = self
+            .lock_search()
+            .take()
+            .expect("search should only be joined once");
+        search
+            .join()
+            .unwrap_or_else(|panic| std::panic::resume_unwind(panic))
+    }
+
+    fn lock_keys(&self) -> MutexGuard<'_, mpsc::Receiver<FoundKey>> {
+        self.keys.lock().expect("lock should not be poisoned")
+    }
+
+    fn lock_search(&self) -> MutexGuard<'_, Option<JoinHandle<Result<RunSummary, Error>>>> {
+        self.search.lock().expect("lock should not be poisoned")
+    }
+}
+
+impl Drop for SearchHandle {
+    fn drop(&mut self) {
+        self.stop_handle.stop();
+    }
+}
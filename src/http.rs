@@ -0,0 +1,97 @@
+//! HTTP API for monitoring and controlling a search, enabled with the `http-api` feature.
+//!
+//! Endpoints:
+//!
+//! - `GET /status`: counters, rate and the current patterns
+//! - `GET /keys`: fingerprints of the found keys (never any secret key material)
+//! - `POST /pause`, `POST /resume`, `POST /stop`
+//!
+//! There is no authentication, so the API should only listen on trusted interfaces.
+
+use std::{sync::atomic::Ordering, time::Duration};
+
+use anyhow::anyhow;
+use crossbeam_channel::Sender;
+use serde_json::{Value, json};
+use tiny_http::{Header, Method, Request, Response, Server};
+
+use crate::{Fingerprunk, Message, Verbosity};
+
+/// How often the HTTP thread checks whether the search has stopped.
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Starts listening on `address`, e.g. `127.0.0.1:8080`.
+pub(crate) fn listen(address: &str) -> anyhow::Result<Server> {
+    Server::http(address).map_err(|err| anyhow!("Failed to listen on {address}: {err}"))
+}
+
+impl Fingerprunk {
+    /// Serves HTTP requests until the search stops.
+    pub(crate) fn http_thread(&self, server: &Server, sender: Sender<Message>) {
+        while !self.stop.load(Ordering::Relaxed) {
+            match server.recv_timeout(POLL_INTERVAL) {
+                Ok(Some(request)) => self.handle_http_request(request, &sender),
+                Ok(None) => {}
+                Err(err) => self.log(
+                    Verbosity::Normal,
+                    format_args!("Failed to receive HTTP request: {err}"),
+                ),
+            }
+        }
+    }
+
+    fn handle_http_request(&self, request: Request, sender: &Sender<Message>) {
+        let (status, body) = match (request.method(), request.url()) {
+            (Method::Get, "/status") => (200, self.http_status()),
+            (Method::Get, "/keys") => {
+                let found_fingerprints = self
+                    .found_fingerprints
+                    .lock()
+                    .expect("lock should not be poisoned");
+                let keys: Vec<_> = found_fingerprints
+                    .iter()
+                    .map(|fingerprint| json!({ "fingerprint": fingerprint }))
+                    .collect();
+                (200, Value::from(keys))
+            }
+            (Method::Post, "/pause" | "/resume") => {
+                self.paused
+                    .store(request.url() == "/pause", Ordering::Relaxed);
+                (200, json!({ "ok": true }))
+            }
+            (Method::Post, "/stop") => {
+                let _ = sender.send(Message::Stop);
+                (200, json!({ "ok": true }))
+            }
+            (_, "/status" | "/keys" | "/pause" | "/resume" | "/stop") => {
+                (405, json!({ "error": "method not allowed" }))
+            }
+            _ => (404, json!({ "error": "not found" })),
+        };
+
+        let content_type =
+            Header::from_bytes("Content-Type", "application/json").expect("header should be valid");
+        let response = Response::from_string(body.to_string())
+            .with_status_code(status)
+            .with_header(content_type);
+        if let Err(err) = request.respond(response) {
+            self.log(
+                Verbosity::Verbose,
+                format_args!("Failed to send HTTP response: {err}"),
+            );
+        }
+    }
+
+    fn http_status(&self) -> Value {
+        let elapsed = self.started_instant.elapsed().as_secs_f64();
+        let tried = self.counter_tried.load(Ordering::Relaxed);
+        json!({
+            "tried": tried,
+            "found": self.counter_found.load(Ordering::Relaxed),
+            "rate": tried as f64 / elapsed,
+            "elapsed_secs": elapsed,
+            "paused": self.paused.load(Ordering::Relaxed),
+            "pattern": self.matcher().to_string(),
+        })
+    }
+}
@@ -0,0 +1,226 @@
+//! Scheduling the workers on CPUs with performance and efficiency cores, like Intel's hybrid CPUs
+//! or ARM's big.LITTLE.
+//!
+//! Efficiency cores try keys at a fraction of the rate of performance cores. Depending on the
+//! [`CorePolicy`], the workers either leave them alone or get batches sized by the speed of their
+//! core, so that a batch takes about equally long on every core.
+
+use std::{fs, path::Path, str::FromStr};
+
+use anyhow::bail;
+
+use crate::topology::{parse_cpu_list, read_trimmed};
+
+/// CPUs of the performance cores of Intel's hybrid CPUs.
+const INTEL_CORE_CPUS: &str = "/sys/devices/cpu_core/cpus";
+/// CPUs of the efficiency cores of Intel's hybrid CPUs.
+const INTEL_ATOM_CPUS: &str = "/sys/devices/cpu_atom/cpus";
+const CPUS_DIR: &str = "/sys/devices/system/cpu";
+
+/// Assumed speed of efficiency cores relative to performance cores if neither their capacity nor
+/// their frequency is known.
+const DEFAULT_EFFICIENCY_SPEED: f64 = 0.5;
+
+/// Which cores the workers run on, see [`Config::cores`](crate::Config::cores).
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum CorePolicy {
+    /// All cores, leaving the scheduling to the operating system as on other CPUs.
+    #[default]
+    All,
+    /// Only the performance cores, with one worker per CPU of them unless the number of threads
+    /// is given.
+    Performance,
+    /// All cores, with every worker pinned to a CPU and the batches of the workers on efficiency
+    /// cores shrunk by their speed.
+    Weighted,
+}
+
+impl FromStr for CorePolicy {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> anyhow::Result<Self> {
+        match s {
+            "all" => Ok(Self::All),
+            "performance" => Ok(Self::Performance),
+            "weighted" => Ok(Self::Weighted),
+            _ => bail!("Unknown core policy {s:?}, expected all, performance or weighted"),
+        }
+    }
+}
+
+/// The CPUs of the performance and efficiency cores of this machine.
+#[derive(Clone, Debug)]
+pub(crate) struct CoreTypes {
+    pub(crate) performance: Vec<usize>,
+    pub(crate) efficiency: Vec<usize>,
+    /// Speed of the efficiency cores relative to the performance cores, between 0 and 1.
+    efficiency_speed: f64,
+}
+
+impl CoreTypes {
+    /// Detects the kinds of cores of this machine.
+    ///
+    /// Returns `None` if all cores are of the same kind or their kinds are unknown, e.g. on other
+    /// systems than Linux.
+    pub(crate) fn detect() -> anyhow::Result<Option<Self>> {
+        let (performance, efficiency) = match (
+            read_cpu_list(Path::new(INTEL_CORE_CPUS))?,
+            read_cpu_list(Path::new(INTEL_ATOM_CPUS))?,
+        ) {
+            (Some(performance), Some(efficiency)) => (performance, efficiency),
+            // Other CPUs only tell the cores apart by their capacity
+            _ => match split_by_capacity()? {
+                Some(split) => split,
+                None => return Ok(None),
+            },
+        };
+        if performance.is_empty() || efficiency.is_empty() {
+            return Ok(None);
+        }
+
+        let efficiency_speed = match (mean_speed(&performance), mean_speed(&efficiency)) {
+            (Some(performance), Some(efficiency)) if efficiency < performance => {
+                efficiency / performance
+            }
+            _ => DEFAULT_EFFICIENCY_SPEED,
+        };
+        Ok(Some(Self {
+            performance,
+            efficiency,
+            efficiency_speed,
+        }))
+    }
+}
+
+/// Which CPU every worker runs on and how many keys it tries per batch.
+#[derive(Clone, Debug)]
+pub(crate) struct CorePlacement {
+    /// CPU, batch size and whether it is an efficiency core of every worker.
+    workers: Vec<(usize, usize, bool)>,
+}
+
+impl CorePlacement {
+    /// Spreads `workers` workers over the cores allowed by `policy`, one per CPU as far as there
+    /// are enough, giving those on efficiency cores smaller batches than `batch_size` if
+    /// weighted.
+    pub(crate) fn new(
+        policy: CorePolicy,
+        types: &CoreTypes,
+        workers: usize,
+        batch_size: usize,
+    ) -> Self {
+        let efficiency_batch_size = match policy {
+            CorePolicy::Weighted => {
+                ((batch_size as f64 * types.efficiency_speed).round() as usize).max(1)
+            }
+            _ => batch_size,
+        };
+        let performance = types
+            .performance
+            .iter()
+            .map(|&cpu| (cpu, batch_size, false));
+        let efficiency = types
+            .efficiency
+            .iter()
+            .map(|&cpu| (cpu, efficiency_batch_size, true));
+        let cpus: Vec<_> = match policy {
+            CorePolicy::Performance => performance.collect(),
+            // Performance cores first, so that a few workers run on the fastest cores
+            CorePolicy::All | CorePolicy::Weighted => performance.chain(efficiency).collect(),
+        };
+        Self {
+            workers: cpus.into_iter().cycle().take(workers).collect(),
+        }
+    }
+
+    /// Pins the calling thread to the CPU of worker `num`. Returns whether that worked.
+    pub(crate) fn pin(&self, num: usize) -> bool {
+        let (cpu, _, _) = self.workers[num];
+        core_affinity::set_for_current(core_affinity::CoreId { id: cpu })
+    }
+
+    /// The number of keys worker `num` tries per batch.
+    pub(crate) fn batch_size(&self, num: usize) -> usize {
+        self.workers[num].1
+    }
+
+    /// The placement for the startup log, e.g. `8 workers on performance cores, 8 on efficiency
+    /// cores with batches of 6`.
+    pub(crate) fn describe(&self) -> String {
+        let efficiency: Vec<_> = self
+            .workers
+            .iter()
+            .filter(|(_, _, efficiency)| *efficiency)
+            .collect();
+        let performance = self.workers.len() - efficiency.len();
+        match efficiency.first() {
+            Some((_, batch_size, _)) => format!(
+                "{performance} workers on performance cores, {} on efficiency cores with \
+                 batches of {batch_size}",
+                efficiency.len()
+            ),
+            None => format!("{performance} workers on performance cores"),
+        }
+    }
+}
+
+/// Reads a CPU list, returning `None` if the file doesn't exist.
+fn read_cpu_list(path: &Path) -> anyhow::Result<Option<Vec<usize>>> {
+    if !path.exists() {
+        return Ok(None);
+    }
+    parse_cpu_list(&read_trimmed(path)?).map(Some)
+}
+
+/// Splits the CPUs into those with the highest capacity and the others, if all CPUs have one.
+fn split_by_capacity() -> anyhow::Result<Option<(Vec<usize>, Vec<usize>)>> {
+    let Ok(entries) = fs::read_dir(CPUS_DIR) else {
+        return Ok(None);
+    };
+
+    let mut capacities = Vec::new();
+    for entry in entries {
+        let entry = entry?;
+        let name = entry.file_name();
+        let Some(cpu) = name
+            .to_str()
+            .and_then(|name| name.strip_prefix("cpu"))
+            .and_then(|cpu| cpu.parse::<usize>().ok())
+        else {
+            continue;
+        };
+        let Some(capacity) = read_number(&entry.path().join("cpu_capacity")) else {
+            return Ok(None);
+        };
+        capacities.push((cpu, capacity));
+    }
+    capacities.sort_unstable_by_key(|&(cpu, _)| cpu);
+
+    let Some(max) = capacities.iter().map(|&(_, capacity)| capacity).max() else {
+        return Ok(None);
+    };
+    let (performance, efficiency): (Vec<_>, Vec<_>) = capacities
+        .into_iter()
+        .partition(|&(_, capacity)| capacity == max);
+    Ok(Some((
+        performance.into_iter().map(|(cpu, _)| cpu).collect(),
+        efficiency.into_iter().map(|(cpu, _)| cpu).collect(),
+    )))
+}
+
+/// The mean capacity or, if unknown, maximum frequency of `cpus`.
+fn mean_speed(cpus: &[usize]) -> Option<f64> {
+    let speeds = cpus
+        .iter()
+        .map(|cpu| {
+            let dir = Path::new(CPUS_DIR).join(format!("cpu{cpu}"));
+            read_number(&dir.join("cpu_capacity"))
+                .or_else(|| read_number(&dir.join("cpufreq/cpuinfo_max_freq")))
+        })
+        .collect::<Option<Vec<_>>>()?;
+    Some(speeds.iter().sum::<u64>() as f64 / speeds.len() as f64)
+}
+
+fn read_number(path: &Path) -> Option<u64> {
+    fs::read_to_string(path).ok()?.trim().parse().ok()
+}
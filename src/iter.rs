@@ -0,0 +1,68 @@
+//! Iterating over the found keys while the search runs in the background.
+
+use std::{
+    sync::mpsc,
+    thread::{self, JoinHandle},
+};
+
+use crate::{Error, Fingerprunk, RunSummary, StopHandle, sink::ChannelSink, sink::FoundKey};
+
+/// Iterator over the keys found by a search running in a background thread, see
+/// [`Fingerprunk::find_iter`].
+///
+/// The iterator ends when the search does; [`Self::finish`] tells whether it succeeded. Dropping
+/// the iterator stops the search and waits for it.
+#[derive(Debug)]
+pub struct FindIter {
+    keys: mpsc::Receiver<FoundKey>,
+    stop_handle: StopHandle,
+    search: Option<JoinHandle<Result<RunSummary, Error>>>,
+}
+
+impl FindIter {
+    pub(crate) fn spawn(mut fingerprunk: Fingerprunk) -> Self {
+        let (sender, keys) = mpsc::channel();
+        fingerprunk.set_sink(ChannelSink::new(sender));
+        let stop_handle = fingerprunk.stop_handle();
+        let search = thread::Builder::new()
+            .name("search".to_string())
+            .spawn(move || fingerprunk.run())
+            .expect("should spawn the search thread");
+        Self {
+            keys,
+            stop_handle,
+            search: Some(search),
+        }
+    }
+
+    /// Stops the search, waits for it and returns its result. Keys found in the meantime are
+    /// handed to nobody.
+    pub fn finish(mut self) -> Result<RunSummary, Error> {
+        self.stop_handle.stop();
+        self.join().expect("search should only be joined once")
+    }
+
+    fn join(&mut self) -> Option<Result<RunSummary, Error>> {
+        self.search.take().map(|search| {
+            search
+                .join()
+                .unwrap_or_else(|panic| std::panic::resume_unwind(panic))
+        })
+    }
+}
+
+impl Iterator for FindIter {
+    type Item = FoundKey;
+
+    fn next(&mut self) -> Option<FoundKey> {
+        self.keys.recv().ok()
+    }
+}
+
+impl Drop for FindIter {
+    fn drop(&mut self) {
+        self.stop_handle.stop();
+        // Errors can only be observed through `finish`
+        let _ = self.join();
+    }
+}
@@ -0,0 +1,232 @@
+//! Batch runs finding keys for several identities at once, e.g. for a whole team.
+//!
+//! Every job is a search of its own with its own pattern, quota and output directory, but all
+//! jobs share the worker threads: each generated key is checked against every unfinished job,
+//! which is much cheaper than running a process per job.
+//!
+//! Jobs with a higher priority are matched first, so a key matching several jobs goes to the most
+//! wanted one, and are listed first in the status.
+//!
+//! A jobs file is a CSV file with a header line naming its columns: `name` and `pattern` are
+//! required, `email`, `quota` (the number of keys to find, 1 by default), `priority` (0 by
+//! default) and `output` (the directory for the job's keys) are optional. Fields containing
+//! commas can be quoted with `"`, in which a literal `"` is written as `""`. Empty lines and
+//! lines starting with `#` are ignored.
+//!
+//! ```text
+//! name,email,pattern,quota
+//! Alice,alice@example.org,^A11CE,1
+//! Bob,bob@example.org,"^B0B[0-9]{2,}",2
+//! ```
+
+use std::{
+    fmt::Write as _,
+    fs,
+    num::NonZeroU64,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{Context, anyhow, bail};
+use fancy_regex::Regex;
+
+use crate::pattern::parse_pattern;
+
+/// An identity to find keys for.
+#[derive(Clone, Debug)]
+pub struct Job {
+    /// Name of the identity, which also names its default output directory.
+    pub name: String,
+    pub email: Option<String>,
+    pub regex: Regex,
+    /// Number of keys to find for this identity.
+    pub quota: NonZeroU64,
+    /// Jobs with a higher priority are matched first.
+    pub priority: i64,
+    /// Directory to write the keys to instead of the job's subdirectory of
+    /// [`Config::output_dir`](crate::Config::output_dir).
+    pub output: Option<PathBuf>,
+}
+
+impl Job {
+    /// The user ID attached to the keys found for this job, e.g. `Alice <alice@example.org>`.
+    #[must_use]
+    pub fn user_id(&self) -> String {
+        let mut user_id = self.name.clone();
+        if let Some(email) = &self.email {
+            write!(user_id, " <{email}>").expect("should write into string without error");
+        }
+        user_id
+    }
+}
+
+const COLUMNS: [&str; 6] = ["name", "email", "pattern", "quota", "priority", "output"];
+
+/// Reads a jobs file as described in the [module documentation](self).
+pub fn read_jobs_file(path: &Path) -> anyhow::Result<Vec<Job>> {
+    let content = fs::read_to_string(path)
+        .with_context(|| format!("Failed to read jobs file {}", path.display()))?;
+
+    let mut lines = content
+        .lines()
+        .enumerate()
+        .map(|(num, line)| (num + 1, line.trim()))
+        .filter(|(_, line)| !line.is_empty() && !line.starts_with('#'));
+
+    let (_, header) = lines
+        .next()
+        .ok_or_else(|| anyhow!("Jobs file {} is empty", path.display()))?;
+    let header = split_fields(header)
+        .with_context(|| format!("Invalid header in jobs file {}", path.display()))?;
+    let column = |name: &str| header.iter().position(|column| column == name);
+    if let Some(unknown) = header
+        .iter()
+        .find(|column| !COLUMNS.contains(&column.as_str()))
+    {
+        bail!("Unknown column {unknown:?} in jobs file {}", path.display());
+    }
+    let (Some(name_column), Some(pattern_column)) = (column("name"), column("pattern")) else {
+        bail!(
+            "Jobs file {} needs the columns name and pattern",
+            path.display()
+        );
+    };
+    let email_column = column("email");
+    let quota_column = column("quota");
+    let priority_column = column("priority");
+    let output_column = column("output");
+
+    let mut jobs: Vec<Job> = Vec::new();
+    for (num, line) in lines {
+        let context = || format!("Invalid job in {} line {num}", path.display());
+        let fields = split_fields(line).with_context(context)?;
+        if fields.len() != header.len() {
+            return Err(anyhow!(
+                "Expected {} fields, found {}",
+                header.len(),
+                fields.len()
+            ))
+            .with_context(context);
+        }
+        let field = |column: Option<usize>| {
+            column
+                .map(|column| fields[column].as_str())
+                .filter(|field| !field.is_empty())
+        };
+
+        let name = field(Some(name_column))
+            .ok_or_else(|| anyhow!("Missing name"))
+            .with_context(context)?;
+        // The name is used as a directory name
+        if name.starts_with('.') || name.contains(['/', '\\']) {
+            return Err(anyhow!("Invalid name {name:?}")).with_context(context);
+        }
+        let pattern = field(Some(pattern_column))
+            .ok_or_else(|| anyhow!("Missing pattern"))
+            .with_context(context)?;
+        let regex = parse_pattern(pattern).with_context(context)?;
+        let quota = match field(quota_column) {
+            Some(quota) => quota
+                .parse()
+                .with_context(|| format!("Invalid quota {quota:?}"))
+                .with_context(context)?,
+            None => NonZeroU64::MIN,
+        };
+        let priority = match field(priority_column) {
+            Some(priority) => priority
+                .parse()
+                .with_context(|| format!("Invalid priority {priority:?}"))
+                .with_context(context)?,
+            None => 0,
+        };
+
+        // Jobs are told apart by their names in the output and by their patterns when counting
+        if let Some(other) = jobs
+            .iter()
+            .find(|job| job.name == name || job.regex.as_str() == pattern)
+        {
+            return Err(anyhow!(
+                "Name or pattern already used by job {:?}",
+                other.name
+            ))
+            .with_context(context);
+        }
+        jobs.push(Job {
+            name: name.to_string(),
+            email: field(email_column).map(ToString::to_string),
+            regex,
+            quota,
+            priority,
+            output: field(output_column).map(PathBuf::from),
+        });
+    }
+
+    if jobs.is_empty() {
+        bail!("Jobs file {} contains no jobs", path.display());
+    }
+    Ok(jobs)
+}
+
+/// Splits a CSV line into its fields, removing quotes.
+fn split_fields(line: &str) -> anyhow::Result<Vec<String>> {
+    let mut fields = Vec::new();
+    let mut chars = line.chars().peekable();
+    loop {
+        let mut field = String::new();
+        if chars.peek() == Some(&'"') {
+            chars.next();
+            loop {
+                match chars.next() {
+                    Some('"') if chars.peek() == Some(&'"') => {
+                        chars.next();
+                        field.push('"');
+                    }
+                    Some('"') => break,
+                    Some(c) => field.push(c),
+                    None => bail!("Unterminated quote"),
+                }
+            }
+            if !matches!(chars.peek(), Some(',') | None) {
+                bail!("Unexpected character after quoted field");
+            }
+        } else {
+            while let Some(c) = chars.next_if(|c| *c != ',') {
+                field.push(c);
+            }
+        }
+        fields.push(field.trim().to_string());
+
+        if chars.next().is_none() {
+            return Ok(fields);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn split_fields_trims_plain_fields() {
+        assert_eq!(split_fields("a, b ,c").unwrap(), ["a", "b", "c"]);
+        assert_eq!(split_fields("").unwrap(), [""]);
+        assert_eq!(split_fields("a,,").unwrap(), ["a", "", ""]);
+    }
+
+    #[test]
+    fn split_fields_unquotes_quoted_fields() {
+        assert_eq!(
+            split_fields(r#""Doe, Jane",x"#).unwrap(),
+            ["Doe, Jane", "x"]
+        );
+        assert_eq!(
+            split_fields(r#""say ""hi""","#).unwrap(),
+            [r#"say "hi""#, ""]
+        );
+    }
+
+    #[test]
+    fn split_fields_rejects_broken_quotes() {
+        assert!(split_fields(r#""open"#).is_err());
+        assert!(split_fields(r#"a,"b"c"#).is_err());
+    }
+}
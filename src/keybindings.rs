@@ -0,0 +1,78 @@
+//! Keyboard shortcuts while the status is redrawn in place.
+
+use std::{io, sync::atomic::Ordering, thread::Thread, time::Duration};
+
+use crossbeam_channel::Sender;
+use crossterm::{
+    event::{self, Event, KeyCode, KeyEventKind, KeyModifiers},
+    terminal,
+};
+
+use crate::{Fingerprunk, Message, Verbosity};
+
+/// How often the keybindings thread checks whether the search has stopped.
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+impl Fingerprunk {
+    /// Handles key presses until the search stops.
+    ///
+    /// The terminal is put into raw mode meanwhile so that keys are read without waiting for
+    /// Enter. Errors only disable the keybindings, the search continues either way.
+    pub(crate) fn keybindings_thread(&self, status_displayer: &Thread, sender: Sender<Message>) {
+        if let Err(err) = terminal::enable_raw_mode() {
+            self.log(
+                Verbosity::Verbose,
+                format_args!("Failed to enable keybindings: {err}"),
+            );
+            return;
+        }
+
+        let result = self.keybindings_loop(status_displayer, &sender);
+        let _ = terminal::disable_raw_mode();
+
+        if let Err(err) = result {
+            self.log(
+                Verbosity::Verbose,
+                format_args!("Failed to read key presses: {err}"),
+            );
+        }
+    }
+
+    fn keybindings_loop(
+        &self,
+        status_displayer: &Thread,
+        sender: &Sender<Message>,
+    ) -> io::Result<()> {
+        while !self.stop.load(Ordering::Relaxed) {
+            if !event::poll(POLL_INTERVAL)? {
+                continue;
+            }
+            let Event::Key(key) = event::read()? else {
+                continue;
+            };
+            if key.kind != KeyEventKind::Press {
+                continue;
+            }
+
+            match key.code {
+                KeyCode::Char('q') => {
+                    let _ = sender.send(Message::Stop);
+                }
+                // Raw mode swallows the SIGINT, so handle Ctrl-C ourselves.
+                KeyCode::Char('c') if key.modifiers.contains(KeyModifiers::CONTROL) => {
+                    let _ = sender.send(Message::Stop);
+                }
+                KeyCode::Char('p') => {
+                    let paused = !self.paused.fetch_xor(true, Ordering::Relaxed);
+                    self.log(Verbosity::Normal, if paused { "Paused" } else { "Resumed" });
+                    status_displayer.unpark();
+                }
+                KeyCode::Char('s') => status_displayer.unpark(),
+                KeyCode::Enter => self.bell_pending.store(false, Ordering::Relaxed),
+                _ => {}
+            }
+        }
+
+        Ok(())
+    }
+}
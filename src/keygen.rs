@@ -0,0 +1,400 @@
+//! Generating the candidate keys and the certificates of found keys.
+//!
+//! Every worker thread owns a [`KeyGenerator`] creating the keys whose fingerprints are checked.
+//! By default, these are fresh Ed25519 keys from [`Ed25519Generator`], or [`Ed25519V6Generator`]
+//! for v6 keys (see [`Config::key_version`](crate::Config::key_version)), and X25519 keys from
+//! [`X25519Generator`] for age identities; other algorithms, keys derived from a seed or keys
+//! generated elsewhere can be plugged in with
+//! [`Fingerprunk::set_key_generator`](crate::Fingerprunk::set_key_generator).
+//!
+//! The functions of this module can also be used on their own, e.g. to build a certificate like
+//! Fingerprunk does for a key found by other means.
+
+use std::{fmt, str::FromStr};
+
+use anyhow::{Context, bail};
+
+use sequoia_openpgp::{
+    Cert, Fingerprint, Packet, Profile, armor,
+    crypto::{KeyPair, Password},
+    packet::{
+        Key, UserID,
+        key::{Key4, Key6, KeyParts, KeyRole, PrimaryRole, SecretParts, SubordinateRole},
+        prelude::SignatureBuilder,
+        signature::subpacket::NotationDataFlags,
+    },
+    policy::StandardPolicy,
+    serialize::Serialize,
+    types::{Curve, HashAlgorithm, KeyFlags, SignatureType, SymmetricAlgorithm},
+};
+
+use crate::format::KeyFormat;
+
+/// A primary key with its secret.
+pub type SecretKey = Key<SecretParts, PrimaryRole>;
+
+/// A subkey with its secret.
+pub type SecretSubkey = Key<SecretParts, SubordinateRole>;
+
+/// The version of the keys searched for, which decides the length of their fingerprints and
+/// where the key ID is in them.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum KeyVersion {
+    /// RFC 4880 keys with 160-bit SHA-1 fingerprints ending in the key ID, which all OpenPGP
+    /// implementations support.
+    #[default]
+    V4,
+    /// RFC 9580 keys with 256-bit SHA-256 fingerprints starting with the key ID.
+    V6,
+}
+
+impl KeyVersion {
+    pub const ALL: [Self; 2] = [Self::V4, Self::V6];
+
+    /// The number of hex digits of the fingerprints.
+    #[must_use]
+    pub fn fingerprint_hex_len(self) -> usize {
+        match self {
+            Self::V4 => 40,
+            Self::V6 => 64,
+        }
+    }
+}
+
+impl fmt::Display for KeyVersion {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::V4 => "v4",
+            Self::V6 => "v6",
+        })
+    }
+}
+
+impl FromStr for KeyVersion {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> anyhow::Result<Self> {
+        match s.trim_start_matches(['v', 'V']) {
+            "4" => Ok(Self::V4),
+            "6" => Ok(Self::V6),
+            _ => bail!("Unknown key version {s:?}, expected 4 or 6"),
+        }
+    }
+}
+
+/// A generated key whose fingerprint is checked against the patterns.
+#[derive(Clone, Debug)]
+pub struct Candidate {
+    pub key: SecretKey,
+    pub fingerprint: Fingerprint,
+}
+
+impl Candidate {
+    pub fn new(key: SecretKey) -> Self {
+        let fingerprint = key.fingerprint();
+        Self { key, fingerprint }
+    }
+}
+
+/// Creates candidate keys for one worker thread.
+///
+/// Errors are retried a few times with growing pauses in between. Errors that persist, or too
+/// many of them over the search, end it with
+/// [`Error::KeyGeneration`](crate::Error::KeyGeneration).
+pub trait KeyGenerator: Send {
+    fn generate(&mut self) -> anyhow::Result<Candidate>;
+}
+
+impl<G: KeyGenerator + ?Sized> KeyGenerator for Box<G> {
+    fn generate(&mut self) -> anyhow::Result<Candidate> {
+        (**self).generate()
+    }
+}
+
+/// Generates random Ed25519 keys, the default.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Ed25519Generator;
+
+impl KeyGenerator for Ed25519Generator {
+    fn generate(&mut self) -> anyhow::Result<Candidate> {
+        generate_candidate()
+    }
+}
+
+/// Generates random X25519 keys, the default for
+/// [`KeyFormat::Age`](crate::format::KeyFormat::Age).
+#[derive(Clone, Copy, Debug, Default)]
+pub struct X25519Generator;
+
+impl KeyGenerator for X25519Generator {
+    fn generate(&mut self) -> anyhow::Result<Candidate> {
+        Ok(Candidate::new(Key::V4(Key4::generate_x25519()?)))
+    }
+}
+
+/// Generates random v6 Ed25519 keys, the default for [`KeyVersion::V6`].
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Ed25519V6Generator;
+
+impl KeyGenerator for Ed25519V6Generator {
+    fn generate(&mut self) -> anyhow::Result<Candidate> {
+        Ok(Candidate::new(Key::V6(Key6::generate_ed25519()?)))
+    }
+}
+
+/// Generates a random Ed25519 key like the workers do by default.
+pub fn generate_candidate() -> anyhow::Result<Candidate> {
+    Ok(Candidate::new(Key::V4(Key4::generate_ecc(
+        true,
+        Curve::Ed25519,
+    )?)))
+}
+
+/// Generates a random encryption subkey for a primary key of `version`: a Curve25519 ECDH key for
+/// v4, which GnuPG understands, and an X25519 key for v6.
+pub fn generate_subkey(version: KeyVersion) -> anyhow::Result<SecretSubkey> {
+    Ok(match version {
+        KeyVersion::V4 => Key::V4(Key4::generate_ecc(false, Curve::Cv25519)?),
+        KeyVersion::V6 => Key::V6(Key6::generate_x25519()?),
+    })
+}
+
+/// The fingerprint of a key in uppercase hex without spaces, which is what patterns are matched
+/// against.
+#[must_use]
+pub fn fingerprint_hex<P: KeyParts, R: KeyRole>(key: &Key<P, R>) -> String {
+    format!("{:X}", key.fingerprint())
+}
+
+/// Buffer for [`encode_fingerprint_hex`], large enough for the hex of a v6 fingerprint.
+pub type HexBuffer = [u8; 64];
+
+/// Writes a fingerprint in uppercase hex into `buf` and returns it, like [`fingerprint_hex`] but
+/// without allocating or going through the formatting machinery, as the workers do for every key.
+///
+/// Returns `None` for fingerprints of unknown versions that don't fit into the buffer.
+pub fn encode_fingerprint_hex<'a>(
+    fingerprint: &Fingerprint,
+    buf: &'a mut HexBuffer,
+) -> Option<&'a str> {
+    const DIGITS: &[u8; 16] = b"0123456789ABCDEF";
+
+    let bytes = fingerprint.as_bytes();
+    let hex = buf.get_mut(..bytes.len() * 2)?;
+    for (pair, byte) in hex.chunks_exact_mut(2).zip(bytes) {
+        pair[0] = DIGITS[usize::from(byte >> 4)];
+        pair[1] = DIGITS[usize::from(byte & 0xF)];
+    }
+    Some(std::str::from_utf8(hex).expect("hex digits should be valid UTF-8"))
+}
+
+/// Name of the notations holding the identity proofs of Keyoxide profiles.
+pub const PROOF_NOTATION: &str = "proof@ariadne.id";
+
+/// Options for [`build_cert`].
+#[derive(Clone, Debug, Default)]
+#[non_exhaustive]
+pub struct CertOptions {
+    /// User ID to bind to the key as primary user ID, e.g. `Alice <alice@example.org>`.
+    pub user_id: Option<String>,
+    /// Password to encrypt the secret key with.
+    pub password: Option<Password>,
+    /// Subkey to bind to the key for encryption, e.g. from [`generate_subkey`].
+    pub subkey: Option<SecretSubkey>,
+    /// Key certifying the user ID, in addition to the key's own binding signature.
+    pub certifier: Option<Certifier>,
+    /// Identity proofs as Keyoxide reads them, e.g. `https://github.com/alice`, each added to the
+    /// self-signatures as `proof@ariadne.id` notation.
+    pub proofs: Vec<String>,
+}
+
+/// A key certifying the user IDs of other keys, e.g. an organization's CA key, so that those who
+/// trust it trust the certified keys, too.
+#[derive(Clone)]
+pub struct Certifier(KeyPair);
+
+impl Certifier {
+    /// Takes the certification key of `cert`, which must have its secret, decrypting it with the
+    /// password from `password` if it is encrypted.
+    pub fn from_cert(
+        cert: &Cert,
+        password: impl FnOnce() -> anyhow::Result<Password>,
+    ) -> anyhow::Result<Self> {
+        let policy = StandardPolicy::new();
+        let key = cert
+            .keys()
+            .with_policy(&policy, None)
+            .alive()
+            .revoked(false)
+            .for_certification()
+            .secret()
+            .next()
+            .with_context(|| {
+                format!(
+                    "{:X} has no usable certification key with its secret",
+                    cert.fingerprint()
+                )
+            })?;
+        let key = key.key().clone();
+        let key = if key.has_unencrypted_secret() {
+            key
+        } else {
+            let fingerprint = key.fingerprint();
+            key.decrypt_secret(&password()?)
+                .with_context(|| format!("Failed to decrypt certification key {fingerprint:X}"))?
+        };
+        Ok(Self(key.into_keypair()?))
+    }
+
+    /// The fingerprint of the certification key.
+    #[must_use]
+    pub fn fingerprint(&self) -> Fingerprint {
+        self.0.public().fingerprint()
+    }
+}
+
+impl fmt::Debug for Certifier {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("Certifier")
+            .field(&self.fingerprint())
+            .finish()
+    }
+}
+
+/// Builds a certificate from a key the way Fingerprunk does for found keys.
+///
+/// The certificate consists of the secret key, a direct key signature preferring SHA-512 and
+/// AES-256 and, if given, a user ID and an encryption subkey with their binding signatures, the
+/// user ID also with a certification by [`CertOptions::certifier`]. The proofs go into both the
+/// direct key signature and the user ID's binding signature.
+pub fn build_cert(key: &SecretKey, options: &CertOptions) -> anyhow::Result<Cert> {
+    let mut sig = SignatureBuilder::new(SignatureType::DirectKey)
+        .set_hash_algo(HashAlgorithm::SHA512)
+        .set_preferred_hash_algorithms(vec![HashAlgorithm::SHA512, HashAlgorithm::SHA256])?
+        .set_preferred_symmetric_algorithms(vec![
+            SymmetricAlgorithm::AES256,
+            SymmetricAlgorithm::AES128,
+        ])?;
+    for proof in &options.proofs {
+        sig = sig.add_notation(
+            PROOF_NOTATION,
+            proof.as_bytes(),
+            NotationDataFlags::empty().set_human_readable(),
+            false,
+        )?;
+    }
+
+    let mut signer = key
+        .clone()
+        .into_keypair()
+        .expect("key should have a secret");
+    let binding = match &options.user_id {
+        Some(user_id) => {
+            let user_id = UserID::from(user_id.as_str());
+            let binding = sig
+                .clone()
+                .set_type(SignatureType::PositiveCertification)
+                .set_primary_userid(true)?
+                .sign_userid_binding(&mut signer, key.parts_as_public(), &user_id)?;
+            let certification = match &options.certifier {
+                Some(Certifier(certifier)) => Some(
+                    SignatureBuilder::new(SignatureType::GenericCertification)
+                        .set_hash_algo(HashAlgorithm::SHA512)
+                        .sign_userid_binding(
+                            &mut certifier.clone(),
+                            key.parts_as_public(),
+                            &user_id,
+                        )?,
+                ),
+                None => None,
+            };
+            Some((user_id, binding, certification))
+        }
+        None => None,
+    };
+    let subkey_binding = match &options.subkey {
+        Some(subkey) => Some(
+            SignatureBuilder::new(SignatureType::SubkeyBinding)
+                .set_hash_algo(HashAlgorithm::SHA512)
+                .set_key_flags(
+                    KeyFlags::empty()
+                        .set_transport_encryption()
+                        .set_storage_encryption(),
+                )?
+                .sign_subkey_binding(&mut signer, key.parts_as_public(), subkey)?,
+        ),
+        None => None,
+    };
+    let sig = sig.sign_direct_key(&mut signer, key.parts_as_public())?;
+
+    let secret_key_packet = Packet::SecretKey({
+        let mut key = key.clone();
+        if let Some(password) = &options.password {
+            let (k, mut secret) = key.take_secret();
+            secret.encrypt_in_place(&k, password)?;
+            key = k.add_secret(secret).0;
+        }
+        key
+    });
+
+    let mut packets = vec![secret_key_packet, Packet::from(sig)];
+    if let Some((user_id, binding, certification)) = binding {
+        packets.extend([Packet::from(user_id), Packet::from(binding)]);
+        packets.extend(certification.map(Packet::from));
+    }
+    if let (Some(subkey), Some(binding)) = (&options.subkey, subkey_binding) {
+        let mut subkey = subkey.clone();
+        if let Some(password) = &options.password {
+            let (k, mut secret) = subkey.take_secret();
+            secret.encrypt_in_place(&k, password)?;
+            subkey = k.add_secret(secret).0;
+        }
+        packets.extend([Packet::SecretSubkey(subkey), Packet::from(binding)]);
+    }
+    Cert::try_from(packets)
+}
+
+/// Signs `message` with a key, as proof that whoever publishes the key holds its secret, and
+/// returns the armored detached signature, which `gpg --verify` checks against the message.
+pub fn sign_proof(key: &SecretKey, message: &[u8]) -> anyhow::Result<Vec<u8>> {
+    let mut signer = key
+        .clone()
+        .into_keypair()
+        .context("The key has no unencrypted secret")?;
+    let sig = SignatureBuilder::new(SignatureType::Binary)
+        .set_hash_algo(HashAlgorithm::SHA512)
+        .sign_message(&mut signer, message)?;
+
+    let mut writer = armor::Writer::new(Vec::new(), armor::Kind::Signature)?;
+    writer.set_profile(if key.version() == 6 {
+        Profile::RFC9580
+    } else {
+        Profile::RFC4880
+    })?;
+    Packet::from(sig).serialize(&mut writer)?;
+    Ok(writer.finalize()?)
+}
+
+/// Creates the generator of every worker thread from the thread's number.
+pub(crate) struct GeneratorFactory(
+    pub(crate) Box<dyn Fn(usize) -> Box<dyn KeyGenerator> + Send + Sync>,
+    /// Whether the generators are the default Ed25519 ones.
+    pub(crate) bool,
+);
+
+impl GeneratorFactory {
+    /// The default generators for keys of `format` and `version`.
+    pub(crate) fn for_format(format: KeyFormat, version: KeyVersion) -> Self {
+        match (format, version) {
+            (KeyFormat::Age, _) => Self(Box::new(|_| Box::new(X25519Generator)), true),
+            (_, KeyVersion::V4) => Self(Box::new(|_| Box::new(Ed25519Generator)), true),
+            (_, KeyVersion::V6) => Self(Box::new(|_| Box::new(Ed25519V6Generator)), true),
+        }
+    }
+}
+
+impl fmt::Debug for GeneratorFactory {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("GeneratorFactory")
+    }
+}
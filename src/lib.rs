@@ -1,54 +1,363 @@
-#![forbid(unsafe_code)]
+#![cfg_attr(
+    not(any(feature = "ffi", feature = "alloc-check")),
+    forbid(unsafe_code)
+)]
+#![cfg_attr(any(feature = "ffi", feature = "alloc-check"), deny(unsafe_code))]
+
+pub mod age;
+#[cfg(feature = "alloc-check")]
+mod alloc_check;
+pub mod audit;
+#[cfg(feature = "cluster")]
+pub mod cluster;
+pub mod collision;
+mod config;
+mod control;
+mod cpu_monitor;
+mod dry_run;
+mod energy;
+mod error;
+pub mod estimate;
+#[cfg(feature = "ffi")]
+pub mod ffi;
+mod finalizer;
+pub mod format;
+mod handle;
+#[cfg(feature = "http-api")]
+mod http;
+#[cfg(feature = "hybrid")]
+mod hybrid;
+mod iter;
+pub mod jobs;
+#[cfg(not(target_arch = "wasm32"))]
+mod keybindings;
+pub mod keygen;
+pub mod matcher;
+pub mod notify;
+#[cfg(feature = "numa")]
+mod numa;
+pub mod observer;
+pub mod onion;
+#[cfg(feature = "otel")]
+pub mod otel;
+pub mod pattern;
+#[cfg(feature = "plugin")]
+pub mod plugin;
+pub mod policy;
+#[cfg(feature = "rayon")]
+mod pool;
+#[cfg(feature = "sandbox")]
+mod sandbox;
+pub mod scan;
+#[cfg(all(unix, feature = "cli"))]
+mod signals;
+pub mod sink;
+pub mod ssh;
+mod state;
+mod stats;
+pub mod status;
+mod stop;
+#[cfg(feature = "tokio")]
+mod stream;
+#[cfg(target_os = "linux")]
+mod systemd;
+mod throughput;
+#[cfg(any(feature = "numa", feature = "hybrid"))]
+mod topology;
+#[cfg(feature = "tui")]
+mod tui;
+mod tune;
+#[cfg(feature = "wasm")]
+pub mod wasm;
+#[cfg(feature = "watch")]
+mod watch;
 
 use std::{
-    fmt::{self, Write},
-    io,
-    num::NonZeroU64,
+    cmp::Reverse,
+    collections::BTreeMap,
+    fmt, fs,
+    io::{self, IsTerminal, Write as _},
+    num::{NonZeroU64, NonZeroUsize},
+    panic::{self, AssertUnwindSafe},
+    path::PathBuf,
     sync::{
+        Arc, Mutex, RwLock,
         atomic::{AtomicBool, AtomicU64, Ordering},
         mpsc,
     },
     thread,
-    time::{Duration, Instant},
+    time::{Duration, Instant, SystemTime},
 };
 
+use anyhow::{Context, bail};
+pub use config::ConfigBuilder;
+use crossbeam_channel::Sender;
+#[cfg(not(target_arch = "wasm32"))]
+use crossterm::{
+    cursor, queue,
+    style::{Print, Stylize},
+    terminal::{Clear, ClearType},
+};
+pub use error::{ConfigError, Error};
+use estimate::Estimate;
 use fancy_regex::Regex;
-use num_integer::Integer;
-use sequoia_openpgp::{
-    Cert, Packet, armor,
-    crypto::Password,
-    packet::{
-        Key,
-        key::{Key4, PrimaryRole, SecretParts},
-        prelude::SignatureBuilder,
-    },
-    serialize::Serialize,
-    types::{Curve, HashAlgorithm, SignatureType, SymmetricAlgorithm},
+pub use finalizer::OutputOrder;
+use format::KeyFormat;
+pub use handle::SearchHandle;
+#[cfg(feature = "hybrid")]
+pub use hybrid::CorePolicy;
+pub use iter::FindIter;
+use jobs::Job;
+use keygen::{
+    CertOptions, Certifier, GeneratorFactory, HexBuffer, KeyGenerator, KeyVersion, SecretKey,
+    SecretSubkey,
 };
+use matcher::{Matcher, PatternSet};
+use notify::EmailConfig;
+use observer::{Event, Observer, Observers};
+pub use policy::Policy;
+use sequoia_openpgp::{Cert, Packet, crypto::Password};
+use serde_json::{Value, json};
+use sink::{ChannelSink, FoundKey, KeySink, UserSink, WriterSink};
+use stats::Clock;
+pub use stats::{Stats, StatsHandle};
+use status::{
+    DurationDhms, Field, RateMeter, StatusMode, StatusTemplate, format_timestamp, humanize,
+    progress_bar,
+};
+pub use stop::StopHandle;
+#[cfg(feature = "tokio")]
+pub use stream::KeyStream;
+pub use tune::Tuning;
+
+/// Failed attempts in a row after which a worker gives up generating keys.
+const KEYGEN_RETRIES: u32 = 5;
+
+/// Pause before the first retry, doubled for every further one.
+const KEYGEN_RETRY_DELAY: Duration = Duration::from_millis(100);
 
-type SecretKey = Key<SecretParts, PrimaryRole>;
+/// Failures to generate keys that a search tolerates in total, so that a generator that keeps
+/// failing in short bursts still ends the search instead of crawling along.
+const KEYGEN_FAILURE_BUDGET: u64 = 100;
 
+/// A message to the finalizer: keys and failures from the workers, stop requests from everything
+/// else, each on a channel of its own.
 #[allow(clippy::large_enum_variant)]
 enum Message {
     Key(SecretKey),
+    /// The certificate of the key numbered `seq` was built by a finalizer thread.
+    Built(u64, Box<finalizer::Found>, anyhow::Result<Cert>),
+    /// A worker or finalizer thread failed and the search can't go on.
+    Failed(Error),
     Stop,
 }
 
+/// How much information is printed to stderr besides the status display.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Verbosity {
+    /// Nothing but fatal errors.
+    Quiet,
+    /// Only the status display, if enabled.
+    #[default]
+    Normal,
+    /// Additionally, the configuration and matcher at startup.
+    Verbose,
+    /// Additionally, details about every match.
+    Debug,
+}
+
 #[derive(Clone, Debug)]
 pub struct Config {
-    pub regex: Regex,
-    pub status_enabled: bool,
+    pub regexes: Vec<Regex>,
+    /// Regexes with a label, whose matches can be routed with [`Config::label_outputs`].
+    pub labeled_regexes: Vec<(String, Regex)>,
+    /// Directories to write the keys matching the regexes with the given labels to.
+    pub label_outputs: BTreeMap<String, PathBuf>,
+    /// Matchers other than regexes, such as [`matcher::Prefix`] or [`matcher::WordList`].
+    pub matchers: Vec<Arc<dyn Matcher>>,
+    /// File with additional regexes, one per line, which is re-read by
+    /// [`Fingerprunk::reload_patterns`].
+    pub patterns_file: Option<PathBuf>,
+    /// Reload the patterns file whenever it changes.
+    #[cfg(feature = "watch")]
+    pub watch_patterns: bool,
+    pub status_mode: StatusMode,
     pub stop_after: Option<NonZeroU64>,
+    /// Keep every key instead of matching, up to [`Self::stop_after`], see
+    /// [`ConfigBuilder::farm`].
+    pub farm: bool,
+    /// Number of worker threads generating keys, by default one per CPU.
+    pub threads: Option<NonZeroUsize>,
+    /// Number of keys a worker tries before it updates the counters and checks whether to stop,
+    /// by default 1.
+    pub batch_size: Option<NonZeroUsize>,
+    /// Pick the number of threads and the batch size by measuring before searching, see
+    /// [`Fingerprunk::auto_tune`].
+    pub auto_tune: bool,
+    /// Number of threads building the certificates of found keys, by default 1, which is the
+    /// thread handing them to the key sink. More help if signing or encrypting the keys can't
+    /// keep up with finding them.
+    pub finalizers: Option<NonZeroUsize>,
+    /// The order found keys are output in with several [`Config::finalizers`].
+    pub output_order: OutputOrder,
+    /// Spread the workers over the nodes of a multi-socket machine and pin them there.
+    #[cfg(feature = "numa")]
+    pub numa: bool,
+    /// Which cores of CPUs with performance and efficiency cores the workers run on.
+    #[cfg(feature = "hybrid")]
+    pub cores: CorePolicy,
     pub password: Option<Password>,
+    /// Version of the keys to generate, which the lengths of the regexes are checked against.
+    /// Ignored by custom key generators.
+    pub key_version: KeyVersion,
+    /// User ID bound to the found keys, e.g. `Alice <alice@example.org>`. Jobs bind their own.
+    pub user_id: Option<String>,
+    /// Regex the fingerprint of an encryption subkey must match. Every found key then gets
+    /// subkeys generated for it until one matches, which is bound to it, so that both
+    /// fingerprints `gpg -k` shows are vanity.
+    pub subkey_regex: Option<Regex>,
+    /// Key certifying the user IDs of the found keys, e.g. an organization's CA key, so that
+    /// trust in it extends to them right away.
+    pub certify_with: Option<Certifier>,
+    /// Message every found key signs, e.g. a statement naming the key's owner, so that a
+    /// detached signature proving possession of the secret key can be published with it. See
+    /// [`MatchMeta::proof`](sink::MatchMeta::proof).
+    pub sign_proof: Option<Vec<u8>>,
+    /// Identity proofs added to the self-signatures of the found keys as Ariadne notations, e.g.
+    /// `https://github.com/alice` or `dns:example.org?type=TXT`, so that the keys can be used as
+    /// Keyoxide profiles right away.
+    pub proofs: Vec<String>,
+    pub humanize_numbers: bool,
+    pub status_template: StatusTemplate,
+    #[cfg(feature = "tui")]
+    pub tui: bool,
+    pub verbosity: Verbosity,
+    pub bell: bool,
+    pub bell_repeat: Option<Duration>,
+    #[cfg(feature = "webhook")]
+    pub webhook: Option<String>,
+    /// Exports metrics and spans of the search via OTLP, see [`otel`].
+    #[cfg(feature = "otel")]
+    pub otel: bool,
+    pub notify_email: Option<EmailConfig>,
+    #[cfg(unix)]
+    pub log_syslog: bool,
+    #[cfg(unix)]
+    pub control_socket: Option<PathBuf>,
+    /// Reads control commands from stdin if it is not a terminal, e.g. when a supervising
+    /// script pipes them in. Off for libraries, whose host process may use stdin otherwise.
+    pub stdin_control: bool,
+    /// File in which a checkpoint of the search is persisted across runs.
+    pub state_file: Option<PathBuf>,
+    /// Fail if there is no checkpoint in the state file to continue from.
+    pub resume: bool,
+    /// Continue from a checkpoint even if the patterns or settings have changed.
+    pub allow_config_change: bool,
+    /// Directory to write found keys to, one file per key, instead of stdout.
+    pub output_dir: Option<PathBuf>,
+    /// Identities to find keys for instead of the regexes, see [`jobs`]. Jobs without their own
+    /// output directory get a subdirectory of [`Config::output_dir`].
+    pub jobs: Vec<Job>,
+    /// Key IDs of existing keys; found keys colliding with them are discarded with a warning.
+    pub avoid_keyids: Option<collision::KeyIds>,
+    /// Base URL of a keyserver speaking VKS, like `https://keys.openpgp.org`, on which found
+    /// keys' key IDs are looked up; collisions with published keys are warned about.
+    #[cfg(feature = "keyserver")]
+    pub keyserver: Option<String>,
+    /// Stop once all jobs with at least this priority are done, abandoning the others.
+    pub stop_with_priority: Option<i64>,
+    /// Write log messages, status updates and found keys' fingerprints to stdout as JSON lines
+    /// instead of text to stderr. Requires [`Config::output_dir`].
+    pub json_logs: bool,
+    #[cfg(feature = "http-api")]
+    pub listen: Option<String>,
+    /// Coordinator to receive the patterns from and to send found keys to instead of stdout.
+    #[cfg(feature = "cluster")]
+    pub coordinator: Option<cluster::WorkerConfig>,
+    /// Confine the process to what the search needs once it is set up, with Landlock and seccomp
+    /// on Linux and unveil and pledge on OpenBSD. This affects the whole process and can't be
+    /// undone.
+    #[cfg(feature = "sandbox")]
+    pub sandbox: bool,
+    /// Refuse options using the network and, with the `sandbox` feature on Linux, make creating
+    /// internet sockets fail, e.g. for key ceremonies on air-gapped machines.
+    pub offline: bool,
+    /// Rules to check the issued keys against, see [`policy`]. Unchecked if `None`.
+    pub policy: Option<Policy>,
+    /// File to append a hash-chained record of every emitted key to, see [`audit`].
+    pub audit_log: Option<PathBuf>,
+    /// The format of the keys searched for, e.g. OpenSSH Ed25519 keys, matched by their OpenSSH
+    /// fingerprints and written as OpenSSH key files instead of OpenPGP keys, see [`format`].
+    pub key_format: KeyFormat,
+}
+
+/// Statistics and results of a finished search.
+#[derive(Clone, Debug)]
+pub struct RunSummary {
+    pub tried: u64,
+    pub found: u64,
+    pub elapsed: Duration,
+    /// Fingerprints of the found keys, including those of previous runs when resuming.
+    pub fingerprints: Vec<String>,
+    /// Whether the search was stopped before it was done, e.g. by a signal, a
+    /// [`StopHandle`] or the key sink, rather than by finding the requested keys.
+    pub interrupted: bool,
+    /// Joules used by the CPU packages during the search, if their RAPL counters are readable.
+    pub energy: Option<f64>,
 }
 
 #[derive(Debug)]
 pub struct Fingerprunk {
     config: Config,
+    matcher: RwLock<Arc<PatternSet>>,
+    /// Incremented whenever the matcher is replaced, so that the workers know when to update
+    /// their copies of it.
+    matcher_generation: AtomicU64,
+    /// Regexes added while running, which are kept when reloading the patterns file.
+    added_regexes: Mutex<Vec<Regex>>,
     started_instant: Instant,
     stop: AtomicBool,
-    counter_tried: AtomicU64,
-    counter_found: AtomicU64,
+    /// See [`RunSummary::interrupted`].
+    interrupted: AtomicBool,
+    /// Failures of the key generators that were retried, see [`KEYGEN_FAILURE_BUDGET`].
+    keygen_failures: AtomicU64,
+    paused: Arc<AtomicBool>,
+    counter_tried: Arc<AtomicU64>,
+    counter_found: Arc<AtomicU64>,
+    /// Start, end and rate of the search for [`StatsHandle`]s.
+    clock: Arc<Clock>,
+    counters_worker: Vec<AtomicU64>,
+    /// See [`Config::batch_size`].
+    batch_size: usize,
+    /// Where the workers run with [`Config::numa`], if the machine has several nodes.
+    #[cfg(feature = "numa")]
+    placement: Option<numa::Placement>,
+    /// Where the workers run with [`Config::cores`], if the CPU has several kinds of cores.
+    #[cfg(feature = "hybrid")]
+    core_placement: Option<hybrid::CorePlacement>,
+    found_fingerprints: Mutex<Vec<String>>,
+    /// Opened when the search starts, see [`Config::audit_log`].
+    audit_log: Mutex<Option<audit::AuditLog>>,
+    /// Where all keys go if they aren't written out by Fingerprunk itself, like `"sink"`.
+    routed_to: Option<&'static str>,
+    /// Number of found keys by the regex they matched.
+    found_per_pattern: Mutex<BTreeMap<String, u64>>,
+    pending_messages: Mutex<Vec<String>>,
+    bell_pending: Arc<AtomicBool>,
+    /// Energy used by the search, if it can be measured.
+    energy: Mutex<Option<energy::EnergyMeter>>,
+    /// Utilization and throttling of the CPUs, if they can be watched.
+    cpu_monitor: Mutex<Option<cpu_monitor::CpuMonitor>>,
+    /// Sink for the found keys instead of [`OutputSink`](sink::OutputSink).
+    sink: Mutex<Option<UserSink>>,
+    observers: Observers,
+    stop_handle: StopHandle,
+    key_generator: GeneratorFactory,
+    /// Pool to run the workers on instead of threads of their own.
+    #[cfg(feature = "rayon")]
+    rayon_pool: Option<pool::RayonPool>,
+    #[cfg(feature = "alloc-check")]
+    worker_allocations: alloc_check::WorkerAllocations,
 }
 
 impl From<Config> for Fingerprunk {
@@ -57,34 +366,465 @@ impl From<Config> for Fingerprunk {
     }
 }
 
+impl IntoIterator for Fingerprunk {
+    type Item = FoundKey;
+    type IntoIter = FindIter;
+
+    fn into_iter(self) -> FindIter {
+        self.find_iter()
+    }
+}
+
 impl Fingerprunk {
     #[must_use]
-    pub fn new_from_config(config: Config) -> Self {
+    pub fn new_from_config(mut config: Config) -> Self {
+        let key_generator = GeneratorFactory::for_format(config.key_format, config.key_version);
+        // The matcher checks the jobs in this order, so the most wanted ones come first
+        config.jobs.sort_by_key(|job| Reverse(job.priority));
+        let threads = config.threads.map_or_else(num_cpus::get, NonZeroUsize::get);
+        let batch_size = config.batch_size.map_or(1, NonZeroUsize::get);
         Self {
+            matcher: RwLock::new(Arc::new(PatternSet::new(
+                config
+                    .regexes
+                    .iter()
+                    .chain(config.labeled_regexes.iter().map(|(_, regex)| regex))
+                    .chain(config.jobs.iter().map(|job| &job.regex))
+                    .map(|regex| Arc::new(regex.clone()) as Arc<dyn Matcher>)
+                    .chain(config.matchers.iter().cloned())
+                    .collect(),
+            ))),
+            matcher_generation: AtomicU64::new(0),
+            added_regexes: Mutex::new(Vec::new()),
             config,
             started_instant: Instant::now(),
             stop: AtomicBool::new(false),
-            counter_tried: AtomicU64::new(0),
-            counter_found: AtomicU64::new(0),
+            interrupted: AtomicBool::new(false),
+            keygen_failures: AtomicU64::new(0),
+            paused: Arc::new(AtomicBool::new(false)),
+            counter_tried: Arc::new(AtomicU64::new(0)),
+            counter_found: Arc::new(AtomicU64::new(0)),
+            clock: Arc::new(Clock::default()),
+            counters_worker: (0..threads).map(|_| AtomicU64::new(0)).collect(),
+            batch_size,
+            #[cfg(feature = "numa")]
+            placement: None,
+            #[cfg(feature = "hybrid")]
+            core_placement: None,
+            found_fingerprints: Mutex::new(Vec::new()),
+            audit_log: Mutex::new(None),
+            routed_to: None,
+            found_per_pattern: Mutex::new(BTreeMap::new()),
+            pending_messages: Mutex::new(Vec::new()),
+            bell_pending: Arc::new(AtomicBool::new(false)),
+            energy: Mutex::new(None),
+            cpu_monitor: Mutex::new(None),
+            sink: Mutex::new(None),
+            observers: Observers::default(),
+            stop_handle: StopHandle::default(),
+            key_generator,
+            #[cfg(feature = "rayon")]
+            rayon_pool: None,
+            #[cfg(feature = "alloc-check")]
+            worker_allocations: alloc_check::WorkerAllocations::default(),
+        }
+    }
+
+    /// Searches until enough keys have been found or the search is stopped.
+    pub fn run(mut self) -> Result<RunSummary, Error> {
+        let sink = self
+            .sink
+            .get_mut()
+            .expect("lock should not be poisoned")
+            .take()
+            .map(|UserSink(sink)| sink);
+        self.search(sink).map_err(Error::from_anyhow)?;
+        Ok(self.into_summary())
+    }
+
+    /// Like [`Self::run`], but writes all found keys to `writer` instead of stdout or the output
+    /// directories.
+    ///
+    /// The writer may borrow from the caller, e.g. `&mut Vec<u8>` or a locked stdout, as it is
+    /// only used until the search has stopped.
+    pub fn run_with_writer(mut self, writer: impl io::Write + Send) -> Result<RunSummary, Error> {
+        self.search(Some(Box::new(WriterSink::new(writer))))
+            .map_err(Error::from_anyhow)?;
+        Ok(self.into_summary())
+    }
+
+    /// Like [`Self::run`], but returns the found keys instead of writing them to stdout or the
+    /// output directories, leaving their serialization to the caller.
+    pub fn run_collect(mut self) -> Result<(Vec<Cert>, RunSummary), Error> {
+        let (sender, receiver) = mpsc::channel();
+        self.set_sink(ChannelSink::new(sender));
+        let summary = self.run()?;
+        Ok((receiver.into_iter().map(|key| key.cert).collect(), summary))
+    }
+
+    /// Runs the search in the background and iterates over the found keys as they are found.
+    ///
+    /// The search stops when the iterator is dropped, so `find_iter().take(3)` finds three keys.
+    #[must_use]
+    pub fn find_iter(self) -> FindIter {
+        FindIter::spawn(self)
+    }
+
+    /// Runs the search in the background and returns a handle to pause, resume and stop it, read
+    /// its stats and receive the found keys, e.g. from a GUI.
+    #[must_use]
+    pub fn spawn(self) -> SearchHandle {
+        SearchHandle::spawn(self)
+    }
+
+    /// Runs the search in the background and streams the found keys as they are found, for use
+    /// in async code.
+    ///
+    /// The search stops when the stream is dropped.
+    #[cfg(feature = "tokio")]
+    #[must_use]
+    pub fn stream(self) -> KeyStream {
+        KeyStream::spawn(self)
+    }
+
+    /// Hands the found keys to `sink` instead of writing them to stdout or the output
+    /// directories. Remote workers always send their keys to the coordinator.
+    pub fn set_sink(&mut self, sink: impl KeySink + 'static) {
+        *self.sink.get_mut().expect("lock should not be poisoned") = Some(UserSink(Box::new(sink)));
+    }
+
+    /// Generates the candidate keys with the generators created by `factory` instead of random
+    /// Ed25519 keys. The factory is called once per worker thread with the thread's number, so
+    /// every thread can e.g. derive its keys from its own seed.
+    pub fn set_key_generator<G: KeyGenerator + 'static>(
+        &mut self,
+        factory: impl Fn(usize) -> G + Send + Sync + 'static,
+    ) {
+        self.key_generator = GeneratorFactory(Box::new(move |num| Box::new(factory(num))), false);
+    }
+
+    /// Runs the workers as tasks of `pool`, or of the global rayon pool if `None`, instead of
+    /// threads of their own, so that the search shares the CPUs with the rest of an application
+    /// using rayon.
+    ///
+    /// Unless [`Config::threads`] is set, there is one worker per thread of the pool. The workers
+    /// occupy their pool threads until the search stops.
+    #[cfg(feature = "rayon")]
+    pub fn use_rayon_pool(&mut self, pool: Option<Arc<rayon::ThreadPool>>) {
+        let pool = pool.map_or(pool::RayonPool::Global, pool::RayonPool::Custom);
+        if self.config.threads.is_none() {
+            self.counters_worker = (0..pool.threads()).map(|_| AtomicU64::new(0)).collect();
+        }
+        self.rayon_pool = Some(pool);
+    }
+
+    /// Passes the events of the search to `observer`.
+    pub fn add_observer(&mut self, observer: impl Observer + 'static) {
+        self.observers.0.push(Box::new(observer));
+    }
+
+    /// Returns a channel receiving the events of the search. It is closed once the search has
+    /// stopped and the [`Fingerprunk`] has been dropped.
+    pub fn subscribe(&mut self) -> mpsc::Receiver<Event> {
+        let (sender, receiver) = mpsc::channel();
+        self.add_observer(sender);
+        receiver
+    }
+
+    /// Returns a handle to stop the search from another thread.
+    #[must_use]
+    pub fn stop_handle(&self) -> StopHandle {
+        self.stop_handle.clone()
+    }
+
+    /// Returns a handle to read the [`Stats`] of the search from another thread.
+    #[must_use]
+    pub fn stats_handle(&self) -> StatsHandle {
+        StatsHandle {
+            tried: Arc::clone(&self.counter_tried),
+            found: Arc::clone(&self.counter_found),
+            clock: Arc::clone(&self.clock),
         }
     }
 
-    pub fn run(mut self) -> anyhow::Result<()> {
+    /// The current [`Stats`], see also [`Self::stats_handle`].
+    #[must_use]
+    pub fn stats(&self) -> Stats {
+        self.stats_handle().stats()
+    }
+
+    fn into_summary(self) -> RunSummary {
+        RunSummary {
+            tried: self.counter_tried.load(Ordering::Relaxed),
+            found: self.counter_found.load(Ordering::Relaxed),
+            elapsed: self.started_instant.elapsed(),
+            fingerprints: self
+                .found_fingerprints
+                .into_inner()
+                .expect("lock should not be poisoned"),
+            interrupted: self.interrupted.into_inner(),
+            energy: self
+                .energy
+                .into_inner()
+                .expect("lock should not be poisoned")
+                .map(|meter| meter.joules()),
+        }
+    }
+
+    /// Searches, handing the found keys to `sink` or, if `None`, to the default sink of the key
+    /// format, [`OutputSink`](sink::OutputSink) for OpenPGP keys.
+    fn search<'a>(&mut self, sink: Option<Box<dyn KeySink + 'a>>) -> anyhow::Result<()> {
         self.started_instant = Instant::now();
 
-        let (sender, receiver) = mpsc::sync_channel(16);
+        if self.config.offline {
+            self.block_network();
+        }
+        #[cfg(feature = "otel")]
+        if self.config.otel {
+            self.add_observer(otel::OtelObserver::new()?);
+        }
+
+        if self.config.patterns_file.is_some() {
+            self.reload_patterns()?;
+        }
+        #[cfg(feature = "cluster")]
+        let coordinator_link = match &self.config.coordinator {
+            Some(worker_config) => {
+                let (link, regexes) = cluster::CoordinatorLink::connect(worker_config)?;
+                self.set_matcher(PatternSet::from_regexes(regexes));
+                if let Some(ranges) = link.ranges() {
+                    if self.config.key_format != KeyFormat::OpenPgp
+                        || self.config.key_version != KeyVersion::V4
+                        || !self.key_generator.1
+                        || self.config.auto_tune
+                    {
+                        return Err(ConfigError::GrindTimestamps.into());
+                    }
+                    let ranges = Arc::clone(ranges);
+                    self.key_generator = GeneratorFactory(
+                        Box::new(move |_| {
+                            Box::new(cluster::TimestampGenerator::new(Arc::clone(&ranges)))
+                        }),
+                        false,
+                    );
+                }
+                Some(link)
+            }
+            None => None,
+        };
+        if self.matcher().is_empty() {
+            return Err(ConfigError::NoPatterns.into());
+        }
+        if self.config.auto_tune {
+            self.log(
+                Verbosity::Normal,
+                "Tuning the number of threads and the batch size...",
+            );
+            let tuning = self.auto_tune()?;
+            self.log(
+                Verbosity::Normal,
+                format_args!(
+                    "Tuned to {} {} with batches of {} keys ({} keys/s)",
+                    tuning.threads,
+                    if tuning.threads.get() == 1 {
+                        "thread"
+                    } else {
+                        "threads"
+                    },
+                    tuning.batch_size,
+                    self.format_number(tuning.rate)
+                ),
+            );
+            // The search starts after tuning
+            self.started_instant = Instant::now();
+        }
+        #[cfg(feature = "numa")]
+        if self.config.numa {
+            self.placement = numa::Placement::new(self.counters_worker.len())?;
+            if self.placement.is_none() {
+                self.log(
+                    Verbosity::Normal,
+                    "This machine has a single NUMA node, so the workers are not pinned",
+                );
+            }
+        }
+        #[cfg(feature = "hybrid")]
+        if self.config.cores != CorePolicy::All {
+            match hybrid::CoreTypes::detect()? {
+                Some(types) => {
+                    // Tuned or given thread counts are kept
+                    if self.config.cores == CorePolicy::Performance
+                        && self.config.threads.is_none()
+                        && !self.config.auto_tune
+                    {
+                        self.counters_worker = (0..types.performance.len())
+                            .map(|_| AtomicU64::new(0))
+                            .collect();
+                    }
+                    self.core_placement = Some(hybrid::CorePlacement::new(
+                        self.config.cores,
+                        &types,
+                        self.counters_worker.len(),
+                        self.batch_size,
+                    ));
+                }
+                None => self.log(
+                    Verbosity::Normal,
+                    "This CPU has a single kind of cores, so the workers are not pinned",
+                ),
+            }
+        }
+        // Metered from here on, so that tuning isn't counted
+        *self.energy.get_mut().expect("lock should not be poisoned") = energy::EnergyMeter::start();
+        *self
+            .cpu_monitor
+            .get_mut()
+            .expect("lock should not be poisoned") = cpu_monitor::CpuMonitor::start();
+        #[cfg(feature = "watch")]
+        let patterns_watcher = match &self.config.patterns_file {
+            Some(path) if self.config.watch_patterns => Some(watch::PatternsWatcher::new(path)?),
+            _ => None,
+        };
+        self.load_state()?;
+        let found = self.counter_found.load(Ordering::Relaxed);
+        if self.config.stop_after.is_some_and(|s| found >= s.get()) {
+            self.log(
+                Verbosity::Normal,
+                format_args!(
+                    "{found} {} found already",
+                    if found == 1 { "key was" } else { "keys were" }
+                ),
+            );
+            return Ok(());
+        }
+        if !self.config.jobs.is_empty() && !self.update_job_matcher() {
+            self.log(
+                Verbosity::Normal,
+                format_args!("{} already", self.jobs_done_message()),
+            );
+            return Ok(());
+        }
+
+        #[cfg(feature = "cluster")]
+        let keys_to_coordinator = coordinator_link.is_some();
+        #[cfg(not(feature = "cluster"))]
+        let keys_to_coordinator = false;
+        // Keys that are collected or sent to the coordinator aren't written out here
+        let keys_written = !keys_to_coordinator && sink.is_none();
+        match &self.config.output_dir {
+            Some(dir) => fs::create_dir_all(dir)
+                .with_context(|| format!("Failed to create output directory {}", dir.display()))?,
+            None if self.config.json_logs && keys_written => {
+                bail!("JSON logs are written to stdout, so found keys need an output directory")
+            }
+            None => {}
+        }
+        for job in self.config.jobs.iter().filter(|_| keys_written) {
+            let Some(dir) = self.job_dir(job) else {
+                bail!("Job {} needs an output directory", job.name);
+            };
+            fs::create_dir_all(&dir)
+                .with_context(|| format!("Failed to create output directory {}", dir.display()))?;
+        }
+        self.routed_to = if keys_to_coordinator {
+            Some("coordinator")
+        } else if !keys_written {
+            Some("sink")
+        } else {
+            None
+        };
+        if let Some(path) = &self.config.audit_log {
+            *self
+                .audit_log
+                .get_mut()
+                .expect("lock should not be poisoned") = Some(audit::AuditLog::open(path)?);
+        }
+        for (label, dir) in &self.config.label_outputs {
+            if !self.config.labeled_regexes.iter().any(|(l, _)| l == label) {
+                return Err(ConfigError::UnknownLabel(label.clone()).into());
+            }
+            fs::create_dir_all(dir)
+                .with_context(|| format!("Failed to create output directory {}", dir.display()))?;
+        }
+
+        // Bounded, so that the workers wait for the finalizer if it can't keep up
+        let (key_sender, key_receiver) = crossbeam_channel::bounded(16);
+        let (sender, receiver) = crossbeam_channel::unbounded();
+        self.stop_handle.attach(sender.clone());
+
+        // Embedding applications handle signals themselves and stop with the stop handle
+        #[cfg(feature = "cli")]
+        forward_ctrlc(sender.clone())?;
+        #[cfg(all(unix, feature = "cli"))]
+        let signals = signals::register()?;
+        #[cfg(all(unix, feature = "cli"))]
+        let signals_handle = signals.handle();
 
+        // With keybindings, Enter is handled by the keybindings thread instead
+        if self.config.bell
+            && self.config.bell_repeat.is_some()
+            && io::stdin().is_terminal()
+            && !self.keybindings_enabled()
         {
-            let sender = sender.clone();
-            ctrlc::set_handler(move || {
-                let _ = sender.send(Message::Stop);
-            })?;
+            self.spawn_bell_acknowledger()?;
         }
+        // Supervising scripts can send commands through a pipe
+        let stdin_lines = if self.config.stdin_control && !io::stdin().is_terminal() {
+            Some(control::spawn_stdin_reader()?)
+        } else {
+            None
+        };
 
-        thread::scope(|scope| {
-            let ref_self = &self;
+        #[cfg(unix)]
+        let control_socket = self
+            .config
+            .control_socket
+            .as_deref()
+            .map(control::ControlSocket::bind)
+            .transpose()?;
+        #[cfg(feature = "http-api")]
+        let http_server = self
+            .config
+            .listen
+            .as_deref()
+            .map(http::listen)
+            .transpose()?;
+
+        self.log_startup();
+        self.log_policy_warnings();
+        self.log_version_warnings();
+        #[cfg(feature = "sandbox")]
+        if self.config.sandbox {
+            self.enter_sandbox()?;
+        }
+        #[cfg(feature = "tracing")]
+        tracing::info!(
+            regex = %self.matcher(),
+            workers = self.counters_worker.len(),
+            "search started"
+        );
 
-            let status_displayer = if self.config.status_enabled {
+        // The start may have been moved back when resuming
+        self.clock.start(self.started_instant);
+        let mut sink = sink.unwrap_or_else(|| self.config.key_format.default_sink());
+
+        let result = thread::scope(|scope| {
+            let ref_self = &*self;
+
+            // The TUI replaces the status display
+            #[cfg(feature = "tui")]
+            let tui = self
+                .config
+                .tui
+                .then(|| {
+                    let sender = sender.clone();
+                    thread::Builder::new()
+                        .name("tui".to_string())
+                        .spawn_scoped(scope, move || ref_self.tui_thread(sender))
+                })
+                .transpose()?;
+
+            let status_displayer = if self.status_displayer_enabled() {
                 Some(
                     thread::Builder::new()
                         .name("status_displayer".to_string())
@@ -94,165 +834,1284 @@ impl Fingerprunk {
                 None
             };
 
-            for num in 0..num_cpus::get() {
+            #[cfg(not(target_arch = "wasm32"))]
+            if let Some(status_displayer) = &status_displayer
+                && self.keybindings_enabled()
+            {
                 let sender = sender.clone();
+                let status_displayer = status_displayer.thread().clone();
+                thread::Builder::new()
+                    .name("keybindings".to_string())
+                    .spawn_scoped(scope, move || {
+                        ref_self.keybindings_thread(&status_displayer, sender)
+                    })?;
+            }
+
+            let state_saver = self
+                .config
+                .state_file
+                .as_deref()
+                .map(|path| {
+                    thread::Builder::new()
+                        .name("state_saver".to_string())
+                        .spawn_scoped(scope, move || ref_self.state_thread(path))
+                })
+                .transpose()?;
+
+            let progress = thread::Builder::new()
+                .name("progress".to_string())
+                .spawn_scoped(scope, move || ref_self.progress_thread())?;
+
+            #[cfg(target_os = "linux")]
+            let systemd = systemd::SystemdNotifier::from_env()
+                .map(|notifier| {
+                    thread::Builder::new()
+                        .name("systemd".to_string())
+                        .spawn_scoped(scope, move || ref_self.systemd_thread(notifier))
+                })
+                .transpose()?;
 
+            if let Some(stdin_lines) = stdin_lines {
+                let sender = sender.clone();
                 thread::Builder::new()
-                    .name(format!("worker-{num:03}"))
-                    .spawn_scoped(scope, move || ref_self.worker_thread(sender))?;
+                    .name("stdin_commands".to_string())
+                    .spawn_scoped(scope, move || {
+                        ref_self.stdin_command_thread(stdin_lines, sender)
+                    })?;
             }
 
-            let mut stdout = io::stdout().lock();
+            #[cfg(unix)]
+            if let Some(control_socket) = &control_socket {
+                let sender = sender.clone();
+                thread::Builder::new()
+                    .name("control".to_string())
+                    .spawn_scoped(scope, move || {
+                        ref_self.control_thread(control_socket, sender)
+                    })?;
+            }
+
+            #[cfg(feature = "http-api")]
+            if let Some(http_server) = &http_server {
+                let sender = sender.clone();
+                thread::Builder::new()
+                    .name("http".to_string())
+                    .spawn_scoped(scope, move || ref_self.http_thread(http_server, sender))?;
+            }
+
+            #[cfg(all(unix, feature = "cli"))]
+            {
+                let sender = sender.clone();
+                thread::Builder::new()
+                    .name("signals".to_string())
+                    .spawn_scoped(scope, move || ref_self.signals_thread(signals, sender))?;
+            }
+
+            #[cfg(feature = "watch")]
+            if let Some(patterns_watcher) = patterns_watcher {
+                thread::Builder::new()
+                    .name("watch".to_string())
+                    .spawn_scoped(scope, move || ref_self.watch_thread(patterns_watcher))?;
+            }
+
+            #[cfg(feature = "cluster")]
+            if let Some(link) = coordinator_link {
+                let (cluster_sender, cluster_receiver) = mpsc::channel();
+                sink = Box::new(cluster::CoordinatorSink::new(cluster_sender, &link));
+                let sender = sender.clone();
+                thread::Builder::new()
+                    .name("cluster".to_string())
+                    .spawn_scoped(scope, move || {
+                        ref_self.cluster_thread(link, cluster_receiver, sender)
+                    })?;
+            }
+
+            let notifiers = self.notifiers();
+            let notify_sender = if notifiers.is_empty() {
+                None
+            } else {
+                let (notify_sender, notify_receiver) = mpsc::channel();
+                thread::Builder::new()
+                    .name("notifier".to_string())
+                    .spawn_scoped(scope, move || {
+                        ref_self.notifier_thread(notifiers, notify_receiver)
+                    })?;
+                Some(notify_sender)
+            };
+
+            // With several finalizer threads, the certificates are built there and sent back. So
+            // they are for subkeys to grind, which would keep stop requests waiting here otherwise
+            let build_sender = if self.finalizers() > 1 || self.config.subkey_regex.is_some() {
+                let (build_sender, build_receiver) = crossbeam_channel::unbounded();
+                for num in 0..self.finalizers() {
+                    let (build_receiver, key_sender) = (build_receiver.clone(), key_sender.clone());
+                    let name = format!("finalizer-{num:02}");
+                    thread::Builder::new()
+                        .name(name.clone())
+                        .spawn_scoped(scope, move || {
+                            catch_panic(&name, &key_sender.clone(), || {
+                                ref_self.finalizer_thread(build_receiver, key_sender);
+                            });
+                        })?;
+                }
+                Some(build_sender)
+            } else {
+                None
+            };
 
-            // Receive and process messages from the workers and the ctrl-c handler
-            for message in receiver {
-                match message {
-                    Message::Key(key) => {
-                        let cert = self.key_to_cert(&key)?;
-                        self.serialize_cert(cert, &mut stdout)?;
+            self.emit(Event::Started {
+                workers: self.counters_worker.len(),
+            });
+            ref_self.spawn_workers(scope, &key_sender)?;
 
-                        // Increase "found" counter and stop if enough matches have been found
-                        let prev = self.counter_found.fetch_add(1, Ordering::Relaxed);
-                        if self.config.stop_after.is_some_and(|s| prev + 1 == s.get()) {
-                            break;
+            #[cfg(feature = "tracing")]
+            let finalizer_span = tracing::info_span!("finalizer").entered();
+
+            // Receive and process messages from the workers and the ctrl-c handler. Failures end
+            // the search like a stop request, so that all other threads are stopped below, and so
+            // do panics, e.g. of the sink, which would otherwise leave the workers running forever.
+            let finalized = panic::catch_unwind(AssertUnwindSafe(|| {
+                let mut stopping = false;
+                // Keys handed to the finalizer threads whose certificates haven't come back yet
+                let mut building = 0;
+                let mut next_seq = 0;
+                let mut reorder = finalizer::Reorder::default();
+                loop {
+                    let message = if stopping && building == 0 {
+                        // Only the keys the workers have already sent are still handled
+                        match key_receiver.try_recv() {
+                            Ok(message) => message,
+                            Err(_) => break,
+                        }
+                    } else if stopping {
+                        // The keys being built are still delivered
+                        key_receiver.recv().unwrap_or(Message::Stop)
+                    } else {
+                        // Stop requests go first, so that they aren't stuck behind found keys
+                        crossbeam_channel::select_biased! {
+                            recv(receiver) -> message => message.unwrap_or(Message::Stop),
+                            recv(key_receiver) -> message => message.unwrap_or(Message::Stop),
+                        }
+                    };
+                    match message {
+                        Message::Key(key) => {
+                            let Some(mut found) = self.prepare_found(key)? else {
+                                continue;
+                            };
+                            if let Some(build_sender) = &build_sender {
+                                let _ = build_sender.send((next_seq, found));
+                                next_seq += 1;
+                                building += 1;
+                                continue;
+                            }
+                            let cert = self.build_found(&mut found);
+                            let flow = self.deliver_found(
+                                Box::new(found),
+                                cert,
+                                sink.as_mut(),
+                                notify_sender.as_ref(),
+                            )?;
+                            if flow.is_break() {
+                                break;
+                            }
+                        }
+                        Message::Built(seq, found, cert) => {
+                            building -= 1;
+                            let ready = reorder.push(self.config.output_order, seq, found, cert);
+                            let mut done = false;
+                            for (found, cert) in ready {
+                                let flow = self.deliver_found(
+                                    found,
+                                    cert,
+                                    sink.as_mut(),
+                                    notify_sender.as_ref(),
+                                )?;
+                                if flow.is_break() {
+                                    done = true;
+                                    break;
+                                }
+                            }
+                            if done {
+                                break;
+                            }
+                        }
+                        Message::Failed(err) => return Err(err.into()),
+                        Message::Stop => {
+                            #[cfg(feature = "tracing")]
+                            tracing::debug!("stop requested");
+                            self.interrupted.store(true, Ordering::Relaxed);
+                            self.stop.store(true, Ordering::Relaxed);
+                            stopping = true;
                         }
                     }
-                    Message::Stop => break,
                 }
+                anyhow::Ok(())
+            }))
+            .unwrap_or_else(|payload| Err(Error::panicked("finalizer", &*payload).into()));
+
+            #[cfg(feature = "tracing")]
+            {
+                drop(finalizer_span);
+                tracing::info!(
+                    tried = self.counter_tried.load(Ordering::Relaxed),
+                    found = self.counter_found.load(Ordering::Relaxed),
+                    "search stopped"
+                );
             }
 
-            // Ask all other threads to stop
+            // Ask all other threads to stop; the notifier stops once it has delivered everything
             self.stop.store(true, Ordering::Relaxed);
+            // Workers and finalizer threads waiting for room in the channel give up on their keys
+            drop(key_receiver);
+            drop(build_sender);
+            drop(notify_sender);
+            // Lets the cluster thread send the remaining keys to the coordinator
+            drop(sink);
+            #[cfg(all(unix, feature = "cli"))]
+            signals_handle.close();
 
-            // Unpark the status displayer thread, if existant
+            // Unpark the status displayer, state saver, progress and systemd threads, if existant
             if let Some(status_displayer) = status_displayer {
                 status_displayer.thread().unpark();
             }
+            if let Some(state_saver) = state_saver {
+                state_saver.thread().unpark();
+            }
+            progress.thread().unpark();
+            #[cfg(target_os = "linux")]
+            if let Some(systemd) = systemd {
+                systemd.thread().unpark();
+            }
+
+            #[cfg(feature = "tui")]
+            if let Some(tui) = tui {
+                tui.join()
+                    .unwrap_or_else(|payload| Err(Error::panicked("tui", &*payload).into()))?;
+            }
 
-            Ok(())
-        })
+            finalized
+        });
+
+        // All workers have stopped by now, so the counters are final
+        self.stop_handle.detach();
+        self.clock.stop();
+        self.emit(Event::Stopped {
+            tried: self.counter_tried.load(Ordering::Relaxed),
+            found: self.counter_found.load(Ordering::Relaxed),
+            elapsed: self.started_instant.elapsed(),
+        });
+        if let Some(path) = &self.config.state_file {
+            self.current_state().save(path)?;
+        }
+        if self.node_shares().is_some() {
+            let avg_rate = self.counter_tried.load(Ordering::Relaxed) as f64
+                / self.started_instant.elapsed().as_secs_f64();
+            self.log(
+                Verbosity::Verbose,
+                format_args!("Average rate per NUMA node: {}", self.node_rates(avg_rate)),
+            );
+        }
+        if self.energy_used().is_some() {
+            self.log(
+                Verbosity::Normal,
+                format_args!(
+                    "Energy used: {}",
+                    self.energy_summary(self.counter_found.load(Ordering::Relaxed))
+                ),
+            );
+        }
+        #[cfg(feature = "alloc-check")]
+        {
+            let (generating, matching) = self
+                .worker_allocations
+                .per_key(self.counter_tried.load(Ordering::Relaxed));
+            self.log(
+                Verbosity::Verbose,
+                format_args!(
+                    "Allocations per key: {generating:.1} generating, {matching:.3} matching"
+                ),
+            );
+        }
+
+        result
     }
 
-    fn worker_thread(&self, sender: mpsc::SyncSender<Message>) {
-        let mut fingerprint_hex = String::with_capacity(20 * 2);
+    /// Makes creating internet sockets fail for an offline search, or warns that this isn't
+    /// possible here, leaving only the options using the network refused.
+    fn block_network(&self) {
+        #[cfg(feature = "sandbox")]
+        let blocked = sandbox::block_network();
+        #[cfg(not(feature = "sandbox"))]
+        let blocked: anyhow::Result<()> = Err(anyhow::anyhow!("built without the sandbox feature"));
+        match blocked {
+            Ok(()) => self.log(Verbosity::Verbose, "Blocked internet sockets"),
+            Err(err) => self.log(
+                Verbosity::Normal,
+                format_args!("Internet sockets are not blocked: {err:#}"),
+            ),
+        }
+    }
 
-        while !self.stop.load(Ordering::Relaxed) {
-            let key =
-                Key4::generate_ecc(true, Curve::Ed25519).expect("should be able to generate key");
-            fingerprint_hex.clear();
-            write!(fingerprint_hex, "{:X}", key.fingerprint())
-                .expect("should write into string without error");
-            if self.check_fingerprint(&fingerprint_hex) {
-                // The channel might already be closed here if we're stopping.
-                // That is fine, so we just ignore the error.
-                let _ = sender.send(Message::Key(Key::V4(key)));
+    /// Spawns a thread that acknowledges a repeating bell whenever Enter is pressed.
+    ///
+    /// The thread is detached because it blocks on reading stdin, so it outlives [`Self::run`].
+    fn spawn_bell_acknowledger(&self) -> io::Result<()> {
+        let bell_pending = Arc::clone(&self.bell_pending);
+        thread::Builder::new()
+            .name("bell_acknowledger".to_string())
+            .spawn(move || {
+                for _ in io::stdin().lines() {
+                    bell_pending.store(false, Ordering::Relaxed);
+                }
+            })?;
+        Ok(())
+    }
+
+    /// Rings the terminal bell if a key has been found since the bell was last acknowledged.
+    ///
+    /// Without a repeat interval, ringing once counts as acknowledgement.
+    fn ring_bell(&self, last_rung: &mut Option<Instant>) {
+        if !self.bell_pending.load(Ordering::Relaxed) {
+            *last_rung = None;
+            return;
+        }
+
+        let due = match self.config.bell_repeat {
+            None => {
+                self.bell_pending.store(false, Ordering::Relaxed);
+                true
             }
-            self.counter_tried.fetch_add(1, Ordering::Relaxed);
+            Some(interval) => last_rung.is_none_or(|last_rung| last_rung.elapsed() >= interval),
+        };
+        if due {
+            eprint!("\x07");
+            *last_rung = Some(Instant::now());
         }
     }
 
-    #[inline]
-    fn check_fingerprint(&self, fingerprint_hex: &str) -> bool {
-        self.config
-            .regex
-            .is_match(fingerprint_hex)
-            .expect("should check regex without error")
+    fn tui_enabled(&self) -> bool {
+        #[cfg(feature = "tui")]
+        return self.config.tui;
+        #[cfg(not(feature = "tui"))]
+        false
+    }
+
+    /// Whether the status displayer thread runs; the TUI replaces it if enabled.
+    fn status_displayer_enabled(&self) -> bool {
+        self.config.status_mode != StatusMode::Disabled && !self.tui_enabled()
     }
 
-    fn key_to_cert(&self, key: &SecretKey) -> anyhow::Result<Cert> {
-        let sig = SignatureBuilder::new(SignatureType::DirectKey)
-            .set_hash_algo(HashAlgorithm::SHA512)
-            .set_preferred_hash_algorithms(vec![HashAlgorithm::SHA512, HashAlgorithm::SHA256])?
-            .set_preferred_symmetric_algorithms(vec![
-                SymmetricAlgorithm::AES256,
-                SymmetricAlgorithm::AES128,
-            ])?;
+    /// Whether key presses are handled, which is only done for the CLI and needs the status
+    /// redrawn in place and a terminal on stdin.
+    fn keybindings_enabled(&self) -> bool {
+        cfg!(all(feature = "cli", not(target_arch = "wasm32")))
+            && self.config.status_mode == StatusMode::Redraw
+            && self.status_displayer_enabled()
+            && io::stdin().is_terminal()
+    }
 
-        let mut signer = key
-            .clone()
-            .into_keypair()
-            .expect("key should have a secret");
-        let sig = sig.sign_direct_key(&mut signer, key.parts_as_public())?;
-
-        let secret_key_packet = Packet::SecretKey({
-            let mut key = key.clone();
-            if let Some(ref password) = self.config.password {
-                let (k, mut secret) = key.take_secret();
-                secret.encrypt_in_place(&k, password)?;
-                key = k.add_secret(secret).0;
+    /// Prints a message to stderr if the verbosity is at least `level`.
+    ///
+    /// While the status is redrawn in place, the message is handed over to the status displayer
+    /// thread so that it is printed above the status instead of being overwritten by it.
+    fn log(&self, level: Verbosity, message: impl fmt::Display) {
+        if self.config.verbosity < level || self.tui_enabled() {
+            return;
+        }
+
+        if self.config.json_logs {
+            let level = match level {
+                Verbosity::Quiet => "error",
+                Verbosity::Normal | Verbosity::Verbose => "info",
+                Verbosity::Debug => "debug",
+            };
+            self.print_json(
+                "log",
+                json!({ "level": level, "message": message.to_string() }),
+            );
+        } else if self.config.status_mode == StatusMode::Redraw && self.status_displayer_enabled() {
+            self.pending_messages
+                .lock()
+                .expect("lock should not be poisoned")
+                .push(message.to_string());
+        } else {
+            eprintln!("{message}");
+        }
+    }
+
+    /// Prints an event with the given fields as a line of JSON to stdout.
+    fn print_json(&self, event: &str, fields: Value) {
+        let mut object = json!({
+            "time": format_timestamp(SystemTime::now()),
+            "event": event,
+        });
+        if let (Some(object), Value::Object(fields)) = (object.as_object_mut(), fields) {
+            object.extend(fields);
+        }
+
+        let mut stdout = io::stdout().lock();
+        let _ = writeln!(stdout, "{object}");
+        let _ = stdout.flush();
+    }
+
+    fn log_startup(&self) {
+        if self.config.verbosity < Verbosity::Verbose {
+            return;
+        }
+
+        for (name, value) in self.config_lines() {
+            self.log(Verbosity::Verbose, format_args!("{name}: {value}"));
+        }
+        if self.keybindings_enabled() {
+            self.log(
+                Verbosity::Verbose,
+                "Keys: p to pause/resume, s to refresh the status, q to quit",
+            );
+        }
+    }
+
+    /// Warns about regexes that likely assume the fingerprints of another key version.
+    fn log_version_warnings(&self) {
+        // Custom generators may create keys of any version
+        if !self.key_generator.1 {
+            return;
+        }
+        let regexes = self
+            .config
+            .regexes
+            .iter()
+            .chain(self.config.labeled_regexes.iter().map(|(_, regex)| regex))
+            .chain(self.config.jobs.iter().map(|job| &job.regex));
+        for regex in regexes {
+            if let Some(warning) = pattern::version_warning(regex, self.config.key_version) {
+                self.log(Verbosity::Normal, format_args!("Warning: {warning}"));
             }
-            key
+        }
+    }
+
+    /// The effective configuration as name/value pairs, as logged at startup.
+    fn config_lines(&self) -> Vec<(&'static str, String)> {
+        let matcher = self.matcher();
+        let estimate = match matcher.estimate() {
+            Some(estimate) => format!(
+                "regex, 1 in {} keys matches ({:?} estimate)",
+                self.format_number(estimate.expected_tries()),
+                estimate.method
+            ),
+            None => "regex, match probability unknown".to_string(),
+        };
+        let mut lines = vec![
+            (
+                "Regex",
+                if self.config.farm {
+                    "none, every key is kept".to_string()
+                } else {
+                    matcher.to_string()
+                },
+            ),
+            (
+                "Resumed",
+                match self.counter_tried.load(Ordering::Relaxed) {
+                    0 => "no".to_string(),
+                    tried => format!(
+                        "{tried} keys tried and {} found in {}",
+                        self.counter_found.load(Ordering::Relaxed),
+                        DurationDhms(self.started_instant.elapsed())
+                    ),
+                },
+            ),
+            ("Matcher", estimate),
+            ("Worker threads", self.counters_worker.len().to_string()),
+            (
+                "Finalizer threads",
+                match (self.finalizers(), self.config.output_order) {
+                    (1, _) => "1".to_string(),
+                    (finalizers, OutputOrder::Found) => format!("{finalizers}, in order found"),
+                    (finalizers, OutputOrder::Built) => format!("{finalizers}, in order built"),
+                },
+            ),
+            (
+                "Stop after",
+                self.config
+                    .stop_after
+                    .map_or_else(|| "never".to_string(), |s| format!("{s} keys")),
+            ),
+            (
+                "Encryption",
+                if self.config.password.is_some() {
+                    "password"
+                } else {
+                    "none"
+                }
+                .to_string(),
+            ),
+            ("Status", format!("{:?}", self.config.status_mode)),
+            (
+                "Output",
+                self.config
+                    .output_dir
+                    .as_ref()
+                    .map_or_else(|| "stdout".to_string(), |dir| dir.display().to_string()),
+            ),
+        ];
+        if !self.config.jobs.is_empty() {
+            lines.push(("Jobs", self.jobs_progress()));
+        }
+        if let Some(user_id) = &self.config.user_id {
+            lines.push(("User ID", user_id.clone()));
+        }
+        if let Some(path) = &self.config.state_file {
+            lines.push(("State file", path.display().to_string()));
+        }
+        if let Some(path) = &self.config.audit_log {
+            lines.push(("Audit log", path.display().to_string()));
+        }
+        if self.config.key_version != KeyVersion::V4 {
+            lines.push(("Key version", self.config.key_version.to_string()));
+        }
+        if self.config.key_format != KeyFormat::OpenPgp {
+            lines.push(("Key format", self.config.key_format.to_string()));
+        }
+        if let Some(regex) = &self.config.subkey_regex {
+            lines.push(("Subkey regex", regex.to_string()));
+        }
+        if let Some(certifier) = &self.config.certify_with {
+            lines.push(("Certified by", format!("{:X}", certifier.fingerprint())));
+        }
+        if let Some(message) = &self.config.sign_proof {
+            lines.push(("Proof message", format!("{} bytes", message.len())));
+        }
+        for proof in &self.config.proofs {
+            lines.push(("Identity proof", proof.clone()));
+        }
+        if self.config.offline {
+            lines.push(("Network", "offline".to_string()));
+        }
+        if let Some(policy) = self.config.policy {
+            lines.push(("Policy", format!("{policy:?}").to_lowercase()));
+        }
+        if let Some(avoid_keyids) = &self.config.avoid_keyids {
+            lines.push(("Avoided key IDs", avoid_keyids.len().to_string()));
+        }
+        #[cfg(feature = "keyserver")]
+        if let Some(url) = &self.config.keyserver {
+            lines.push(("Keyserver", url.clone()));
+        }
+        #[cfg(feature = "numa")]
+        if let Some(placement) = &self.placement {
+            let groups: Vec<_> = placement
+                .groups()
+                .map(|(id, workers)| format!("node {id}: {} workers", workers.len()))
+                .collect();
+            lines.push(("NUMA nodes", groups.join(", ")));
+        }
+        #[cfg(feature = "hybrid")]
+        if let Some(placement) = &self.core_placement {
+            lines.push(("Cores", placement.describe()));
+        }
+        lines
+    }
+
+    fn log_match(&self, fingerprint_hex: &str) {
+        if self.config.verbosity < Verbosity::Debug {
+            return;
+        }
+
+        let matched = match self.matcher().find(fingerprint_hex) {
+            Some(range) => format!("{:?} at {range:?}", &fingerprint_hex[range.clone()]),
+            _ => "unknown part".to_string(),
+        };
+        self.log(
+            Verbosity::Debug,
+            format_args!(
+                "Found {fingerprint_hex} after {} tries in {}, matched {matched}",
+                self.counter_tried.load(Ordering::Relaxed),
+                DurationDhms(self.started_instant.elapsed())
+            ),
+        );
+    }
+
+    /// Starts the workers, as threads of their own or as tasks of the rayon pool.
+    fn spawn_workers<'scope>(
+        &'scope self,
+        scope: &'scope thread::Scope<'scope, '_>,
+        sender: &Sender<Message>,
+    ) -> io::Result<()> {
+        #[cfg(feature = "rayon")]
+        if let Some(pool) = &self.rayon_pool {
+            let sender = sender.clone();
+            // The pool's scope only returns once all workers are done, so it gets a thread of its
+            // own to block
+            thread::Builder::new()
+                .name("rayon_workers".to_string())
+                .spawn_scoped(scope, move || {
+                    pool.in_place_scope(|pool_scope| {
+                        for num in 0..self.counters_worker.len() {
+                            let sender = sender.clone();
+                            pool_scope.spawn(move |_| self.catching_worker_thread(num, sender));
+                        }
+                    });
+                })?;
+            return Ok(());
+        }
+
+        for num in 0..self.counters_worker.len() {
+            let sender = sender.clone();
+
+            thread::Builder::new()
+                .name(format!("worker-{num:03}"))
+                .spawn_scoped(scope, move || self.catching_worker_thread(num, sender))?;
+        }
+        Ok(())
+    }
+
+    /// Runs a worker, reporting a panic to the finalizer instead of going on without it.
+    fn catching_worker_thread(&self, num: usize, sender: Sender<Message>) {
+        catch_panic(&format!("worker-{num:03}"), &sender.clone(), || {
+            self.worker_thread(num, sender);
         });
+    }
+
+    fn worker_thread(&self, num: usize, sender: Sender<Message>) {
+        const PAUSE_POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+        #[cfg(feature = "tracing")]
+        let _span = tracing::debug_span!("worker", num).entered();
+        #[cfg(feature = "tracing")]
+        tracing::debug!("worker started");
+
+        // Pinned before setting anything up, so that the memory is allocated on the worker's node
+        #[cfg(feature = "numa")]
+        if let Some(placement) = &self.placement
+            && !placement.pin(num)
+        {
+            self.log(
+                Verbosity::Verbose,
+                format_args!("Failed to pin worker {num} to its NUMA node"),
+            );
+        }
+        #[cfg(feature = "hybrid")]
+        if let Some(placement) = &self.core_placement
+            && !placement.pin(num)
+        {
+            self.log(
+                Verbosity::Verbose,
+                format_args!("Failed to pin worker {num} to its core"),
+            );
+        }
+        let batch_size = self.worker_batch_size(num);
+
+        let mut generator = (self.key_generator.0)(num);
+        let mut hex_buffer: HexBuffer = [0; 64];
+        // A copy of its own keeps the workers from sharing the lock and the scratch space of the
+        // regexes, it is only updated when the matcher is replaced
+        let mut matcher_generation = self.matcher_generation.load(Ordering::Acquire);
+        let mut matcher = self.matcher().for_worker();
+        #[cfg(feature = "alloc-check")]
+        let mut meter = alloc_check::Meter::new(&matcher);
+
+        'search: while !self.stop.load(Ordering::Relaxed) {
+            if self.paused.load(Ordering::Relaxed) {
+                thread::park_timeout(PAUSE_POLL_INTERVAL);
+                continue;
+            }
+
+            let generation = self.matcher_generation.load(Ordering::Acquire);
+            if generation != matcher_generation {
+                matcher_generation = generation;
+                matcher = self.matcher().for_worker();
+                #[cfg(feature = "alloc-check")]
+                {
+                    meter = alloc_check::Meter::new(&matcher);
+                }
+            }
+            for _ in 0..batch_size {
+                let candidate = match generator.generate() {
+                    Ok(candidate) => candidate,
+                    Err(err) => match self.retry_generate(num, &mut generator, err) {
+                        Ok(Some(candidate)) => candidate,
+                        Ok(None) => break 'search,
+                        Err(err) => {
+                            let _ = sender.send(Message::Failed(Error::KeyGeneration(err)));
+                            break 'search;
+                        }
+                    },
+                };
+                // Only fingerprints of unknown versions don't fit into the buffer
+                let long_fingerprint_hex;
+                let format = self.config.key_format;
+                let fingerprint_hex = if let Some(algorithm) = format.algorithm() {
+                    // The text of the format instead, e.g. the OpenSSH fingerprint in base64
+                    match format.encode_text(&candidate.key, &mut hex_buffer) {
+                        Some(text) => text,
+                        None => {
+                            let _ = sender.send(Message::Failed(Error::KeyGeneration(
+                                anyhow::anyhow!(
+                                    "{} keys need {algorithm} keys, not {}",
+                                    format.name(),
+                                    candidate.key.pk_algo()
+                                ),
+                            )));
+                            break 'search;
+                        }
+                    }
+                } else {
+                    match keygen::encode_fingerprint_hex(&candidate.fingerprint, &mut hex_buffer) {
+                        Some(hex) => hex,
+                        None => {
+                            long_fingerprint_hex = format!("{:X}", candidate.fingerprint);
+                            &long_fingerprint_hex
+                        }
+                    }
+                };
+                #[cfg(feature = "alloc-check")]
+                meter.generated();
+                let is_match = matcher.is_match(fingerprint_hex);
+                #[cfg(feature = "alloc-check")]
+                meter.matched(fingerprint_hex, !matches!(is_match, Ok(false)));
+                match is_match {
+                    Ok(true) => {
+                        #[cfg(feature = "tracing")]
+                        tracing::trace!(fingerprint = %fingerprint_hex, "candidate matched");
+                        // The channel might already be closed here if we're stopping.
+                        // That is fine, so we just ignore the error.
+                        let _ = sender.send(Message::Key(candidate.key));
+                    }
+                    Ok(false) => {}
+                    Err(err) => {
+                        let _ = sender.send(Message::Failed(err));
+                        break 'search;
+                    }
+                }
+            }
+            self.counter_tried
+                .fetch_add(batch_size as u64, Ordering::Relaxed);
+            self.counters_worker[num].fetch_add(batch_size as u64, Ordering::Relaxed);
+            #[cfg(feature = "alloc-check")]
+            meter.flush(&self.worker_allocations);
+        }
+
+        #[cfg(feature = "tracing")]
+        tracing::debug!(
+            tried = self.counters_worker[num].load(Ordering::Relaxed),
+            "worker stopped"
+        );
+    }
+
+    /// Retries generating a key after `err` with growing pauses, as RNGs and crypto backends can
+    /// fail for a moment, e.g. while the system is short of memory. Returns `None` if the search
+    /// is stopped while waiting.
+    ///
+    /// Gives up after [`KEYGEN_RETRIES`] failures in a row or once the failures of all workers
+    /// exceed [`KEYGEN_FAILURE_BUDGET`], returning the last error.
+    #[cold]
+    fn retry_generate(
+        &self,
+        num: usize,
+        generator: &mut impl KeyGenerator,
+        mut err: anyhow::Error,
+    ) -> anyhow::Result<Option<keygen::Candidate>> {
+        // Generators may fail because the search stopped
+        if self.stop.load(Ordering::Relaxed) {
+            return Ok(None);
+        }
+        let mut delay = KEYGEN_RETRY_DELAY;
+        for _ in 0..KEYGEN_RETRIES {
+            let failures = self.keygen_failures.fetch_add(1, Ordering::Relaxed) + 1;
+            if failures > KEYGEN_FAILURE_BUDGET {
+                return Err(err.context(format!(
+                    "Giving up after {failures} failures to generate keys"
+                )));
+            }
+            self.log(
+                Verbosity::Normal,
+                format_args!(
+                    "Worker {num} failed to generate a key, retrying in {delay:?}: {err:#}"
+                ),
+            );
+            thread::park_timeout(delay);
+            if self.stop.load(Ordering::Relaxed) {
+                return Ok(None);
+            }
+            match generator.generate() {
+                Ok(candidate) => return Ok(Some(candidate)),
+                Err(next) => err = next,
+            }
+            delay *= 2;
+        }
+        Err(err.context(format!(
+            "Giving up after failing to generate a key {} times in a row",
+            KEYGEN_RETRIES + 1
+        )))
+    }
 
-        Cert::try_from(vec![secret_key_packet, Packet::from(sig)])
+    /// The number of keys worker `num` tries per batch, which is smaller on efficiency cores if
+    /// the batches are weighted by the core.
+    #[cfg_attr(not(feature = "hybrid"), allow(unused_variables))]
+    fn worker_batch_size(&self, num: usize) -> usize {
+        #[cfg(feature = "hybrid")]
+        if let Some(placement) = &self.core_placement {
+            return placement.batch_size(num);
+        }
+        self.batch_size
     }
 
-    fn serialize_cert(&self, cert: Cert, to: impl io::Write) -> anyhow::Result<()> {
-        let mut comments = cert.armor_headers();
-        comments.push(format!(
-            "Generated with Fingerprunk. Regex: {}",
-            self.config.regex
-        ));
+    /// The current matcher, which may change while the search is running.
+    fn matcher(&self) -> Arc<PatternSet> {
+        Arc::clone(&self.matcher.read().expect("lock should not be poisoned"))
+    }
+
+    /// Replaces the matcher, also in the workers.
+    fn set_matcher(&self, matcher: PatternSet) {
+        *self.matcher.write().expect("lock should not be poisoned") = Arc::new(matcher);
+        self.matcher_generation.fetch_add(1, Ordering::Release);
+    }
+
+    /// Makes keys matching `regex` count as found, too, from now on.
+    fn add_regex(&self, regex: Regex) {
+        let mut added_regexes = self
+            .added_regexes
+            .lock()
+            .expect("lock should not be poisoned");
+        self.set_matcher(self.matcher().with_regex(regex.clone()));
+        added_regexes.push(regex);
+    }
 
-        let headers: Vec<_> = comments
-            .into_iter()
-            .map(|s| ("Comment".to_string(), s))
+    /// Number of keys found for `job`, including those of previous runs.
+    fn job_found(&self, job: &Job) -> u64 {
+        self.found_per_pattern
+            .lock()
+            .expect("lock should not be poisoned")
+            .get(job.regex.as_str())
+            .copied()
+            .unwrap_or(0)
+    }
+
+    /// The directory for the keys found for `job`, if any.
+    fn job_dir(&self, job: &Job) -> Option<PathBuf> {
+        job.output
+            .clone()
+            .or_else(|| Some(self.config.output_dir.as_ref()?.join(&job.name)))
+    }
+
+    fn jobs_done_message(&self) -> String {
+        match self.config.stop_with_priority {
+            Some(priority) => format!("All jobs with priority {priority} or higher are done"),
+            None => "All jobs are done".to_string(),
+        }
+    }
+
+    /// Progress of every job by priority, e.g. `Alice 1/2, Bob 0/1`.
+    fn jobs_progress(&self) -> String {
+        let progress: Vec<_> = self
+            .config
+            .jobs
+            .iter()
+            .map(|job| format!("{} {}/{}", job.name, self.job_found(job), job.quota))
             .collect();
+        if progress.is_empty() {
+            "-".to_string()
+        } else {
+            progress.join(", ")
+        }
+    }
 
-        let mut writer = armor::Writer::with_headers(to, armor::Kind::SecretKey, headers)?;
+    /// The share of the tried keys of every NUMA node the workers are spread over, if they are.
+    fn node_shares(&self) -> Option<Vec<(usize, f64)>> {
+        #[cfg(feature = "numa")]
+        if let Some(placement) = &self.placement {
+            let tried: Vec<_> = self
+                .counters_worker
+                .iter()
+                .map(|counter| counter.load(Ordering::Relaxed))
+                .collect();
+            let total = tried.iter().sum::<u64>().max(1) as f64;
+            return Some(
+                placement
+                    .groups()
+                    .map(|(id, workers)| {
+                        let node_tried: u64 = workers.iter().map(|&num| tried[num]).sum();
+                        (id, node_tried as f64 / total)
+                    })
+                    .collect(),
+            );
+        }
+        None
+    }
+
+    /// `rate` split up by the NUMA nodes the keys were tried on, e.g. `0: 1.2M, 1: 1.1M`.
+    fn node_rates(&self, rate: f64) -> String {
+        match self.node_shares() {
+            Some(shares) => shares
+                .iter()
+                .map(|(id, share)| format!("{id}: {}", self.format_number(rate * share)))
+                .collect::<Vec<_>>()
+                .join(", "),
+            None => "-".to_string(),
+        }
+    }
+
+    /// Makes the workers search only for the jobs whose quota isn't met yet, returning `false`
+    /// if there are none left or all jobs with [`Config::stop_with_priority`] are done.
+    fn update_job_matcher(&self) -> bool {
+        if let Some(priority) = self.config.stop_with_priority
+            && self
+                .config
+                .jobs
+                .iter()
+                .filter(|job| job.priority >= priority)
+                .all(|job| self.job_found(job) >= job.quota.get())
+        {
+            return false;
+        }
+
+        let regexes: Vec<_> = self
+            .config
+            .jobs
+            .iter()
+            .filter(|job| self.job_found(job) < job.quota.get())
+            .map(|job| job.regex.clone())
+            .collect();
+        if regexes.is_empty() {
+            return false;
+        }
+        let matcher = PatternSet::from_regexes(regexes);
+        if matcher.to_string() != self.matcher().to_string() {
+            self.log(
+                Verbosity::Verbose,
+                format_args!("Searching for the remaining jobs: {matcher}"),
+            );
+            self.set_matcher(matcher);
+        }
+        true
+    }
 
-        // Set the profile to RFC4880 because we generate v4 keys.
-        writer.set_profile(sequoia_openpgp::Profile::RFC4880)?;
+    /// Re-reads the patterns file and atomically replaces the matcher used by the workers.
+    ///
+    /// On error, the current matcher is kept. Statistics are not affected either way.
+    pub fn reload_patterns(&self) -> anyhow::Result<()> {
+        let Some(path) = &self.config.patterns_file else {
+            bail!("No patterns file given");
+        };
+        let file_regexes = matcher::read_patterns_file(path, Some(self.config.key_version))?;
 
-        cert.serialize(&mut writer)?;
-        writer.finalize()?;
+        let added_regexes = self
+            .added_regexes
+            .lock()
+            .expect("lock should not be poisoned");
+        let regexes = self
+            .config
+            .regexes
+            .iter()
+            .chain(self.config.labeled_regexes.iter().map(|(_, regex)| regex))
+            .chain(&file_regexes)
+            .chain(added_regexes.iter())
+            .map(|regex| Arc::new(regex.clone()) as Arc<dyn Matcher>)
+            .chain(self.config.matchers.iter().cloned())
+            .collect();
+        self.set_matcher(PatternSet::new(regexes));
 
         Ok(())
     }
 
+    /// Reloads the patterns file, logging the outcome instead of returning it.
+    #[cfg_attr(
+        not(any(all(unix, feature = "cli"), feature = "watch")),
+        allow(dead_code)
+    )]
+    fn reload_patterns_and_log(&self) {
+        match self.reload_patterns() {
+            Ok(()) => self.log(
+                Verbosity::Normal,
+                format_args!("Reloaded patterns: {}", self.matcher()),
+            ),
+            Err(err) => self.log(
+                Verbosity::Normal,
+                format_args!("Failed to reload patterns: {err:#}"),
+            ),
+        }
+    }
+
+    /// Turns a found key into a certificate, binding `user_id` to it if given.
+    fn key_to_cert(
+        &self,
+        key: &SecretKey,
+        user_id: Option<String>,
+        subkey: Option<SecretSubkey>,
+    ) -> anyhow::Result<Cert> {
+        // Keys that can't sign, like the X25519 keys of age identities, can't certify anything
+        if !key.pk_algo().for_signing() {
+            return Cert::try_from(vec![Packet::SecretKey(key.clone())]);
+        }
+        keygen::build_cert(
+            key,
+            &CertOptions {
+                user_id,
+                password: self.config.password.clone(),
+                subkey,
+                certifier: self.config.certify_with.clone(),
+                proofs: self.config.proofs.clone(),
+            },
+        )
+    }
+
+    /// The directory for a key found for `job` or by a regex with `label`, if any.
+    fn key_dir(&self, job: Option<&Job>, label: Option<&str>) -> Option<PathBuf> {
+        if let Some(job) = job {
+            return self.job_dir(job);
+        }
+        label
+            .and_then(|label| self.config.label_outputs.get(label))
+            .or(self.config.output_dir.as_ref())
+            .cloned()
+    }
+
     fn status_displayer_thread(&self) {
-        const UPDATE_INTERVAL: Duration = Duration::from_millis(250);
+        const REDRAW_INTERVAL: Duration = Duration::from_millis(250);
+        const APPEND_INTERVAL: Duration = Duration::from_secs(10);
 
-        eprint!("\n\n\n\n\n");
+        let update_interval = match self.config.status_mode {
+            StatusMode::Append => APPEND_INTERVAL,
+            _ => REDRAW_INTERVAL,
+        };
+
+        #[cfg(feature = "tracing")]
+        let _span = tracing::debug_span!("status_displayer").entered();
+        #[cfg(feature = "tracing")]
+        tracing::debug!(mode = ?self.config.status_mode, "status displayer started");
+
+        if self.config.status_mode == StatusMode::Redraw {
+            eprint!("{}", "\n".repeat(self.config.status_template.lines()));
+        }
+
+        let mut rate_meter = RateMeter::new(self.started_instant);
+        let mut bell_last_rung = None;
 
         while !self.stop.load(Ordering::Relaxed) {
-            self.print_status();
+            rate_meter.update(Instant::now(), self.counter_tried.load(Ordering::Relaxed));
+            self.print_status(&rate_meter);
+            // Logs are no place for bells
+            if self.config.status_mode == StatusMode::Redraw {
+                self.ring_bell(&mut bell_last_rung);
+            }
             // We are parking the thread instead of sleeping so we can unpark it when we want to
             // stop the program.
-            thread::park_timeout(UPDATE_INTERVAL);
+            thread::park_timeout(update_interval);
         }
 
-        self.print_status();
+        self.print_status(&rate_meter);
+        if self.config.status_mode == StatusMode::Redraw {
+            self.ring_bell(&mut bell_last_rung);
+        }
     }
 
-    fn print_status(&self) {
-        struct DurationDhms(Duration);
+    fn print_status(&self, rate_meter: &RateMeter) {
+        let number = |value: f64| self.format_number(value);
 
-        impl fmt::Display for DurationDhms {
-            fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-                let seconds = self.0.as_secs();
-                let (minutes, seconds) = seconds.div_rem(&60);
-                let (hours, minutes) = minutes.div_rem(&60);
-                let (days, hours) = hours.div_rem(&24);
+        let elapsed = self.started_instant.elapsed();
+        let tried = self.counter_tried.load(Ordering::Relaxed);
+        let avg_rate = tried as f64 / elapsed.as_secs_f64();
+        let rate = rate_meter.smoothed().unwrap_or(avg_rate);
+        let found = self.counter_found.load(Ordering::Relaxed);
+        let matcher = self.matcher();
+        let estimate = matcher
+            .estimate()
+            .filter(|estimate| estimate.probability > 0.0);
+        let expected = self.expected_tries(estimate, found);
 
-                write!(f, "{days}d {hours: >2}h {minutes: >2}m {seconds: >2}s")
-            }
+        if self.config.json_logs {
+            self.print_json(
+                "status",
+                json!({
+                    "elapsed_secs": elapsed.as_secs(),
+                    "tried": tried,
+                    "rate": rate.round(),
+                    "avg_rate": avg_rate.round(),
+                    "found": found,
+                    "expected": expected.map(f64::round),
+                    "energy_joules": self.energy_used().map(f64::round),
+                    "pattern": matcher.to_string(),
+                    "jobs": self
+                        .config
+                        .jobs
+                        .iter()
+                        .map(|job| json!({
+                            "name": job.name,
+                            "found": self.job_found(job),
+                            "quota": job.quota,
+                        }))
+                        .collect::<Vec<_>>(),
+                }),
+            );
+            return;
         }
 
-        const FORMAT_WIDTH: usize = 12;
+        const DEFAULT_BAR_WIDTH: usize = 30;
 
-        let duration = DurationDhms(self.started_instant.elapsed());
-        let keys = self.counter_tried.load(Ordering::Relaxed);
-        let keys_per_sec = keys as f64 / duration.0.as_secs_f64();
-        let found = self.counter_found.load(Ordering::Relaxed);
-        eprint!(
-            "\x1b[F\x1b[F\x1b[F\x1b[F\x1b[F\
-                Time:  {duration}\n\
-                Tried: {keys: >w$} keys\n\
-                Rate:  {keys_per_sec: >w$.0} keys/s\n\
-                ---\n\
-                Found: {found: >w$} keys\n",
-            w = FORMAT_WIDTH
+        let status = self
+            .config
+            .status_template
+            .render(|field, width| match field {
+                Field::Elapsed => DurationDhms(elapsed).to_string(),
+                Field::Tried => number(tried as f64),
+                Field::Rate => number(rate),
+                Field::RateMin => rate_meter
+                    .min_max()
+                    .map_or_else(|| "-".to_string(), |(min, _)| number(min)),
+                Field::RateMax => rate_meter
+                    .min_max()
+                    .map_or_else(|| "-".to_string(), |(_, max)| number(max)),
+                Field::AvgRate => number(avg_rate),
+                Field::Expected => expected.map_or_else(|| "-".to_string(), number),
+                Field::Estimate => estimate.map_or_else(
+                    || "unknown".to_string(),
+                    |estimate| estimate.method.to_string(),
+                ),
+                Field::Progress => expected.map_or_else(
+                    || "-".to_string(),
+                    |expected| format!("{:.1}", tried as f64 / expected * 100.0),
+                ),
+                Field::Eta => estimate
+                    .and_then(|estimate| {
+                        Duration::try_from_secs_f64(estimate.median_tries() / rate).ok()
+                    })
+                    .map_or_else(|| "unknown".to_string(), |d| DurationDhms(d).to_string()),
+                Field::Bar => progress_bar(
+                    expected.map(|expected| tried as f64 / expected),
+                    width.unwrap_or(DEFAULT_BAR_WIDTH),
+                ),
+                Field::Found => found.to_string(),
+                Field::Last => match self
+                    .found_fingerprints
+                    .lock()
+                    .expect("lock should not be poisoned")
+                    .last()
+                {
+                    Some(fingerprint) => self.highlight_match(fingerprint),
+                    None => "-".to_string(),
+                },
+                Field::Pattern => matcher.to_string(),
+                Field::Jobs => self.jobs_progress(),
+                Field::Nodes => self.node_rates(rate),
+                Field::Energy => self.energy_summary(found),
+                Field::Cpu => self.cpu_status(),
+            });
+
+        // Failing to display the status is not worth aborting the search over.
+        let _ = match self.config.status_mode {
+            StatusMode::Append => self.append_status(&status),
+            _ => self.redraw_status(&status),
+        };
+    }
+
+    /// Prints `status` below the previous output, prefixing each line with a timestamp.
+    fn append_status(&self, status: &str) -> io::Result<()> {
+        let timestamp = format_timestamp(SystemTime::now());
+        let mut stderr = io::stderr().lock();
+
+        for line in status.split('\n') {
+            writeln!(stderr, "[{timestamp}] {line}")?;
+        }
+        stderr.flush()
+    }
+
+    /// Overwrites the previously printed status with `status`.
+    #[cfg(not(target_arch = "wasm32"))]
+    fn redraw_status(&self, status: &str) -> io::Result<()> {
+        let lines = u16::try_from(self.config.status_template.lines()).unwrap_or(u16::MAX);
+        let mut stderr = io::stderr().lock();
+
+        queue!(stderr, cursor::MoveToPreviousLine(lines))?;
+        let pending_messages = std::mem::take(
+            &mut *self
+                .pending_messages
+                .lock()
+                .expect("lock should not be poisoned"),
         );
+        for line in pending_messages
+            .iter()
+            .map(String::as_str)
+            .chain(status.split('\n'))
+        {
+            queue!(
+                stderr,
+                Print(line),
+                Clear(ClearType::UntilNewLine),
+                // Also return the carriage explicitly, which is not implied in raw mode
+                Print("\r\n")
+            )?;
+        }
+        stderr.flush()
+    }
+
+    /// Without a terminal to redraw the status on, it is appended instead.
+    #[cfg(target_arch = "wasm32")]
+    fn redraw_status(&self, status: &str) -> io::Result<()> {
+        self.append_status(status)
+    }
+
+    fn format_number(&self, value: f64) -> String {
+        if self.config.humanize_numbers {
+            humanize(value).trim_end().to_string()
+        } else {
+            format!("{value:.0}")
+        }
+    }
+
+    /// Expected number of tries until the search is done, given the number of keys found so far.
+    fn expected_tries(&self, estimate: Option<&Estimate>, found: u64) -> Option<f64> {
+        estimate
+            .filter(|estimate| estimate.probability > 0.0)
+            .map(|estimate| {
+                let wanted = self.config.stop_after.map_or(found + 1, NonZeroU64::get);
+                wanted as f64 * estimate.expected_tries()
+            })
+    }
+
+    /// Returns the fingerprint with the part matched by the regex highlighted in color.
+    ///
+    /// Appended status lines are not highlighted so that logs don't contain escape sequences.
+    fn highlight_match(&self, fingerprint_hex: &str) -> String {
+        match self.matcher().find(fingerprint_hex) {
+            #[cfg(not(target_arch = "wasm32"))]
+            Some(range) if self.config.status_mode == StatusMode::Redraw => format!(
+                "{}{}{}",
+                &fingerprint_hex[..range.start],
+                fingerprint_hex[range.clone()].green().bold(),
+                &fingerprint_hex[range.end..]
+            ),
+            _ => fingerprint_hex.to_string(),
+        }
+    }
+}
+
+/// Runs the body of a thread, sending [`Error::Panicked`] to the finalizer if it panics, so that
+/// the search stops with an error instead of hanging or silently losing the thread.
+fn catch_panic(thread: &str, sender: &Sender<Message>, body: impl FnOnce()) {
+    if let Err(payload) = panic::catch_unwind(AssertUnwindSafe(body)) {
+        let _ = sender.send(Message::Failed(Error::panicked(thread, &*payload)));
+    }
+}
+
+/// Makes Ctrl-C stop the search receiving from `sender`.
+///
+/// The handler can only be set once per process, so it stays registered and is pointed at every
+/// new search instead, e.g. for the searches of `fingerprunk self-test`.
+#[cfg(feature = "cli")]
+fn forward_ctrlc(sender: Sender<Message>) -> anyhow::Result<()> {
+    static CURRENT: Mutex<Option<Sender<Message>>> = Mutex::new(None);
+
+    let mut current = CURRENT.lock().expect("lock should not be poisoned");
+    if current.is_none() {
+        ctrlc::set_handler(|| {
+            if let Some(sender) = CURRENT
+                .lock()
+                .expect("lock should not be poisoned")
+                .as_ref()
+            {
+                let _ = sender.send(Message::Stop);
+            }
+        })?;
     }
+    *current = Some(sender);
+    Ok(())
 }
@@ -1,16 +1,54 @@
+use std::fs::File;
+#[cfg(feature = "tracing")]
+use std::sync::Mutex;
 use std::{
+    env,
+    ffi::OsString,
     io::{self, IsTerminal},
-    num::NonZeroU64,
+    num::{NonZeroU64, NonZeroUsize},
+    path::{Path, PathBuf},
+    process::ExitCode,
+    str::FromStr,
+    time::{Duration, Instant, SystemTime},
 };
 
 use anyhow::{Context as AnyhowContext, anyhow};
-use clap::{ArgAction, Parser, ValueEnum};
+use clap::{ArgAction, CommandFactory, Parser, Subcommand, ValueEnum, parser::ValueSource};
 use fancy_regex::Regex;
-use fingerprunk::Fingerprunk;
+#[cfg(feature = "hybrid")]
+use fingerprunk::CorePolicy;
+use fingerprunk::{
+    Config, ConfigError, Fingerprunk, OutputOrder, Policy, Verbosity,
+    collision::KeyIds,
+    estimate::Estimate,
+    format::{FormatPattern, KeyFormat},
+    keygen::{self, Certifier, KeyVersion},
+    matcher::Matcher,
+    notify::EmailConfig,
+    pattern::parse_pattern,
+    scan::{ScanMatch, ScanSummary, Scanner},
+    status::{self, DurationDhms, StatusMode, StatusTemplate, humanize},
+};
+use sequoia_openpgp::{
+    Cert,
+    cert::{CertParser, amalgamation::key::PrimaryKey},
+    parse::Parse,
+    policy::StandardPolicy,
+    serialize::SerializeInto,
+};
 
 #[derive(Parser, Debug)]
-#[command(version, about, long_about = None)]
+#[command(
+    version,
+    about,
+    long_about = None,
+    args_conflicts_with_subcommands = true,
+    subcommand_negates_reqs = true
+)]
 struct Args {
+    #[command(subcommand)]
+    command: Option<Command>,
+
     /// Filter key fingerprints by using a regular expression.
     ///
     /// This regex is matched against the hexadecimal representation of the fingerprint, without
@@ -18,27 +56,1465 @@ struct Args {
     ///
     /// This is implemented using the fancy-regex library: <https://crates.io/crates/fancy-regex>.
     /// You can test and debug your regex here: <https://fancy-regex.github.io/fancy-regex/>.
-    #[arg(short, long)]
-    regex: Regex,
+    ///
+    /// Can be given several times. A regex can be prefixed with a label as in cafe=^CAFE, so
+    /// that its matches can be routed with --out.
+    #[arg(short, long, env = "FINGERPRUNK_REGEX")]
+    #[cfg_attr(
+        not(feature = "plugin"),
+        arg(required_unless_present_any = ["patterns_file", "jobs", "farm"])
+    )]
+    #[cfg_attr(
+        feature = "plugin",
+        arg(required_unless_present_any = ["patterns_file", "jobs", "farm", "matcher_plugin"])
+    )]
+    regex: Vec<LabeledRegex>,
+
+    /// Write the keys matching the regexes with the given label to their own files in this
+    /// directory, e.g. cafe=./cafe-keys/. Can be given several times.
+    #[arg(
+        long,
+        env = "FINGERPRUNK_OUT",
+        value_name = "LABEL=PATH",
+        value_parser = parse_label_output
+    )]
+    out: Vec<(String, PathBuf)>,
+
+    /// Read additional regexes from a file, one per line; empty lines and lines starting with #
+    /// are ignored.
+    ///
+    /// On Unix, the file is re-read on SIGHUP without losing the statistics of the running
+    /// search.
+    #[arg(long, env = "FINGERPRUNK_PATTERNS_FILE", value_name = "PATH")]
+    patterns_file: Option<PathBuf>,
+
+    /// Find the keys a WebAssembly module scores above 0, keeping the criteria out of the
+    /// arguments, the logs and the key files, which only name the file. Can be given several
+    /// times.
+    ///
+    /// The module exports its memory, input() returning the address of a 64-byte buffer, and
+    /// score(len), which scores the fingerprint bytes written there. It can't import anything and
+    /// may execute at most ten million instructions per key.
+    #[cfg(feature = "plugin")]
+    #[arg(
+        long,
+        env = "FINGERPRUNK_MATCHER_PLUGIN",
+        value_name = "FILE",
+        conflicts_with = "farm"
+    )]
+    matcher_plugin: Vec<PathBuf>,
+
+    /// Find keys for several identities, read from a CSV file with the columns name, pattern
+    /// and optionally email, quota, priority and output.
+    ///
+    /// The keys get the user ID "name <email>" and are written to the job's output directory,
+    /// by default the subdirectory of --output-dir named after the identity. All jobs share the
+    /// worker threads, jobs with a higher priority (default: 0) are matched first, and the
+    /// search stops once every quota (default: 1) is met.
+    #[arg(
+        long,
+        env = "FINGERPRUNK_JOBS",
+        value_name = "PATH",
+        conflicts_with_all = ["regex", "patterns_file", "stop_after"]
+    )]
+    jobs: Option<PathBuf>,
+
+    /// Stop once all jobs with this priority or higher have met their quotas, abandoning the
+    /// jobs with a lower priority.
+    #[arg(
+        long,
+        env = "FINGERPRUNK_STOP_WITH_PRIORITY",
+        value_name = "PRIORITY",
+        requires = "jobs",
+        allow_negative_numbers = true
+    )]
+    stop_with_priority: Option<i64>,
+
+    /// Discard found keys whose 64-bit key ID collides with a key in this file, with a warning.
+    ///
+    /// The file is an OpenPGP keyring, e.g. an organization's, or lists fingerprints or key IDs
+    /// in hex, one per line.
+    #[arg(long, env = "FINGERPRUNK_AVOID_KEYIDS", value_name = "FILE")]
+    avoid_keyids: Option<PathBuf>,
+
+    /// Look up the key ID of every found key on a keyserver, by default keys.openpgp.org, and warn
+    /// if a published key has it.
+    ///
+    /// The key is kept either way; whether to keep searching is up to you. The keyserver must
+    /// speak VKS, like Hagrid does.
+    #[cfg(feature = "keyserver")]
+    #[arg(
+        long,
+        env = "FINGERPRUNK_KEYSERVER",
+        value_name = "URL",
+        num_args = 0..=1,
+        default_missing_value = "https://keys.openpgp.org"
+    )]
+    keyserver: Option<String>,
+
+    /// Reload the patterns file automatically whenever it changes.
+    #[cfg(feature = "watch")]
+    #[arg(long, env = "FINGERPRUNK_WATCH_PATTERNS", requires = "patterns_file")]
+    watch_patterns: bool,
+
+    /// Print nothing but found keys and fatal errors.
+    #[arg(
+        short,
+        long,
+        env = "FINGERPRUNK_QUIET",
+        action = ArgAction::SetTrue,
+        conflicts_with_all = ["verbose", "status"]
+    )]
+    quiet: bool,
+
+    /// Print the configuration at startup (-v) and details about every match (-vv).
+    #[arg(short, long, env = "FINGERPRUNK_VERBOSE", action = ArgAction::Count)]
+    verbose: u8,
+
+    /// Write log events of the given level and above (error, warn, info, debug, trace).
+    ///
+    /// Logs are written to stderr unless --log-file is given.
+    #[cfg(feature = "tracing")]
+    #[arg(long, env = "FINGERPRUNK_LOG_LEVEL")]
+    log_level: Option<tracing::Level>,
+
+    /// Write log events to the given file instead of stderr (implies --log-level info).
+    #[cfg(feature = "tracing")]
+    #[arg(long, env = "FINGERPRUNK_LOG_FILE")]
+    log_file: Option<PathBuf>,
+
+    /// Export metrics (keys tried, rate, found keys by pattern) and spans of the search and of
+    /// every found key via OTLP.
+    ///
+    /// The exporter is configured with the standard OTEL_EXPORTER_OTLP_* environment variables and
+    /// sends to http://localhost:4318 by default.
+    #[cfg(feature = "otel")]
+    #[arg(long, env = "FINGERPRUNK_OTEL")]
+    otel: bool,
 
     /// Show status information.
     ///
-    /// By default, status information is only shown if stderr is bound to a terminal and stdout is
-    /// *not* bound to a terminal. The latter in particular prevents found keys (which are printed
-    /// to stdin) from being "overwritten" by status information printed to stderr.
-    #[arg(long, value_enum, default_value_t)]
+    /// By default, status information is only shown if stderr is bound to a terminal that supports
+    /// redrawing the status in place and stdout is *not* bound to a terminal. The latter in
+    /// particular prevents found keys (which are printed to stdin) from being "overwritten" by
+    /// status information printed to stderr.
+    ///
+    /// If stderr is not bound to a terminal (e.g. redirected to a log file), timestamped status
+    /// lines are appended periodically instead.
+    #[arg(long, env = "FINGERPRUNK_STATUS", value_enum, default_value_t)]
     status: StatusEnabled,
 
     /// Stop once the specified number of matching keys has been found.
-    #[arg(long)]
+    #[arg(long, env = "FINGERPRUNK_STOP_AFTER")]
     stop_after: Option<NonZeroU64>,
 
+    /// Generate this many keys without matching them, e.g. for a classroom or test environment.
+    ///
+    /// Every key is kept, with the user ID, subkey and password given as usual, so the keys are
+    /// written as fast as their certificates are built; use --finalizers to build them on several
+    /// threads.
+    #[arg(
+        long,
+        env = "FINGERPRUNK_FARM",
+        value_name = "N",
+        conflicts_with_all = ["regex", "patterns_file", "jobs", "stop_after"]
+    )]
+    farm: Option<NonZeroU64>,
+
+    /// Number of threads generating keys (default: one per CPU).
+    #[arg(long, env = "FINGERPRUNK_THREADS")]
+    threads: Option<NonZeroUsize>,
+
+    /// Number of keys every thread tries before updating the counters (default: 1).
+    #[arg(long, env = "FINGERPRUNK_BATCH_SIZE")]
+    batch_size: Option<NonZeroUsize>,
+
+    /// Number of threads building the certificates of found keys (default: 1).
+    ///
+    /// More threads help if keys are found faster than they can be signed and encrypted, e.g.
+    /// with short patterns and --password. Keys are still output in the order they were found
+    /// unless --unordered is given.
+    #[arg(long, env = "FINGERPRUNK_FINALIZERS")]
+    finalizers: Option<NonZeroUsize>,
+
+    /// Output found keys as soon as their certificates are built rather than in the order they
+    /// were found.
+    #[arg(long, env = "FINGERPRUNK_UNORDERED", requires = "finalizers")]
+    unordered: bool,
+
+    /// Measure which number of threads and batch size is fastest for a few seconds before
+    /// searching.
+    ///
+    /// Thread counts between the number of physical and logical CPUs are tried. A thread count or
+    /// batch size given with --threads or --batch-size is kept.
+    #[arg(long, env = "FINGERPRUNK_AUTO_TUNE")]
+    auto_tune: bool,
+
+    /// Spread the threads over the NUMA nodes of a multi-socket machine and pin them there.
+    ///
+    /// Every thread keeps its memory on its own node then. The rate of every node can be shown
+    /// with the {nodes} placeholder of --status-template and is printed at the end with -v.
+    #[cfg(feature = "numa")]
+    #[arg(long, env = "FINGERPRUNK_NUMA")]
+    numa: bool,
+
+    /// Which cores of a CPU with performance and efficiency cores to run on: all, performance or
+    /// weighted.
+    ///
+    /// With performance, the threads are pinned to the performance cores, one per CPU of them
+    /// unless given with --threads. With weighted, they are pinned to all cores and those on
+    /// efficiency cores try fewer keys per batch, in proportion to the speed of their core.
+    #[cfg(feature = "hybrid")]
+    #[arg(
+        long,
+        env = "FINGERPRUNK_CORES",
+        value_name = "POLICY",
+        default_value = "all"
+    )]
+    cores: CorePolicy,
+
+    /// Generate v4 keys, which every OpenPGP implementation supports, or v6 keys (RFC 9580).
+    ///
+    /// v6 fingerprints have 64 hex digits instead of 40 and start with the key ID instead of
+    /// ending with it. The regexes are checked against the length of the chosen version.
+    #[arg(
+        long,
+        env = "FINGERPRUNK_KEY_VERSION",
+        value_name = "VERSION",
+        default_value = "4"
+    )]
+    key_version: KeyVersion,
+
+    /// Bind this user ID to found keys, e.g. "Alice <alice@example.org>".
+    #[arg(
+        long,
+        env = "FINGERPRUNK_UID",
+        value_name = "USER_ID",
+        conflicts_with = "jobs"
+    )]
+    uid: Option<String>,
+
+    /// Add an encryption subkey whose fingerprint matches this regex to found keys.
+    ///
+    /// After a key matches, subkeys are generated for it until one matches, too, so that both
+    /// fingerprints `gpg -k` lists are vanity. This happens on the finalizer threads, one key
+    /// each, while the workers keep searching, so the regex should match far more often than the
+    /// others, or use --finalizers.
+    #[arg(long, env = "FINGERPRUNK_SUBKEY_REGEX", value_name = "REGEX")]
+    subkey_regex: Option<String>,
+
+    /// Certify the user IDs of found keys with the key in this file, e.g. an organization's CA
+    /// key.
+    ///
+    /// The file must contain the secret certification key, armored or binary; its password is
+    /// prompted for if it is encrypted. Everyone who trusts that key then sees the found keys as
+    /// valid right away.
+    #[arg(long, env = "FINGERPRUNK_CERTIFY_WITH", value_name = "FILE")]
+    certify_with: Option<PathBuf>,
+
+    /// Sign the message in this file with every found key, writing the detached signature next to
+    /// the key.
+    ///
+    /// The signature goes into <FINGERPRINT>.proof.asc in the output directory, or follows the key
+    /// on stdout. Published with the key, it proves that whoever published it holds the secret
+    /// key, e.g. for a message naming the owner and the date.
+    #[arg(long, env = "FINGERPRUNK_SIGN_PROOF", value_name = "FILE")]
+    sign_proof: Option<PathBuf>,
+
+    /// Add an identity proof to found keys, so that they can be used as Keyoxide profiles right
+    /// away. Can be given multiple times.
+    ///
+    /// Each URI is added to the self-signatures as proof@ariadne.id notation, e.g.
+    /// https://github.com/alice or dns:example.org?type=TXT. The claims still have to be made on
+    /// those services, naming the fingerprint of the found key.
+    #[arg(long = "proof", env = "FINGERPRUNK_PROOF", value_name = "URI")]
+    proofs: Vec<String>,
+
+    /// Use a custom layout for the status display.
+    ///
+    /// Available placeholders: {elapsed}, {tried}, {rate}, {rate_min}, {rate_max}, {avg_rate},
+    /// {expected}, {estimate}, {progress}, {eta}, {bar}, {found}, {last}, {pattern}, {jobs},
+    /// {nodes}, {energy} and {cpu}.
+    /// Write {name:width} to right-align a value, {{ and }} for literal braces and \n for a line
+    /// break.
+    #[arg(long, env = "FINGERPRUNK_STATUS_TEMPLATE")]
+    status_template: Option<StatusTemplate>,
+
+    /// Show the status as a single-line progress bar towards the expected number of tries.
+    #[arg(
+        long,
+        env = "FINGERPRUNK_PROGRESS_BAR",
+        action = ArgAction::SetTrue,
+        conflicts_with = "status_template"
+    )]
+    progress_bar: bool,
+
+    /// Show a full-screen terminal interface instead of the status display.
+    ///
+    /// Keys: p to pause/resume, s to refresh, q to quit.
+    #[cfg(feature = "tui")]
+    #[arg(long, env = "FINGERPRUNK_TUI", action = ArgAction::SetTrue, conflicts_with = "headless")]
+    tui: bool,
+
+    /// Ring the terminal bell when a key is found while the status is displayed.
+    #[arg(long, env = "FINGERPRUNK_BELL", action = ArgAction::SetTrue)]
+    bell: bool,
+
+    /// Repeat the bell every given number of seconds until Enter is pressed.
+    #[arg(
+        long,
+        env = "FINGERPRUNK_BELL_REPEAT",
+        value_name = "SECONDS",
+        requires = "bell"
+    )]
+    bell_repeat: Option<NonZeroU64>,
+
+    /// POST a JSON description of every found key (fingerprint, pattern, elapsed time, number of
+    /// tried keys and host name) to the given URL.
+    #[cfg(feature = "webhook")]
+    #[arg(long, env = "FINGERPRUNK_WEBHOOK", value_name = "URL")]
+    webhook: Option<String>,
+
+    /// Email the fingerprint and a run summary to the given address for every found key.
+    ///
+    /// The email is submitted through a sendmail-compatible command, so SMTP settings are taken
+    /// from the local mail transfer agent (e.g. msmtp or Postfix).
+    #[arg(long, env = "FINGERPRUNK_NOTIFY_EMAIL", value_name = "ADDRESS")]
+    notify_email: Option<String>,
+
+    /// The sendmail-compatible command used for --notify-email.
+    #[arg(
+        long,
+        env = "FINGERPRUNK_SENDMAIL",
+        value_name = "COMMAND",
+        default_value = "sendmail",
+        requires = "notify_email"
+    )]
+    sendmail: PathBuf,
+
+    /// Log every found key to syslog (or journald) as an audit trail.
+    #[cfg(unix)]
+    #[arg(long, env = "FINGERPRUNK_LOG_SYSLOG")]
+    log_syslog: bool,
+
+    /// Detach from the terminal and keep running in the background.
+    ///
+    /// Found keys are written to the file given by --daemon-stdout. SIGINT and SIGTERM stop the
+    /// search, SIGHUP reloads the patterns file.
+    #[cfg(unix)]
+    #[arg(long, env = "FINGERPRUNK_DAEMON", requires = "daemon_stdout")]
+    daemon: bool,
+
+    /// Write the process ID of the daemon to this file.
+    #[cfg(unix)]
+    #[arg(
+        long,
+        env = "FINGERPRUNK_PIDFILE",
+        value_name = "PATH",
+        requires = "daemon"
+    )]
+    pidfile: Option<PathBuf>,
+
+    /// Append found keys to this file in daemon mode.
+    #[cfg(unix)]
+    #[arg(
+        long,
+        env = "FINGERPRUNK_DAEMON_STDOUT",
+        value_name = "PATH",
+        requires = "daemon"
+    )]
+    daemon_stdout: Option<PathBuf>,
+
+    /// Append status and log messages to this file in daemon mode instead of discarding them.
+    #[cfg(unix)]
+    #[arg(
+        long,
+        env = "FINGERPRUNK_DAEMON_STDERR",
+        value_name = "PATH",
+        requires = "daemon"
+    )]
+    daemon_stderr: Option<PathBuf>,
+
+    /// Accept control commands (status, pause, resume, stop, add-pattern REGEX) on a Unix socket.
+    #[cfg(unix)]
+    #[arg(long, env = "FINGERPRUNK_CONTROL_SOCKET", value_name = "PATH")]
+    control_socket: Option<PathBuf>,
+
+    /// Serve a JSON API for status, found fingerprints, pause/resume and stop on this address.
+    ///
+    /// The API is unauthenticated, so only listen on trusted interfaces, e.g. 127.0.0.1:8080.
+    #[cfg(feature = "http-api")]
+    #[arg(long, env = "FINGERPRUNK_LISTEN", value_name = "ADDRESS")]
+    listen: Option<String>,
+
+    /// Checkpoint the search to this file and continue from there when restarting, so that
+    /// searches spanning several runs report their cumulative effort.
+    ///
+    /// The checkpoint contains the number of tried and found keys (also per pattern), the elapsed
+    /// time and the fingerprints of the found keys, but no secret keys.
+    #[arg(long, env = "FINGERPRUNK_STATE_FILE", value_name = "PATH")]
+    state_file: Option<PathBuf>,
+
+    /// Fail if the state file doesn't contain a checkpoint to continue from.
+    #[arg(long, env = "FINGERPRUNK_RESUME", requires = "state_file")]
+    resume: bool,
+
+    /// Continue from the checkpoint even if the patterns or --stop-after have changed.
+    #[arg(long, env = "FINGERPRUNK_ALLOW_CONFIG_CHANGE", requires = "state_file")]
+    allow_config_change: bool,
+
+    /// Write every found key to its own file <FINGERPRINT>.asc in this directory instead of
+    /// stdout.
+    #[arg(long, env = "FINGERPRUNK_OUTPUT_DIR", value_name = "PATH")]
+    output_dir: Option<PathBuf>,
+
+    /// Answer yes to all questions before the search, e.g. whether to print found keys to a
+    /// terminal, where the secret keys end up in the scrollback.
+    ///
+    /// Without a terminal to ask on, the search fails instead of asking.
+    #[arg(short, long, env = "FINGERPRUNK_YES")]
+    yes: bool,
+
+    /// Like --yes, but also search despite hard warnings, e.g. for a pattern matching so often
+    /// without --stop-after that keys are written about as fast as they are generated.
+    #[arg(long, env = "FINGERPRUNK_FORCE")]
+    force: bool,
+
+    /// Run without a terminal, e.g. in a container: log JSON lines to stdout instead of text to
+    /// stderr and write found keys only to --output-dir.
+    ///
+    /// Like all options, this can also be set through the environment, here with
+    /// FINGERPRUNK_HEADLESS=true.
+    #[arg(
+        long,
+        env = "FINGERPRUNK_HEADLESS",
+        requires = "output_dir",
+        conflicts_with = "password"
+    )]
+    headless: bool,
+
+    /// Show exact integers instead of humanized numbers (e.g. "1.37 G") in the status display.
+    #[arg(long, env = "FINGERPRUNK_RAW_NUMBERS", action = ArgAction::SetTrue)]
+    raw_numbers: bool,
+
     /// Prompt for a password and use it to encrypt found keys.
     ///
     /// By default, found keys are printed to stdout unencrypted. Use this if you actually plan to
     /// use generated keys.
     #[arg(short, long, action = ArgAction::SetTrue)]
     password: bool,
+
+    /// Check the configuration and print it instead of searching.
+    ///
+    /// Checks that the patterns can match, the output directories and the state file are
+    /// writable, the password can be prompted for and the webhook, coordinator etc. are
+    /// reachable. No keys are generated.
+    #[arg(long, env = "FINGERPRUNK_DRY_RUN")]
+    dry_run: bool,
+
+    /// Confine the process once the search is set up (Linux and OpenBSD).
+    ///
+    /// Afterwards, only the output directories, the directory of the state file and the patterns
+    /// file can be opened, other programs can't be run and the network can only be used for the
+    /// webhook and the coordinator. Uses Landlock and seccomp on Linux, unveil and pledge on
+    /// OpenBSD.
+    #[cfg(feature = "sandbox")]
+    #[arg(long, env = "FINGERPRUNK_SANDBOX", conflicts_with = "notify_email")]
+    sandbox: bool,
+
+    /// Refuse options using the network, like --webhook and --notify-email, and make creating
+    /// internet sockets fail where possible (Linux with the sandbox feature).
+    ///
+    /// Meant for key ceremonies on air-gapped machines.
+    #[arg(long, env = "FINGERPRUNK_OFFLINE")]
+    offline: bool,
+
+    /// Check the found keys against common key-issuance rules and warn about shortcomings, like
+    /// SHA-1 fingerprints, no expiration and unencrypted secret keys.
+    ///
+    /// With strict, the shortcomings that options can avoid are errors: secret keys without a
+    /// password or with one shorter than 12 characters.
+    #[arg(long, env = "FINGERPRUNK_POLICY", value_name = "POLICY")]
+    policy: Option<Policy>,
+
+    /// Append a record of every emitted secret key to this file: its fingerprint, the time, the
+    /// pattern it matched, where it was written and a hash of the search's settings.
+    ///
+    /// Every record includes the hash of the previous one, so that changes to the file are
+    /// detected by `fingerprunk audit-verify`. Appending to a file whose chain is broken fails.
+    #[arg(long, env = "FINGERPRUNK_AUDIT_LOG", value_name = "FILE")]
+    audit_log: Option<PathBuf>,
+
+    /// The format of the keys to search for: openpgp, ssh, age or onion.
+    ///
+    /// With ssh, the regexes are matched against the base64 of the OpenSSH fingerprint of
+    /// Ed25519 keys, which `ssh-keygen -l` shows after "SHA256:", case-sensitively. Found keys
+    /// are written as unencrypted OpenSSH private keys followed by their public key line, or with
+    /// --output-dir as a private key file and a .pub file each, with the user ID as comment.
+    ///
+    /// With age, the regexes are matched against the recipient of X25519 identities, like
+    /// "age1...", which is lowercase Bech32 without the letters b, i and o and the digit 1 after
+    /// the prefix. Found keys are written as identity files like those of age-keygen.
+    ///
+    /// With onion, the regexes are matched against the 56 characters of Tor v3 onion addresses
+    /// before ".onion", which are lowercase base32 (a-z and 2-7). Found keys are written as the
+    /// hostname followed by the key for ADD_ONION, or with --output-dir as a hidden service
+    /// directory named after the hostname.
+    #[arg(
+        long,
+        env = "FINGERPRUNK_KEY_FORMAT",
+        value_name = "FORMAT",
+        default_value = "openpgp"
+    )]
+    key_format: KeyFormat,
+
+    /// Run the search defined by this preset in the config file.
+    ///
+    /// A preset sets options like the command line does, e.g. [preset.cafe] with regex = "^CAFE"
+    /// and stop_after = 1. Options given on the command line or through the environment take
+    /// precedence over the preset. Presets can't be used with subcommands, which ignore
+    /// FINGERPRUNK_PRESET.
+    #[arg(long, env = "FINGERPRUNK_PRESET", value_name = "NAME")]
+    preset: Option<String>,
+
+    /// Read presets from this TOML file (default: fingerprunk/config.toml in the user's config
+    /// directory, e.g. ~/.config/fingerprunk/config.toml).
+    #[arg(long, env = "FINGERPRUNK_CONFIG", value_name = "PATH")]
+    config: Option<PathBuf>,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Estimate how long finding a key matching the regexes takes on this machine.
+    ///
+    /// The match probability is derived from the regexes if they are literals and measured on
+    /// random fingerprints otherwise. Unless given with --rate, the rate is measured by
+    /// searching for a few seconds.
+    Estimate(EstimateArgs),
+
+    /// Measure this machine, estimate the regexes and recommend how to search for them.
+    ///
+    /// Prints the time until the first match with 50%, 90% and 99% chance and whether the search
+    /// calls for more threads, another build, more machines or a shorter pattern.
+    Calibrate(CalibrateArgs),
+
+    /// Measure how many keys per second this machine generates and checks.
+    ///
+    /// Key generation and matching are measured on a single thread first, then the whole search
+    /// on all threads, so that machines and settings can be compared.
+    Bench(BenchArgs),
+
+    /// Check which fingerprints of existing keys match the regexes.
+    ///
+    /// Reads certificates or keyrings, armored or binary, and prints every primary key and subkey
+    /// with the regex it matches, if any. Fails if no key matches, e.g. for checks in CI.
+    Verify(VerifyArgs),
+
+    /// Find the keys matching the regexes in existing keyrings or keyserver dumps.
+    ///
+    /// Reads certificates, armored or binary, e.g. from `gpg --export`, and prints every primary
+    /// key and subkey matching any of the regexes. Unparseable certificates are skipped.
+    Scan(ScanArgs),
+
+    /// Check the hash chain of an audit log written with --audit-log.
+    ///
+    /// Prints the number of records and the hash of the last one, which commits to all of them.
+    /// Fails at the first record that was changed, removed or reordered.
+    AuditVerify {
+        #[arg(value_name = "FILE")]
+        file: PathBuf,
+    },
+
+    /// Check that this build finds, writes and encrypts keys correctly and can be stopped.
+    ///
+    /// Searches for keys matching a trivial regex, checks that their armor parses and that they
+    /// are valid under the standard policy, and reports every check. Fails if any check does.
+    SelfTest(SelfTestArgs),
+
+    /// Print a completion script for a shell to stdout.
+    ///
+    /// For example, `fingerprunk completions bash > /etc/bash_completion.d/fingerprunk`.
+    Completions {
+        #[arg(value_enum)]
+        shell: clap_complete::Shell,
+    },
+
+    /// Coordinate a search distributed over remote workers.
+    ///
+    /// Workers connect to the given address, receive the patterns and report their statistics
+    /// and found keys, which are printed to stdout.
+    #[cfg(feature = "cluster")]
+    Serve(ServeArgs),
+
+    /// Search on behalf of a coordinator started with `fingerprunk serve`.
+    ///
+    /// The patterns are received from the coordinator and found keys are sent to it instead of
+    /// stdout. The connection is re-established automatically when lost.
+    #[cfg(feature = "cluster")]
+    Worker(WorkerArgs),
+}
+
+#[derive(clap::Args, Debug)]
+struct BenchArgs {
+    /// Measure every configuration for this long, e.g. 30s or 2m.
+    #[arg(long, value_name = "DURATION", default_value = "5s", value_parser = parse_duration)]
+    duration: Duration,
+
+    /// Regex to match against, see the main command.
+    #[arg(short, long, default_value = "^C0FFEE", value_parser = parse_pattern)]
+    regex: Regex,
+}
+
+#[derive(clap::Args, Debug)]
+struct VerifyArgs {
+    /// Regex to match the fingerprints against, see the main command. Can be given several times.
+    #[arg(short, long, required = true, value_parser = parse_pattern)]
+    regex: Vec<Regex>,
+
+    /// Files to read the keys from, or - for stdin.
+    #[arg(value_name = "FILE", required = true)]
+    files: Vec<PathBuf>,
+}
+
+#[derive(clap::Args, Debug)]
+struct ScanArgs {
+    /// Regex to match the fingerprints against, see the main command. Can be given several times.
+    #[arg(short, long, required_unless_present = "patterns_file", value_parser = parse_pattern)]
+    regex: Vec<Regex>,
+
+    /// Read additional regexes from a file, one per line.
+    #[arg(long, value_name = "PATH")]
+    patterns_file: Option<PathBuf>,
+
+    /// Files to read the certificates from (default: stdin).
+    #[arg(value_name = "FILE")]
+    files: Vec<PathBuf>,
+}
+
+#[derive(clap::Args, Debug)]
+struct SelfTestArgs {
+    /// Also import a found key into a temporary GnuPG home, which requires gpg.
+    #[arg(long)]
+    gpg: bool,
+}
+
+#[derive(clap::Args, Debug)]
+struct CalibrateArgs {
+    /// Regex to search for, see the main command. Can be given several times.
+    #[arg(short, long, required = true, value_parser = parse_pattern)]
+    regex: Vec<Regex>,
+
+    /// Measure the single-threaded and the full search for this long each, e.g. 30s.
+    #[arg(long, value_name = "DURATION", default_value = "5s", value_parser = parse_duration)]
+    duration: Duration,
+}
+
+#[derive(clap::Args, Debug)]
+struct EstimateArgs {
+    /// Regex to estimate, see the main command. Can be given several times to estimate finding a
+    /// key matching any of them.
+    #[arg(short, long, required = true, value_parser = parse_pattern)]
+    regex: Vec<Regex>,
+
+    /// Assume this many keys per second instead of measuring the rate.
+    #[arg(long, value_name = "KEYS_PER_SEC")]
+    rate: Option<f64>,
+
+    /// Measure the rate for this many seconds.
+    #[arg(
+        long,
+        value_name = "SECONDS",
+        default_value = "5",
+        conflicts_with = "rate"
+    )]
+    measure_secs: NonZeroU64,
+}
+
+#[cfg(feature = "cluster")]
+#[derive(clap::Args, Debug)]
+struct ServeArgs {
+    /// Address to accept worker connections on, e.g. 0.0.0.0:7878 for all interfaces, which
+    /// requires --token-file.
+    #[arg(
+        long,
+        env = "FINGERPRUNK_LISTEN",
+        value_name = "ADDRESS",
+        default_value = "127.0.0.1:7878"
+    )]
+    listen: String,
+
+    /// Filter key fingerprints by using a regular expression, see the main command.
+    #[arg(
+        short,
+        long,
+        env = "FINGERPRUNK_REGEX",
+        required_unless_present = "patterns_file",
+        value_parser = parse_pattern
+    )]
+    regex: Option<Regex>,
+
+    /// Read additional regexes from a file, one per line.
+    #[arg(long, env = "FINGERPRUNK_PATTERNS_FILE", value_name = "PATH")]
+    patterns_file: Option<PathBuf>,
+
+    /// Stop all workers once the specified number of matching keys has been found in total.
+    #[arg(long, env = "FINGERPRUNK_STOP_AFTER")]
+    stop_after: Option<NonZeroU64>,
+
+    /// Show exact integers instead of humanized numbers in the status lines.
+    #[arg(long, env = "FINGERPRUNK_RAW_NUMBERS", action = ArgAction::SetTrue)]
+    raw_numbers: bool,
+
+    /// Require workers to present the token stored in this file.
+    #[arg(long, env = "FINGERPRUNK_TOKEN_FILE", value_name = "PATH")]
+    token_file: Option<PathBuf>,
+
+    /// Accept TLS connections using the certificate chain in this PEM file.
+    #[arg(
+        long,
+        env = "FINGERPRUNK_TLS_CERT",
+        value_name = "PATH",
+        requires = "tls_key"
+    )]
+    tls_cert: Option<PathBuf>,
+
+    /// Private key for --tls-cert, as PEM file.
+    #[arg(
+        long,
+        env = "FINGERPRUNK_TLS_KEY",
+        value_name = "PATH",
+        requires = "tls_cert"
+    )]
+    tls_key: Option<PathBuf>,
+
+    /// Serve a web dashboard with the progress of the search on this address.
+    ///
+    /// The dashboard is unauthenticated, so only listen on trusted interfaces, e.g.
+    /// 127.0.0.1:8080.
+    #[arg(long, env = "FINGERPRUNK_DASHBOARD", value_name = "ADDRESS")]
+    dashboard: Option<String>,
+
+    /// Discard found keys that workers didn't encrypt with --encrypt-to.
+    #[arg(long, env = "FINGERPRUNK_REQUIRE_ENCRYPTION")]
+    require_encryption: bool,
+
+    /// Have the workers vary the creation time of keys derived from a common seed, each in its
+    /// own ranges of candidates.
+    ///
+    /// Found keys are reported with the range they were found in. Workers have to generate
+    /// OpenPGP v4 keys and can't use --auto-tune.
+    #[arg(long, env = "FINGERPRUNK_GRIND_TIMESTAMPS")]
+    grind_timestamps: bool,
+}
+
+#[cfg(feature = "cluster")]
+#[derive(clap::Args, Debug)]
+struct WorkerArgs {
+    /// Address of the coordinator.
+    #[arg(long, env = "FINGERPRUNK_CONNECT", value_name = "HOST:PORT")]
+    connect: String,
+
+    /// Name identifying this worker to the coordinator (default: the host name).
+    #[arg(long, env = "FINGERPRUNK_WORKER_NAME")]
+    name: Option<String>,
+
+    /// Present the token stored in this file to the coordinator.
+    #[arg(long, env = "FINGERPRUNK_TOKEN_FILE", value_name = "PATH")]
+    token_file: Option<PathBuf>,
+
+    /// Connect using TLS, trusting the certificates in this PEM file.
+    #[arg(long, env = "FINGERPRUNK_TLS_CA", value_name = "PATH")]
+    tls_ca: Option<PathBuf>,
+
+    /// Encrypt found keys to the OpenPGP certificate in this file before sending them.
+    ///
+    /// Neither the network nor the coordinator's host see the secret keys then, only the holder
+    /// of the certificate's secret key can decrypt them.
+    #[arg(long, env = "FINGERPRUNK_ENCRYPT_TO", value_name = "PATH")]
+    encrypt_to: Option<PathBuf>,
+
+    /// Number of threads generating keys (default: one per CPU).
+    #[arg(long, env = "FINGERPRUNK_THREADS")]
+    threads: Option<NonZeroUsize>,
+
+    /// Print nothing but fatal errors.
+    #[arg(
+        short,
+        long,
+        env = "FINGERPRUNK_QUIET",
+        action = ArgAction::SetTrue,
+        conflicts_with_all = ["verbose", "status"]
+    )]
+    quiet: bool,
+
+    /// Print the configuration at startup (-v) and details about every match (-vv).
+    #[arg(short, long, env = "FINGERPRUNK_VERBOSE", action = ArgAction::Count)]
+    verbose: u8,
+
+    /// Show status information, see the main command.
+    #[arg(long, env = "FINGERPRUNK_STATUS", value_enum, default_value_t)]
+    status: StatusEnabled,
+
+    /// Show exact integers instead of humanized numbers (e.g. "1.37 G") in the status display.
+    #[arg(long, env = "FINGERPRUNK_RAW_NUMBERS", action = ArgAction::SetTrue)]
+    raw_numbers: bool,
+}
+
+/// Reads a cluster token, ignoring surrounding whitespace such as a trailing line break.
+#[cfg(feature = "cluster")]
+fn read_token_file(path: &Path) -> anyhow::Result<String> {
+    let token = std::fs::read_to_string(path)
+        .with_context(|| format!("Failed to read token file {}", path.display()))?;
+    let token = token.trim();
+    if token.is_empty() || token.contains(char::is_whitespace) {
+        return Err(anyhow!(
+            "Token file {} must contain a single token",
+            path.display()
+        ));
+    }
+    Ok(token.to_string())
+}
+
+fn estimate(args: EstimateArgs) -> anyhow::Result<()> {
+    let estimate = Estimate::for_regexes(&args.regex);
+    let regexes: Vec<_> = args.regex.iter().map(Regex::as_str).collect();
+    println!("Regex:         {}", regexes.join(" | "));
+    let Some(estimate) = estimate.filter(|estimate| estimate.probability > 0.0) else {
+        println!(
+            "Probability:   {}",
+            match estimate {
+                Some(_) => "0, no fingerprint can match",
+                None => "unknown, too low to measure by sampling",
+            }
+        );
+        return Ok(());
+    };
+    println!(
+        "Probability:   1 in {} keys ({})",
+        humanize(estimate.expected_tries()).trim_end(),
+        estimate.method
+    );
+
+    let rate = match args.rate {
+        Some(rate) => rate,
+        None => {
+            eprintln!("Measuring the rate for {} seconds...", args.measure_secs);
+            measure_rate(&regexes, Duration::from_secs(args.measure_secs.get()))?
+        }
+    };
+    println!("Rate:          {} keys/s", humanize(rate).trim_end());
+
+    println!(
+        "Expected time: {}",
+        format_time(estimate.expected_tries(), rate)
+    );
+    println!(
+        "Median time:   {}",
+        format_time(estimate.median_tries(), rate)
+    );
+    Ok(())
+}
+
+/// Formats the time it takes to try `tries` keys at `rate` keys per second.
+fn format_time(tries: f64, rate: f64) -> String {
+    Duration::try_from_secs_f64(tries / rate).map_or_else(
+        |_| "practically never".to_string(),
+        |d| DurationDhms(d).to_string(),
+    )
+}
+
+fn bench(args: BenchArgs) -> anyhow::Result<()> {
+    let threads = num_cpus::get();
+    println!("Backend: {}", sequoia_openpgp::crypto::backend());
+    println!("Threads: {threads}");
+    println!("Regex:   {}", args.regex.as_str());
+    println!();
+
+    // Printed as soon as measured, as every configuration takes the whole duration
+    let print = |name: &str, rate: f64| {
+        println!("{name:<34}{:>10} keys/s", humanize(rate).trim_end());
+    };
+    print(
+        "key generation, 1 thread",
+        measure_single_thread(None, args.duration)?,
+    );
+    print(
+        "generation + matching, 1 thread",
+        measure_single_thread(Some(&args.regex), args.duration)?,
+    );
+    print(
+        &format!(
+            "search, {threads} {}",
+            if threads == 1 { "thread" } else { "threads" }
+        ),
+        measure_rate(&[args.regex.as_str()], args.duration)?,
+    );
+    Ok(())
+}
+
+/// Generates keys on this thread for `duration`, matching them against `regex` if given, and
+/// returns the rate.
+fn measure_single_thread(regex: Option<&Regex>, duration: Duration) -> anyhow::Result<f64> {
+    let started = Instant::now();
+    let mut tried = 0u64;
+    while started.elapsed() < duration {
+        let candidate = keygen::generate_candidate()?;
+        if let Some(regex) = regex {
+            regex.is_match(&keygen::fingerprint_hex(&candidate.key))?;
+        }
+        tried += 1;
+    }
+    Ok(tried as f64 / started.elapsed().as_secs_f64())
+}
+
+fn calibrate(args: CalibrateArgs) -> anyhow::Result<()> {
+    let regexes: Vec<_> = args.regex.iter().map(Regex::as_str).collect();
+    let backend = sequoia_openpgp::crypto::backend();
+    let threads = num_cpus::get();
+    println!("Regex:       {}", regexes.join(" | "));
+    let Some(estimate) =
+        Estimate::for_regexes(&args.regex).filter(|estimate| estimate.probability > 0.0)
+    else {
+        return Err(anyhow!(
+            "The match probability of the regexes is unknown or zero, so there is nothing to \
+             calibrate"
+        ));
+    };
+    println!(
+        "Probability: 1 in {} keys ({})",
+        humanize(estimate.expected_tries()).trim_end(),
+        estimate.method
+    );
+    println!("Backend:     {backend}");
+
+    eprintln!(
+        "Measuring for {} seconds{}...",
+        args.duration.as_secs(),
+        if threads > 1 {
+            " on 1 and all threads"
+        } else {
+            ""
+        }
+    );
+    let rate = measure_rate(&regexes, args.duration)?;
+    // How much of the ideal speedup over a single thread the full search achieves
+    let scaling = if threads > 1 {
+        let single_rate = measure_single_thread(args.regex.first(), args.duration)?;
+        let scaling = rate / (single_rate * threads as f64);
+        println!(
+            "Rate:        {} keys/s on 1 thread, {} keys/s on {threads} ({:.0}% scaling)",
+            humanize(single_rate).trim_end(),
+            humanize(rate).trim_end(),
+            scaling * 100.0
+        );
+        scaling
+    } else {
+        println!("Rate:        {} keys/s", humanize(rate).trim_end());
+        1.0
+    };
+    println!(
+        "First match: 50% within {}, 90% within {}, 99% within {}",
+        format_time(estimate.tries_for_chance(0.5), rate),
+        format_time(estimate.tries_for_chance(0.9), rate),
+        format_time(estimate.tries_for_chance(0.99), rate)
+    );
+
+    println!();
+    println!("Recommendations:");
+    if threads == 1 {
+        println!("- This machine runs a single thread, one with more cores searches faster");
+    } else if scaling < 0.5 {
+        println!(
+            "- The search only scales to {:.1} of {threads} threads, other processes may be \
+             competing for the CPU",
+            scaling * threads as f64
+        );
+    } else {
+        println!("- Keep using all {threads} threads, which is the default");
+    }
+    if backend.contains("RustCrypto") {
+        println!(
+            "- This build uses Sequoia's experimental RustCrypto backend, a build with the \
+             default Nettle backend is usually faster"
+        );
+    }
+    let days = estimate.tries_for_chance(0.9) / rate / 86400.0;
+    if days > 365.0 {
+        println!(
+            "- This pattern is out of reach for this machine, it needs a search orders of \
+             magnitude faster than a CPU (e.g. on GPUs, which Fingerprunk doesn't support); \
+             consider a shorter pattern"
+        );
+    } else if days > 7.0 {
+        if cfg!(feature = "cluster") {
+            println!(
+                "- Distribute the search over several machines with `fingerprunk serve` and \
+                 `fingerprunk worker`, and save its state with --state-file to resume it"
+            );
+        } else {
+            println!(
+                "- Save the state of the search with --state-file to resume it, or build with \
+                 the cluster feature to distribute it over several machines"
+            );
+        }
+    } else if days > 1.0 / 24.0 {
+        println!(
+            "- Run the search in the background, e.g. as a service or in a terminal multiplexer"
+        );
+    } else {
+        println!("- Nothing to tune, this pattern is found quickly");
+    }
+    Ok(())
+}
+
+fn verify(args: VerifyArgs) -> anyhow::Result<()> {
+    let mut matched = 0u64;
+    for path in &args.files {
+        let parser = if path.as_os_str() == "-" {
+            CertParser::from_reader(io::stdin())
+        } else {
+            CertParser::from_file(path)
+        }
+        .with_context(|| format!("Failed to read {}", path.display()))?;
+
+        for cert in parser {
+            let cert = cert.with_context(|| format!("Failed to parse {}", path.display()))?;
+            for key in cert.keys() {
+                let fingerprint = keygen::fingerprint_hex(key.key());
+                let kind = if key.primary() {
+                    "primary key"
+                } else {
+                    "subkey"
+                };
+                match first_match(&args.regex, &fingerprint)? {
+                    Some(regex) => {
+                        matched += 1;
+                        println!("{fingerprint} ({kind}) matches {}", regex.as_str());
+                    }
+                    None => println!("{fingerprint} ({kind}) matches nothing"),
+                }
+            }
+        }
+    }
+
+    match matched {
+        0 => Err(anyhow!("No key matches")),
+        _ => Ok(()),
+    }
+}
+
+fn audit_verify(path: &Path) -> anyhow::Result<()> {
+    let summary = fingerprunk::audit::verify(path)?;
+    println!(
+        "{} records, the chain is intact, last hash {}",
+        summary.records, summary.last_hash
+    );
+    Ok(())
+}
+
+fn scan(args: ScanArgs) -> anyhow::Result<()> {
+    let mut builder = Config::builder().patterns_file(args.patterns_file);
+    for regex in &args.regex {
+        builder = builder.regex(regex.as_str());
+    }
+    let scanner = Scanner::new(&builder.build()?)?;
+
+    let print = |found: ScanMatch| {
+        let key = if found.primary {
+            "primary key".to_string()
+        } else {
+            format!("subkey of {}", found.cert_fingerprint)
+        };
+        match found.user_id {
+            Some(user_id) => println!(
+                "{} ({key}, {user_id:?}) matches {}",
+                found.fingerprint, found.pattern
+            ),
+            None => println!("{} ({key}) matches {}", found.fingerprint, found.pattern),
+        }
+    };
+    let mut total = ScanSummary::default();
+    let stdin = [PathBuf::from("-")];
+    let files = if args.files.is_empty() {
+        &stdin[..]
+    } else {
+        &args.files
+    };
+    for path in files {
+        let summary = if path.as_os_str() == "-" {
+            scanner.scan(io::stdin(), print)
+        } else {
+            File::open(path)
+                .map_err(anyhow::Error::from)
+                .and_then(|file| scanner.scan(io::BufReader::new(file), print))
+        }
+        .with_context(|| format!("Failed to scan {}", path.display()))?;
+        total.certs += summary.certs;
+        total.keys += summary.keys;
+        total.matches += summary.matches;
+        total.skipped += summary.skipped;
+    }
+
+    eprintln!(
+        "Scanned {} certificates with {} keys, {} matching, {} unparseable skipped",
+        total.certs, total.keys, total.matches, total.skipped
+    );
+    Ok(())
+}
+
+fn self_test(args: SelfTestArgs) -> anyhow::Result<()> {
+    const REGEX: &str = "^[0-9A-F]";
+    const PASSWORD: &str = "self-test";
+
+    let mut failed = 0;
+    let mut report = |check: &str, result: anyhow::Result<()>| match result {
+        Ok(()) => println!("ok    {check}"),
+        Err(err) => {
+            failed += 1;
+            println!("FAIL  {check}: {err:#}");
+        }
+    };
+
+    let found = self_test_search(REGEX, None);
+    let first = found.as_ref().ok().and_then(|certs| certs.first().cloned());
+    report("find keys valid under the standard policy", found.map(drop));
+    report(
+        "encrypt found keys with a password",
+        self_test_search(REGEX, Some(PASSWORD)).and_then(|certs| {
+            for cert in certs {
+                let key = cert.primary_key().key().parts_as_secret()?;
+                if !key.secret().is_encrypted() {
+                    return Err(anyhow!("Key {} is not encrypted", cert.fingerprint()));
+                }
+                key.clone()
+                    .decrypt_secret(&PASSWORD.into())
+                    .context("Failed to decrypt key")?;
+            }
+            Ok(())
+        }),
+    );
+    report("stop a running search", self_test_stop());
+    if args.gpg {
+        report(
+            "import a found key into GnuPG",
+            first
+                .ok_or_else(|| anyhow!("No key found to import"))
+                .and_then(|cert| self_test_gpg(&cert)),
+        );
+    }
+
+    match failed {
+        0 => Ok(()),
+        _ => Err(anyhow!("{failed} self-test checks failed")),
+    }
+}
+
+/// Finds two keys matching `regex` and checks them and the counters.
+fn self_test_search(regex: &str, password: Option<&str>) -> anyhow::Result<Vec<Cert>> {
+    let config = Config::builder()
+        .regex(regex)
+        .stop_after(NonZeroU64::new(2))
+        .password(password.map(Into::into))
+        .verbosity(Verbosity::Quiet)
+        .build()?;
+    let mut armored = Vec::new();
+    let summary = Fingerprunk::new_from_config(config).run_with_writer(&mut armored)?;
+
+    let regex = parse_pattern(regex)?;
+    let policy = StandardPolicy::new();
+    let certs = CertParser::from_bytes(&armored)?.collect::<Result<Vec<_>, _>>()?;
+    for cert in &certs {
+        let fingerprint = format!("{:X}", cert.fingerprint());
+        if !regex.is_match(&fingerprint)? {
+            return Err(anyhow!("Key {fingerprint} doesn't match {regex}"));
+        }
+        if !cert.is_tsk() {
+            return Err(anyhow!("Key {fingerprint} has no secret"));
+        }
+        cert.with_policy(&policy, None)
+            .with_context(|| format!("Key {fingerprint} is invalid"))?;
+    }
+    let fingerprints: Vec<_> = certs
+        .iter()
+        .map(|cert| format!("{:X}", cert.fingerprint()))
+        .collect();
+    if summary.found != 2 || fingerprints != summary.fingerprints || summary.tried < summary.found {
+        return Err(anyhow!(
+            "Wrote {} keys, but counted {} found in {} tried",
+            certs.len(),
+            summary.found,
+            summary.tried
+        ));
+    }
+    Ok(certs)
+}
+
+/// Stops a search that won't find anything soon and checks that it does stop.
+fn self_test_stop() -> anyhow::Result<()> {
+    let config = Config::builder()
+        .regex("^0{20}")
+        .verbosity(Verbosity::Quiet)
+        .build()?;
+    let fingerprunk = Fingerprunk::new_from_config(config);
+    let stop_handle = fingerprunk.stop_handle();
+    let (sender, receiver) = std::sync::mpsc::channel();
+    std::thread::spawn(move || {
+        let _ = sender.send(fingerprunk.run_with_writer(io::sink()));
+    });
+
+    std::thread::sleep(Duration::from_millis(500));
+    stop_handle.stop();
+    let summary = receiver
+        .recv_timeout(Duration::from_secs(10))
+        .map_err(|_| anyhow!("Search didn't stop within 10 seconds"))??;
+    if summary.tried == 0 {
+        return Err(anyhow!("Search didn't try any keys"));
+    }
+    Ok(())
+}
+
+/// Reads the key to certify found keys with, prompting for its password if it is encrypted.
+fn load_certifier(path: &Path) -> anyhow::Result<Certifier> {
+    let cert = Cert::from_file(path)
+        .with_context(|| format!("Failed to read certification key {}", path.display()))?;
+    Certifier::from_cert(&cert, || {
+        let password = rpassword::prompt_password(format!(
+            "Enter password for certification key {:X}: ",
+            cert.fingerprint()
+        ))
+        .context("Failed to prompt password")?;
+        Ok(password.into())
+    })
+}
+
+/// Imports the key of `cert` into a temporary GnuPG home and checks that gpg lists it.
+fn self_test_gpg(cert: &Cert) -> anyhow::Result<()> {
+    use std::process::{Command, Stdio};
+
+    // GnuPG refuses keys without a user ID
+    let mut options = keygen::CertOptions::default();
+    options.user_id = Some("Fingerprunk self-test <self-test@example.org>".to_string());
+    let key = cert.primary_key().key().parts_as_secret()?.clone();
+    let armored = keygen::build_cert(&key, &options)?
+        .as_tsk()
+        .armored()
+        .to_vec()?;
+    let fingerprint = format!("{:X}", cert.fingerprint());
+
+    let home = std::env::temp_dir().join(format!("fingerprunk-self-test-{}", std::process::id()));
+    let mut dir_builder = std::fs::DirBuilder::new();
+    #[cfg(unix)]
+    std::os::unix::fs::DirBuilderExt::mode(&mut dir_builder, 0o700);
+    dir_builder
+        .create(&home)
+        .with_context(|| format!("Failed to create {}", home.display()))?;
+
+    let result = (|| {
+        let gpg = || {
+            let mut command = Command::new("gpg");
+            command.arg("--homedir").arg(&home).arg("--batch");
+            command
+        };
+        let mut import = gpg()
+            .arg("--import")
+            .stdin(Stdio::piped())
+            .stdout(Stdio::null())
+            .stderr(Stdio::piped())
+            .spawn()
+            .context("Failed to run gpg")?;
+        io::Write::write_all(
+            &mut import.stdin.take().expect("stdin should be piped"),
+            &armored,
+        )?;
+        let output = import.wait_with_output()?;
+        if !output.status.success() {
+            return Err(anyhow!(
+                "gpg --import failed: {}",
+                String::from_utf8_lossy(&output.stderr).trim()
+            ));
+        }
+
+        let output = gpg()
+            .args(["--list-secret-keys", "--with-colons"])
+            .stderr(Stdio::null())
+            .output()?;
+        let listed = String::from_utf8_lossy(&output.stdout);
+        if !listed.contains(&fingerprint) {
+            return Err(anyhow!("gpg doesn't list secret key {fingerprint}"));
+        }
+        Ok(())
+    })();
+
+    // The import started an agent for the home, which would outlive it otherwise
+    let _ = Command::new("gpgconf")
+        .arg("--homedir")
+        .arg(&home)
+        .args(["--kill", "gpg-agent"])
+        .status();
+    let _ = std::fs::remove_dir_all(&home);
+    result
+}
+
+/// Returns the first of `regexes` matching `fingerprint`.
+fn first_match<'a>(regexes: &'a [Regex], fingerprint: &str) -> anyhow::Result<Option<&'a Regex>> {
+    for regex in regexes {
+        if regex.is_match(fingerprint)? {
+            return Ok(Some(regex));
+        }
+    }
+    Ok(None)
+}
+
+/// Searches for the regexes for `duration`, discarding the found keys, and returns the average
+/// rate.
+fn measure_rate(regexes: &[&str], duration: Duration) -> anyhow::Result<f64> {
+    let mut builder = Config::builder().verbosity(Verbosity::Quiet);
+    for regex in regexes {
+        builder = builder.regex(*regex);
+    }
+    let fingerprunk = Fingerprunk::new_from_config(builder.build()?);
+    let stop_handle = fingerprunk.stop_handle();
+    std::thread::spawn(move || {
+        std::thread::sleep(duration);
+        stop_handle.stop();
+    });
+    let summary = fingerprunk.run_with_writer(io::sink())?;
+    Ok(summary.tried as f64 / summary.elapsed.as_secs_f64())
+}
+
+#[cfg(feature = "cluster")]
+fn serve(args: ServeArgs) -> anyhow::Result<()> {
+    use fingerprunk::cluster::{Coordinator, CoordinatorConfig};
+
+    Coordinator::new(CoordinatorConfig {
+        listen: args.listen,
+        regexes: args.regex.into_iter().collect(),
+        patterns_file: args.patterns_file,
+        stop_after: args.stop_after,
+        humanize_numbers: !args.raw_numbers,
+        token: args
+            .token_file
+            .as_deref()
+            .map(read_token_file)
+            .transpose()?,
+        tls: args.tls_cert.zip(args.tls_key),
+        dashboard: args.dashboard,
+        require_encryption: args.require_encryption,
+        grind_timestamps: args.grind_timestamps,
+    })
+    .run()
+}
+
+#[cfg(feature = "cluster")]
+fn worker(args: WorkerArgs) -> anyhow::Result<()> {
+    let status_mode = if args.quiet {
+        StatusMode::Disabled
+    } else {
+        args.status.evaluate()
+    };
+    let config = Config::builder()
+        .status_mode(status_mode)
+        .humanize_numbers(!args.raw_numbers)
+        .status_template(match status_mode {
+            StatusMode::Append => StatusTemplate::LOG_LINE
+                .parse()
+                .expect("log line template should be valid"),
+            _ => StatusTemplate::default(),
+        })
+        .verbosity(verbosity(args.quiet, args.verbose))
+        .threads(args.threads)
+        .coordinator(Some(fingerprunk::cluster::WorkerConfig {
+            connect: args.connect,
+            name: args.name.unwrap_or_else(|| {
+                gethostname::gethostname()
+                    .to_string_lossy()
+                    .replace(char::is_whitespace, "_")
+            }),
+            token: args
+                .token_file
+                .as_deref()
+                .map(read_token_file)
+                .transpose()?,
+            tls_ca: args.tls_ca,
+            encrypt_to: args.encrypt_to,
+        }))
+        .build()?;
+
+    Fingerprunk::new_from_config(config).run()?;
+    Ok(())
+}
+
+/// A regex given with `-r`, optionally prefixed with a label as in `cafe=^CAFE`.
+#[derive(Clone, Debug)]
+struct LabeledRegex {
+    label: Option<String>,
+    pattern: String,
+}
+
+impl FromStr for LabeledRegex {
+    type Err = ConfigError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (label, pattern) = match s.split_once('=') {
+            Some((label, pattern)) if is_label(label) => (Some(label.to_string()), pattern),
+            _ => (None, s),
+        };
+        // Rejected by the config builder anyway, but reported nicer as an invalid argument. With
+        // --key-format, patterns aren't hex, which only the builder knows about.
+        if let Err(err) = parse_pattern(pattern) {
+            fingerprunk::format::parse_pattern(pattern).map_err(|_| err)?;
+        }
+        Ok(Self {
+            label,
+            pattern: pattern.to_string(),
+        })
+    }
+}
+
+fn is_label(s: &str) -> bool {
+    !s.is_empty()
+        && s.chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+}
+
+/// Parses a duration in seconds, minutes or hours, e.g. `30s`, `2m` or `1h`. Plain numbers are
+/// seconds.
+fn parse_duration(s: &str) -> Result<Duration, String> {
+    let (number, unit) = match s.find(|c: char| !c.is_ascii_digit()) {
+        Some(index) => s.split_at(index),
+        None => (s, "s"),
+    };
+    let number: u64 = number
+        .parse()
+        .map_err(|_| "expected a number followed by s, m or h".to_string())?;
+    let seconds = match unit {
+        "s" => number,
+        "m" => number * 60,
+        "h" => number * 3600,
+        _ => return Err(format!("unknown unit {unit:?}, expected s, m or h")),
+    };
+    match seconds {
+        0 => Err("duration must not be zero".to_string()),
+        seconds => Ok(Duration::from_secs(seconds)),
+    }
+}
+
+fn parse_label_output(s: &str) -> Result<(String, PathBuf), String> {
+    match s.split_once('=') {
+        Some((label, path)) if is_label(label) && !path.is_empty() => {
+            Ok((label.to_string(), PathBuf::from(path)))
+        }
+        _ => Err("expected LABEL=PATH".to_string()),
+    }
+}
+
+fn verbosity(quiet: bool, verbose: u8) -> Verbosity {
+    match (quiet, verbose) {
+        (true, _) => Verbosity::Quiet,
+        (false, 0) => Verbosity::Normal,
+        (false, 1) => Verbosity::Verbose,
+        (false, _) => Verbosity::Debug,
+    }
 }
 
 #[derive(ValueEnum, Clone, Copy, Debug, Default)]
@@ -50,19 +1526,423 @@ enum StatusEnabled {
 }
 
 impl StatusEnabled {
-    fn evaluate(self) -> bool {
+    fn evaluate(self) -> StatusMode {
         match self {
-            Self::Auto => io::stderr().is_terminal() && !io::stdout().is_terminal(),
-            Self::Always => true,
-            Self::Never => false,
+            Self::Auto if !io::stderr().is_terminal() => StatusMode::Append,
+            Self::Auto if status::stderr_supports_redraw() && !io::stdout().is_terminal() => {
+                StatusMode::Redraw
+            }
+            Self::Auto => StatusMode::Disabled,
+            Self::Always if status::stderr_supports_redraw() => StatusMode::Redraw,
+            Self::Always => StatusMode::Append,
+            Self::Never => StatusMode::Disabled,
+        }
+    }
+}
+
+/// How the process exits, so that scripts can tell the outcomes apart. Documented in the README.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Exit {
+    /// The requested keys were found, or the subcommand succeeded.
+    Success = 0,
+    /// The search or subcommand failed, e.g. because a found key couldn't be written.
+    Error = 1,
+    /// The command line or the configuration is invalid, as for clap's usage errors.
+    Config = 2,
+    /// The search was stopped before it found any key.
+    StoppedNone = 3,
+    /// The search was stopped after finding some, but not all of the keys requested with
+    /// --stop-after or the jobs.
+    StoppedSome = 4,
+}
+
+impl Exit {
+    fn for_error(err: &anyhow::Error) -> Self {
+        let config = err.chain().any(|cause| {
+            cause.is::<ConfigError>()
+                || matches!(
+                    cause.downcast_ref::<fingerprunk::Error>(),
+                    Some(fingerprunk::Error::Config(_))
+                )
+        });
+        if config { Self::Config } else { Self::Error }
+    }
+}
+
+impl From<Exit> for ExitCode {
+    fn from(exit: Exit) -> Self {
+        Self::from(exit as u8)
+    }
+}
+
+/// An error in the command line or the configuration that isn't a [`ConfigError`], e.g. in a
+/// preset or jobs file, which exits with [`Exit::Config`].
+#[derive(Debug, thiserror::Error)]
+#[error(transparent)]
+struct InvalidConfig(anyhow::Error);
+
+/// Parses the command line, with the options of the preset given with --preset filled in.
+fn parse_args() -> anyhow::Result<Args> {
+    let cli: Vec<_> = env::args_os().collect();
+    // Only looking for --preset and --config, as the preset may provide required options. With
+    // --help or --version, there are no matches at all.
+    let matches = Args::command()
+        .ignore_errors(true)
+        .try_get_matches_from(&cli)
+        .unwrap_or_default();
+    let preset = match matches.try_get_one::<String>("preset").ok().flatten() {
+        Some(preset) if matches.subcommand().is_none() => preset,
+        _ => return Ok(Args::parse_from(cli)),
+    };
+    // Subcommands conflict with the options of the search, so --preset can't be used with them.
+    // Failing now names --preset instead of the options from the preset.
+    if let Err(err) = Args::try_parse_from(&cli)
+        && err.kind() == clap::error::ErrorKind::ArgumentConflict
+    {
+        err.exit();
+    }
+    let path = match matches.try_get_one::<PathBuf>("config").ok().flatten() {
+        Some(path) => path.clone(),
+        None => default_config_path().ok_or_else(|| {
+            anyhow!("No config directory found for preset {preset}, use --config")
+        })?,
+    };
+
+    let content = std::fs::read_to_string(&path)
+        .with_context(|| format!("Failed to read config file {}", path.display()))?;
+    let table: toml::Table = content
+        .parse()
+        .with_context(|| format!("Invalid config file {}", path.display()))?;
+    let Some(options) = table
+        .get("preset")
+        .and_then(|presets| presets.get(preset))
+        .and_then(toml::Value::as_table)
+    else {
+        return Err(anyhow!("There is no preset {preset} in {}", path.display()));
+    };
+
+    let command = Args::command();
+    let given = |arg: &clap::Arg| {
+        matches!(
+            matches.value_source(arg.get_id().as_str()),
+            Some(ValueSource::CommandLine | ValueSource::EnvVariable)
+        )
+    };
+    let conflicting = |arg: &clap::Arg, other: &clap::Arg| {
+        command
+            .get_arg_conflicts_with(arg)
+            .iter()
+            .any(|conflict| conflict.get_id() == other.get_id())
+    };
+    let mut preset_args = Vec::new();
+    for (key, value) in options {
+        let long = key.replace('_', "-");
+        let Some(arg) = command
+            .get_arguments()
+            .find(|arg| arg.get_long() == Some(long.as_str()))
+            .filter(|arg| !["preset", "config"].contains(&arg.get_id().as_str()))
+        else {
+            return Err(anyhow!("Unknown option {key} in preset {preset}"));
+        };
+        // The command line and the environment take precedence, also over conflicting options
+        if given(arg)
+            || command
+                .get_arguments()
+                .filter(|other| given(other))
+                .any(|other| conflicting(arg, other) || conflicting(other, arg))
+        {
+            continue;
+        }
+        preset_args.extend(
+            preset_option_args(arg, value)
+                .with_context(|| format!("Invalid option {key} in preset {preset}"))?,
+        );
+    }
+
+    let mut cli = cli.into_iter();
+    Ok(Args::parse_from(
+        cli.next().into_iter().chain(preset_args).chain(cli),
+    ))
+}
+
+/// Turns an option of a preset into command line arguments.
+fn preset_option_args(arg: &clap::Arg, value: &toml::Value) -> anyhow::Result<Vec<OsString>> {
+    let long = arg
+        .get_long()
+        .expect("options in presets should be long options");
+    let values = match value {
+        toml::Value::Array(values) => values.iter().collect(),
+        value => vec![value],
+    };
+
+    let mut args = Vec::new();
+    for value in values {
+        match value {
+            toml::Value::Boolean(false) if !arg.get_action().takes_values() => {}
+            toml::Value::Boolean(true) if !arg.get_action().takes_values() => {
+                args.push(format!("--{long}").into());
+            }
+            toml::Value::Integer(count) if matches!(arg.get_action(), ArgAction::Count) => {
+                args.extend((0..*count).map(|_| format!("--{long}").into()));
+            }
+            _ if !arg.get_action().takes_values() => return Err(anyhow!("expected true or false")),
+            toml::Value::String(value) => args.push(format!("--{long}={value}").into()),
+            toml::Value::Integer(value) => args.push(format!("--{long}={value}").into()),
+            toml::Value::Float(value) => args.push(format!("--{long}={value}").into()),
+            toml::Value::Boolean(value) => args.push(format!("--{long}={value}").into()),
+            _ => return Err(anyhow!("expected a string, number or boolean")),
+        }
+    }
+    Ok(args)
+}
+
+/// The config file in the user's config directory, e.g. `~/.config/fingerprunk/config.toml`.
+fn default_config_path() -> Option<PathBuf> {
+    let dir = env::var_os("XDG_CONFIG_HOME")
+        .filter(|dir| !dir.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| env::var_os("APPDATA").map(PathBuf::from))
+        .or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))?;
+    Some(dir.join("fingerprunk").join("config.toml"))
+}
+
+/// Checks that the password prompt can be shown, which reads from the terminal even if stdin is
+/// redirected.
+fn check_password_prompt() -> anyhow::Result<()> {
+    #[cfg(unix)]
+    let reachable = File::open("/dev/tty").is_ok();
+    #[cfg(not(unix))]
+    let reachable = io::stdin().is_terminal();
+    if !reachable {
+        return Err(anyhow!("No terminal to prompt for the password"));
+    }
+    Ok(())
+}
+
+/// Whether found keys end up on stdout: without an output directory, unless all regexes are
+/// labeled and routed elsewhere or the jobs are searched for, which have output directories.
+fn keys_to_stdout(args: &Args) -> bool {
+    #[cfg(feature = "plugin")]
+    if args.output_dir.is_none() && args.jobs.is_none() && !args.matcher_plugin.is_empty() {
+        return true;
+    }
+    args.output_dir.is_none()
+        && args.jobs.is_none()
+        && (args.farm.is_some()
+            || args.patterns_file.is_some()
+            || args.regex.iter().any(|regex| match &regex.label {
+                Some(label) => !args.out.iter().any(|(l, _)| l == label),
+                None => true,
+            }))
+}
+
+/// Match probability above which a pattern is confirmed like with [`Confirmations::confirm`] if
+/// the search doesn't stop, as it would write keys many times a second on fast machines.
+const PERMISSIVE_PROBABILITY: f64 = 1.0 / 65536.0;
+
+/// Match probability above which a pattern needs --force if the search doesn't stop, as it would
+/// write keys about as fast as they are generated.
+const RECKLESS_PROBABILITY: f64 = 1.0 / 256.0;
+
+/// Asks before risky searches, answered for all of them with --yes and --force.
+struct Confirmations {
+    yes: bool,
+    force: bool,
+}
+
+impl Confirmations {
+    /// Warns about `warning` and asks whether to continue, unless --yes or --force is given. Fails
+    /// if the answer is no or there is nobody to ask.
+    fn confirm(&self, warning: &str) -> anyhow::Result<()> {
+        if self.yes || self.force {
+            return Ok(());
+        }
+
+        eprintln!("Warning: {warning}");
+        if !io::stdin().is_terminal() {
+            return Err(anyhow!(
+                "Not continuing without confirmation, use --yes to confirm"
+            ));
+        }
+        eprint!("Continue anyway? [y/N] ");
+        let mut answer = String::new();
+        io::stdin()
+            .read_line(&mut answer)
+            .with_context(|| "Failed to read the answer")?;
+        match answer.trim().to_lowercase().as_str() {
+            "y" | "yes" => Ok(()),
+            _ => Err(anyhow!("Not continuing")),
+        }
+    }
+
+    /// Fails because of `warning` unless --force is given, which only warns. Unlike with
+    /// [`Confirmations::confirm`], --yes isn't enough.
+    fn insist(&self, warning: &str) -> anyhow::Result<()> {
+        if !self.force {
+            return Err(anyhow!("{warning}, use --force to continue anyway"));
+        }
+        eprintln!("Warning: {warning}");
+        Ok(())
+    }
+
+    /// Asks about the risks of the search configured by `args`.
+    fn check_search(&self, args: &Args) -> anyhow::Result<()> {
+        #[cfg(unix)]
+        let detached = args.daemon;
+        #[cfg(not(unix))]
+        let detached = false;
+        if !detached && io::stdout().is_terminal() && keys_to_stdout(args) {
+            self.confirm(
+                "found keys will be printed to this terminal, where the secret keys stay in the \
+                 scrollback. Use --output-dir or redirect stdout to write them to a file instead",
+            )?;
+        }
+
+        // Regexes from the patterns file may change while searching, so only -r is checked
+        if args.stop_after.is_some() || args.jobs.is_some() {
+            return Ok(());
+        }
+        for regex in &args.regex {
+            let estimate = if args.key_format != KeyFormat::OpenPgp {
+                FormatPattern::new(&regex.pattern, args.key_format)
+                    .ok()
+                    .and_then(|pattern| pattern.estimate())
+            } else {
+                parse_pattern(&regex.pattern)
+                    .ok()
+                    .and_then(|regex| Estimate::for_regex(&regex))
+            };
+            let Some(estimate) = estimate else {
+                continue;
+            };
+            let warning = format!(
+                "{} matches about 1 in {} keys and --stop-after isn't given, so the search writes \
+                 keys until it is stopped",
+                regex.pattern,
+                humanize(estimate.expected_tries()).trim_end(),
+            );
+            if estimate.probability > RECKLESS_PROBABILITY {
+                self.insist(&warning)?;
+            } else if estimate.probability > PERMISSIVE_PROBABILITY {
+                self.confirm(&warning)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Detaches the process, redirecting stdout and stderr to the configured files.
+#[cfg(unix)]
+fn daemonize(args: &Args) -> anyhow::Result<()> {
+    fn open_append(path: &PathBuf) -> anyhow::Result<File> {
+        File::options()
+            .create(true)
+            .append(true)
+            .open(path)
+            .with_context(|| format!("Failed to open {}", path.display()))
+    }
+
+    let cwd = std::env::current_dir().with_context(|| "Failed to get working directory")?;
+    let mut daemon = daemonize::Daemonize::new().working_directory(cwd);
+    if let Some(path) = &args.pidfile {
+        daemon = daemon.pid_file(path);
+    }
+    if let Some(path) = &args.daemon_stdout {
+        daemon = daemon.stdout(open_append(path)?);
+    }
+    if let Some(path) = &args.daemon_stderr {
+        daemon = daemon.stderr(open_append(path)?);
+    }
+
+    daemon.start().with_context(|| "Failed to daemonize")
+}
+
+#[cfg(feature = "tracing")]
+fn init_logging(args: &Args) -> anyhow::Result<()> {
+    let level = match (args.log_level, &args.log_file) {
+        (Some(level), _) => level,
+        (None, Some(_)) => tracing::Level::INFO,
+        (None, None) => return Ok(()),
+    };
+
+    let subscriber = tracing_subscriber::fmt().with_max_level(level);
+    match &args.log_file {
+        Some(path) => {
+            let file = File::options()
+                .create(true)
+                .append(true)
+                .open(path)
+                .with_context(|| format!("Failed to open log file {}", path.display()))?;
+            subscriber
+                .with_ansi(false)
+                .with_writer(Mutex::new(file))
+                .init();
         }
+        None => subscriber.with_writer(io::stderr).init(),
     }
+
+    Ok(())
+}
+
+fn main() -> ExitCode {
+    let (exit, err) = match run() {
+        Ok(exit) => return exit.into(),
+        Err(err) => match err.downcast::<InvalidConfig>() {
+            Ok(InvalidConfig(err)) => (Exit::Config, err),
+            Err(err) => (Exit::for_error(&err), err),
+        },
+    };
+    eprintln!("Error: {err:?}");
+    exit.into()
 }
 
-fn main() -> anyhow::Result<()> {
-    let args = Args::parse();
+fn run() -> anyhow::Result<Exit> {
+    let mut args = parse_args().map_err(InvalidConfig)?;
 
-    let password = if args.password {
+    let result = match args.command.take() {
+        Some(Command::Estimate(estimate_args)) => estimate(estimate_args),
+        Some(Command::Calibrate(calibrate_args)) => calibrate(calibrate_args),
+        Some(Command::Bench(bench_args)) => bench(bench_args),
+        Some(Command::Verify(verify_args)) => verify(verify_args),
+        Some(Command::Scan(scan_args)) => scan(scan_args),
+        Some(Command::AuditVerify { file }) => audit_verify(&file),
+        Some(Command::SelfTest(self_test_args)) => self_test(self_test_args),
+        Some(Command::Completions { shell }) => {
+            clap_complete::generate(
+                shell,
+                &mut Args::command(),
+                "fingerprunk",
+                &mut io::stdout(),
+            );
+            Ok(())
+        }
+        #[cfg(feature = "cluster")]
+        Some(Command::Serve(serve_args)) => serve(serve_args),
+        #[cfg(feature = "cluster")]
+        Some(Command::Worker(worker_args)) => worker(worker_args),
+        None => return search(args),
+    };
+    result.map(|()| Exit::Success)
+}
+
+/// Runs the search configured by the top-level options.
+fn search(args: Args) -> anyhow::Result<Exit> {
+    #[cfg(feature = "tracing")]
+    init_logging(&args)?;
+
+    if !args.dry_run {
+        let confirmations = Confirmations {
+            yes: args.yes,
+            force: args.force,
+        };
+        confirmations.check_search(&args).map_err(InvalidConfig)?;
+    }
+
+    let password = if args.password && args.dry_run {
+        check_password_prompt().map_err(InvalidConfig)?;
+        // Only shown as "password" in the printed configuration
+        Some(String::new().into())
+    } else if args.password {
         let password = rpassword::prompt_password(
             "Enter password for encrypting found keys (leave empty for no encryption): ",
         )
@@ -82,14 +1962,207 @@ fn main() -> anyhow::Result<()> {
         None
     };
 
-    let config = fingerprunk::Config {
-        regex: args.regex,
-        status_enabled: args.status.evaluate(),
-        stop_after: args.stop_after,
-        password,
+    let certifier = args
+        .certify_with
+        .as_deref()
+        .map(load_certifier)
+        .transpose()
+        .map_err(InvalidConfig)?;
+    let proof_message = args
+        .sign_proof
+        .as_deref()
+        .map(|path| {
+            std::fs::read(path)
+                .with_context(|| format!("Failed to read proof message {}", path.display()))
+        })
+        .transpose()
+        .map_err(InvalidConfig)?;
+
+    #[cfg(unix)]
+    if args.daemon && !args.dry_run {
+        daemonize(&args)?;
+    }
+
+    let status_mode = match args.status {
+        _ if args.quiet => StatusMode::Disabled,
+        // There is no terminal to redraw the status on
+        StatusEnabled::Never => StatusMode::Disabled,
+        _ if args.headless => StatusMode::Append,
+        status => status.evaluate(),
     };
+    let verbosity = verbosity(args.quiet, args.verbose);
+    let headless = args.headless;
+    let dry_run = args.dry_run;
+    let stop_after = args.stop_after.or(args.farm);
+    let has_jobs = args.jobs.is_some();
 
-    Fingerprunk::new_from_config(config).run()?;
+    let mut builder = Config::builder();
+    for regex in args.regex {
+        builder = match regex.label {
+            Some(label) => builder.labeled_regex(label, regex.pattern),
+            None => builder.regex(regex.pattern),
+        };
+    }
+    for (label, dir) in args.out {
+        builder = builder.label_output(label, dir);
+    }
+    #[cfg(feature = "plugin")]
+    for path in &args.matcher_plugin {
+        let plugin = fingerprunk::plugin::PluginMatcher::load(path).map_err(InvalidConfig)?;
+        builder = builder.matcher(plugin);
+    }
+    #[cfg(feature = "watch")]
+    let builder = builder.watch_patterns(args.watch_patterns);
+    #[cfg(feature = "tui")]
+    let builder = builder.tui(args.tui);
+    #[cfg(feature = "numa")]
+    let builder = builder.numa(args.numa);
+    #[cfg(feature = "hybrid")]
+    let builder = builder.cores(args.cores);
+    #[cfg(feature = "webhook")]
+    let builder = builder.webhook(args.webhook);
+    #[cfg(feature = "otel")]
+    let builder = builder.otel(args.otel);
+    #[cfg(feature = "keyserver")]
+    let builder = builder.keyserver(args.keyserver);
+    #[cfg(feature = "sandbox")]
+    let builder = builder.sandbox(args.sandbox);
+    let builder = builder
+        .offline(args.offline)
+        .policy(args.policy)
+        .audit_log(args.audit_log)
+        .key_format(args.key_format)
+        .stdin_control(true);
+    #[cfg(unix)]
+    let builder = builder
+        .log_syslog(args.log_syslog)
+        .control_socket(args.control_socket);
+    #[cfg(feature = "http-api")]
+    let builder = builder.listen(args.listen);
+    let config = builder
+        .patterns_file(args.patterns_file)
+        .status_mode(status_mode)
+        .stop_after(args.stop_after)
+        .farm(args.farm)
+        .threads(args.threads)
+        .batch_size(args.batch_size)
+        .auto_tune(args.auto_tune)
+        .finalizers(
+            args.finalizers,
+            if args.unordered {
+                OutputOrder::Built
+            } else {
+                OutputOrder::Found
+            },
+        )
+        .password(password)
+        .key_version(args.key_version)
+        .user_id(args.uid)
+        .subkey_regex(args.subkey_regex)
+        .certify_with(certifier)
+        .sign_proof(proof_message)
+        .proofs(args.proofs)
+        .humanize_numbers(!args.raw_numbers)
+        .status_template(if args.progress_bar {
+            StatusTemplate::PROGRESS_BAR
+                .parse()
+                .expect("progress bar template should be valid")
+        } else {
+            args.status_template.unwrap_or_else(|| match status_mode {
+                StatusMode::Append => StatusTemplate::LOG_LINE
+                    .parse()
+                    .expect("log line template should be valid"),
+                _ => StatusTemplate::default(),
+            })
+        })
+        .verbosity(verbosity)
+        .bell(
+            args.bell,
+            args.bell_repeat
+                .map(|seconds| Duration::from_secs(seconds.get())),
+        )
+        .notify_email(args.notify_email.map(|to| EmailConfig {
+            to,
+            sendmail: args.sendmail,
+        }))
+        .state_file(args.state_file, args.resume, args.allow_config_change)
+        .output_dir(args.output_dir)
+        .jobs(
+            args.jobs
+                .as_deref()
+                .map(fingerprunk::jobs::read_jobs_file)
+                .transpose()
+                .map_err(InvalidConfig)?
+                .unwrap_or_default(),
+        )
+        .stop_with_priority(args.stop_with_priority)
+        .avoid_keyids(
+            args.avoid_keyids
+                .as_deref()
+                .map(KeyIds::read)
+                .transpose()
+                .map_err(InvalidConfig)?,
+        )
+        .json_logs(headless)
+        .build()?;
 
-    Ok(())
+    if dry_run {
+        let lines = Fingerprunk::new_from_config(config)
+            .dry_run()
+            .map_err(|err| InvalidConfig(err.into()))?;
+        for (name, value) in lines {
+            println!("{name}: {value}");
+        }
+        println!("Configuration OK");
+        return Ok(Exit::Success);
+    }
+
+    let wants_all = stop_after.is_some() || has_jobs;
+    let result = Fingerprunk::new_from_config(config)
+        .run()
+        .map(|summary| {
+            if !summary.interrupted {
+                Exit::Success
+            } else if summary.found == 0 {
+                Exit::StoppedNone
+            } else if wants_all {
+                Exit::StoppedSome
+            } else {
+                Exit::Success
+            }
+        })
+        .map_err(anyhow::Error::from);
+    if headless && let Err(err) = &result {
+        // Keep stdout parseable as JSON lines, even for fatal errors
+        println!(
+            "{}",
+            serde_json::json!({
+                "time": status::format_timestamp(SystemTime::now()),
+                "event": "error",
+                "message": format!("{err:#}"),
+            })
+        );
+        std::process::exit(Exit::for_error(err) as i32);
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_duration_accepts_units() {
+        assert_eq!(parse_duration("30"), Ok(Duration::from_secs(30)));
+        assert_eq!(parse_duration("30s"), Ok(Duration::from_secs(30)));
+        assert_eq!(parse_duration("2m"), Ok(Duration::from_secs(120)));
+        assert_eq!(parse_duration("1h"), Ok(Duration::from_secs(3600)));
+    }
+
+    #[test]
+    fn parse_duration_rejects_invalid_durations() {
+        for invalid in ["", "m", "1d", "10 s", "1.5h", "-1s", "0", "0m"] {
+            assert!(parse_duration(invalid).is_err(), "{invalid:?}");
+        }
+    }
 }
@@ -0,0 +1,686 @@
+//! Deciding which fingerprints are found.
+//!
+//! Every generated fingerprint is checked against the regexes of the configuration and the
+//! [`Matcher`]s added with [`ConfigBuilder::matcher`](crate::ConfigBuilder::matcher). Besides
+//! regexes, there are matchers for literal prefixes and suffixes ([`Prefix`], [`Suffix`]), for
+//! words from a dictionary ([`WordList`]), for a minimum score ([`Score`]) and for the shape of the
+//! OpenSSH-style randomart ([`Randomart`]). Other strategies can be plugged in by implementing
+//! [`Matcher`], or, with the `plugin` feature, as WebAssembly module (see [`crate::plugin`]).
+
+use std::{fmt, fs, ops::Range, path::Path, sync::Arc, sync::OnceLock};
+
+use anyhow::Context;
+use fancy_regex::Regex;
+
+use crate::{
+    ConfigError, Error,
+    estimate::{Estimate, FINGERPRINT_HEX_LEN, Method},
+    keygen::KeyVersion,
+    pattern::{self, parse_pattern, parse_pattern_for},
+};
+
+/// Decides whether a fingerprint is wanted.
+///
+/// Matchers are shared by all worker threads and called for every generated key, so they should
+/// be fast. Their [`Display`](fmt::Display) output names them in the status display, the logs and
+/// [`MatchMeta::pattern`](crate::sink::MatchMeta::pattern).
+pub trait Matcher: fmt::Debug + fmt::Display + Send + Sync {
+    /// Checks a fingerprint given in uppercase hex. Returning an error ends the search.
+    fn is_match(&self, fingerprint_hex: &str) -> Result<bool, Error>;
+
+    /// The part of a matching fingerprint to highlight, if any.
+    fn find(&self, _fingerprint_hex: &str) -> Option<Range<usize>> {
+        None
+    }
+
+    /// The probability of a random fingerprint matching, measured by sampling unless overridden.
+    fn estimate(&self) -> Option<Estimate> {
+        Estimate::sampled(|fingerprint_hex| self.is_match(fingerprint_hex).ok())
+    }
+
+    /// The regex this matcher is equivalent to, if any, so that it can be checked in one pass
+    /// together with the other regexes.
+    fn as_regex(&self) -> Option<&Regex> {
+        None
+    }
+
+    /// A copy of this matcher for a single worker thread, so that its state, like the scratch
+    /// space of a regex, isn't shared between the workers. Matchers without state return `None`
+    /// and are shared.
+    ///
+    /// Matchers with state keep it behind a lock, which is never contended with a copy per worker.
+    fn clone_for_worker(&self) -> Option<Arc<dyn Matcher>> {
+        None
+    }
+}
+
+impl Matcher for Regex {
+    fn is_match(&self, fingerprint_hex: &str) -> Result<bool, Error> {
+        Regex::is_match(self, fingerprint_hex).map_err(|source| Error::Regex {
+            regex: self.to_string(),
+            source: Box::new(source),
+        })
+    }
+
+    fn find(&self, fingerprint_hex: &str) -> Option<Range<usize>> {
+        Regex::find(self, fingerprint_hex)
+            .ok()
+            .flatten()
+            .map(|m| m.range())
+    }
+
+    fn estimate(&self) -> Option<Estimate> {
+        Estimate::for_regex(self)
+    }
+
+    fn as_regex(&self) -> Option<&Regex> {
+        Some(self)
+    }
+
+    fn clone_for_worker(&self) -> Option<Arc<dyn Matcher>> {
+        Some(Arc::new(self.clone()))
+    }
+}
+
+/// Matches fingerprints starting with the given hex digits, like the regex `^CAFE` but faster.
+#[derive(Clone, Debug)]
+pub struct Prefix(String);
+
+impl Prefix {
+    pub fn new(hex: &str) -> Result<Self, ConfigError> {
+        parse_literal(hex, '^').map(Self)
+    }
+}
+
+impl Matcher for Prefix {
+    fn is_match(&self, fingerprint_hex: &str) -> Result<bool, Error> {
+        Ok(fingerprint_hex.starts_with(&self.0))
+    }
+
+    fn find(&self, _fingerprint_hex: &str) -> Option<Range<usize>> {
+        Some(0..self.0.len())
+    }
+
+    fn estimate(&self) -> Option<Estimate> {
+        Some(literal_estimate(&self.0, true))
+    }
+}
+
+impl fmt::Display for Prefix {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "^{}", self.0)
+    }
+}
+
+/// Matches fingerprints ending with the given hex digits, like the regex `CAFE$` but faster.
+#[derive(Clone, Debug)]
+pub struct Suffix(String);
+
+impl Suffix {
+    pub fn new(hex: &str) -> Result<Self, ConfigError> {
+        parse_literal(hex, '$').map(Self)
+    }
+}
+
+impl Matcher for Suffix {
+    fn is_match(&self, fingerprint_hex: &str) -> Result<bool, Error> {
+        Ok(fingerprint_hex.ends_with(&self.0))
+    }
+
+    fn find(&self, fingerprint_hex: &str) -> Option<Range<usize>> {
+        Some(fingerprint_hex.len().saturating_sub(self.0.len())..fingerprint_hex.len())
+    }
+
+    fn estimate(&self) -> Option<Estimate> {
+        Some(literal_estimate(&self.0, true))
+    }
+}
+
+impl fmt::Display for Suffix {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}$", self.0)
+    }
+}
+
+/// Matches fingerprints containing any of a list of words, e.g. the hexspeak words of a
+/// dictionary.
+#[derive(Clone, Debug)]
+pub struct WordList {
+    /// Longest first, so that the longest word is highlighted.
+    words: Vec<String>,
+}
+
+impl WordList {
+    /// Keeps the words of at least `min_len` characters that can be spelled in hex, ignoring case.
+    /// Fails if no word is left.
+    pub fn new(
+        words: impl IntoIterator<Item = impl AsRef<str>>,
+        min_len: usize,
+    ) -> Result<Self, ConfigError> {
+        let mut words: Vec<_> = words
+            .into_iter()
+            .map(|word| word.as_ref().trim().to_ascii_uppercase())
+            .filter(|word| {
+                (min_len.max(1)..=FINGERPRINT_HEX_LEN).contains(&word.len())
+                    && word.bytes().all(|b| b.is_ascii_hexdigit())
+            })
+            .collect();
+        words.sort_by(|a, b| b.len().cmp(&a.len()).then_with(|| a.cmp(b)));
+        words.dedup();
+        if words.is_empty() {
+            return Err(ConfigError::NoPatterns);
+        }
+        Ok(Self { words })
+    }
+
+    /// Reads the words from a file with one word per line, like `/usr/share/dict/words`.
+    pub fn read(path: &Path, min_len: usize) -> anyhow::Result<Self> {
+        let content = fs::read_to_string(path)
+            .with_context(|| format!("Failed to read word list {}", path.display()))?;
+        Self::new(content.lines(), min_len)
+            .with_context(|| format!("No usable words in {}", path.display()))
+    }
+}
+
+impl Matcher for WordList {
+    fn is_match(&self, fingerprint_hex: &str) -> Result<bool, Error> {
+        Ok(self.words.iter().any(|word| fingerprint_hex.contains(word)))
+    }
+
+    fn find(&self, fingerprint_hex: &str) -> Option<Range<usize>> {
+        self.words.iter().find_map(|word| {
+            let start = fingerprint_hex.find(word)?;
+            Some(start..start + word.len())
+        })
+    }
+
+    fn estimate(&self) -> Option<Estimate> {
+        Estimate::combine(
+            self.words
+                .iter()
+                .map(|word| Some(literal_estimate(word, false))),
+        )
+    }
+}
+
+impl fmt::Display for WordList {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "any of {} words", self.words.len())
+    }
+}
+
+/// Matches fingerprints whose score is at least a minimum.
+///
+/// `Score::new("longest run", 8, longest_run)` finds fingerprints with a character repeated at
+/// least eight times in a row.
+pub struct Score<F> {
+    name: String,
+    min: u32,
+    score: F,
+}
+
+impl<F: Fn(&str) -> u32 + Send + Sync> Score<F> {
+    /// Matches the fingerprints for which `score` returns at least `min`; `name` describes the
+    /// score in the status display and logs.
+    pub fn new(name: impl Into<String>, min: u32, score: F) -> Self {
+        Self {
+            name: name.into(),
+            min,
+            score,
+        }
+    }
+}
+
+impl<F: Fn(&str) -> u32 + Send + Sync> Matcher for Score<F> {
+    fn is_match(&self, fingerprint_hex: &str) -> Result<bool, Error> {
+        Ok((self.score)(fingerprint_hex) >= self.min)
+    }
+}
+
+impl<F> fmt::Debug for Score<F> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Score")
+            .field("name", &self.name)
+            .field("min", &self.min)
+            .finish_non_exhaustive()
+    }
+}
+
+impl<F> fmt::Display for Score<F> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} >= {}", self.name, self.min)
+    }
+}
+
+/// Scores a fingerprint by its longest run of a repeated character.
+#[must_use]
+pub fn longest_run(fingerprint_hex: &str) -> u32 {
+    let mut longest = 0;
+    let mut run = 0;
+    let mut previous = None;
+    for b in fingerprint_hex.bytes() {
+        run = if previous == Some(b) { run + 1 } else { 1 };
+        longest = longest.max(run);
+        previous = Some(b);
+    }
+    longest
+}
+
+/// Width of a [`randomart`] picture.
+pub const RANDOMART_WIDTH: usize = 17;
+/// Height of a [`randomart`] picture.
+pub const RANDOMART_HEIGHT: usize = 9;
+
+/// Characters of the randomart by the number of visits, followed by those of start and end.
+const RANDOMART_SYMBOLS: &[u8] = b" .o+=*BOX@%&#/^SE";
+
+/// Draws the randomart of a fingerprint like OpenSSH's `VisualHostKey`, without the border.
+///
+/// Returns [`RANDOMART_HEIGHT`] rows of [`RANDOMART_WIDTH`] characters.
+#[must_use]
+pub fn randomart(fingerprint_hex: &str) -> Vec<String> {
+    const VISITS_MAX: u8 = RANDOMART_SYMBOLS.len() as u8 - 3;
+
+    let mut field = [[0u8; RANDOMART_WIDTH]; RANDOMART_HEIGHT];
+    let start = (RANDOMART_WIDTH / 2, RANDOMART_HEIGHT / 2);
+    let (mut x, mut y) = start;
+    for byte in hex_bytes(fingerprint_hex) {
+        let mut input = byte;
+        for _ in 0..4 {
+            // Each pair of bits moves the bishop diagonally, staying within the field
+            x = if input & 1 == 1 {
+                (x + 1).min(RANDOMART_WIDTH - 1)
+            } else {
+                x.saturating_sub(1)
+            };
+            y = if input & 2 == 2 {
+                (y + 1).min(RANDOMART_HEIGHT - 1)
+            } else {
+                y.saturating_sub(1)
+            };
+            if field[y][x] < VISITS_MAX {
+                field[y][x] += 1;
+            }
+            input >>= 2;
+        }
+    }
+    field[start.1][start.0] = VISITS_MAX + 1;
+    field[y][x] = VISITS_MAX + 2;
+
+    field
+        .iter()
+        .map(|row| {
+            row.iter()
+                .map(|&visits| char::from(RANDOMART_SYMBOLS[usize::from(visits)]))
+                .collect()
+        })
+        .collect()
+}
+
+fn hex_bytes(hex: &str) -> impl Iterator<Item = u8> + '_ {
+    hex.as_bytes()
+        .chunks_exact(2)
+        .filter_map(|pair| u8::from_str_radix(std::str::from_utf8(pair).ok()?, 16).ok())
+}
+
+/// Matches fingerprints whose [`randomart`] fits a template.
+#[derive(Clone, Debug)]
+pub struct Randomart {
+    template: Vec<Vec<char>>,
+}
+
+impl Randomart {
+    /// Parses a template of [`RANDOMART_HEIGHT`] lines of [`RANDOMART_WIDTH`] characters, where
+    /// `?` stands for any character and every other character has to appear in the randomart at
+    /// that position, e.g. a space for a field the bishop never visited.
+    pub fn new(template: &str) -> Result<Self, ConfigError> {
+        let template: Vec<Vec<char>> = template
+            .lines()
+            .map(|line| line.chars().collect())
+            .collect();
+        if template.len() != RANDOMART_HEIGHT
+            || template.iter().any(|line| line.len() != RANDOMART_WIDTH)
+        {
+            return Err(ConfigError::RandomartTemplate);
+        }
+        Ok(Self { template })
+    }
+}
+
+impl Matcher for Randomart {
+    fn is_match(&self, fingerprint_hex: &str) -> Result<bool, Error> {
+        Ok(randomart(fingerprint_hex)
+            .iter()
+            .zip(&self.template)
+            .all(|(row, template)| {
+                row.chars()
+                    .zip(template)
+                    .all(|(c, &wanted)| wanted == '?' || wanted == c)
+            }))
+    }
+}
+
+impl fmt::Display for Randomart {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let wanted = self
+            .template
+            .iter()
+            .flatten()
+            .filter(|&&c| c != '?')
+            .count();
+        write!(f, "randomart with {wanted} fixed fields")
+    }
+}
+
+/// Uppercases a literal and checks that it fits into a fingerprint, reporting errors for the
+/// regex equivalent to it.
+fn parse_literal(hex: &str, anchor: char) -> Result<String, ConfigError> {
+    let literal = hex.to_ascii_uppercase();
+    let pattern = || match anchor {
+        '^' => format!("^{hex}"),
+        _ => format!("{hex}{anchor}"),
+    };
+    if let Some(character) = literal.chars().find(|c| !c.is_ascii_hexdigit()) {
+        return Err(ConfigError::NotHex {
+            pattern: pattern(),
+            character,
+        });
+    }
+    if literal.is_empty() || literal.len() > FINGERPRINT_HEX_LEN {
+        return Err(ConfigError::Length {
+            pattern: pattern(),
+            min: literal.len(),
+            max: Some(literal.len()),
+            anchored: false,
+            version: Some(KeyVersion::V4),
+        });
+    }
+    Ok(literal)
+}
+
+/// The probability of a literal appearing at a fixed position or anywhere in a fingerprint.
+fn literal_estimate(literal: &str, anchored: bool) -> Estimate {
+    let single = 16f64.powi(-(literal.len() as i32));
+    if anchored {
+        Estimate {
+            probability: single,
+            method: Method::Exact,
+        }
+    } else {
+        Estimate {
+            probability: ((FINGERPRINT_HEX_LEN - literal.len() + 1) as f64 * single).min(1.0),
+            method: Method::Approximate,
+        }
+    }
+}
+
+/// The regexes and matchers of which at least one has to match for a key to be found.
+#[derive(Clone, Debug)]
+pub(crate) struct PatternSet {
+    matchers: Vec<Arc<dyn Matcher>>,
+    /// The regexes among the matchers that need no fancy features, checked in one pass.
+    regex_set: Option<RegexSet>,
+    /// Indices of the matchers that aren't in `regex_set`, which are checked one by one, with
+    /// the hex digits a fingerprint has to start with for the matcher to be run, if any.
+    others: Vec<(usize, Option<String>)>,
+    /// Computed on first use because sampling may take a while.
+    estimate: OnceLock<Option<Estimate>>,
+}
+
+/// Regexes compiled into a single automaton, see [`PatternSet`].
+#[derive(Clone, Debug)]
+struct RegexSet {
+    set: regex::RegexSet,
+    /// Index of the matcher of every regex of `set`.
+    matchers: Vec<usize>,
+}
+
+impl RegexSet {
+    /// Compiles the regexes among `matchers` that the regex crate supports, i.e. those without
+    /// backreferences, lookaround etc. Returns `None` if a set wouldn't pay off.
+    fn new(matchers: &[Arc<dyn Matcher>]) -> Option<Self> {
+        let (patterns, matchers): (Vec<_>, Vec<_>) = matchers
+            .iter()
+            .enumerate()
+            .filter_map(|(i, matcher)| Some((matcher.as_regex()?.as_str(), i)))
+            .filter(|(pattern, _)| regex::Regex::new(pattern).is_ok())
+            .unzip();
+        // A single regex is already delegated to the regex crate by fancy-regex
+        if patterns.len() < 2 {
+            return None;
+        }
+        let set = regex::RegexSet::new(patterns).ok()?;
+        Some(Self { set, matchers })
+    }
+
+    /// The index of the first matcher in the set that matches.
+    #[inline]
+    fn first_match(&self, fingerprint_hex: &str) -> Option<usize> {
+        // Most fingerprints don't match, and finding out is cheaper than finding all matches
+        if !self.set.is_match(fingerprint_hex) {
+            return None;
+        }
+        let first = self.set.matches(fingerprint_hex).into_iter().next()?;
+        Some(self.matchers[first])
+    }
+}
+
+impl PatternSet {
+    pub(crate) fn new(matchers: Vec<Arc<dyn Matcher>>) -> Self {
+        let regex_set = RegexSet::new(&matchers);
+        let others = (0..matchers.len())
+            .filter(|i| {
+                regex_set
+                    .as_ref()
+                    .is_none_or(|set| !set.matchers.contains(i))
+            })
+            .map(|i| {
+                let prefix = matchers[i]
+                    .as_regex()
+                    .and_then(|regex| pattern::anchored_prefix(regex.as_str()))
+                    .map(ToString::to_string);
+                (i, prefix)
+            })
+            .collect();
+        Self {
+            matchers,
+            regex_set,
+            others,
+            estimate: OnceLock::new(),
+        }
+    }
+
+    /// A copy of the set for a single worker thread, see [`Matcher::clone_for_worker`].
+    pub(crate) fn for_worker(&self) -> Self {
+        Self {
+            matchers: self
+                .matchers
+                .iter()
+                .map(|matcher| {
+                    matcher
+                        .clone_for_worker()
+                        .unwrap_or_else(|| Arc::clone(matcher))
+                })
+                .collect(),
+            // Clones of a regex set get their own scratch space as well
+            regex_set: self.regex_set.clone(),
+            others: self.others.clone(),
+            estimate: self.estimate.clone(),
+        }
+    }
+
+    pub(crate) fn from_regexes(regexes: Vec<Regex>) -> Self {
+        Self::new(
+            regexes
+                .into_iter()
+                .map(|regex| Arc::new(regex) as Arc<dyn Matcher>)
+                .collect(),
+        )
+    }
+
+    /// Returns a new set that additionally matches `regex`.
+    pub(crate) fn with_regex(&self, regex: Regex) -> Self {
+        let mut matchers = self.matchers.clone();
+        matchers.push(Arc::new(regex));
+        Self::new(matchers)
+    }
+
+    /// The estimated probability of a random key matching.
+    pub(crate) fn estimate(&self) -> Option<&Estimate> {
+        self.estimate
+            .get_or_init(|| Estimate::combine(self.matchers.iter().map(|m| m.estimate())))
+            .as_ref()
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        self.matchers.is_empty()
+    }
+
+    /// Whether checking a fingerprint allocates nothing once the scratch space of the regexes has
+    /// been set up, which is only known for regexes without fancy features. Other matchers, like
+    /// fancy regexes with their backtracking stack, may allocate.
+    #[cfg(feature = "alloc-check")]
+    pub(crate) fn allocation_free(&self) -> bool {
+        self.matchers.iter().all(|matcher| {
+            matcher
+                .as_regex()
+                .is_some_and(|regex| regex::Regex::new(regex.as_str()).is_ok())
+        })
+    }
+
+    /// Returns the first matcher matching the fingerprint.
+    pub(crate) fn matching(&self, fingerprint_hex: &str) -> Result<Option<&dyn Matcher>, Error> {
+        let first_in_set = self
+            .regex_set
+            .as_ref()
+            .and_then(|set| set.first_match(fingerprint_hex));
+        // Only the matchers before the first match in the set can take precedence over it
+        for (i, prefix) in &self.others {
+            let i = *i;
+            if first_in_set.is_some_and(|first| first < i) {
+                break;
+            }
+            // Anchored regexes can't match without their prefix, which is much cheaper to check
+            if prefix
+                .as_ref()
+                .is_some_and(|prefix| !fingerprint_hex.starts_with(prefix.as_str()))
+            {
+                continue;
+            }
+            if self.matchers[i].is_match(fingerprint_hex)? {
+                return Ok(Some(self.matchers[i].as_ref()));
+            }
+        }
+        Ok(first_in_set.map(|i| self.matchers[i].as_ref()))
+    }
+
+    #[inline]
+    pub(crate) fn is_match(&self, fingerprint_hex: &str) -> Result<bool, Error> {
+        Ok(self.matching(fingerprint_hex)?.is_some())
+    }
+
+    /// Finds the part to highlight of the first matcher that matches.
+    pub(crate) fn find(&self, fingerprint_hex: &str) -> Option<Range<usize>> {
+        self.matchers
+            .iter()
+            .filter(|matcher| matcher.is_match(fingerprint_hex).unwrap_or(false))
+            .find_map(|matcher| matcher.find(fingerprint_hex))
+    }
+}
+
+impl fmt::Display for PatternSet {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (i, matcher) in self.matchers.iter().enumerate() {
+            if i > 0 {
+                f.write_str(" | ")?;
+            }
+            write!(f, "{matcher}")?;
+        }
+        Ok(())
+    }
+}
+
+/// Reads a patterns file with one regex per line, ignoring empty lines and `#` comments.
+///
+/// The lengths of the regexes are checked against the fingerprints of `version`, or of any
+/// version if `None`.
+pub(crate) fn read_patterns_file(
+    path: &Path,
+    version: Option<KeyVersion>,
+) -> anyhow::Result<Vec<Regex>> {
+    let content = fs::read_to_string(path)
+        .with_context(|| format!("Failed to read patterns file {}", path.display()))?;
+
+    content
+        .lines()
+        .enumerate()
+        .map(|(num, line)| (num, line.trim()))
+        .filter(|(_, line)| !line.is_empty() && !line.starts_with('#'))
+        .map(|(num, line)| {
+            match version {
+                Some(version) => parse_pattern_for(line, version),
+                None => parse_pattern(line),
+            }
+            .with_context(|| format!("Invalid regex in {} line {}", path.display(), num + 1))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pattern_set(patterns: &[&str]) -> PatternSet {
+        PatternSet::from_regexes(
+            patterns
+                .iter()
+                .map(|pattern| Regex::new(pattern).unwrap())
+                .collect(),
+        )
+    }
+
+    fn matching(set: &PatternSet, fingerprint_hex: &str) -> Option<String> {
+        set.matching(fingerprint_hex)
+            .unwrap()
+            .map(ToString::to_string)
+    }
+
+    #[test]
+    fn regex_set_leaves_out_fancy_regexes() {
+        let set = pattern_set(&["^A", "(.)\\1{3}", "^B"]);
+        assert_eq!(set.regex_set.as_ref().unwrap().matchers, [0, 2]);
+        assert_eq!(set.others, [(1, None)]);
+    }
+
+    #[test]
+    fn earlier_set_regex_takes_precedence() {
+        let set = pattern_set(&["A$", "^A"]);
+        let fingerprint = "A0000000000000000000000000000000000000BA";
+        assert_eq!(matching(&set, fingerprint).as_deref(), Some("A$"));
+    }
+
+    #[test]
+    fn earlier_fancy_regex_takes_precedence() {
+        let set = pattern_set(&["^A", "(.)\\1{3}", "^B"]);
+        let fingerprint = "BAAAA000000000000000000000000000000000FF";
+        assert_eq!(matching(&set, fingerprint).as_deref(), Some("(.)\\1{3}"));
+    }
+
+    #[test]
+    fn earlier_set_regex_takes_precedence_over_fancy() {
+        let set = pattern_set(&["^A", "(.)\\1{3}", "^B"]);
+        let fingerprint = "AAAAA000000000000000000000000000000000FF";
+        assert_eq!(matching(&set, fingerprint).as_deref(), Some("^A"));
+    }
+
+    #[test]
+    fn fancy_regex_matches_without_set_match() {
+        let set = pattern_set(&["^A", "(.)\\1{3}", "^B"]);
+        let fingerprint = "C1111000000000000000000000000000000000FF";
+        assert_eq!(matching(&set, fingerprint).as_deref(), Some("(.)\\1{3}"));
+        assert_eq!(
+            matching(&set, "C123456789ABCDEF0123456789ABCDEF01234567"),
+            None
+        );
+    }
+}
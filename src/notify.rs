@@ -0,0 +1,233 @@
+//! Notifications about found keys, delivered off the finalizer so they can't slow it down.
+
+use std::{
+    io::Write,
+    path::PathBuf,
+    process::{Command, Stdio},
+    sync::mpsc,
+    time::Duration,
+};
+
+use anyhow::{Context, bail};
+
+use crate::{Fingerprunk, Verbosity, status::DurationDhms};
+
+/// Settings for email notifications, which are handed to a sendmail-compatible command.
+///
+/// SMTP servers, credentials etc. are configured in the mail transfer agent behind that command
+/// (e.g. msmtp or Postfix).
+#[derive(Clone, Debug)]
+pub struct EmailConfig {
+    /// Recipient address.
+    pub to: String,
+    /// The sendmail-compatible command used to submit the email.
+    pub sendmail: PathBuf,
+}
+
+/// Information about a found key that is passed on to notification channels.
+#[derive(Clone, Debug)]
+pub(crate) struct FoundEvent {
+    pub fingerprint: String,
+    pub pattern: String,
+    /// The label of the matched regex, if any.
+    pub label: Option<String>,
+    /// Where the key was written to, see [`Fingerprunk::key_destination`].
+    pub destination: String,
+    pub elapsed: Duration,
+    pub tried: u64,
+}
+
+/// A channel through which found keys are announced.
+pub(crate) trait Notifier: Send {
+    /// Short description used in error messages.
+    fn name(&self) -> &str;
+
+    fn notify(&mut self, event: &FoundEvent) -> anyhow::Result<()>;
+}
+
+impl Fingerprunk {
+    /// Creates the notifiers for all configured notification channels.
+    pub(crate) fn notifiers(&self) -> Vec<Box<dyn Notifier>> {
+        let mut notifiers: Vec<Box<dyn Notifier>> = Vec::new();
+
+        if let Some(email) = &self.config.notify_email {
+            notifiers.push(Box::new(Email(email.clone())));
+        }
+
+        #[cfg(unix)]
+        if self.config.log_syslog {
+            notifiers.push(Box::new(Syslog));
+        }
+
+        #[cfg(feature = "webhook")]
+        if let Some(url) = &self.config.webhook {
+            notifiers.push(Box::new(Webhook::new(url.clone())));
+        }
+
+        notifiers
+    }
+
+    /// Delivers notifications until the sending side of the channel is dropped.
+    pub(crate) fn notifier_thread(
+        &self,
+        mut notifiers: Vec<Box<dyn Notifier>>,
+        receiver: mpsc::Receiver<FoundEvent>,
+    ) {
+        for event in receiver {
+            for notifier in &mut notifiers {
+                if let Err(err) = notifier.notify(&event) {
+                    self.log(
+                        Verbosity::Normal,
+                        format_args!(
+                            "Failed to send {} notification for {}: {err:#}",
+                            notifier.name(),
+                            event.fingerprint
+                        ),
+                    );
+                }
+            }
+        }
+    }
+}
+
+/// Submits an email to a sendmail-compatible command for every found key.
+struct Email(EmailConfig);
+
+impl Notifier for Email {
+    fn name(&self) -> &str {
+        "email"
+    }
+
+    fn notify(&mut self, event: &FoundEvent) -> anyhow::Result<()> {
+        let host = host_name();
+        let message = format!(
+            "To: {to}\r\n\
+            Subject: Fingerprunk found key {fingerprint}\r\n\
+            Content-Type: text/plain; charset=utf-8\r\n\
+            \r\n\
+            Fingerprunk on {host} found a key matching {pattern}{label}.\r\n\
+            \r\n\
+            Fingerprint: {fingerprint}\r\n\
+            Elapsed:     {elapsed}\r\n\
+            Tried:       {tried} keys\r\n",
+            to = self.0.to,
+            fingerprint = event.fingerprint,
+            pattern = event.pattern,
+            label = event
+                .label
+                .as_ref()
+                .map(|label| format!(" (labeled {label})"))
+                .unwrap_or_default(),
+            elapsed = DurationDhms(event.elapsed),
+            tried = event.tried,
+        );
+
+        let mut child = Command::new(&self.0.sendmail)
+            .args(["-oi", "--", &self.0.to])
+            .stdin(Stdio::piped())
+            .spawn()
+            .with_context(|| format!("Failed to run {}", self.0.sendmail.display()))?;
+        child
+            .stdin
+            .take()
+            .expect("stdin should be piped")
+            .write_all(message.as_bytes())?;
+
+        let status = child.wait()?;
+        if !status.success() {
+            bail!("{} exited with {status}", self.0.sendmail.display());
+        }
+
+        Ok(())
+    }
+}
+
+/// Logs every found key to the local syslog daemon (or journald, which listens on the same socket).
+#[cfg(unix)]
+struct Syslog;
+
+#[cfg(unix)]
+impl Syslog {
+    const SOCKET_PATH: &str = "/dev/log";
+    /// Facility `user`, severity `notice`.
+    const PRIORITY: u8 = (1 << 3) | 5;
+}
+
+#[cfg(unix)]
+impl Notifier for Syslog {
+    fn name(&self) -> &str {
+        "syslog"
+    }
+
+    fn notify(&mut self, event: &FoundEvent) -> anyhow::Result<()> {
+        let message = format!(
+            "<{}>fingerprunk[{}]: Found key {} matching {} after {} tried keys, written to {}",
+            Self::PRIORITY,
+            std::process::id(),
+            event.fingerprint,
+            event.pattern,
+            event.tried,
+            event.destination,
+        );
+
+        // Connect for every message so that a restarted syslog daemon doesn't break logging.
+        std::os::unix::net::UnixDatagram::unbound()?
+            .send_to(message.as_bytes(), Self::SOCKET_PATH)
+            .with_context(|| format!("Failed to write to {}", Self::SOCKET_PATH))?;
+
+        Ok(())
+    }
+}
+
+/// POSTs a JSON description of every found key to a URL.
+#[cfg(feature = "webhook")]
+struct Webhook {
+    url: String,
+    agent: ureq::Agent,
+}
+
+#[cfg(feature = "webhook")]
+impl Webhook {
+    const TIMEOUT: Duration = Duration::from_secs(10);
+
+    fn new(url: String) -> Self {
+        let agent = ureq::Agent::config_builder()
+            .timeout_global(Some(Self::TIMEOUT))
+            .build()
+            .into();
+        Self { url, agent }
+    }
+}
+
+#[cfg(feature = "webhook")]
+impl Notifier for Webhook {
+    fn name(&self) -> &str {
+        "webhook"
+    }
+
+    fn notify(&mut self, event: &FoundEvent) -> anyhow::Result<()> {
+        self.agent.post(&self.url).send_json(serde_json::json!({
+            "fingerprint": event.fingerprint,
+            "pattern": event.pattern,
+            "label": event.label,
+            "elapsed_secs": event.elapsed.as_secs_f64(),
+            "tried": event.tried,
+            "host": host_name(),
+        }))?;
+
+        Ok(())
+    }
+}
+
+/// The name of this host for the notifications.
+fn host_name() -> String {
+    #[cfg(not(target_arch = "wasm32"))]
+    {
+        gethostname::gethostname().to_string_lossy().into_owned()
+    }
+    // There is no host name in a browser
+    #[cfg(target_arch = "wasm32")]
+    {
+        "localhost".to_string()
+    }
+}
@@ -0,0 +1,93 @@
+//! Placing the workers on the nodes of multi-socket machines.
+//!
+//! Every worker is pinned to a CPU of one node before it sets up its key generator and its copy of
+//! the matcher, so that the kernel allocates their memory on that node and the worker never has to
+//! reach across to another socket's memory.
+
+use std::fs;
+
+use crate::topology::{parse_cpu_list, read_trimmed};
+
+const NODES_DIR: &str = "/sys/devices/system/node";
+
+/// Which CPU, and thereby which node, every worker runs on.
+#[derive(Clone, Debug)]
+pub(crate) struct Placement {
+    /// IDs of the nodes the workers are spread over.
+    nodes: Vec<usize>,
+    /// CPU and index into `nodes` of every worker.
+    workers: Vec<(usize, usize)>,
+}
+
+impl Placement {
+    /// Spreads `workers` workers evenly over the nodes of this machine, one per CPU as far as
+    /// there are enough.
+    ///
+    /// Returns `None` if the machine has a single node, where placing the workers makes no
+    /// difference, or if its topology is unknown, e.g. on other systems than Linux.
+    pub(crate) fn new(workers: usize) -> anyhow::Result<Option<Self>> {
+        let nodes = read_nodes()?;
+        if nodes.len() < 2 {
+            return Ok(None);
+        }
+
+        // Taking turns between the nodes, so that even a few workers use all of them
+        let longest = nodes.iter().map(|(_, cpus)| cpus.len()).max().unwrap_or(0);
+        let cpus: Vec<_> = (0..longest)
+            .flat_map(|i| {
+                nodes
+                    .iter()
+                    .enumerate()
+                    .filter_map(move |(node, (_, cpus))| Some((*cpus.get(i)?, node)))
+            })
+            .collect();
+        Ok(Some(Self {
+            nodes: nodes.into_iter().map(|(id, _)| id).collect(),
+            workers: cpus.into_iter().cycle().take(workers).collect(),
+        }))
+    }
+
+    /// Pins the calling thread to the CPU of worker `num`. Returns whether that worked.
+    pub(crate) fn pin(&self, num: usize) -> bool {
+        let (cpu, _) = self.workers[num];
+        core_affinity::set_for_current(core_affinity::CoreId { id: cpu })
+    }
+
+    /// The ID of every node with the numbers of the workers on it.
+    pub(crate) fn groups(&self) -> impl Iterator<Item = (usize, Vec<usize>)> + '_ {
+        self.nodes.iter().enumerate().map(|(index, &id)| {
+            let workers = (self.workers.iter().enumerate())
+                .filter(|&(_, &(_, node))| node == index)
+                .map(|(num, _)| num)
+                .collect();
+            (id, workers)
+        })
+    }
+}
+
+/// Reads the online nodes and their CPUs, sorted by node ID.
+fn read_nodes() -> anyhow::Result<Vec<(usize, Vec<usize>)>> {
+    let Ok(entries) = fs::read_dir(NODES_DIR) else {
+        return Ok(Vec::new());
+    };
+
+    let mut nodes = Vec::new();
+    for entry in entries {
+        let entry = entry?;
+        let name = entry.file_name();
+        let Some(id) = name
+            .to_str()
+            .and_then(|name| name.strip_prefix("node"))
+            .and_then(|id| id.parse().ok())
+        else {
+            continue;
+        };
+        let cpus = parse_cpu_list(&read_trimmed(&entry.path().join("cpulist"))?)?;
+        // Nodes with memory but no CPUs, e.g. of CXL memory, can't run workers
+        if !cpus.is_empty() {
+            nodes.push((id, cpus));
+        }
+    }
+    nodes.sort_unstable();
+    Ok(nodes)
+}
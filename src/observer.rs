@@ -0,0 +1,116 @@
+//! Events about the progress of a search, for frontends and services embedding Fingerprunk.
+//!
+//! Observers are registered with [`Fingerprunk::add_observer`], or an event channel is created
+//! with [`Fingerprunk::subscribe`]. Unlike a [`KeySink`](crate::sink::KeySink), observers never
+//! see key material and can't influence the search.
+
+use std::{
+    fmt,
+    sync::{atomic::Ordering, mpsc},
+    thread,
+    time::{Duration, Instant},
+};
+
+use crate::{Fingerprunk, sink::MatchMeta, status::RateMeter, throughput};
+
+/// Interval of the [`Event::Progress`] events.
+const PROGRESS_INTERVAL: Duration = Duration::from_secs(1);
+
+#[derive(Clone, Debug)]
+#[non_exhaustive]
+pub enum Event {
+    /// The search has been set up and the workers are starting.
+    Started { workers: usize },
+    /// Sent every second while searching.
+    Progress {
+        tried: u64,
+        /// Keys tried per second, smoothed over the last few seconds.
+        rate: f64,
+        found: u64,
+        elapsed: Duration,
+    },
+    /// A key has been found and handed to the key sink.
+    Found(MatchMeta),
+    /// All threads have stopped, whether the search succeeded or failed.
+    Stopped {
+        tried: u64,
+        found: u64,
+        elapsed: Duration,
+    },
+}
+
+/// Receives the events of a search.
+///
+/// Observers are called from several threads and should return quickly, as they hold up the
+/// thread emitting the event.
+pub trait Observer: Send + Sync {
+    fn on_event(&self, event: &Event);
+}
+
+/// Sends the events through a channel. Events are dropped once the receiver is.
+impl Observer for mpsc::Sender<Event> {
+    fn on_event(&self, event: &Event) {
+        let _ = self.send(event.clone());
+    }
+}
+
+/// The registered observers, which may not implement [`Debug`](fmt::Debug).
+#[derive(Default)]
+pub(crate) struct Observers(pub(crate) Vec<Box<dyn Observer>>);
+
+impl fmt::Debug for Observers {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Observers({})", self.0.len())
+    }
+}
+
+impl Fingerprunk {
+    pub(crate) fn emit(&self, event: Event) {
+        for observer in &self.observers.0 {
+            observer.on_event(&event);
+        }
+    }
+
+    pub(crate) fn observed(&self) -> bool {
+        !self.observers.0.is_empty()
+    }
+
+    /// Updates the rate for [`StatsHandle`](crate::StatsHandle)s and emits [`Event::Progress`]
+    /// until the search stops. The rate is checked once after a few seconds, see
+    /// [`Fingerprunk::check_throughput`].
+    pub(crate) fn progress_thread(&self) {
+        let mut rate_meter = RateMeter::new(self.started_instant);
+        let mut throughput_checked = false;
+        while !self.stop.load(Ordering::Relaxed) {
+            // Parked so that it can be unparked when stopping
+            thread::park_timeout(PROGRESS_INTERVAL);
+
+            let tried = self.counter_tried.load(Ordering::Relaxed);
+            let elapsed = self.started_instant.elapsed();
+            rate_meter.update(Instant::now(), tried);
+            let rate = rate_meter
+                .smoothed()
+                .unwrap_or(tried as f64 / elapsed.as_secs_f64());
+            self.clock.set_rate(rate);
+            // Often enough for the energy counters not to wrap around unnoticed
+            self.energy_used();
+            self.update_cpu_monitor();
+            // Pauses would make any rate look low
+            if !throughput_checked
+                && elapsed >= throughput::CHECK_AFTER
+                && !self.paused.load(Ordering::Relaxed)
+            {
+                self.check_throughput(rate);
+                throughput_checked = true;
+            }
+            if self.observed() {
+                self.emit(Event::Progress {
+                    tried,
+                    rate,
+                    found: self.counter_found.load(Ordering::Relaxed),
+                    elapsed,
+                });
+            }
+        }
+    }
+}
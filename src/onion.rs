@@ -0,0 +1,181 @@
+//! Searching for Tor onion services, see [`KeyFormat::Onion`](crate::format::KeyFormat::Onion).
+//!
+//! Version 3 onion addresses are the base32 of an Ed25519 public key, a checksum and the version,
+//! so the workers generate the same Ed25519 keys as for OpenPGP and match the patterns against the
+//! 56 lowercase characters of the address before `.onion`. Found keys are written by
+//! [`OnionSink`] as the key files of a hidden service directory.
+
+use std::{
+    fmt,
+    fs::DirBuilder,
+    io::{self, Write},
+    ops::ControlFlow,
+};
+
+use anyhow::Context;
+use sequoia_openpgp::{
+    Cert, crypto,
+    packet::{
+        Key,
+        key::{KeyParts, KeyRole},
+    },
+    types::HashAlgorithm,
+};
+
+use crate::{
+    keygen::HexBuffer,
+    sink::{KeySink, MatchMeta, write_file},
+    ssh,
+};
+
+/// Length of an onion address without the `.onion` suffix.
+pub const ADDRESS_LEN: usize = 56;
+
+/// The base32 digits of onion addresses.
+pub(crate) const BASE32_DIGITS: &[u8; 32] = b"abcdefghijklmnopqrstuvwxyz234567";
+
+const VERSION: u8 = 3;
+const SECRET_KEY_HEADER: &[u8; 32] = b"== ed25519v1-secret: type0 ==\0\0\0";
+const PUBLIC_KEY_HEADER: &[u8; 32] = b"== ed25519v1-public: type0 ==\0\0\0";
+
+/// Writes the onion address of the Ed25519 public key `public` into `buf` and returns it, without
+/// `.onion`, as the workers do for every key.
+pub fn encode_address<'a>(public: &[u8; 32], buf: &'a mut HexBuffer) -> &'a str {
+    let mut context = HashAlgorithm::SHA3_256
+        .context()
+        .expect("SHA3-256 should be supported")
+        .for_digest();
+    context.update(b".onion checksum");
+    context.update(public);
+    context.update(&[VERSION]);
+    let checksum = context.into_digest().expect("SHA3-256 should be supported");
+
+    let mut bytes = [0u8; 35];
+    bytes[..32].copy_from_slice(public);
+    bytes[32..34].copy_from_slice(&checksum[..2]);
+    bytes[34] = VERSION;
+    let address = &mut buf[..ADDRESS_LEN];
+    for (digits, chunk) in address.chunks_exact_mut(8).zip(bytes.chunks_exact(5)) {
+        let n = chunk.iter().fold(0u64, |n, &byte| n << 8 | u64::from(byte));
+        for (i, digit) in digits.iter_mut().enumerate() {
+            *digit = BASE32_DIGITS[(n >> (35 - 5 * i) & 0x1F) as usize];
+        }
+    }
+    std::str::from_utf8(address).expect("base32 digits should be valid UTF-8")
+}
+
+/// The onion address of an Ed25519 key without `.onion`, which is what patterns are matched
+/// against, or `None` for keys of other algorithms.
+#[must_use]
+pub fn address<P: KeyParts, R: KeyRole>(key: &Key<P, R>) -> Option<String> {
+    let mut buf: HexBuffer = [0; 64];
+    Some(encode_address(&ssh::ed25519_public(key)?, &mut buf).to_string())
+}
+
+/// An Ed25519 key as the key of an onion service.
+pub struct OnionKey {
+    public: [u8; 32],
+    /// The secret scalar followed by the hash prefix, as Tor stores Ed25519 secret keys.
+    expanded: crypto::mem::Protected,
+}
+
+impl OnionKey {
+    /// Takes the key of a certificate built by Fingerprunk. Fails if the key isn't an unencrypted
+    /// Ed25519 key.
+    pub fn from_cert(cert: &Cert) -> anyhow::Result<Self> {
+        let (public, seed) = ssh::ed25519_secret(cert, "Onion")?;
+        let mut context = HashAlgorithm::SHA512.context()?.for_digest();
+        context.update(&seed);
+        let mut expanded = crypto::mem::Protected::from(context.into_digest()?);
+        expanded[0] &= 248;
+        expanded[31] &= 63;
+        expanded[31] |= 64;
+        Ok(Self { public, expanded })
+    }
+
+    /// The address with `.onion`, as in the `hostname` file.
+    #[must_use]
+    pub fn hostname(&self) -> String {
+        let mut buf: HexBuffer = [0; 64];
+        format!("{}.onion", encode_address(&self.public, &mut buf))
+    }
+
+    /// The `hs_ed25519_secret_key` file of a hidden service directory.
+    #[must_use]
+    pub fn secret_key_file(&self) -> crypto::mem::Protected {
+        [&SECRET_KEY_HEADER[..], &self.expanded[..]].concat().into()
+    }
+
+    /// The `hs_ed25519_public_key` file of a hidden service directory.
+    #[must_use]
+    pub fn public_key_file(&self) -> Vec<u8> {
+        [&PUBLIC_KEY_HEADER[..], &self.public].concat()
+    }
+
+    /// The key as `ADD_ONION` of the Tor control protocol takes it, e.g. `ED25519-V3:...`.
+    #[must_use]
+    pub fn control_key(&self) -> String {
+        format!("ED25519-V3:{}", ssh::base64(&self.expanded))
+    }
+}
+
+impl fmt::Debug for OnionKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("OnionKey")
+            .field("hostname", &self.hostname())
+            .finish_non_exhaustive()
+    }
+}
+
+/// Writes the found keys as onion service keys, the default sink for
+/// [`KeyFormat::Onion`](crate::format::KeyFormat::Onion).
+///
+/// Like [`OutputSink`](crate::sink::OutputSink), every key goes into the directory the
+/// configuration routes it to, here into a hidden service directory named after its hostname, with
+/// the `hostname`, `hs_ed25519_public_key` and `hs_ed25519_secret_key` files Tor expects, or to
+/// stdout if there is none, the hostname followed by the key for `ADD_ONION`.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct OnionSink;
+
+impl KeySink for OnionSink {
+    fn found(&mut self, cert: Cert, meta: MatchMeta) -> anyhow::Result<ControlFlow<()>> {
+        let key = OnionKey::from_cert(&cert)?;
+        let hostname = key.hostname();
+        match &meta.dir {
+            Some(dir) => {
+                let service_dir = dir.join(&hostname);
+                let mut builder = DirBuilder::new();
+                // Tor refuses hidden service directories others can access
+                #[cfg(unix)]
+                std::os::unix::fs::DirBuilderExt::mode(&mut builder, 0o700);
+                builder
+                    .create(&service_dir)
+                    .with_context(|| format!("Failed to create {}", service_dir.display()))?;
+                write_file(
+                    &service_dir,
+                    "hs_ed25519_secret_key",
+                    &key.secret_key_file(),
+                    true,
+                )?;
+                write_file(
+                    &service_dir,
+                    "hs_ed25519_public_key",
+                    &key.public_key_file(),
+                    false,
+                )?;
+                write_file(
+                    &service_dir,
+                    "hostname",
+                    format!("{hostname}\n").as_bytes(),
+                    false,
+                )?;
+            }
+            None => {
+                let mut stdout = io::stdout().lock();
+                stdout.write_all(format!("{hostname}\n{}\n", key.control_key()).as_bytes())?;
+                stdout.flush()?;
+            }
+        }
+        Ok(ControlFlow::Continue(()))
+    }
+}
@@ -0,0 +1,178 @@
+//! Exporting metrics and spans of a search via OTLP, see [`Config::otel`](crate::Config::otel).
+//!
+//! [`OtelObserver`] turns the [events](crate::observer::Event) of a search into OpenTelemetry
+//! data, so that fleets of searches show up in the same dashboards as everything else:
+//!
+//! - `fingerprunk.keys.tried`, a counter of the generated keys,
+//! - `fingerprunk.keys.rate`, a gauge of the keys tried per second,
+//! - `fingerprunk.keys.found`, a counter of the found keys by `pattern`, `label` and `job`,
+//! - a `search` span from the start of the search to its end, and
+//! - a `key` span for every found key from its match to its delivery to the key sink, including
+//!   building and encrypting its certificate.
+//!
+//! The exporters are configured with the standard `OTEL_EXPORTER_OTLP_*` environment variables
+//! and send the data via HTTP to `http://localhost:4318` by default.
+
+use std::{
+    sync::{
+        Mutex,
+        atomic::{AtomicU64, Ordering},
+    },
+    time::SystemTime,
+};
+
+use opentelemetry::{
+    Context, KeyValue,
+    metrics::{Counter, Gauge, MeterProvider},
+    trace::{Span, TraceContextExt, Tracer, TracerProvider},
+};
+use opentelemetry_sdk::{
+    Resource,
+    metrics::SdkMeterProvider,
+    trace::{SdkTracer, SdkTracerProvider},
+};
+
+use crate::observer::{Event, Observer};
+
+const SCOPE: &str = "fingerprunk";
+
+/// Exports the events of a search as OpenTelemetry metrics and spans, see [`crate::otel`].
+///
+/// The data is exported in the background and flushed when the search stops.
+#[derive(Debug)]
+pub struct OtelObserver {
+    tracer_provider: SdkTracerProvider,
+    meter_provider: SdkMeterProvider,
+    tracer: SdkTracer,
+    tried: Counter<u64>,
+    rate: Gauge<f64>,
+    found: Counter<u64>,
+    /// The count of tried keys already added to [`Self::tried`].
+    tried_reported: AtomicU64,
+    /// The `search` span and when it started, while searching.
+    search: Mutex<Option<(Context, SystemTime)>>,
+}
+
+impl OtelObserver {
+    /// Sets up the OTLP exporters from the `OTEL_EXPORTER_OTLP_*` environment variables.
+    pub fn new() -> anyhow::Result<Self> {
+        let resource = Resource::builder().with_service_name(SCOPE).build();
+        let tracer_provider = SdkTracerProvider::builder()
+            .with_batch_exporter(
+                opentelemetry_otlp::SpanExporter::builder()
+                    .with_http()
+                    .build()?,
+            )
+            .with_resource(resource.clone())
+            .build();
+        let meter_provider = SdkMeterProvider::builder()
+            .with_periodic_exporter(
+                opentelemetry_otlp::MetricExporter::builder()
+                    .with_http()
+                    .build()?,
+            )
+            .with_resource(resource)
+            .build();
+
+        let meter = meter_provider.meter(SCOPE);
+        Ok(Self {
+            tracer: tracer_provider.tracer(SCOPE),
+            tried: meter
+                .u64_counter("fingerprunk.keys.tried")
+                .with_unit("{key}")
+                .with_description("Keys generated and checked against the patterns")
+                .build(),
+            rate: meter
+                .f64_gauge("fingerprunk.keys.rate")
+                .with_unit("{key}/s")
+                .with_description("Keys tried per second, smoothed over the last few seconds")
+                .build(),
+            found: meter
+                .u64_counter("fingerprunk.keys.found")
+                .with_unit("{key}")
+                .with_description("Keys found, by the pattern they matched")
+                .build(),
+            tried_reported: AtomicU64::new(0),
+            search: Mutex::new(None),
+            tracer_provider,
+            meter_provider,
+        })
+    }
+
+    /// Adds the keys tried since the last report to the counter.
+    fn report_tried(&self, tried: u64) {
+        let reported = self.tried_reported.fetch_max(tried, Ordering::Relaxed);
+        if tried > reported {
+            self.tried.add(tried - reported, &[]);
+        }
+    }
+
+    fn search(&self) -> std::sync::MutexGuard<'_, Option<(Context, SystemTime)>> {
+        self.search
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+impl Observer for OtelObserver {
+    fn on_event(&self, event: &Event) {
+        match event {
+            Event::Started { workers } => {
+                let span = self
+                    .tracer
+                    .span_builder("search")
+                    .with_attributes([KeyValue::new("workers", *workers as i64)])
+                    .start(&self.tracer);
+                *self.search() = Some((Context::new().with_span(span), SystemTime::now()));
+            }
+            Event::Progress { tried, rate, .. } => {
+                self.report_tried(*tried);
+                self.rate.record(*rate, &[]);
+            }
+            Event::Found(meta) => {
+                let mut attributes = vec![KeyValue::new("pattern", meta.pattern.clone())];
+                attributes.extend(
+                    meta.label
+                        .clone()
+                        .map(|label| KeyValue::new("label", label)),
+                );
+                attributes.extend(meta.job.clone().map(|job| KeyValue::new("job", job)));
+                self.found.add(1, &attributes);
+
+                let (parent, started) = self
+                    .search()
+                    .clone()
+                    .unwrap_or_else(|| (Context::new(), SystemTime::now()));
+                attributes.extend([
+                    KeyValue::new("fingerprint", meta.fingerprint.clone()),
+                    KeyValue::new("tried", meta.tried as i64),
+                ]);
+                self.tracer
+                    .span_builder("key")
+                    .with_start_time(started + meta.elapsed)
+                    .with_attributes(attributes)
+                    .start_with_context(&self.tracer, &parent)
+                    .end();
+            }
+            Event::Stopped { tried, found, .. } => {
+                self.report_tried(*tried);
+                if let Some((search, _)) = self.search().take() {
+                    let span = search.span();
+                    span.set_attribute(KeyValue::new("tried", *tried as i64));
+                    span.set_attribute(KeyValue::new("found", *found as i64));
+                    span.end();
+                }
+                // Observers can't report errors, and the search is over anyway
+                let _ = self.tracer_provider.force_flush();
+                let _ = self.meter_provider.force_flush();
+            }
+        }
+    }
+}
+
+impl Drop for OtelObserver {
+    fn drop(&mut self) {
+        let _ = self.tracer_provider.shutdown();
+        let _ = self.meter_provider.shutdown();
+    }
+}
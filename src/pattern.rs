@@ -0,0 +1,473 @@
+//! Checking and normalizing the regexes to search for.
+//!
+//! Fingerprints are matched in uppercase hex, so [`parse_pattern`] uppercases the letters of a
+//! regex and rejects regexes that can never match a fingerprint: those containing characters
+//! other than hex digits, those requiring more characters than a fingerprint has and those
+//! anchored at both ends whose length doesn't fit a fingerprint, like `^CAFE$`.
+//!
+//! Fingerprints of v4 keys have 40 hex digits and those of v6 keys 64, so [`parse_pattern`]
+//! accepts lengths fitting either, and [`parse_pattern_for`] only those fitting the version of
+//! the keys searched for.
+//!
+//! Escapes, group syntax and quantifiers are left untouched. Regexes with alternations, groups or
+//! the `x` flag are only normalized, as their length isn't checked.
+
+use fancy_regex::Regex;
+
+use crate::{error::ConfigError, keygen::KeyVersion};
+
+/// Normalizes and checks `pattern` as described in the [module documentation](self) and compiles
+/// it, accepting lengths that fit the fingerprints of any key version.
+pub fn parse_pattern(pattern: &str) -> Result<Regex, ConfigError> {
+    parse(pattern, None)
+}
+
+/// Like [`parse_pattern`], but rejects lengths that don't fit the fingerprints of `version`.
+pub fn parse_pattern_for(pattern: &str, version: KeyVersion) -> Result<Regex, ConfigError> {
+    parse(pattern, Some(version))
+}
+
+/// Rejects an already compiled regex like [`parse_pattern_for`] if its length doesn't fit the
+/// fingerprints of `version`.
+pub fn check_pattern_length(regex: &Regex, version: KeyVersion) -> Result<(), ConfigError> {
+    check_length(
+        regex.as_str(),
+        &Scanner::new(regex.as_str()).scan()?,
+        Some(version),
+    )
+}
+
+/// A warning if `regex` likely assumes the fingerprints of another version than `version`.
+///
+/// Regexes anchored at the end only usually target the key ID, which is at the end of v4
+/// fingerprints but at the start of v6 ones.
+#[must_use]
+pub fn version_warning(regex: &Regex, version: KeyVersion) -> Option<String> {
+    let scanned = Scanner::new(regex.as_str()).scan().ok()?;
+    (version == KeyVersion::V6 && scanned.ends_anchored && !scanned.anchored).then(|| {
+        format!(
+            "Regex {regex} is anchored at the end, where v4 fingerprints have the key ID, but \
+             the key ID of v6 keys is at the start of their fingerprint"
+        )
+    })
+}
+
+fn parse(pattern: &str, version: Option<KeyVersion>) -> Result<Regex, ConfigError> {
+    let scanned = Scanner::new(pattern).scan()?;
+    check_length(pattern, &scanned, version)?;
+    Regex::new(&scanned.normalized).map_err(|source| ConfigError::Regex {
+        pattern: pattern.to_string(),
+        source: Box::new(source),
+    })
+}
+
+/// Rejects `pattern` if it matches no fingerprint length of `version`, or of any version if
+/// `None`.
+fn check_length(
+    pattern: &str,
+    scanned: &Scanned,
+    version: Option<KeyVersion>,
+) -> Result<(), ConfigError> {
+    let Some(Length { min, max }) = scanned.length else {
+        return Ok(());
+    };
+    let fits = |len: usize| min <= len && (!scanned.anchored || max.is_none_or(|max| max >= len));
+    let fitting = match version {
+        Some(version) => fits(version.fingerprint_hex_len()),
+        None => KeyVersion::ALL
+            .iter()
+            .any(|version| fits(version.fingerprint_hex_len())),
+    };
+    if fitting {
+        return Ok(());
+    }
+    Err(ConfigError::Length {
+        pattern: pattern.to_string(),
+        min,
+        max,
+        anchored: scanned.anchored,
+        version,
+    })
+}
+
+/// The hex digits every fingerprint matched by a `^`-anchored regex starts with, e.g. `C0FF` for
+/// `^C0FFE?`, so that the regex only needs to be run on fingerprints starting with them.
+///
+/// Returns `None` for regexes that aren't anchored, start with something other than a hex digit or
+/// have an alternation outside of groups, as in `^CAFE|BEEF`.
+pub(crate) fn anchored_prefix(pattern: &str) -> Option<&str> {
+    let rest = pattern.strip_prefix('^')?;
+
+    let (mut depth, mut escaped, mut in_class) = (0usize, false, false);
+    for c in rest.chars() {
+        match c {
+            _ if escaped => escaped = false,
+            '\\' => escaped = true,
+            '[' => in_class = true,
+            ']' => in_class = false,
+            _ if in_class => {}
+            '(' => depth += 1,
+            ')' => depth = depth.saturating_sub(1),
+            '|' if depth == 0 => return None,
+            _ => {}
+        }
+    }
+
+    let digits = rest
+        .find(|c: char| !matches!(c, '0'..='9' | 'A'..='F'))
+        .unwrap_or(rest.len());
+    // A quantifier applies to the last digit only
+    let quantified = rest[digits..].starts_with(['?', '*', '+', '{']);
+    let len = if quantified {
+        digits.saturating_sub(1)
+    } else {
+        digits
+    };
+    (len > 0).then(|| &rest[..len])
+}
+
+/// Bounds on the number of characters a regex matches.
+#[derive(Clone, Copy, Debug)]
+struct Length {
+    min: usize,
+    /// `None` if unbounded.
+    max: Option<usize>,
+}
+
+impl Length {
+    const ONE: Self = Self {
+        min: 1,
+        max: Some(1),
+    };
+    const ZERO: Self = Self {
+        min: 0,
+        max: Some(0),
+    };
+}
+
+struct Scanned {
+    normalized: String,
+    /// `None` if the regex is too complex to tell.
+    length: Option<Length>,
+    /// Whether the regex is anchored at both ends.
+    anchored: bool,
+    /// Whether the regex is anchored at the end.
+    ends_anchored: bool,
+}
+
+struct Scanner<'a> {
+    pattern: &'a str,
+    chars: std::iter::Peekable<std::str::CharIndices<'a>>,
+    normalized: String,
+    /// `None` once the regex turned out to be too complex.
+    length: Option<Length>,
+    /// Length of the last atom, to which a following quantifier applies.
+    last: Option<Length>,
+}
+
+impl<'a> Scanner<'a> {
+    fn new(pattern: &'a str) -> Self {
+        Self {
+            pattern,
+            chars: pattern.char_indices().peekable(),
+            normalized: String::with_capacity(pattern.len()),
+            length: Some(Length::ZERO),
+            last: None,
+        }
+    }
+
+    fn scan(mut self) -> Result<Scanned, ConfigError> {
+        let starts_anchored = self.pattern.starts_with('^');
+        let mut ends_anchored = false;
+
+        while let Some((index, c)) = self.chars.next() {
+            ends_anchored = false;
+            match c {
+                '\\' => {
+                    self.normalized.push(c);
+                    let width = self.escape();
+                    self.atom(width);
+                }
+                '[' => {
+                    self.normalized.push(c);
+                    self.class();
+                    self.atom(Length::ONE);
+                }
+                '(' => {
+                    self.normalized.push(c);
+                    if self.chars.next_if(|&(_, c)| c == '?').is_some() {
+                        self.normalized.push('?');
+                        if self.group_prefix() {
+                            // Comments and whitespace can't be told apart from literals
+                            return Ok(Scanned {
+                                normalized: self.pattern.to_string(),
+                                length: None,
+                                anchored: false,
+                                ends_anchored: false,
+                            });
+                        }
+                    }
+                    self.complex();
+                }
+                ')' | '|' => {
+                    self.normalized.push(c);
+                    self.complex();
+                }
+                '^' => {
+                    self.normalized.push(c);
+                    self.atom(Length::ZERO);
+                }
+                '$' => {
+                    self.normalized.push(c);
+                    self.atom(Length::ZERO);
+                    ends_anchored = true;
+                }
+                '.' => {
+                    self.normalized.push(c);
+                    self.atom(Length::ONE);
+                }
+                '*' | '+' | '?' => {
+                    self.normalized.push(c);
+                    let (min, max) = match c {
+                        '*' => (0, None),
+                        '+' => (1, None),
+                        _ => (0, Some(1)),
+                    };
+                    self.quantify(min, max);
+                    // Lazy or possessive
+                    if let Some((_, c)) = self.chars.next_if(|&(_, c)| c == '?' || c == '+') {
+                        self.normalized.push(c);
+                    }
+                }
+                '{' => {
+                    let Some((text, min, max)) = self.counted_repetition(index) else {
+                        return Err(self.not_hex(c));
+                    };
+                    self.normalized.push_str(text);
+                    for _ in 1..text.len() {
+                        self.chars.next();
+                    }
+                    self.quantify(min, max);
+                    if let Some((_, c)) = self.chars.next_if(|&(_, c)| c == '?' || c == '+') {
+                        self.normalized.push(c);
+                    }
+                }
+                c => {
+                    let upper = c.to_ascii_uppercase();
+                    if !upper.is_ascii_hexdigit() {
+                        return Err(self.not_hex(c));
+                    }
+                    self.normalized.push(upper);
+                    self.atom(Length::ONE);
+                }
+            }
+        }
+
+        Ok(Scanned {
+            normalized: self.normalized,
+            length: self.length,
+            anchored: starts_anchored && ends_anchored,
+            ends_anchored,
+        })
+    }
+
+    /// Copies an escape sequence after the backslash and returns the number of characters it
+    /// matches.
+    fn escape(&mut self) -> Length {
+        let Some((_, c)) = self.chars.next() else {
+            return Length::ZERO;
+        };
+        self.normalized.push(c);
+        match c {
+            // Unicode classes, hex escapes and named backreferences with their arguments
+            'p' | 'P' | 'x' | 'k' | 'u' | 'U' => {
+                let close = match self.chars.peek() {
+                    Some((_, '{')) => '}',
+                    Some((_, '<')) => '>',
+                    _ => {
+                        // Short forms like \pL and \x41
+                        let count = match c {
+                            'p' | 'P' => 1,
+                            'x' => 2,
+                            _ => 0,
+                        };
+                        for _ in 0..count {
+                            if let Some((_, c)) = self.chars.next() {
+                                self.normalized.push(c);
+                            }
+                        }
+                        return Length::ONE;
+                    }
+                };
+                for (_, c) in self.chars.by_ref() {
+                    self.normalized.push(c);
+                    if c == close {
+                        break;
+                    }
+                }
+                if c == 'k' {
+                    self.complex();
+                }
+                Length::ONE
+            }
+            'b' | 'B' | 'A' | 'z' | 'Z' | 'G' => Length::ZERO,
+            '1'..='9' => {
+                self.complex();
+                Length::ONE
+            }
+            _ => Length::ONE,
+        }
+    }
+
+    /// Copies a character class after the opening bracket, uppercasing its letters.
+    fn class(&mut self) {
+        // A closing bracket right at the start is a literal
+        if let Some((_, c)) = self.chars.next_if(|&(_, c)| c == '^') {
+            self.normalized.push(c);
+        }
+        if let Some((_, c)) = self.chars.next_if(|&(_, c)| c == ']') {
+            self.normalized.push(c);
+        }
+        while let Some((_, c)) = self.chars.next() {
+            match c {
+                '\\' => {
+                    self.normalized.push(c);
+                    self.escape();
+                }
+                '[' => {
+                    self.normalized.push(c);
+                    if self.chars.peek().is_some_and(|&(_, c)| c == ':') {
+                        // POSIX classes like [:alpha:] are case-sensitive
+                        for (_, c) in self.chars.by_ref() {
+                            self.normalized.push(c);
+                            if c == ']' {
+                                break;
+                            }
+                        }
+                    } else {
+                        self.class();
+                    }
+                }
+                ']' => {
+                    self.normalized.push(c);
+                    return;
+                }
+                c => self.normalized.push(c.to_ascii_uppercase()),
+            }
+        }
+    }
+
+    /// Copies the syntax of a group after `(?`. Returns whether the group sets the `x` flag.
+    fn group_prefix(&mut self) -> bool {
+        match self.chars.peek().map(|&(_, c)| c) {
+            // Named groups
+            Some('P' | '<') => {
+                let is_lookbehind = {
+                    let mut ahead = self.chars.clone();
+                    ahead.next();
+                    matches!(ahead.peek(), Some((_, '=' | '!')))
+                };
+                for (_, c) in self.chars.by_ref() {
+                    self.normalized.push(c);
+                    if c == '>' || (is_lookbehind && (c == '=' || c == '!')) {
+                        break;
+                    }
+                }
+                false
+            }
+            // Flags, as in (?i) or (?i-x:...)
+            Some(c) if c.is_ascii_alphabetic() || c == '-' => {
+                let mut extended = false;
+                while let Some((_, c)) = self
+                    .chars
+                    .next_if(|&(_, c)| c.is_ascii_alphabetic() || c == '-')
+                {
+                    extended |= c == 'x';
+                    self.normalized.push(c);
+                }
+                extended
+            }
+            _ => false,
+        }
+    }
+
+    /// Parses a counted repetition like `{2}`, `{2,}` or `{2,4}` starting at `index`.
+    fn counted_repetition(&self, index: usize) -> Option<(&'a str, usize, Option<usize>)> {
+        let rest = &self.pattern[index..];
+        let end = rest.find('}')?;
+        let text = &rest[..=end];
+        let inner = &text[1..end];
+        let (min, max) = match inner.split_once(',') {
+            Some((min, "")) => (min.trim().parse().ok()?, None),
+            Some((min, max)) => (min.trim().parse().ok()?, Some(max.trim().parse().ok()?)),
+            None => {
+                let count = inner.trim().parse().ok()?;
+                (count, Some(count))
+            }
+        };
+        Some((text, min, max))
+    }
+
+    fn atom(&mut self, width: Length) {
+        if let Some(length) = &mut self.length {
+            length.min += width.min;
+            length.max = length.max.zip(width.max).map(|(a, b)| a + b);
+        }
+        self.last = Some(width);
+    }
+
+    /// Applies a quantifier to the last atom.
+    fn quantify(&mut self, min: usize, max: Option<usize>) {
+        let (Some(length), Some(last)) = (&mut self.length, self.last.take()) else {
+            return;
+        };
+        length.min = length.min - last.min + last.min.saturating_mul(min);
+        length.max = match (length.max, last.max, max) {
+            (Some(total), Some(last_max), Some(max)) => {
+                Some(total - last_max + last_max.saturating_mul(max))
+            }
+            (Some(total), Some(0), None) => Some(total),
+            _ => None,
+        };
+    }
+
+    fn not_hex(&self, character: char) -> ConfigError {
+        ConfigError::NotHex {
+            pattern: self.pattern.to_string(),
+            character,
+        }
+    }
+
+    /// Gives up on the length, e.g. because of alternations or groups.
+    fn complex(&mut self) {
+        self.length = None;
+        self.last = None;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn anchored_prefix_stops_before_quantified_digit() {
+        assert_eq!(anchored_prefix("^C0FFE?"), Some("C0FF"));
+        assert_eq!(anchored_prefix("^C0FFEE"), Some("C0FFEE"));
+        assert_eq!(anchored_prefix("^C0FFE{2}.*"), Some("C0FF"));
+        assert_eq!(anchored_prefix("^C[0O]FFEE"), Some("C"));
+    }
+
+    #[test]
+    fn anchored_prefix_rejects_top_level_alternation() {
+        assert_eq!(anchored_prefix("^CAFE|BEEF"), None);
+        assert_eq!(anchored_prefix("^CA(FE|BE)"), Some("CA"));
+        assert_eq!(anchored_prefix("^CA[|]FE"), Some("CA"));
+    }
+
+    #[test]
+    fn anchored_prefix_needs_anchor_and_digits() {
+        assert_eq!(anchored_prefix("CAFE"), None);
+        assert_eq!(anchored_prefix("^(CAFE)"), None);
+        assert_eq!(anchored_prefix("^C?AFE"), None);
+    }
+}
@@ -0,0 +1,181 @@
+//! Matchers loaded from WebAssembly modules, see [`PluginMatcher`].
+//!
+//! A plugin decides which keys are found without its criteria ever showing up in the process
+//! arguments, the logs or the comment of the key files, which only name the plugin's file. The
+//! module runs in an interpreter without access to anything but its own memory, which is limited
+//! to [`MAX_MEMORY`] bytes, and every call is limited to [`FUEL_PER_CALL`] instructions, so a
+//! plugin can't exhaust the machine or hang the search.
+//!
+//! A plugin module exports:
+//!
+//! - `memory`, its linear memory,
+//! - `input() -> i32`, the address of a buffer of at least 64 bytes the fingerprint is written
+//!   to,
+//! - `score(len: i32) -> i32`, which scores the `len` bytes in the buffer; keys scoring above 0
+//!   are found.
+//!
+//! The buffer holds the bytes of the fingerprint for OpenPGP keys, and the text the patterns are
+//! matched against for other [formats](crate::format::KeyFormat), like the onion address. Modules
+//! may be given in the binary or in the text format.
+
+use std::{
+    fmt, fs,
+    path::Path,
+    sync::{Arc, Mutex},
+};
+
+use anyhow::{Context, anyhow};
+use wasmi::{Engine, Linker, Memory, Module, Store, StoreLimits, StoreLimitsBuilder, TypedFunc};
+
+use crate::{Error, matcher::Matcher};
+
+/// Number of instructions a plugin may execute per call before the search fails.
+pub const FUEL_PER_CALL: u64 = 10_000_000;
+
+/// Number of bytes the linear memory of a plugin may grow to, for every worker thread.
+pub const MAX_MEMORY: usize = 16 * 1024 * 1024;
+
+/// Size of the input buffer a plugin has to provide.
+const INPUT_LEN: usize = 64;
+
+/// Matches the keys a WebAssembly module scores above 0, see [`crate::plugin`].
+pub struct PluginMatcher {
+    /// The file name of the module, which is all that the status display, the logs and the key
+    /// files reveal about the plugin.
+    name: String,
+    module: Module,
+    instance: Mutex<PluginInstance>,
+}
+
+impl PluginMatcher {
+    /// Loads and instantiates the module in the file at `path`.
+    pub fn load(path: &Path) -> anyhow::Result<Self> {
+        let wasm =
+            fs::read(path).with_context(|| format!("Failed to read plugin {}", path.display()))?;
+        let name = path.file_name().map_or_else(
+            || path.display().to_string(),
+            |name| name.display().to_string(),
+        );
+        Self::new(name, &wasm).with_context(|| format!("Failed to load plugin {}", path.display()))
+    }
+
+    /// Instantiates the module `wasm`, named `name` in messages.
+    pub fn new(name: impl Into<String>, wasm: &[u8]) -> anyhow::Result<Self> {
+        let mut config = wasmi::Config::default();
+        config.consume_fuel(true);
+        let module = Module::new(&Engine::new(&config), wasm)?;
+        let instance = PluginInstance::new(&module)?;
+        Ok(Self {
+            name: name.into(),
+            module,
+            instance: Mutex::new(instance),
+        })
+    }
+
+    /// The score of a fingerprint given in uppercase hex, or of another text for other formats.
+    pub fn score(&self, text: &str) -> anyhow::Result<i32> {
+        let mut bytes = [0u8; INPUT_LEN];
+        let input = match decode_hex(text, &mut bytes) {
+            Some(bytes) => bytes,
+            None => text.as_bytes(),
+        };
+        self.instance
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .score(input)
+            .with_context(|| format!("Plugin {} failed", self.name))
+    }
+}
+
+impl Matcher for PluginMatcher {
+    fn is_match(&self, fingerprint_hex: &str) -> Result<bool, Error> {
+        Ok(self.score(fingerprint_hex)? > 0)
+    }
+
+    fn clone_for_worker(&self) -> Option<Arc<dyn Matcher>> {
+        // The module was instantiated once already, so this only fails if memory runs out
+        let instance = PluginInstance::new(&self.module).ok()?;
+        Some(Arc::new(Self {
+            name: self.name.clone(),
+            module: self.module.clone(),
+            instance: Mutex::new(instance),
+        }))
+    }
+}
+
+impl fmt::Debug for PluginMatcher {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("PluginMatcher")
+            .field("name", &self.name)
+            .finish_non_exhaustive()
+    }
+}
+
+impl fmt::Display for PluginMatcher {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "plugin {}", self.name)
+    }
+}
+
+/// An instance of a plugin module with its own memory.
+struct PluginInstance {
+    store: Store<StoreLimits>,
+    memory: Memory,
+    input: usize,
+    score: TypedFunc<i32, i32>,
+}
+
+impl PluginInstance {
+    fn new(module: &Module) -> anyhow::Result<Self> {
+        let limits = StoreLimitsBuilder::new().memory_size(MAX_MEMORY).build();
+        let mut store = Store::new(module.engine(), limits);
+        store.limiter(|limits| limits);
+        store.set_fuel(FUEL_PER_CALL)?;
+        // Nothing is linked, so modules importing anything fail here
+        let instance = Linker::new(module.engine()).instantiate_and_start(&mut store, module)?;
+        let memory = instance
+            .get_memory(&store, "memory")
+            .context("The module exports no memory")?;
+        let input_fn = instance.get_typed_func::<(), i32>(&store, "input")?;
+        let score = instance.get_typed_func::<i32, i32>(&store, "score")?;
+
+        store.set_fuel(FUEL_PER_CALL)?;
+        let input = u32::try_from(input_fn.call(&mut store, ())?)
+            .map_err(|_| anyhow!("The input buffer has a negative address"))?;
+        let input = usize::try_from(input)?;
+        if memory.data_size(&store) < input + INPUT_LEN {
+            return Err(anyhow!("The input buffer exceeds the module's memory"));
+        }
+        Ok(Self {
+            store,
+            memory,
+            input,
+            score,
+        })
+    }
+
+    fn score(&mut self, input: &[u8]) -> anyhow::Result<i32> {
+        let len = input.len().min(INPUT_LEN);
+        self.memory
+            .write(&mut self.store, self.input, &input[..len])
+            .map_err(|err| anyhow!("{err}"))?;
+        self.store.set_fuel(FUEL_PER_CALL)?;
+        Ok(self.score.call(&mut self.store, len as i32)?)
+    }
+}
+
+/// Decodes uppercase hex digits into `buf` and returns the bytes, or `None` if `text` isn't hex
+/// or too long.
+fn decode_hex<'a>(text: &str, buf: &'a mut [u8; INPUT_LEN]) -> Option<&'a [u8]> {
+    if !text.len().is_multiple_of(2)
+        || text.len() / 2 > INPUT_LEN
+        || !text.bytes().all(|b| matches!(b, b'0'..=b'9' | b'A'..=b'F'))
+    {
+        return None;
+    }
+    let bytes = &mut buf[..text.len() / 2];
+    for (byte, pair) in bytes.iter_mut().zip(text.as_bytes().chunks_exact(2)) {
+        *byte = u8::from_str_radix(std::str::from_utf8(pair).ok()?, 16).ok()?;
+    }
+    Some(bytes)
+}
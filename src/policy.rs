@@ -0,0 +1,143 @@
+//! Checking the keys Fingerprunk issues against common key-issuance rules.
+//!
+//! Found keys are bare primary keys that don't expire, with the secret protected by the
+//! password if any. That is fine for a vanity key that is completed later, e.g. with subkeys and
+//! an expiration date in GnuPG, but organizations often require more of the keys they issue. The
+//! [`Policy`] set in [`Config::policy`](crate::Config::policy) decides whether the shortcomings
+//! that the configuration can avoid are merely warned about or refused.
+
+use std::{fmt, str::FromStr};
+
+use anyhow::bail;
+use serde_json::json;
+
+use crate::{Config, Fingerprunk, Verbosity, keygen::KeyVersion};
+
+/// Length in characters below which a password is too short to hold up against guessing, as
+/// the S2K only slows every guess down.
+const MIN_PASSWORD_LEN: usize = 12;
+
+/// How strictly to treat policy warnings, see [`Config::policy`](crate::Config::policy).
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Policy {
+    /// Log all warnings and search anyway.
+    Warn,
+    /// Refuse the configuration if it leads to warnings that it could avoid, see
+    /// [`PolicyWarning::denied_by_strict`].
+    Strict,
+}
+
+impl FromStr for Policy {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> anyhow::Result<Self> {
+        match s {
+            "warn" => Ok(Self::Warn),
+            "strict" => Ok(Self::Strict),
+            _ => bail!("Unknown policy {s:?}, expected warn or strict"),
+        }
+    }
+}
+
+/// A shortcoming of the keys that a configuration issues.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum PolicyWarning {
+    /// The keys are v4 keys, whose fingerprints are SHA-1 hashes, see
+    /// [`Config::key_version`](crate::Config::key_version).
+    Sha1Fingerprint,
+    /// The keys don't expire.
+    NoExpiration,
+    /// The keys have no subkeys, so they can't be used for signing or encryption yet.
+    CertifyOnly,
+    /// The secret keys aren't encrypted, as no password is given.
+    Unencrypted,
+    /// The password is so short that the S2K protecting the secret keys is weak.
+    WeakS2k,
+}
+
+impl PolicyWarning {
+    /// A short, stable name of the warning, e.g. for scripts reading the JSON logs.
+    #[must_use]
+    pub fn code(self) -> &'static str {
+        match self {
+            Self::Sha1Fingerprint => "sha1-fingerprint",
+            Self::NoExpiration => "no-expiration",
+            Self::CertifyOnly => "certify-only",
+            Self::Unencrypted => "unencrypted",
+            Self::WeakS2k => "weak-s2k",
+        }
+    }
+
+    /// Whether [`Policy::Strict`] refuses configurations with this warning. Only the warnings
+    /// that the configuration can avoid are.
+    #[must_use]
+    pub fn denied_by_strict(self) -> bool {
+        matches!(self, Self::Unencrypted | Self::WeakS2k)
+    }
+}
+
+impl fmt::Display for PolicyWarning {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Sha1Fingerprint => "keys are v4 keys, whose fingerprints are SHA-1 hashes",
+            Self::NoExpiration => "keys don't expire",
+            Self::CertifyOnly => {
+                "keys are certify-only primary keys, add subkeys for signing and encryption"
+            }
+            Self::Unencrypted => "secret keys are written unencrypted, as no password is given",
+            Self::WeakS2k => "the password is too short to protect the secret keys well",
+        })
+    }
+}
+
+/// The warnings about the keys issued with `config`, assuming the default key generator.
+#[must_use]
+pub fn evaluate(config: &Config) -> Vec<PolicyWarning> {
+    let mut warnings = Vec::new();
+    if config.key_version == KeyVersion::V4 {
+        warnings.push(PolicyWarning::Sha1Fingerprint);
+    }
+    warnings.push(PolicyWarning::NoExpiration);
+    if config.subkey_regex.is_none() {
+        warnings.push(PolicyWarning::CertifyOnly);
+    }
+    match &config.password {
+        None => warnings.push(PolicyWarning::Unencrypted),
+        Some(password)
+            if password.map(|bytes| String::from_utf8_lossy(bytes).chars().count())
+                < MIN_PASSWORD_LEN =>
+        {
+            warnings.push(PolicyWarning::WeakS2k);
+        }
+        Some(_) => {}
+    }
+    warnings
+}
+
+impl Fingerprunk {
+    /// Logs the policy warnings about the found keys, as JSON events with `json_logs`.
+    pub(crate) fn log_policy_warnings(&self) {
+        if self.config.policy.is_none() || self.config.verbosity < Verbosity::Normal {
+            return;
+        }
+
+        for warning in evaluate(&self.config) {
+            // Other key generators may create other versions of keys
+            if warning == PolicyWarning::Sha1Fingerprint && !self.key_generator.1 {
+                continue;
+            }
+            if self.config.json_logs {
+                self.print_json(
+                    "policy",
+                    json!({ "code": warning.code(), "message": warning.to_string() }),
+                );
+            } else {
+                self.log(
+                    Verbosity::Normal,
+                    format_args!("Policy warning ({}): {warning}", warning.code()),
+                );
+            }
+        }
+    }
+}
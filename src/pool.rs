@@ -0,0 +1,32 @@
+//! Running the workers as tasks of a rayon thread pool instead of threads of their own, so that an
+//! embedding application using rayon doesn't end up with twice as many busy threads as CPUs.
+
+use std::sync::Arc;
+
+use rayon::{Scope, ThreadPool};
+
+/// The pool the workers run on, see
+/// [`Fingerprunk::use_rayon_pool`](crate::Fingerprunk::use_rayon_pool).
+#[derive(Debug)]
+pub(crate) enum RayonPool {
+    Global,
+    Custom(Arc<ThreadPool>),
+}
+
+impl RayonPool {
+    /// The number of threads of the pool, which is the default number of workers.
+    pub(crate) fn threads(&self) -> usize {
+        match self {
+            Self::Global => rayon::current_num_threads(),
+            Self::Custom(pool) => pool.current_num_threads(),
+        }
+    }
+
+    /// Runs `f` with a scope of the pool, returning once all tasks spawned in it are done.
+    pub(crate) fn in_place_scope<'scope>(&self, f: impl FnOnce(&Scope<'scope>)) {
+        match self {
+            Self::Global => rayon::in_place_scope(f),
+            Self::Custom(pool) => pool.in_place_scope(f),
+        }
+    }
+}
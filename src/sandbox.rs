@@ -0,0 +1,230 @@
+//! Confining the process once the search is set up, so that a compromised dependency can't do
+//! much more than the search itself: Landlock and seccomp on Linux, unveil and pledge on OpenBSD.
+//!
+//! Files opened before, like stdout, the log file and the sockets, stay usable. Afterwards, only
+//! the output directories, the directory of the state file and the patterns file can be opened,
+//! besides what the status display and the network notifications need. Running other programs is
+//! forbidden, and so is the network unless the webhook, the keyserver lookup, the OpenTelemetry
+//! export or a coordinator needs it.
+//!
+//! Offline searches block the network alone on Linux, see
+//! [`Config::offline`](crate::Config::offline).
+
+use std::path::{Path, PathBuf};
+
+use crate::{Fingerprunk, Verbosity};
+
+/// Directories readable by every search, for the CPU topology, statistics and the terminal.
+const SYSTEM_READABLE: [&str; 2] = ["/proc", "/sys"];
+
+/// Devices writable by every search.
+const SYSTEM_WRITABLE: [&str; 2] = ["/dev/null", "/dev/tty"];
+
+/// Directories readable by searches using the network, for resolving host names and checking
+/// certificates.
+const NETWORK_READABLE: [&str; 4] = ["/etc", "/lib", "/lib64", "/usr/lib"];
+
+/// What the search still needs once it is set up.
+#[derive(Debug, Default)]
+struct Profile {
+    readable: Vec<PathBuf>,
+    writable: Vec<PathBuf>,
+    network: bool,
+}
+
+impl Fingerprunk {
+    /// Confines the process to what the search needs from now on.
+    ///
+    /// This can't be undone and affects all threads of the process, including those of an
+    /// embedding application.
+    pub(crate) fn enter_sandbox(&self) -> anyhow::Result<()> {
+        let profile = self.sandbox_profile();
+        let outcome = confine(&profile)?;
+        self.log(Verbosity::Normal, format_args!("Sandboxed: {outcome}"));
+        Ok(())
+    }
+
+    fn sandbox_profile(&self) -> Profile {
+        let mut profile = Profile {
+            readable: SYSTEM_READABLE.iter().map(PathBuf::from).collect(),
+            writable: SYSTEM_WRITABLE.iter().map(PathBuf::from).collect(),
+            network: false,
+        };
+        profile.readable.extend(self.config.patterns_file.clone());
+        profile.writable.extend(self.config.output_dir.clone());
+        profile
+            .writable
+            .extend(self.config.jobs.iter().filter_map(|job| self.job_dir(job)));
+        profile
+            .writable
+            .extend(self.config.label_outputs.values().cloned());
+        if let Some(path) = &self.config.state_file {
+            // Written to a temporary file next to it first
+            let dir = path.parent().filter(|dir| !dir.as_os_str().is_empty());
+            profile
+                .writable
+                .push(dir.unwrap_or(Path::new(".")).to_path_buf());
+        }
+
+        #[cfg(feature = "webhook")]
+        if self.config.webhook.is_some() {
+            profile.network = true;
+        }
+        #[cfg(feature = "keyserver")]
+        if self.config.keyserver.is_some() {
+            profile.network = true;
+        }
+        #[cfg(feature = "cluster")]
+        if self.config.coordinator.is_some() {
+            profile.network = true;
+        }
+        #[cfg(feature = "otel")]
+        if self.config.otel {
+            profile.network = true;
+        }
+        if profile.network {
+            profile
+                .readable
+                .extend(NETWORK_READABLE.iter().map(PathBuf::from));
+        }
+        profile
+    }
+}
+
+/// Applies `profile` with Landlock, which older kernels lack, and seccomp. Returns what was
+/// applied.
+#[cfg(target_os = "linux")]
+fn confine(profile: &Profile) -> anyhow::Result<String> {
+    use landlock::{
+        ABI, Access, AccessFs, Ruleset, RulesetAttr, RulesetCreatedAttr, RulesetStatus,
+        path_beneath_rules,
+    };
+
+    let abi = ABI::V5;
+    let status = Ruleset::default()
+        .handle_access(AccessFs::from_all(abi))?
+        .create()?
+        .add_rules(path_beneath_rules(
+            &profile.readable,
+            AccessFs::from_read(abi),
+        ))?
+        .add_rules(path_beneath_rules(
+            &profile.writable,
+            AccessFs::from_all(abi),
+        ))?
+        .restrict_self()?;
+    let landlock = match status.ruleset {
+        RulesetStatus::FullyEnforced => "Landlock",
+        RulesetStatus::PartiallyEnforced => "Landlock (partially, as this kernel is older)",
+        RulesetStatus::NotEnforced => "no Landlock, as this kernel lacks it",
+    };
+
+    seccomp::apply(&seccomp::DENIED, profile.network)?;
+    Ok(format!("{landlock} and seccomp"))
+}
+
+/// Makes creating internet sockets fail in all threads.
+#[cfg(target_os = "linux")]
+pub(crate) fn block_network() -> anyhow::Result<()> {
+    seccomp::apply(&[], false)
+}
+
+#[cfg(not(target_os = "linux"))]
+pub(crate) fn block_network() -> anyhow::Result<()> {
+    anyhow::bail!("Blocking the network is only supported on Linux")
+}
+
+#[cfg(target_os = "linux")]
+mod seccomp {
+    use std::collections::BTreeMap;
+
+    use seccompiler::{
+        BpfProgram, SeccompAction, SeccompCmpArgLen, SeccompCmpOp, SeccompCondition, SeccompFilter,
+        SeccompRule, TargetArch,
+    };
+
+    /// Syscalls no search needs: running programs, inspecting other processes, and changing the
+    /// kernel, mounts, namespaces or keyrings.
+    pub(super) const DENIED: [i64; 21] = [
+        libc::SYS_execve,
+        libc::SYS_execveat,
+        libc::SYS_ptrace,
+        libc::SYS_process_vm_readv,
+        libc::SYS_process_vm_writev,
+        libc::SYS_mount,
+        libc::SYS_umount2,
+        libc::SYS_pivot_root,
+        libc::SYS_chroot,
+        libc::SYS_unshare,
+        libc::SYS_setns,
+        libc::SYS_init_module,
+        libc::SYS_finit_module,
+        libc::SYS_delete_module,
+        libc::SYS_kexec_load,
+        libc::SYS_bpf,
+        libc::SYS_perf_event_open,
+        libc::SYS_userfaultfd,
+        libc::SYS_add_key,
+        libc::SYS_request_key,
+        libc::SYS_keyctl,
+    ];
+
+    /// Makes the `denied` syscalls, and creating internet sockets unless `network`, fail with
+    /// `EPERM` in all threads.
+    pub(super) fn apply(denied: &[i64], network: bool) -> anyhow::Result<()> {
+        let mut rules: BTreeMap<i64, Vec<SeccompRule>> = denied
+            .iter()
+            .map(|&syscall| (syscall, Vec::new()))
+            .collect();
+        if !network {
+            let domains = [libc::AF_INET, libc::AF_INET6]
+                .into_iter()
+                .map(|domain| {
+                    SeccompRule::new(vec![SeccompCondition::new(
+                        0,
+                        SeccompCmpArgLen::Dword,
+                        SeccompCmpOp::Eq,
+                        domain as u64,
+                    )?])
+                })
+                .collect::<Result<_, _>>()?;
+            rules.insert(libc::SYS_socket, domains);
+        }
+
+        let filter = SeccompFilter::new(
+            rules,
+            SeccompAction::Allow,
+            SeccompAction::Errno(libc::EPERM as u32),
+            TargetArch::try_from(std::env::consts::ARCH)?,
+        )?;
+        let program: BpfProgram = filter.try_into()?;
+        seccompiler::apply_filter_all_threads(&program)?;
+        Ok(())
+    }
+}
+
+/// Applies `profile` with unveil and pledge.
+#[cfg(target_os = "openbsd")]
+fn confine(profile: &Profile) -> anyhow::Result<String> {
+    use std::os::unix::ffi::OsStrExt;
+
+    for (paths, permissions) in [(&profile.readable, "r"), (&profile.writable, "rwc")] {
+        for path in paths.iter().filter(|path| path.exists()) {
+            unveil::unveil(path.as_os_str().as_bytes(), permissions)?;
+        }
+    }
+    // Locks the list of paths
+    unveil::unveil("", "")?;
+
+    let mut promises = "stdio rpath wpath cpath flock tty unix".to_string();
+    if profile.network {
+        promises.push_str(" inet dns");
+    }
+    pledge::pledge(promises.as_str(), None::<&str>)?;
+    Ok("unveil and pledge".to_string())
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "openbsd")))]
+fn confine(_profile: &Profile) -> anyhow::Result<String> {
+    anyhow::bail!("Sandboxing is only supported on Linux and OpenBSD")
+}
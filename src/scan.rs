@@ -0,0 +1,116 @@
+//! Matching the fingerprints of existing certificates instead of generated keys, e.g. to find the
+//! vanity keys in a keyring or a keyserver dump.
+
+use std::{io, sync::Arc};
+
+use sequoia_openpgp::{
+    Cert,
+    cert::{CertParser, amalgamation::key::PrimaryKey},
+    parse::Parse,
+};
+
+use crate::{
+    Config, Error, keygen,
+    matcher::{self, Matcher, PatternSet},
+};
+
+/// A key of a scanned certificate whose fingerprint matches.
+#[derive(Clone, Debug)]
+#[non_exhaustive]
+pub struct ScanMatch {
+    /// Fingerprint of the key in uppercase hex.
+    pub fingerprint: String,
+    /// Fingerprint of the certificate, which differs from `fingerprint` for subkeys.
+    pub cert_fingerprint: String,
+    /// Whether the key is the primary key of the certificate.
+    pub primary: bool,
+    /// The first user ID of the certificate, if any.
+    pub user_id: Option<String>,
+    /// The pattern the key matched.
+    pub pattern: String,
+}
+
+/// Counts of a finished [`Scanner::scan`].
+#[derive(Clone, Copy, Debug, Default)]
+pub struct ScanSummary {
+    pub certs: u64,
+    pub keys: u64,
+    pub matches: u64,
+    /// Certificates that couldn't be parsed and were skipped.
+    pub skipped: u64,
+}
+
+/// Matches the keys of certificates against the patterns a search would look for.
+#[derive(Debug)]
+pub struct Scanner {
+    patterns: PatternSet,
+}
+
+impl Scanner {
+    /// Uses the patterns of `config` like [`Fingerprunk`](crate::Fingerprunk) does, including
+    /// those of the jobs and the patterns file.
+    pub fn new(config: &Config) -> anyhow::Result<Self> {
+        let file_regexes = match &config.patterns_file {
+            Some(path) => matcher::read_patterns_file(path, Some(config.key_version))?,
+            None => Vec::new(),
+        };
+        let patterns = config
+            .regexes
+            .iter()
+            .chain(config.labeled_regexes.iter().map(|(_, regex)| regex))
+            .chain(config.jobs.iter().map(|job| &job.regex))
+            .chain(&file_regexes)
+            .map(|regex| Arc::new(regex.clone()) as Arc<dyn Matcher>)
+            .chain(config.matchers.iter().cloned())
+            .collect();
+        Ok(Self {
+            patterns: PatternSet::new(patterns),
+        })
+    }
+
+    /// Scans the certificates read from `reader`, armored or binary, calling `on_match` for every
+    /// matching key.
+    ///
+    /// Certificates that can't be parsed are skipped, as large dumps usually contain a few.
+    pub fn scan(
+        &self,
+        reader: impl io::Read + Send + Sync,
+        mut on_match: impl FnMut(ScanMatch),
+    ) -> anyhow::Result<ScanSummary> {
+        let mut summary = ScanSummary::default();
+        for cert in CertParser::from_reader(reader)? {
+            let Ok(cert) = cert else {
+                summary.skipped += 1;
+                continue;
+            };
+            summary.certs += 1;
+            summary.keys += cert.keys().count() as u64;
+            for found in self.scan_cert(&cert)? {
+                summary.matches += 1;
+                on_match(found);
+            }
+        }
+        Ok(summary)
+    }
+
+    /// Returns the keys of `cert` whose fingerprints match.
+    pub fn scan_cert(&self, cert: &Cert) -> Result<Vec<ScanMatch>, Error> {
+        let mut matches = Vec::new();
+        for key in cert.keys() {
+            let fingerprint = keygen::fingerprint_hex(key.key());
+            if let Some(matcher) = self.patterns.matching(&fingerprint)? {
+                matches.push(ScanMatch {
+                    fingerprint,
+                    cert_fingerprint: keygen::fingerprint_hex(cert.primary_key().key()),
+                    primary: key.primary(),
+                    user_id: cert
+                        .userids()
+                        .next()
+                        .map(|uid| String::from_utf8_lossy(uid.userid().value()).into_owned()),
+                    pattern: matcher.to_string(),
+                });
+            }
+        }
+        Ok(matches)
+    }
+}
@@ -0,0 +1,28 @@
+//! Handling of Unix signals besides SIGINT, which is handled via `ctrlc` on all platforms.
+
+use crossbeam_channel::Sender;
+use signal_hook::{
+    consts::{SIGHUP, SIGTERM},
+    iterator::Signals,
+};
+
+use crate::{Fingerprunk, Message};
+
+/// Starts listening for SIGTERM and SIGHUP.
+pub(crate) fn register() -> std::io::Result<Signals> {
+    Signals::new([SIGTERM, SIGHUP])
+}
+
+impl Fingerprunk {
+    /// Stops on SIGTERM and reloads the patterns file on SIGHUP until `signals` is closed.
+    pub(crate) fn signals_thread(&self, mut signals: Signals, sender: Sender<Message>) {
+        for signal in signals.forever() {
+            match signal {
+                SIGHUP => self.reload_patterns_and_log(),
+                _ => {
+                    let _ = sender.send(Message::Stop);
+                }
+            }
+        }
+    }
+}
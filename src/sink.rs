@@ -0,0 +1,251 @@
+//! Where found keys go.
+//!
+//! The finalizer hands every found key to a [`KeySink`]. Without a custom sink set with
+//! [`Fingerprunk::set_sink`](crate::Fingerprunk::set_sink), keys are handled by [`OutputSink`],
+//! which writes them to stdout or to the output directories of the configuration.
+
+use std::{
+    fmt,
+    fs::{self, OpenOptions},
+    io::{self, Write},
+    ops::ControlFlow,
+    path::{Path, PathBuf},
+    sync::mpsc,
+    time::Duration,
+};
+
+use anyhow::Context;
+use sequoia_openpgp::{Cert, armor, serialize::Serialize};
+
+/// Details about a found key.
+#[derive(Clone, Debug)]
+#[non_exhaustive]
+pub struct MatchMeta {
+    /// Fingerprint of the key in uppercase hex.
+    pub fingerprint: String,
+    /// The regex the key matched.
+    pub pattern: String,
+    /// Label of the regex, if it has one.
+    pub label: Option<String>,
+    /// Name of the job the key was found for, if any.
+    pub job: Option<String>,
+    /// The directory the configuration routes the key to, if any.
+    pub dir: Option<PathBuf>,
+    /// Number of keys tried when the key was found.
+    pub tried: u64,
+    /// Time since the search started.
+    pub elapsed: Duration,
+    /// Armored detached signature made with the key over the message of
+    /// [`Config::sign_proof`](crate::Config::sign_proof), if any.
+    pub proof: Option<Vec<u8>>,
+}
+
+/// A found key along with the details about it.
+#[derive(Clone, Debug)]
+pub struct FoundKey {
+    pub cert: Cert,
+    pub meta: MatchMeta,
+}
+
+/// Receives the keys found by a search.
+///
+/// Sinks are called from a single thread, one key after another. Returning an error ends the
+/// search with [`Error::Output`](crate::Error::Output), returning [`ControlFlow::Break`] ends it
+/// like a stop request.
+pub trait KeySink: Send {
+    fn found(&mut self, cert: Cert, meta: MatchMeta) -> anyhow::Result<ControlFlow<()>>;
+}
+
+impl<S: KeySink + ?Sized> KeySink for Box<S> {
+    fn found(&mut self, cert: Cert, meta: MatchMeta) -> anyhow::Result<ControlFlow<()>> {
+        (**self).found(cert, meta)
+    }
+}
+
+/// Writes the armored keys to stdout.
+///
+/// Every key is written with a single write, which pipes carry out as a whole, and flushed right
+/// away, so that readers never see a truncated block even if the process is killed.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct StdoutSink;
+
+impl KeySink for StdoutSink {
+    fn found(&mut self, cert: Cert, meta: MatchMeta) -> anyhow::Result<ControlFlow<()>> {
+        let mut stdout = io::stdout().lock();
+        write_armored(&cert, &meta, &mut stdout)?;
+        stdout.flush()?;
+        Ok(ControlFlow::Continue(()))
+    }
+}
+
+/// Writes the armored keys to any writer, e.g. a file or a buffer.
+#[derive(Clone, Debug)]
+pub struct WriterSink<W> {
+    writer: W,
+}
+
+impl<W: io::Write + Send> WriterSink<W> {
+    pub fn new(writer: W) -> Self {
+        Self { writer }
+    }
+
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+}
+
+impl<W: io::Write + Send> KeySink for WriterSink<W> {
+    fn found(&mut self, cert: Cert, meta: MatchMeta) -> anyhow::Result<ControlFlow<()>> {
+        write_armored(&cert, &meta, &mut self.writer)?;
+        self.writer.flush()?;
+        Ok(ControlFlow::Continue(()))
+    }
+}
+
+/// Writes every key to its own file `<fingerprint>.asc` in a directory, and its
+/// [`proof`](MatchMeta::proof), if any, to `<fingerprint>.proof.asc`.
+#[derive(Clone, Debug)]
+pub struct FileSink {
+    dir: PathBuf,
+}
+
+impl FileSink {
+    /// Writes the keys into `dir`, which must exist.
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self { dir: dir.into() }
+    }
+}
+
+impl KeySink for FileSink {
+    fn found(&mut self, cert: Cert, meta: MatchMeta) -> anyhow::Result<ControlFlow<()>> {
+        write_key_file(&cert, &meta, &self.dir)?;
+        Ok(ControlFlow::Continue(()))
+    }
+}
+
+/// Writes every key into the directory the configuration routes it to ([`MatchMeta::dir`]) like
+/// [`FileSink`], or to stdout like [`StdoutSink`] if there is none.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct OutputSink;
+
+impl KeySink for OutputSink {
+    fn found(&mut self, cert: Cert, meta: MatchMeta) -> anyhow::Result<ControlFlow<()>> {
+        match meta.dir.clone() {
+            Some(dir) => FileSink { dir }.found(cert, meta),
+            None => StdoutSink.found(cert, meta),
+        }
+    }
+}
+
+/// Sends the keys through a channel, e.g. to another thread. The search stops once the receiver
+/// is dropped.
+#[derive(Clone, Debug)]
+pub struct ChannelSink {
+    sender: mpsc::Sender<FoundKey>,
+}
+
+impl ChannelSink {
+    pub fn new(sender: mpsc::Sender<FoundKey>) -> Self {
+        Self { sender }
+    }
+}
+
+impl KeySink for ChannelSink {
+    fn found(&mut self, cert: Cert, meta: MatchMeta) -> anyhow::Result<ControlFlow<()>> {
+        Ok(match self.sender.send(FoundKey { cert, meta }) {
+            Ok(()) => ControlFlow::Continue(()),
+            Err(_) => ControlFlow::Break(()),
+        })
+    }
+}
+
+/// A sink set with [`Fingerprunk::set_sink`](crate::Fingerprunk::set_sink), which may not
+/// implement [`Debug`](fmt::Debug).
+pub(crate) struct UserSink(pub(crate) Box<dyn KeySink>);
+
+impl fmt::Debug for UserSink {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("UserSink")
+    }
+}
+
+/// Writes a key as armored secret key, with a comment naming the regex it matched, followed by
+/// its [`proof`](MatchMeta::proof), if any.
+///
+/// This is the format of the key files, for sinks handing the keys on as text. The block is
+/// armored completely before it is handed to `to` with a single `write_all`, so that a failure
+/// while armoring never leaves a partial block behind.
+pub fn write_armored(cert: &Cert, meta: &MatchMeta, mut to: impl io::Write) -> anyhow::Result<()> {
+    let mut armored = armor_key(cert, meta)?;
+    if let Some(proof) = &meta.proof {
+        armored.extend_from_slice(proof);
+    }
+    to.write_all(&armored)?;
+    Ok(())
+}
+
+fn armor_key(cert: &Cert, meta: &MatchMeta) -> anyhow::Result<Vec<u8>> {
+    let mut comments = cert.armor_headers();
+    comments.push(format!(
+        "Generated with Fingerprunk. Regex: {}",
+        meta.pattern
+    ));
+
+    let headers: Vec<_> = comments
+        .into_iter()
+        .map(|s| ("Comment".to_string(), s))
+        .collect();
+
+    let mut writer = armor::Writer::with_headers(Vec::new(), armor::Kind::SecretKey, headers)?;
+
+    writer.set_profile(if cert.primary_key().key().version() == 6 {
+        sequoia_openpgp::Profile::RFC9580
+    } else {
+        sequoia_openpgp::Profile::RFC4880
+    })?;
+
+    // Serializing the cert itself would drop the secret key
+    cert.as_tsk().serialize(&mut writer)?;
+    Ok(writer.finalize()?)
+}
+
+/// Writes a key to its own file in `dir`.
+fn write_key_file(cert: &Cert, meta: &MatchMeta, dir: &Path) -> anyhow::Result<()> {
+    let armored = armor_key(cert, meta)?;
+    write_file(dir, &format!("{}.asc", meta.fingerprint), &armored, true)?;
+    if let Some(proof) = &meta.proof {
+        write_file(
+            dir,
+            &format!("{}.proof.asc", meta.fingerprint),
+            proof,
+            false,
+        )?;
+    }
+    Ok(())
+}
+
+/// Writes `content` to the file `name` in `dir`, only readable by the owner if it is `secret`.
+///
+/// The file is written under a temporary name first, so that the directory never contains
+/// partially written keys.
+pub(crate) fn write_file(
+    dir: &Path,
+    name: &str,
+    content: &[u8],
+    secret: bool,
+) -> anyhow::Result<()> {
+    let path = dir.join(name);
+    let tmp_path = dir.join(format!(".{name}.tmp"));
+    let mut options = OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, if secret { 0o600 } else { 0o644 });
+
+    let mut file = options
+        .open(&tmp_path)
+        .with_context(|| format!("Failed to create {}", tmp_path.display()))?;
+    file.write_all(content)?;
+    file.sync_all()?;
+    fs::rename(&tmp_path, &path)
+        .with_context(|| format!("Failed to write key to {}", path.display()))
+}
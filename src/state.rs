@@ -0,0 +1,282 @@
+//! Checkpoints, so that searches spanning several runs report their cumulative effort.
+//!
+//! The state file consists of `key=value` lines. Keys that may occur several times are
+//! `pattern_found` (`<count> <regex>`) and `found_key` (a fingerprint). Unknown keys are ignored.
+
+use std::{
+    collections::BTreeMap,
+    fmt::Write as _,
+    fs,
+    io::ErrorKind,
+    path::{Path, PathBuf},
+    sync::atomic::Ordering,
+    thread,
+    time::Duration,
+};
+
+use anyhow::{Context, anyhow, bail};
+
+use crate::{Fingerprunk, Verbosity, format::KeyFormat, keygen::KeyVersion};
+
+/// How often the state file is written while searching.
+const SAVE_INTERVAL: Duration = Duration::from_secs(10);
+
+/// Version of the state file format.
+const VERSION: u32 = 1;
+
+/// Cumulative statistics and results of a search.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub(crate) struct State {
+    /// Hash of the settings that define the search, see [`Fingerprunk::config_hash`].
+    ///
+    /// Missing in files written by older versions.
+    pub config_hash: Option<u64>,
+    pub tried: u64,
+    pub found: u64,
+    pub elapsed: Duration,
+    /// Number of found keys by the regex they matched.
+    pub found_per_pattern: BTreeMap<String, u64>,
+    /// Fingerprints of the found keys.
+    pub found_keys: Vec<String>,
+}
+
+impl State {
+    /// Reads the state file, returning `None` if it doesn't exist yet.
+    pub(crate) fn load(path: &Path) -> anyhow::Result<Option<Self>> {
+        let content = match fs::read_to_string(path) {
+            Ok(content) => content,
+            Err(err) if err.kind() == ErrorKind::NotFound => return Ok(None),
+            Err(err) => {
+                return Err(err)
+                    .with_context(|| format!("Failed to read state file {}", path.display()));
+            }
+        };
+
+        let mut state = Self::default();
+        for (num, line) in content.lines().enumerate() {
+            let invalid = || anyhow!("Invalid state file {} line {}", path.display(), num + 1);
+            let Some((key, value)) = line.split_once('=') else {
+                continue;
+            };
+            let value = value.trim();
+            match key.trim() {
+                "version" => {
+                    let version: u32 = value.parse().map_err(|_| invalid())?;
+                    if version > VERSION {
+                        bail!(
+                            "State file {} was written by a newer version of Fingerprunk",
+                            path.display()
+                        );
+                    }
+                }
+                "config_hash" => {
+                    state.config_hash =
+                        Some(u64::from_str_radix(value, 16).map_err(|_| invalid())?);
+                }
+                "tried" => state.tried = value.parse().map_err(|_| invalid())?,
+                "found" => state.found = value.parse().map_err(|_| invalid())?,
+                "elapsed_secs" => {
+                    state.elapsed = value
+                        .parse()
+                        .ok()
+                        .and_then(|secs| Duration::try_from_secs_f64(secs).ok())
+                        .ok_or_else(invalid)?;
+                }
+                "pattern_found" => {
+                    let (count, pattern) = value.split_once(' ').ok_or_else(invalid)?;
+                    state
+                        .found_per_pattern
+                        .insert(pattern.to_string(), count.parse().map_err(|_| invalid())?);
+                }
+                "found_key" => state.found_keys.push(value.to_string()),
+                _ => {}
+            }
+        }
+
+        Ok(Some(state))
+    }
+
+    /// Writes the state file, replacing it atomically so that it is never left half-written.
+    pub(crate) fn save(&self, path: &Path) -> anyhow::Result<()> {
+        let mut content = String::new();
+        writeln!(content, "version={VERSION}")?;
+        if let Some(config_hash) = self.config_hash {
+            writeln!(content, "config_hash={config_hash:016x}")?;
+        }
+        writeln!(content, "tried={}", self.tried)?;
+        writeln!(content, "found={}", self.found)?;
+        writeln!(content, "elapsed_secs={:.3}", self.elapsed.as_secs_f64())?;
+        for (pattern, count) in &self.found_per_pattern {
+            writeln!(content, "pattern_found={count} {pattern}")?;
+        }
+        for fingerprint in &self.found_keys {
+            writeln!(content, "found_key={fingerprint}")?;
+        }
+
+        let mut tmp_path = path.as_os_str().to_owned();
+        tmp_path.push(".tmp");
+        let tmp_path = PathBuf::from(tmp_path);
+        fs::write(&tmp_path, content)
+            .and_then(|()| fs::rename(&tmp_path, path))
+            .with_context(|| format!("Failed to write state file {}", path.display()))
+    }
+}
+
+impl Fingerprunk {
+    /// Loads the checkpoint from the state file, if configured, and continues from there.
+    ///
+    /// Refuses to continue a search with different patterns or settings unless allowed by the
+    /// configuration.
+    pub(crate) fn load_state(&mut self) -> anyhow::Result<()> {
+        let Some(path) = &self.config.state_file else {
+            return Ok(());
+        };
+
+        match State::load(path)? {
+            Some(state) => {
+                if state.config_hash != Some(self.config_hash()) && !self.config.allow_config_change
+                {
+                    bail!(
+                        "State file {} belongs to a search with different patterns or settings \
+                        (use --allow-config-change to continue anyway)",
+                        path.display()
+                    );
+                }
+                self.restore_state(state);
+                Ok(())
+            }
+            None if self.config.resume => {
+                bail!("Nothing to resume, {} does not exist", path.display())
+            }
+            None => Ok(()),
+        }
+    }
+
+    /// Continues counting from a previously saved state.
+    ///
+    /// The start of the search is moved back by the saved elapsed time, so rates and ETAs
+    /// account for the previous runs as well.
+    fn restore_state(&mut self, state: State) {
+        self.counter_tried.store(state.tried, Ordering::Relaxed);
+        self.counter_found.store(state.found, Ordering::Relaxed);
+        if let Some(started_instant) = self.started_instant.checked_sub(state.elapsed) {
+            self.started_instant = started_instant;
+        }
+        *self
+            .found_per_pattern
+            .get_mut()
+            .expect("lock should not be poisoned") = state.found_per_pattern;
+        *self
+            .found_fingerprints
+            .get_mut()
+            .expect("lock should not be poisoned") = state.found_keys;
+    }
+
+    pub(crate) fn current_state(&self) -> State {
+        State {
+            config_hash: Some(self.config_hash()),
+            tried: self.counter_tried.load(Ordering::Relaxed),
+            found: self.counter_found.load(Ordering::Relaxed),
+            elapsed: self.started_instant.elapsed(),
+            found_per_pattern: self
+                .found_per_pattern
+                .lock()
+                .expect("lock should not be poisoned")
+                .clone(),
+            found_keys: self
+                .found_fingerprints
+                .lock()
+                .expect("lock should not be poisoned")
+                .clone(),
+        }
+    }
+
+    /// A hash of the settings that define the search, i.e. the patterns and when to stop.
+    ///
+    /// This uses FNV-1a instead of the standard library's hasher, whose output may change
+    /// between Rust versions.
+    pub(crate) fn config_hash(&self) -> u64 {
+        let mut config = String::new();
+        if self.config.jobs.is_empty() {
+            // The configured patterns rather than the matcher, which changes with reloads of the
+            // patterns file and added patterns
+            let patterns = self
+                .config
+                .regexes
+                .iter()
+                .chain(self.config.labeled_regexes.iter().map(|(_, regex)| regex))
+                .map(ToString::to_string)
+                .chain(self.config.matchers.iter().map(ToString::to_string))
+                .collect::<Vec<_>>();
+            writeln!(config, "patterns={}", patterns.join(" | "))
+                .expect("should write into string without error");
+            if let Some(path) = &self.config.patterns_file {
+                writeln!(config, "patterns_file={}", path.display())
+                    .expect("should write into string without error");
+            }
+        } else {
+            // The matcher shrinks as jobs are done, so the jobs themselves define the search
+            self.config
+                .jobs
+                .iter()
+                .try_for_each(|job| {
+                    writeln!(
+                        config,
+                        "job={} {} {} {}",
+                        job.user_id(),
+                        job.quota,
+                        job.priority,
+                        job.regex
+                    )
+                })
+                .expect("should write into string without error");
+        }
+        writeln!(config, "stop_after={:?}", self.config.stop_after)
+            .expect("should write into string without error");
+        if let Some(priority) = self.config.stop_with_priority {
+            writeln!(config, "stop_with_priority={priority}")
+                .expect("should write into string without error");
+        }
+        // Only added for v6, so that the states of earlier v4 searches stay valid
+        if self.config.key_version != KeyVersion::V4 {
+            writeln!(config, "key_version={}", self.config.key_version)
+                .expect("should write into string without error");
+        }
+        if self.config.key_format != KeyFormat::OpenPgp {
+            writeln!(config, "key_format={}", self.config.key_format)
+                .expect("should write into string without error");
+        }
+        if let Some(regex) = &self.config.subkey_regex {
+            writeln!(config, "subkey_regex={regex}")
+                .expect("should write into string without error");
+        }
+        if let Some(certifier) = &self.config.certify_with {
+            writeln!(config, "certify_with={:X}", certifier.fingerprint())
+                .expect("should write into string without error");
+        }
+        if let Some(message) = &self.config.sign_proof {
+            writeln!(config, "sign_proof={}", String::from_utf8_lossy(message))
+                .expect("should write into string without error");
+        }
+        for proof in &self.config.proofs {
+            writeln!(config, "proof={proof}").expect("should write into string without error");
+        }
+        config.bytes().fold(0xcbf2_9ce4_8422_2325, |hash, byte| {
+            (hash ^ u64::from(byte)).wrapping_mul(0x0100_0000_01b3)
+        })
+    }
+
+    /// Saves the state periodically until the search stops.
+    pub(crate) fn state_thread(&self, path: &Path) {
+        loop {
+            // Parking instead of sleeping, so that we can be woken up for stopping
+            thread::park_timeout(SAVE_INTERVAL);
+            if self.stop.load(Ordering::Relaxed) {
+                break;
+            }
+            if let Err(err) = self.current_state().save(path) {
+                self.log(Verbosity::Normal, format_args!("{err:#}"));
+            }
+        }
+    }
+}
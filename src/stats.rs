@@ -0,0 +1,100 @@
+//! Progress of a running search, read without locking.
+
+use std::{
+    sync::{
+        Arc,
+        atomic::{AtomicI64, AtomicU64, Ordering},
+    },
+    time::{Duration, Instant},
+};
+
+/// Snapshot of the progress of a search.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Stats {
+    pub tried: u64,
+    pub found: u64,
+    /// Time spent searching, including previous runs when resuming.
+    pub elapsed: Duration,
+    /// Keys tried per second, smoothed over the last few seconds; 0 when not running.
+    pub rate: f64,
+}
+
+/// Reads the [`Stats`] of a search from another thread.
+///
+/// Obtained from [`Fingerprunk::stats_handle`](crate::Fingerprunk::stats_handle) before running
+/// the search. Reading the stats only loads a few atomics, so it can be done as often as needed.
+#[derive(Clone, Debug)]
+pub struct StatsHandle {
+    pub(crate) tried: Arc<AtomicU64>,
+    pub(crate) found: Arc<AtomicU64>,
+    pub(crate) clock: Arc<Clock>,
+}
+
+impl StatsHandle {
+    #[must_use]
+    pub fn stats(&self) -> Stats {
+        Stats {
+            tried: self.tried.load(Ordering::Relaxed),
+            found: self.found.load(Ordering::Relaxed),
+            elapsed: self.clock.elapsed(),
+            rate: f64::from_bits(self.clock.rate.load(Ordering::Relaxed)),
+        }
+    }
+}
+
+/// Start, end and rate of a search, as published by the search for [`StatsHandle`]s.
+#[derive(Debug)]
+pub(crate) struct Clock {
+    epoch: Instant,
+    /// Start of the search in nanoseconds after `epoch`, negative when resuming.
+    started: AtomicI64,
+    /// End of the search in nanoseconds after `epoch`, or [`i64::MAX`] while running.
+    stopped: AtomicI64,
+    /// Bits of the current rate.
+    rate: AtomicU64,
+}
+
+impl Default for Clock {
+    fn default() -> Self {
+        Self {
+            epoch: Instant::now(),
+            started: AtomicI64::new(0),
+            stopped: AtomicI64::new(0),
+            rate: AtomicU64::new(0.0f64.to_bits()),
+        }
+    }
+}
+
+impl Clock {
+    pub(crate) fn start(&self, started_instant: Instant) {
+        let started = match started_instant.checked_duration_since(self.epoch) {
+            Some(after) => nanos(after),
+            None => -nanos(self.epoch - started_instant),
+        };
+        self.started.store(started, Ordering::Relaxed);
+        self.stopped.store(i64::MAX, Ordering::Relaxed);
+    }
+
+    pub(crate) fn stop(&self) {
+        self.stopped.store(self.now(), Ordering::Relaxed);
+        self.set_rate(0.0);
+    }
+
+    pub(crate) fn set_rate(&self, rate: f64) {
+        self.rate.store(rate.to_bits(), Ordering::Relaxed);
+    }
+
+    fn elapsed(&self) -> Duration {
+        let end = self.now().min(self.stopped.load(Ordering::Relaxed));
+        let elapsed = end.saturating_sub(self.started.load(Ordering::Relaxed));
+        Duration::from_nanos(u64::try_from(elapsed).unwrap_or(0))
+    }
+
+    fn now(&self) -> i64 {
+        nanos(self.epoch.elapsed())
+    }
+}
+
+fn nanos(duration: Duration) -> i64 {
+    i64::try_from(duration.as_nanos()).unwrap_or(i64::MAX)
+}
@@ -0,0 +1,431 @@
+//! Building blocks of the status display.
+
+use std::{
+    collections::VecDeque,
+    fmt,
+    io::{self, IsTerminal},
+    str::FromStr,
+    time::{Duration, Instant, SystemTime},
+};
+
+use anyhow::{anyhow, bail};
+use num_integer::Integer;
+
+/// How the status is displayed on stderr.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum StatusMode {
+    /// No status is displayed.
+    Disabled,
+    /// The status is redrawn in place, which requires a capable terminal.
+    Redraw,
+    /// Timestamped status lines are appended periodically, which keeps captured logs readable.
+    Append,
+}
+
+/// A value that can be shown in the status display.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Field {
+    /// Time since the search was started.
+    Elapsed,
+    /// Number of keys tried so far.
+    Tried,
+    /// Recent key generation rate (exponentially-weighted moving average).
+    Rate,
+    /// Lowest rate over the last minute.
+    RateMin,
+    /// Highest rate over the last minute.
+    RateMax,
+    /// Lifetime average key generation rate.
+    AvgRate,
+    /// Expected number of keys to try until the search is done.
+    Expected,
+    /// How the match probability was estimated.
+    Estimate,
+    /// Tried keys as a percentage of the expected number of keys.
+    Progress,
+    /// Median time until the next match at the current rate.
+    Eta,
+    /// Progress bar filled according to `Progress`; the width sets the bar length.
+    Bar,
+    /// Number of matching keys found so far.
+    Found,
+    /// Fingerprint of the last found key, with the matching part highlighted.
+    Last,
+    /// The regexes used for matching.
+    Pattern,
+    /// Found keys and quota of every job.
+    Jobs,
+    /// Rate of the workers on every NUMA node, if they are spread over several.
+    Nodes,
+    /// Energy used by the CPUs so far and per found key, if their RAPL counters are readable.
+    Energy,
+    /// Utilization and clock of the CPUs and whether they are throttled, on Linux.
+    Cpu,
+}
+
+impl Field {
+    const NAMES: [(&str, Field); 18] = [
+        ("elapsed", Field::Elapsed),
+        ("tried", Field::Tried),
+        ("rate", Field::Rate),
+        ("rate_min", Field::RateMin),
+        ("rate_max", Field::RateMax),
+        ("avg_rate", Field::AvgRate),
+        ("expected", Field::Expected),
+        ("estimate", Field::Estimate),
+        ("progress", Field::Progress),
+        ("eta", Field::Eta),
+        ("bar", Field::Bar),
+        ("found", Field::Found),
+        ("last", Field::Last),
+        ("pattern", Field::Pattern),
+        ("jobs", Field::Jobs),
+        ("nodes", Field::Nodes),
+        ("energy", Field::Energy),
+        ("cpu", Field::Cpu),
+    ];
+
+    fn from_name(name: &str) -> Option<Self> {
+        Self::NAMES
+            .iter()
+            .find(|(n, _)| *n == name)
+            .map(|&(_, field)| field)
+    }
+}
+
+#[derive(Clone, Debug)]
+enum Segment {
+    Literal(String),
+    Field { field: Field, width: Option<usize> },
+}
+
+/// A user-defined layout for the status display.
+///
+/// Placeholders are written as `{name}` or `{name:width}`, the latter right-aligning the value to
+/// the given width. `{{` and `}}` produce literal braces and `\n` produces a line break.
+#[derive(Clone, Debug)]
+pub struct StatusTemplate {
+    segments: Vec<Segment>,
+    lines: usize,
+}
+
+impl StatusTemplate {
+    /// The template used if none is specified.
+    pub const DEFAULT: &str = "\
+        Time:  {elapsed}\n\
+        Tried: {tried:12} keys\n\
+        Rate:  {rate:12} keys/s (last 1m: {rate_min}-{rate_max})\n\
+        Avg:   {avg_rate:12} keys/s\n\
+        ---\n\
+        Exp.:  {expected:12} keys ({estimate})\n\
+        Prog.: {progress:12} %\n\
+        ETA:   {eta} (median, next match)\n\
+        ---\n\
+        Found: {found:12} keys\n\
+        Last:  {last}";
+
+    /// A compact single-line template centered around a progress bar.
+    pub const PROGRESS_BAR: &str = "[{bar:30}] {progress:5} % | {tried} / {expected} keys | \
+        {rate} keys/s | ETA {eta} | found {found}";
+
+    /// A single-line template suited for appending to logs.
+    pub const LOG_LINE: &str = "elapsed {elapsed}, tried {tried} keys, {rate} keys/s, \
+        found {found} keys, ETA {eta}";
+
+    /// Names of all available placeholders.
+    pub fn placeholders() -> impl Iterator<Item = &'static str> {
+        Field::NAMES.iter().map(|&(name, _)| name)
+    }
+
+    /// Number of lines the rendered template spans.
+    #[must_use]
+    pub fn lines(&self) -> usize {
+        self.lines
+    }
+
+    /// Renders the template, querying `value` for the value of each placeholder and its width.
+    pub fn render(&self, mut value: impl FnMut(Field, Option<usize>) -> String) -> String {
+        let mut out = String::new();
+        for segment in &self.segments {
+            match segment {
+                Segment::Literal(literal) => out.push_str(literal),
+                Segment::Field { field, width } => {
+                    let value = value(*field, *width);
+                    out.push_str(&format!("{value: >w$}", w = width.unwrap_or(0)));
+                }
+            }
+        }
+        out
+    }
+}
+
+impl Default for StatusTemplate {
+    fn default() -> Self {
+        Self::DEFAULT
+            .parse()
+            .expect("default template should be valid")
+    }
+}
+
+impl FromStr for StatusTemplate {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut segments = Vec::new();
+        let mut literal = String::new();
+        let mut chars = s.chars();
+
+        while let Some(c) = chars.next() {
+            match c {
+                '{' if chars.as_str().starts_with('{') => {
+                    chars.next();
+                    literal.push('{');
+                }
+                '}' if chars.as_str().starts_with('}') => {
+                    chars.next();
+                    literal.push('}');
+                }
+                '\\' if chars.as_str().starts_with('n') => {
+                    chars.next();
+                    literal.push('\n');
+                }
+                '{' => {
+                    let rest = chars.as_str();
+                    let end = rest
+                        .find('}')
+                        .ok_or_else(|| anyhow!("Unclosed placeholder in status template"))?;
+                    let (name, width) = match rest[..end].split_once(':') {
+                        Some((name, width)) => (
+                            name,
+                            Some(width.parse().map_err(|_| {
+                                anyhow!("Invalid width {width:?} for placeholder {{{name}}}")
+                            })?),
+                        ),
+                        None => (&rest[..end], None),
+                    };
+                    let field = Field::from_name(name).ok_or_else(|| {
+                        anyhow!(
+                            "Unknown placeholder {{{name}}} in status template (available: {})",
+                            Self::placeholders().collect::<Vec<_>>().join(", ")
+                        )
+                    })?;
+
+                    if !literal.is_empty() {
+                        segments.push(Segment::Literal(std::mem::take(&mut literal)));
+                    }
+                    segments.push(Segment::Field { field, width });
+                    chars = rest[end + 1..].chars();
+                }
+                '}' => bail!("Unmatched '}}' in status template"),
+                c => literal.push(c),
+            }
+        }
+
+        if !literal.is_empty() {
+            segments.push(Segment::Literal(literal));
+        }
+
+        let lines = 1 + segments
+            .iter()
+            .map(|segment| match segment {
+                Segment::Literal(literal) => literal.matches('\n').count(),
+                Segment::Field { .. } => 0,
+            })
+            .sum::<usize>();
+
+        Ok(Self { segments, lines })
+    }
+}
+
+/// Returns whether stderr is a terminal on which the status display can be redrawn in place.
+///
+/// This is not the case for dumb terminals and for Windows consoles without support for ANSI
+/// escape sequences.
+#[must_use]
+pub fn stderr_supports_redraw() -> bool {
+    if !io::stderr().is_terminal() {
+        return false;
+    }
+
+    #[cfg(windows)]
+    {
+        crossterm::ansi_support::supports_ansi()
+    }
+    #[cfg(not(windows))]
+    {
+        std::env::var_os("TERM").is_some_and(|term| term != "dumb")
+    }
+}
+
+/// Displays a duration as days, hours, minutes and seconds.
+pub struct DurationDhms(pub Duration);
+
+impl fmt::Display for DurationDhms {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let seconds = self.0.as_secs();
+        let (minutes, seconds) = seconds.div_rem(&60);
+        let (hours, minutes) = minutes.div_rem(&60);
+        let (days, hours) = hours.div_rem(&24);
+
+        write!(f, "{days}d {hours: >2}h {minutes: >2}m {seconds: >2}s")
+    }
+}
+
+/// Draws a progress bar of the given width, with `fraction` being clamped to `0.0..=1.0`.
+pub(crate) fn progress_bar(fraction: Option<f64>, width: usize) -> String {
+    let Some(fraction) = fraction else {
+        return "?".repeat(width);
+    };
+
+    let filled = ((fraction.clamp(0.0, 1.0) * width as f64) as usize).min(width);
+    let mut bar = "=".repeat(filled);
+    if filled < width {
+        bar.push('>');
+        bar.push_str(&" ".repeat(width - filled - 1));
+    }
+    bar
+}
+
+/// Formats a point in time as an RFC 3339 timestamp in UTC, e.g. `2025-01-31T12:34:56Z`.
+pub fn format_timestamp(time: SystemTime) -> String {
+    let seconds = time
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs());
+    let (days, seconds) = seconds.div_rem(&86400);
+    let (hours, seconds) = seconds.div_rem(&3600);
+    let (minutes, seconds) = seconds.div_rem(&60);
+
+    // Convert days since the epoch into a civil date, see
+    // <https://howardhinnant.github.io/date_algorithms.html#civil_from_days>.
+    let days = days as i64 + 719_468;
+    let era = days.div_euclid(146_097);
+    let day_of_era = days.rem_euclid(146_097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_index = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month_index + 2) / 5 + 1;
+    let month = if month_index < 10 {
+        month_index + 3
+    } else {
+        month_index - 9
+    };
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+
+    format!("{year:04}-{month:02}-{day:02}T{hours:02}:{minutes:02}:{seconds:02}Z")
+}
+
+/// Formats a number with three significant digits and an SI prefix, e.g. `1.37 G`.
+#[must_use]
+pub fn humanize(value: f64) -> String {
+    const PREFIXES: [&str; 7] = ["", "k", "M", "G", "T", "P", "E"];
+
+    let mut value = value;
+    let mut prefix = 0;
+    while value >= 999.5 && prefix < PREFIXES.len() - 1 {
+        value /= 1000.0;
+        prefix += 1;
+    }
+
+    let decimals = match value {
+        _ if prefix == 0 => 0,
+        v if v < 9.995 => 2,
+        v if v < 99.95 => 1,
+        _ => 0,
+    };
+    format!("{value:.decimals$} {}", PREFIXES[prefix])
+}
+
+/// Keeps track of the recent key generation rate, as opposed to the lifetime average.
+#[derive(Clone, Debug)]
+pub(crate) struct RateMeter {
+    last_instant: Instant,
+    last_tried: u64,
+    smoothed: Option<f64>,
+    window: VecDeque<(Instant, f64)>,
+}
+
+impl RateMeter {
+    /// Time constant of the exponentially-weighted moving average.
+    const SMOOTHING: Duration = Duration::from_secs(10);
+    /// Time span over which the minimum and maximum rate are reported.
+    const WINDOW: Duration = Duration::from_secs(60);
+
+    pub fn new(started_instant: Instant) -> Self {
+        Self {
+            last_instant: started_instant,
+            last_tried: 0,
+            smoothed: None,
+            window: VecDeque::new(),
+        }
+    }
+
+    pub fn update(&mut self, now: Instant, tried: u64) {
+        let elapsed = now.duration_since(self.last_instant).as_secs_f64();
+        if elapsed <= 0.0 {
+            return;
+        }
+
+        let rate = tried.saturating_sub(self.last_tried) as f64 / elapsed;
+        let alpha = 1.0 - (-elapsed / Self::SMOOTHING.as_secs_f64()).exp();
+        self.smoothed = Some(match self.smoothed {
+            Some(smoothed) => smoothed + alpha * (rate - smoothed),
+            None => rate,
+        });
+
+        self.window.push_back((now, rate));
+        while self
+            .window
+            .front()
+            .is_some_and(|&(instant, _)| now.duration_since(instant) > Self::WINDOW)
+        {
+            self.window.pop_front();
+        }
+
+        self.last_instant = now;
+        self.last_tried = tried;
+    }
+
+    pub fn smoothed(&self) -> Option<f64> {
+        self.smoothed
+    }
+
+    pub fn min_max(&self) -> Option<(f64, f64)> {
+        self.window.iter().fold(None, |acc, &(_, rate)| match acc {
+            Some((min, max)) => Some((f64::min(min, rate), f64::max(max, rate))),
+            None => Some((rate, rate)),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Renders `template` with every placeholder replaced by its field.
+    fn render(template: &str) -> String {
+        let template: StatusTemplate = template.parse().unwrap();
+        template.render(|field, _| format!("{field:?}"))
+    }
+
+    #[test]
+    fn status_template_renders_placeholders() {
+        assert_eq!(render("tried {tried} keys"), "tried Tried keys");
+        assert_eq!(render("{found:8}|"), "   Found|");
+        assert_eq!(render("{{eta}} }}"), "{eta} }");
+    }
+
+    #[test]
+    fn status_template_counts_lines() {
+        let template: StatusTemplate = r"{elapsed}\n{tried}\nend".parse().unwrap();
+        assert_eq!(template.lines(), 3);
+        assert_eq!(StatusTemplate::default().lines(), 11);
+    }
+
+    #[test]
+    fn status_template_rejects_invalid_placeholders() {
+        for invalid in ["{tried", "tried}", "{nope}", "{tried:wide}", "{tried:-1}"] {
+            assert!(invalid.parse::<StatusTemplate>().is_err(), "{invalid:?}");
+        }
+    }
+}
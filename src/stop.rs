@@ -0,0 +1,59 @@
+//! Stopping a search from outside of it.
+
+use std::sync::{Arc, Mutex, MutexGuard};
+
+use crossbeam_channel::Sender;
+
+use crate::Message;
+
+/// Stops a search from another thread, like Ctrl-C does.
+///
+/// Obtained from [`Fingerprunk::stop_handle`](crate::Fingerprunk::stop_handle) before running
+/// the search. Stopping before the search has started makes it stop right away.
+#[derive(Clone, Debug, Default)]
+pub struct StopHandle {
+    state: Arc<Mutex<StopState>>,
+}
+
+#[derive(Debug, Default)]
+struct StopState {
+    requested: bool,
+    /// Sender to the finalizer of the running search, if any.
+    sender: Option<Sender<Message>>,
+}
+
+impl StopHandle {
+    /// Asks the search to stop. It stops after the keys found so far have been handled.
+    pub fn stop(&self) {
+        let mut state = self.lock();
+        state.requested = true;
+        if let Some(sender) = state.sender.take() {
+            let _ = sender.send(Message::Stop);
+        }
+    }
+
+    /// Whether [`Self::stop`] has been called.
+    #[must_use]
+    pub fn is_stopped(&self) -> bool {
+        self.lock().requested
+    }
+
+    /// Connects the handle to a starting search, stopping it if that has already been requested.
+    pub(crate) fn attach(&self, sender: Sender<Message>) {
+        let mut state = self.lock();
+        if state.requested {
+            let _ = sender.send(Message::Stop);
+        } else {
+            state.sender = Some(sender);
+        }
+    }
+
+    /// Disconnects the handle from the stopped search.
+    pub(crate) fn detach(&self) {
+        self.lock().sender = None;
+    }
+
+    fn lock(&self) -> MutexGuard<'_, StopState> {
+        self.state.lock().expect("lock should not be poisoned")
+    }
+}
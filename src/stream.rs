@@ -0,0 +1,99 @@
+//! Async stream of the found keys while the search runs in the background.
+
+use std::{
+    ops::ControlFlow,
+    panic::{self, AssertUnwindSafe},
+    pin::Pin,
+    task::{Context, Poll},
+    thread,
+};
+
+use futures_core::Stream;
+use sequoia_openpgp::Cert;
+use tokio::sync::{mpsc, oneshot};
+
+use crate::{
+    Error, Fingerprunk, RunSummary, StopHandle,
+    sink::{FoundKey, KeySink, MatchMeta},
+};
+
+/// Stream of the keys found by a search running in a background thread, see
+/// [`Fingerprunk::stream`].
+///
+/// The search runs on a thread of its own rather than on the async runtime, so the stream can be
+/// polled from any runtime. The stream ends when the search does; [`Self::finish`] tells whether
+/// it succeeded. Dropping the stream stops the search without waiting for it.
+#[derive(Debug)]
+pub struct KeyStream {
+    keys: mpsc::UnboundedReceiver<FoundKey>,
+    stop_handle: StopHandle,
+    result: Option<oneshot::Receiver<Result<RunSummary, Error>>>,
+}
+
+impl KeyStream {
+    pub(crate) fn spawn(mut fingerprunk: Fingerprunk) -> Self {
+        let (sender, keys) = mpsc::unbounded_channel();
+        fingerprunk.set_sink(StreamSink(sender));
+        let stop_handle = fingerprunk.stop_handle();
+        let (result_sender, result) = oneshot::channel();
+        thread::Builder::new()
+            .name("search".to_string())
+            .spawn(move || {
+                let result = panic::catch_unwind(AssertUnwindSafe(|| fingerprunk.run()))
+                    .unwrap_or_else(|payload| Err(Error::panicked("search", &*payload)));
+                let _ = result_sender.send(result);
+            })
+            .expect("should spawn the search thread");
+        Self {
+            keys,
+            stop_handle,
+            result: Some(result),
+        }
+    }
+
+    /// Returns a handle to stop the search, e.g. when a request is cancelled.
+    #[must_use]
+    pub fn stop_handle(&self) -> StopHandle {
+        self.stop_handle.clone()
+    }
+
+    /// Stops the search and waits for its result without blocking the runtime. Keys found in the
+    /// meantime are handed to nobody.
+    pub async fn finish(mut self) -> Result<RunSummary, Error> {
+        self.stop_handle.stop();
+        let result = self
+            .result
+            .take()
+            .expect("search should only be finished once");
+        // Panics are caught in the search thread, so this is only a fallback
+        result
+            .await
+            .unwrap_or_else(|_| Err(Error::panicked("search", &())))
+    }
+}
+
+impl Stream for KeyStream {
+    type Item = FoundKey;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<FoundKey>> {
+        self.keys.poll_recv(cx)
+    }
+}
+
+impl Drop for KeyStream {
+    fn drop(&mut self) {
+        self.stop_handle.stop();
+    }
+}
+
+/// Sends the keys to a [`KeyStream`], stopping the search once it is dropped.
+struct StreamSink(mpsc::UnboundedSender<FoundKey>);
+
+impl KeySink for StreamSink {
+    fn found(&mut self, cert: Cert, meta: MatchMeta) -> anyhow::Result<ControlFlow<()>> {
+        Ok(match self.0.send(FoundKey { cert, meta }) {
+            Ok(()) => ControlFlow::Continue(()),
+            Err(_) => ControlFlow::Break(()),
+        })
+    }
+}
@@ -0,0 +1,101 @@
+//! Integration with systemd's service notification protocol (see `sd_notify(3)`).
+
+use std::{
+    env,
+    os::{
+        linux::net::SocketAddrExt,
+        unix::net::{SocketAddr, UnixDatagram},
+    },
+    sync::atomic::Ordering,
+    thread,
+    time::{Duration, Instant},
+};
+
+use crate::{Fingerprunk, status::RateMeter};
+
+/// Connection to the notification socket of the service manager.
+pub(crate) struct SystemdNotifier {
+    socket: UnixDatagram,
+    addr: SocketAddr,
+    watchdog: Option<Duration>,
+}
+
+impl SystemdNotifier {
+    /// Interval of the `STATUS=` updates.
+    const STATUS_INTERVAL: Duration = Duration::from_secs(10);
+
+    /// Connects to the socket given by `$NOTIFY_SOCKET`, if running as a systemd service.
+    pub(crate) fn from_env() -> Option<Self> {
+        let path = env::var("NOTIFY_SOCKET").ok()?;
+        let addr = match path.strip_prefix('@') {
+            Some(name) => SocketAddr::from_abstract_name(name),
+            None => SocketAddr::from_pathname(&path),
+        }
+        .ok()?;
+
+        // The watchdog may be meant for another process of the service
+        let watchdog_pid = env::var("WATCHDOG_PID").ok();
+        let watchdog = env::var("WATCHDOG_USEC")
+            .ok()
+            .filter(|_| watchdog_pid.is_none_or(|pid| pid == std::process::id().to_string()))
+            .and_then(|usec| usec.parse().ok())
+            .map(Duration::from_micros);
+
+        Some(Self {
+            socket: UnixDatagram::unbound().ok()?,
+            addr,
+            watchdog,
+        })
+    }
+
+    /// Sends a state update, ignoring errors like libsystemd does.
+    fn send(&self, state: &str) {
+        let _ = self.socket.send_to_addr(state.as_bytes(), &self.addr);
+    }
+}
+
+impl Fingerprunk {
+    /// Reports readiness, progress and liveness to systemd until the search stops.
+    pub(crate) fn systemd_thread(&self, notifier: SystemdNotifier) {
+        // Ping the watchdog twice per timeout, as recommended by sd_watchdog_enabled(3)
+        let update_interval = notifier
+            .watchdog
+            .map_or(SystemdNotifier::STATUS_INTERVAL, |watchdog| {
+                (watchdog / 2).min(SystemdNotifier::STATUS_INTERVAL)
+            });
+
+        notifier.send("READY=1");
+
+        let mut rate_meter = RateMeter::new(self.started_instant);
+        let mut last_tried = 0;
+        let mut next_status = Instant::now();
+
+        while !self.stop.load(Ordering::Relaxed) {
+            let tried = self.counter_tried.load(Ordering::Relaxed);
+            rate_meter.update(Instant::now(), tried);
+
+            // Only ping the watchdog while the workers make progress, so hung searches are
+            // restarted.
+            if notifier.watchdog.is_some()
+                && (tried > last_tried || self.paused.load(Ordering::Relaxed))
+            {
+                notifier.send("WATCHDOG=1");
+            }
+            last_tried = tried;
+
+            if Instant::now() >= next_status {
+                notifier.send(&format!(
+                    "STATUS=Tried {} keys ({} keys/s), found {} keys",
+                    self.format_number(tried as f64),
+                    self.format_number(rate_meter.smoothed().unwrap_or(0.0)),
+                    self.counter_found.load(Ordering::Relaxed),
+                ));
+                next_status = Instant::now() + SystemdNotifier::STATUS_INTERVAL;
+            }
+
+            thread::park_timeout(update_interval);
+        }
+
+        notifier.send("STOPPING=1");
+    }
+}
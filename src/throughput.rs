@@ -0,0 +1,84 @@
+//! Checking the search rate shortly after starting, as a binary that is many times slower than
+//! it should be, e.g. a debug build, otherwise goes unnoticed for the days it searches.
+
+use std::{fs, time::Duration};
+
+use crate::{Fingerprunk, Verbosity};
+
+/// How long the search runs before its rate is checked.
+pub(crate) const CHECK_AFTER: Duration = Duration::from_secs(5);
+
+/// Keys a physical core of a desktop CPU from the last decade generates and checks per second,
+/// roughly and on the low side.
+const EXPECTED_RATE_PER_CORE: f64 = 20_000.0;
+
+/// Fraction of the expected rate below which the rate is drastically low.
+const WARN_BELOW: f64 = 0.25;
+
+const CPUFREQ_DIR: &str = "/sys/devices/system/cpu/cpu0/cpufreq";
+
+impl Fingerprunk {
+    /// Warns if `rate` is drastically lower than expected of the CPUs the workers run on, naming
+    /// the likely causes.
+    ///
+    /// Only random Ed25519 keys are checked, as the rate of other key generators is unknown.
+    pub(crate) fn check_throughput(&self, rate: f64) {
+        if !self.key_generator.1 {
+            return;
+        }
+
+        let cores = self.counters_worker.len().min(num_cpus::get_physical());
+        let expected = cores as f64 * EXPECTED_RATE_PER_CORE;
+        if rate >= expected * WARN_BELOW {
+            return;
+        }
+
+        let mut causes = slowdown_causes();
+        if causes.is_empty() {
+            causes.push("the machine may be busy with other work or throttled");
+        }
+        self.log(
+            Verbosity::Normal,
+            format_args!(
+                "Warning: only {} keys/s, while {} {} should manage about {} keys/s: {}",
+                self.format_number(rate),
+                cores,
+                if cores == 1 { "core" } else { "cores" },
+                self.format_number(expected),
+                causes.join("; ")
+            ),
+        );
+    }
+}
+
+/// The reasons for slow searches that can be found out, as advice for the user.
+fn slowdown_causes() -> Vec<&'static str> {
+    let mut causes = Vec::new();
+    if cfg!(debug_assertions) {
+        causes.push("this is a debug build, build with --release");
+    }
+    if power_saving() {
+        causes.push("the CPU is set to save power, e.g. by the powersave frequency governor");
+    }
+    #[cfg(target_arch = "x86_64")]
+    if !std::arch::is_x86_feature_detected!("avx2") {
+        causes.push("the CPU lacks AVX2, which speeds up Ed25519, or hides it from this VM");
+    }
+    causes
+}
+
+/// Whether the frequency of the first CPU is kept low to save power.
+///
+/// The powersave governor of the `intel_pstate` and `amd-pstate` drivers still raises the
+/// frequency under load, unless their energy preference says otherwise.
+fn power_saving() -> bool {
+    let read = |name: &str| {
+        fs::read_to_string(format!("{CPUFREQ_DIR}/{name}"))
+            .map(|value| value.trim().to_string())
+            .unwrap_or_default()
+    };
+    if read("energy_performance_preference") == "power" {
+        return true;
+    }
+    read("scaling_governor") == "powersave" && !read("scaling_driver").contains("pstate")
+}
@@ -0,0 +1,25 @@
+//! Reading the CPU topology from sysfs, shared by the NUMA and hybrid core placement.
+
+use std::{fs, path::Path};
+
+use anyhow::{Context, bail};
+
+pub(crate) fn read_trimmed(path: &Path) -> anyhow::Result<String> {
+    Ok(fs::read_to_string(path)
+        .with_context(|| format!("Failed to read {}", path.display()))?
+        .trim()
+        .to_string())
+}
+
+/// Parses a CPU list like `0-3,8-11` as used by sysfs.
+pub(crate) fn parse_cpu_list(list: &str) -> anyhow::Result<Vec<usize>> {
+    let mut cpus = Vec::new();
+    for range in list.split(',').filter(|range| !range.is_empty()) {
+        let (first, last) = range.split_once('-').unwrap_or((range, range));
+        let (Ok(first), Ok(last)) = (first.parse::<usize>(), last.parse::<usize>()) else {
+            bail!("Invalid CPU list {list:?}");
+        };
+        cpus.extend(first..=last);
+    }
+    Ok(cpus)
+}
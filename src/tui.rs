@@ -0,0 +1,255 @@
+//! Full-screen terminal user interface, enabled with the `tui` feature.
+
+use std::{
+    collections::VecDeque,
+    io,
+    sync::atomic::Ordering,
+    time::{Duration, Instant},
+};
+
+use crossbeam_channel::Sender;
+use crossterm::{
+    event::{self, Event, KeyCode, KeyEventKind, KeyModifiers},
+    execute,
+    terminal::{self, EnterAlternateScreen, LeaveAlternateScreen},
+};
+use ratatui::{
+    Frame, Terminal,
+    backend::CrosstermBackend,
+    layout::{Constraint, Layout},
+    style::{Color, Modifier, Style},
+    text::{Line, Span},
+    widgets::{Block, List, Paragraph, Sparkline},
+};
+
+use crate::{
+    Fingerprunk, Message,
+    status::{DurationDhms, RateMeter},
+};
+
+/// Statistics sampled by the TUI thread.
+struct Samples {
+    rate_meter: RateMeter,
+    rate_history: VecDeque<u64>,
+    worker_tried: Vec<u64>,
+    worker_rates: Vec<f64>,
+    last_instant: Instant,
+}
+
+impl Samples {
+    /// Number of rate samples kept for the rate graph.
+    const HISTORY_LEN: usize = 512;
+
+    fn new(prunk: &Fingerprunk) -> Self {
+        Self {
+            rate_meter: RateMeter::new(prunk.started_instant),
+            rate_history: VecDeque::with_capacity(Self::HISTORY_LEN),
+            worker_tried: vec![0; prunk.counters_worker.len()],
+            worker_rates: vec![0.0; prunk.counters_worker.len()],
+            last_instant: prunk.started_instant,
+        }
+    }
+
+    fn update(&mut self, prunk: &Fingerprunk) {
+        let now = Instant::now();
+        let elapsed = now.duration_since(self.last_instant).as_secs_f64();
+        if elapsed <= 0.0 {
+            return;
+        }
+
+        self.rate_meter
+            .update(now, prunk.counter_tried.load(Ordering::Relaxed));
+        if self.rate_history.len() == Self::HISTORY_LEN {
+            self.rate_history.pop_front();
+        }
+        self.rate_history
+            .push_back(self.rate_meter.smoothed().unwrap_or(0.0) as u64);
+
+        for (num, counter) in prunk.counters_worker.iter().enumerate() {
+            let tried = counter.load(Ordering::Relaxed);
+            self.worker_rates[num] = (tried - self.worker_tried[num]) as f64 / elapsed;
+            self.worker_tried[num] = tried;
+        }
+
+        self.last_instant = now;
+    }
+}
+
+impl Fingerprunk {
+    pub(crate) fn tui_thread(&self, sender: Sender<Message>) -> anyhow::Result<()> {
+        // The TUI is drawn to stderr because found keys are written to stdout.
+        terminal::enable_raw_mode()?;
+        execute!(io::stderr(), EnterAlternateScreen)?;
+
+        let result = self.tui_loop(&sender);
+
+        let _ = execute!(io::stderr(), LeaveAlternateScreen);
+        let _ = terminal::disable_raw_mode();
+
+        if result.is_err() {
+            let _ = sender.send(Message::Stop);
+        }
+        result
+    }
+
+    fn tui_loop(&self, sender: &Sender<Message>) -> anyhow::Result<()> {
+        const UPDATE_INTERVAL: Duration = Duration::from_millis(250);
+
+        let mut terminal = Terminal::new(CrosstermBackend::new(io::stderr()))?;
+        let mut samples = Samples::new(self);
+        let mut next_update = Instant::now();
+        let mut bell_last_rung = None;
+
+        while !self.stop.load(Ordering::Relaxed) {
+            if Instant::now() >= next_update {
+                samples.update(self);
+                self.ring_bell(&mut bell_last_rung);
+                next_update = Instant::now() + UPDATE_INTERVAL;
+            }
+
+            terminal.draw(|frame| self.draw_tui(frame, &samples))?;
+
+            if !event::poll(next_update.saturating_duration_since(Instant::now()))? {
+                continue;
+            }
+            let Event::Key(key) = event::read()? else {
+                continue;
+            };
+            if key.kind != KeyEventKind::Press {
+                continue;
+            }
+
+            match key.code {
+                KeyCode::Char('q') | KeyCode::Esc => {
+                    let _ = sender.send(Message::Stop);
+                }
+                // Raw mode swallows the SIGINT, so handle Ctrl-C ourselves.
+                KeyCode::Char('c') if key.modifiers.contains(KeyModifiers::CONTROL) => {
+                    let _ = sender.send(Message::Stop);
+                }
+                KeyCode::Char('p') => {
+                    self.paused.fetch_xor(true, Ordering::Relaxed);
+                }
+                KeyCode::Char('s') => next_update = Instant::now(),
+                _ => {}
+            }
+        }
+
+        Ok(())
+    }
+
+    fn draw_tui(&self, frame: &mut Frame, samples: &Samples) {
+        let [stats_area, graph_area, lists_area, help_area] = Layout::vertical([
+            Constraint::Length(8),
+            Constraint::Length(8),
+            Constraint::Min(4),
+            Constraint::Length(1),
+        ])
+        .areas(frame.area());
+        let [workers_area, found_area] =
+            Layout::horizontal([Constraint::Percentage(35), Constraint::Percentage(65)])
+                .areas(lists_area);
+
+        let elapsed = self.started_instant.elapsed();
+        let tried = self.counter_tried.load(Ordering::Relaxed);
+        let found = self.counter_found.load(Ordering::Relaxed);
+        let avg_rate = tried as f64 / elapsed.as_secs_f64();
+        let rate = samples.rate_meter.smoothed().unwrap_or(avg_rate);
+        let matcher = self.matcher();
+        let estimate = matcher.estimate();
+        let expected = self.expected_tries(estimate, found);
+        let eta = estimate
+            .filter(|estimate| estimate.probability > 0.0)
+            .and_then(|estimate| Duration::try_from_secs_f64(estimate.median_tries() / rate).ok())
+            .map_or_else(|| "unknown".to_string(), |d| DurationDhms(d).to_string());
+        let state = if self.paused.load(Ordering::Relaxed) {
+            Span::styled("paused", Style::new().fg(Color::Yellow))
+        } else {
+            Span::styled("running", Style::new().fg(Color::Green))
+        };
+
+        let stats = vec![
+            Line::from(vec![Span::raw("State:    "), state]),
+            Line::raw(format!("Pattern:  {matcher}")),
+            Line::raw(format!("Time:     {}", DurationDhms(elapsed))),
+            Line::raw(format!(
+                "Tried:    {} keys ({} keys/s, avg. {} keys/s)",
+                self.format_number(tried as f64),
+                self.format_number(rate),
+                self.format_number(avg_rate)
+            )),
+            Line::raw(match expected {
+                Some(expected) => format!(
+                    "Expected: {} keys ({:.1} % tried), ETA {eta}",
+                    self.format_number(expected),
+                    tried as f64 / expected * 100.0
+                ),
+                None => format!("Expected: unknown, ETA {eta}"),
+            }),
+            Line::raw(format!("Found:    {found} keys")),
+        ];
+        frame.render_widget(
+            Paragraph::new(stats).block(Block::bordered().title(" Fingerprunk ")),
+            stats_area,
+        );
+
+        // Only show as many samples as fit into the graph, newest on the right.
+        let graph_width = usize::from(graph_area.width.saturating_sub(2));
+        let history = samples
+            .rate_history
+            .iter()
+            .skip(samples.rate_history.len().saturating_sub(graph_width))
+            .copied();
+        frame.render_widget(
+            Sparkline::default()
+                .data(history)
+                .style(Style::new().fg(Color::Cyan))
+                .block(Block::bordered().title(" Rate (keys/s) ")),
+            graph_area,
+        );
+
+        let workers =
+            samples.worker_rates.iter().enumerate().map(|(num, rate)| {
+                format!("worker-{num:03} {: >8} keys/s", self.format_number(*rate))
+            });
+        frame.render_widget(
+            List::new(workers).block(Block::bordered().title(" Workers ")),
+            workers_area,
+        );
+
+        let found_fingerprints = self
+            .found_fingerprints
+            .lock()
+            .expect("lock should not be poisoned");
+        let found_lines = found_fingerprints
+            .iter()
+            .rev()
+            .map(|fingerprint| self.highlighted_line(fingerprint));
+        frame.render_widget(
+            List::new(found_lines).block(Block::bordered().title(" Found keys (newest first) ")),
+            found_area,
+        );
+        drop(found_fingerprints);
+
+        frame.render_widget(
+            Paragraph::new("p: pause/resume   s: refresh status   q: quit")
+                .style(Style::new().add_modifier(Modifier::DIM)),
+            help_area,
+        );
+    }
+
+    /// Returns the fingerprint as a line with the part matched by the regex highlighted.
+    fn highlighted_line(&self, fingerprint_hex: &str) -> Line<'static> {
+        match self.matcher().find(fingerprint_hex) {
+            Some(range) => Line::from(vec![
+                Span::raw(fingerprint_hex[..range.start].to_string()),
+                Span::styled(
+                    fingerprint_hex[range.clone()].to_string(),
+                    Style::new().fg(Color::Green).add_modifier(Modifier::BOLD),
+                ),
+                Span::raw(fingerprint_hex[range.end..].to_string()),
+            ]),
+            _ => Line::raw(fingerprint_hex.to_string()),
+        }
+    }
+}
@@ -0,0 +1,142 @@
+//! Picking the number of worker threads and their batch size by measuring, as hyperthreads and
+//! small batches can slow the search down on some machines.
+
+use std::{
+    num::NonZeroUsize,
+    sync::{
+        Arc,
+        atomic::{AtomicBool, AtomicU64, Ordering},
+    },
+    thread,
+    time::{Duration, Instant},
+};
+
+use crate::{
+    Error, Fingerprunk,
+    keygen::{self, HexBuffer},
+    matcher::PatternSet,
+};
+
+/// How long every configuration is measured.
+const TRIAL_DURATION: Duration = Duration::from_millis(500);
+
+/// Batch sizes tried unless [`Config::batch_size`](crate::Config::batch_size) is set.
+const BATCH_SIZES: [usize; 3] = [1, 16, 256];
+
+/// The configuration chosen by [`Fingerprunk::auto_tune`].
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Tuning {
+    pub threads: NonZeroUsize,
+    pub batch_size: NonZeroUsize,
+    /// Keys tried per second with this configuration while tuning.
+    pub rate: f64,
+}
+
+impl Fingerprunk {
+    /// Measures the search rate with several thread counts and batch sizes for a moment each and
+    /// uses the fastest configuration from now on.
+    ///
+    /// Thread counts between the number of physical and logical CPUs are tried, unless the
+    /// configuration sets [`threads`](crate::Config::threads) or
+    /// [`batch_size`](crate::Config::batch_size), which are kept then. Keys found while tuning
+    /// are discarded. [`Self::run`] tunes by itself if
+    /// [`Config::auto_tune`](crate::Config::auto_tune) is set.
+    pub fn auto_tune(&mut self) -> Result<Tuning, Error> {
+        let thread_counts = match self.config.threads {
+            Some(threads) => vec![threads.get()],
+            None => {
+                let (physical, logical) = (num_cpus::get_physical(), num_cpus::get());
+                let mut counts = vec![physical, physical.midpoint(logical), logical];
+                counts.dedup();
+                counts
+            }
+        };
+        let batch_sizes = match self.config.batch_size {
+            Some(batch_size) => vec![batch_size.get()],
+            None => BATCH_SIZES.to_vec(),
+        };
+
+        let matcher = self.matcher();
+        let mut best: Option<Tuning> = None;
+        for &threads in &thread_counts {
+            for &batch_size in &batch_sizes {
+                let rate = self.measure(&matcher, threads, batch_size)?;
+                if best.is_none_or(|best| rate > best.rate) {
+                    best = Some(Tuning {
+                        threads: NonZeroUsize::new(threads).expect("thread count should not be 0"),
+                        batch_size: NonZeroUsize::new(batch_size)
+                            .expect("batch size should not be 0"),
+                        rate,
+                    });
+                }
+            }
+        }
+
+        let tuning = best.expect("at least one configuration should have been measured");
+        self.counters_worker = (0..tuning.threads.get())
+            .map(|_| AtomicU64::new(0))
+            .collect();
+        self.batch_size = tuning.batch_size.get();
+        Ok(tuning)
+    }
+
+    /// Runs `threads` workers trying keys in batches of `batch_size` for [`TRIAL_DURATION`] and
+    /// returns the rate.
+    fn measure(
+        &self,
+        matcher: &Arc<PatternSet>,
+        threads: usize,
+        batch_size: usize,
+    ) -> Result<f64, Error> {
+        let stop = AtomicBool::new(false);
+        let tried = AtomicU64::new(0);
+        let started = Instant::now();
+        let result = thread::scope(|scope| {
+            let workers: Vec<_> = (0..threads)
+                .map(|num| {
+                    let (stop, tried) = (&stop, &tried);
+                    thread::Builder::new()
+                        .name(format!("tune-{num:03}"))
+                        .spawn_scoped(scope, move || {
+                            self.trial_worker(num, matcher, batch_size, stop, tried)
+                        })
+                })
+                .collect::<Result<_, _>>()
+                .map_err(|err| Error::Other(err.into()))?;
+            thread::sleep(TRIAL_DURATION);
+            stop.store(true, Ordering::Relaxed);
+            workers
+                .into_iter()
+                .try_for_each(|worker| worker.join().expect("tuning worker should not panic"))
+        });
+        result?;
+        Ok(tried.into_inner() as f64 / started.elapsed().as_secs_f64())
+    }
+
+    /// Generates and matches keys like the workers of a search do, only counting them.
+    fn trial_worker(
+        &self,
+        num: usize,
+        matcher: &PatternSet,
+        batch_size: usize,
+        stop: &AtomicBool,
+        tried: &AtomicU64,
+    ) -> Result<(), Error> {
+        let mut generator = (self.key_generator.0)(num);
+        let matcher = matcher.for_worker();
+        let mut hex_buffer: HexBuffer = [0; 64];
+        while !stop.load(Ordering::Relaxed) {
+            for _ in 0..batch_size {
+                let candidate = generator.generate().map_err(Error::KeyGeneration)?;
+                // Fingerprints of unknown versions aren't worth tuning for
+                if let Some(hex) =
+                    keygen::encode_fingerprint_hex(&candidate.fingerprint, &mut hex_buffer)
+                {
+                    matcher.is_match(hex)?;
+                }
+            }
+            tried.fetch_add(batch_size as u64, Ordering::Relaxed);
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,120 @@
+//! Searching in a browser, where the search is driven by JavaScript instead of worker threads.
+//!
+//! A [`Search`] tries a given number of keys per call to [`Search::step`], so that a web worker
+//! can run it in a loop and post the progress and found keys to the page in between. Keys are
+//! generated and hashed in WebAssembly, so the secret keys never leave the browser.
+
+use std::time::Duration;
+
+use wasm_bindgen::prelude::*;
+
+use crate::{
+    keygen::{self, CertOptions, Ed25519Generator, KeyGenerator, KeyVersion},
+    matcher::PatternSet,
+    pattern::parse_pattern_for,
+    sink::{self, MatchMeta},
+};
+
+/// A single-threaded search for keys matching any of a set of regexes.
+#[wasm_bindgen]
+#[derive(Debug)]
+pub struct Search {
+    patterns: PatternSet,
+    password: Option<String>,
+    generator: Ed25519Generator,
+    tried: u64,
+}
+
+/// A key found by [`Search::step`].
+#[wasm_bindgen(getter_with_clone)]
+#[derive(Clone, Debug)]
+pub struct FoundKey {
+    /// Fingerprint of the key in uppercase hex.
+    pub fingerprint: String,
+    /// The regex the key matched.
+    pub pattern: String,
+    /// The secret key, armored and encrypted with the password if one was given.
+    pub armored: String,
+}
+
+#[wasm_bindgen]
+impl Search {
+    /// Checks the regexes like the CLI does and prepares the search.
+    #[wasm_bindgen(constructor)]
+    pub fn new(regexes: Vec<String>, password: Option<String>) -> Result<Search, JsError> {
+        let regexes = regexes
+            .iter()
+            .map(|regex| parse_pattern_for(regex, KeyVersion::V4))
+            .collect::<Result<Vec<_>, _>>()?;
+        if regexes.is_empty() {
+            return Err(JsError::new("No patterns given"));
+        }
+        Ok(Self {
+            patterns: PatternSet::from_regexes(regexes),
+            password,
+            generator: Ed25519Generator,
+            tried: 0,
+        })
+    }
+
+    /// Tries up to `count` keys and returns the first matching one, if any.
+    pub fn step(&mut self, count: u32) -> Result<Option<FoundKey>, JsError> {
+        for _ in 0..count {
+            let candidate = self.generator.generate().map_err(js_error)?;
+            let fingerprint = keygen::fingerprint_hex(&candidate.key);
+            self.tried += 1;
+            let Some(matcher) = self.patterns.matching(&fingerprint)? else {
+                continue;
+            };
+
+            let cert = keygen::build_cert(
+                &candidate.key,
+                &CertOptions {
+                    user_id: None,
+                    password: self.password.as_deref().map(Into::into),
+                    subkey: None,
+                    certifier: None,
+                    proofs: Vec::new(),
+                },
+            )
+            .map_err(js_error)?;
+            let meta = MatchMeta {
+                fingerprint: fingerprint.clone(),
+                pattern: matcher.to_string(),
+                label: None,
+                job: None,
+                dir: None,
+                tried: self.tried,
+                proof: None,
+                // Time is kept by the caller, as there is no clock in WebAssembly
+                elapsed: Duration::ZERO,
+            };
+            let mut armored = Vec::new();
+            sink::write_armored(&cert, &meta, &mut armored).map_err(js_error)?;
+            return Ok(Some(FoundKey {
+                fingerprint,
+                pattern: meta.pattern,
+                armored: String::from_utf8(armored).map_err(js_error)?,
+            }));
+        }
+        Ok(None)
+    }
+
+    /// Number of keys tried so far.
+    #[wasm_bindgen(getter)]
+    pub fn tried(&self) -> f64 {
+        self.tried as f64
+    }
+
+    /// The expected number of keys to try per match, if it can be estimated.
+    #[wasm_bindgen(js_name = expectedTries)]
+    pub fn expected_tries(&self) -> Option<f64> {
+        self.patterns
+            .estimate()
+            .map(|estimate| estimate.expected_tries())
+    }
+}
+
+fn js_error(err: impl std::fmt::Display) -> JsError {
+    JsError::new(&format!("{err:#}"))
+}
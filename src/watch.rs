@@ -0,0 +1,81 @@
+//! Reloading the patterns file when it changes, enabled with the `watch` feature.
+
+use std::{
+    ffi::OsString,
+    path::Path,
+    sync::{atomic::Ordering, mpsc},
+    time::Duration,
+};
+
+use anyhow::{Context, anyhow};
+use notify_fs::{RecommendedWatcher, RecursiveMode, Watcher};
+
+use crate::Fingerprunk;
+
+/// How often the watcher thread checks whether the search has stopped.
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// How long to wait for further changes before reloading, as editors often write files in
+/// several steps.
+const DEBOUNCE: Duration = Duration::from_millis(200);
+
+/// A filesystem watcher for the patterns file and the channel it reports changes to.
+pub(crate) struct PatternsWatcher {
+    // Only kept so that watching continues until this is dropped
+    _watcher: RecommendedWatcher,
+    changes: mpsc::Receiver<()>,
+}
+
+impl PatternsWatcher {
+    /// Starts watching the patterns file at `path`.
+    ///
+    /// The parent directory is watched instead of the file itself, because editors commonly
+    /// replace the file when saving.
+    pub(crate) fn new(path: &Path) -> anyhow::Result<Self> {
+        let file_name: OsString = path
+            .file_name()
+            .ok_or_else(|| anyhow!("Invalid patterns file path {}", path.display()))?
+            .into();
+        let dir = match path.parent() {
+            Some(dir) if !dir.as_os_str().is_empty() => dir,
+            _ => Path::new("."),
+        };
+
+        let (sender, changes) = mpsc::channel();
+        let mut watcher =
+            notify_fs::recommended_watcher(move |event: notify_fs::Result<notify_fs::Event>| {
+                let Ok(event) = event else { return };
+                let affects_file = event
+                    .paths
+                    .iter()
+                    .any(|path| path.file_name() == Some(&file_name));
+                if affects_file && !event.kind.is_access() {
+                    let _ = sender.send(());
+                }
+            })?;
+        watcher
+            .watch(dir, RecursiveMode::NonRecursive)
+            .with_context(|| format!("Failed to watch {}", dir.display()))?;
+
+        Ok(Self {
+            _watcher: watcher,
+            changes,
+        })
+    }
+}
+
+impl Fingerprunk {
+    /// Reloads the patterns file whenever it changes until the search stops.
+    pub(crate) fn watch_thread(&self, watcher: PatternsWatcher) {
+        while !self.stop.load(Ordering::Relaxed) {
+            match watcher.changes.recv_timeout(POLL_INTERVAL) {
+                Ok(()) => {}
+                Err(mpsc::RecvTimeoutError::Timeout) => continue,
+                Err(mpsc::RecvTimeoutError::Disconnected) => break,
+            }
+
+            while watcher.changes.recv_timeout(DEBOUNCE).is_ok() {}
+            self.reload_patterns_and_log();
+        }
+    }
+}